@@ -0,0 +1,404 @@
+use wasm_bindgen::prelude::*;
+
+use crate::dct::{self, BlockStats, ZIGZAG};
+use crate::error::Error;
+use crate::heatmap::{ImagePlane, MAX_COEFFICIENT};
+use crate::metrics::{self, SsimWindow};
+use crate::options::{ChromaSubsampling, CompressOptions, CompressionScale, PixelFormat};
+use crate::pipeline::{self, Frame, Planes};
+use crate::quant::Quantizer;
+
+/// Per-block quantity reported by `block_map`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockMetric {
+    /// Mean squared error the quantization introduced into the block.
+    Error,
+    /// Number of DCT coefficients that survived quantization (0–64).
+    NonZero,
+    /// Estimated entropy-coded size of the block in bits.
+    Bits,
+}
+
+impl BlockMetric {
+    fn value(self, stats: &BlockStats) -> f32 {
+        match self {
+            BlockMetric::Error => stats.error,
+            BlockMetric::NonZero => stats.nonzero as f32,
+            BlockMetric::Bits => stats.bits,
+        }
+    }
+}
+
+/// Per-block content measure reported by `block_complexity`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockComplexity {
+    /// Energy of the 63 AC coefficients per pixel, which equals the block's luma variance.
+    AcEnergy,
+    /// Shannon entropy of the block's luma histogram in bits (0 for flat blocks, at most 6).
+    Entropy,
+    /// Share of the AC energy outside the first 16 zigzag positions (0–1); high for
+    /// text, line art and fine texture.
+    HighFrequency,
+}
+
+/// Zigzag position from which [`BlockComplexity::HighFrequency`] counts energy.
+const HIGH_FREQUENCY_START: usize = 16;
+
+impl BlockComplexity {
+    fn value(self, pixels: &[u8; 64], coefficients: &[[f32; 8]; 8]) -> f32 {
+        match self {
+            BlockComplexity::AcEnergy => block_variance(coefficients),
+            BlockComplexity::Entropy => histogram_entropy(pixels),
+            BlockComplexity::HighFrequency => high_frequency_share(coefficients),
+        }
+    }
+}
+
+/// Shannon entropy in bits of the histogram of a block's pixels.
+fn histogram_entropy(pixels: &[u8; 64]) -> f32 {
+    let mut histogram = [0u32; 256];
+    for &p in pixels {
+        histogram[p as usize] += 1;
+    }
+    histogram
+        .iter()
+        .filter(|&&n| n > 0)
+        .map(|&n| {
+            let p = n as f32 / 64.0;
+            -p * libm::log2f(p)
+        })
+        .sum()
+}
+
+/// Luma variance of a block, from its AC coefficients.
+fn block_variance(coefficients: &[[f32; 8]; 8]) -> f32 {
+    ac_energy(coefficients, 1) / 64.0
+}
+
+/// Share of a block's AC energy from [`HIGH_FREQUENCY_START`] on; 0 for flat blocks.
+fn high_frequency_share(coefficients: &[[f32; 8]; 8]) -> f32 {
+    let total = ac_energy(coefficients, 1);
+    if total < 1e-6 {
+        0.0
+    } else {
+        ac_energy(coefficients, HIGH_FREQUENCY_START) / total
+    }
+}
+
+/// Sum of squared coefficients from zigzag position `start` on.
+fn ac_energy(coefficients: &[[f32; 8]; 8], start: usize) -> f32 {
+    dct::ZIGZAG[start..]
+        .iter()
+        .map(|&(u, v)| coefficients[u][v] * coefficients[u][v])
+        .sum()
+}
+
+/// Compresses the luma plane of an RGBA buffer and returns one value per
+/// 8x8 block in raster order, `ceil(width / 8)` blocks per row.
+pub(crate) fn block_map(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    options: &CompressOptions,
+    metric: BlockMetric,
+) -> Result<Vec<f32>, Error> {
+    let mut frame = Frame::from_pixels(data, width, height, PixelFormat::Rgba)?;
+    let planes = pipeline::prepare_planes(&mut frame, options);
+    let quantizer = Quantizer::new(options.compression.clamp(0.0, 1.0), options);
+    let (_, stats) = dct::process_blocks_with_stats(&planes.y, width, height, &quantizer);
+    Ok(stats.iter().map(|s| metric.value(s)).collect())
+}
+
+/// Measures the luma plane of an RGBA buffer without quantizing it and returns
+/// one value per 8x8 block in raster order, `ceil(width / 8)` blocks per row.
+pub(crate) fn block_complexity(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    options: &CompressOptions,
+    measure: BlockComplexity,
+) -> Result<Vec<f32>, Error> {
+    let mut frame = Frame::from_pixels(data, width, height, PixelFormat::Rgba)?;
+    let planes = pipeline::prepare_planes(&mut frame, options);
+    Ok(luma_blocks(&planes)
+        .iter()
+        .map(|(pixels, block)| measure.value(pixels, block))
+        .collect())
+}
+
+/// Histograms of the DCT coefficients of one plane per zigzag index, before
+/// and after quantization, from `coefficient_histogram`.
+///
+/// Every index has `bins` bins of `bin_width`, the middle one centered on 0,
+/// spanning every value 8-bit samples can produce. Histograms are stored one
+/// after another in zigzag order, so the count of bin `b` at index `k` is
+/// at `k * bins + b`.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct CoefficientHistogram {
+    /// Bins per zigzag index.
+    pub bins: u32,
+    /// Width of a bin in coefficient units.
+    pub bin_width: f32,
+    /// Lower edge of the first bin.
+    pub min: f32,
+    before: Vec<u32>,
+    after: Vec<u32>,
+    energy_before: Vec<f64>,
+    energy_after: Vec<f64>,
+}
+
+#[wasm_bindgen]
+impl CoefficientHistogram {
+    /// Counts of the coefficients before quantization, `64 * bins` values.
+    #[wasm_bindgen(getter)]
+    pub fn before(&self) -> Vec<u32> {
+        self.before.clone()
+    }
+
+    /// Counts of the dequantized coefficients, `64 * bins` values.
+    #[wasm_bindgen(getter)]
+    pub fn after(&self) -> Vec<u32> {
+        self.after.clone()
+    }
+
+    /// Mean squared coefficient per zigzag index before quantization, the
+    /// energy each frequency carries.
+    #[wasm_bindgen(getter)]
+    pub fn energy_before(&self) -> Vec<f64> {
+        self.energy_before.clone()
+    }
+
+    /// Mean squared coefficient per zigzag index after quantization.
+    #[wasm_bindgen(getter)]
+    pub fn energy_after(&self) -> Vec<f64> {
+        self.energy_after.clone()
+    }
+}
+
+/// Histograms the 8x8 DCT coefficients of `plane` of an RGBA buffer before
+/// and after quantization at `options.compression`, in bins of `bin_width`
+/// (at least 1).
+pub(crate) fn coefficient_histogram(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    options: &CompressOptions,
+    plane: ImagePlane,
+    bin_width: f32,
+) -> Result<CoefficientHistogram, Error> {
+    let frame = Frame::from_pixels(data, width, height, PixelFormat::Rgba)?;
+    let (before, _, _) = pipeline::plane_coefficients(frame.clone(), options, plane, false);
+    let (after, _, _) = pipeline::plane_coefficients(frame, options, plane, true);
+
+    let bin_width = if bin_width >= 1.0 { bin_width } else { 1.0 };
+    let half = (MAX_COEFFICIENT / bin_width).ceil() as usize;
+    let bins = 2 * half + 1;
+    let min = -(half as f32 + 0.5) * bin_width;
+    let histogram = |blocks: &[[[f32; 8]; 8]]| {
+        let mut counts = vec![0u32; 64 * bins];
+        let mut energy = vec![0.0; 64];
+        for block in blocks {
+            for (k, &(u, v)) in ZIGZAG.iter().enumerate() {
+                let value = block[u][v];
+                let bin = ((value - min) / bin_width).floor().clamp(0.0, (bins - 1) as f32);
+                counts[k * bins + bin as usize] += 1;
+                energy[k] += (value as f64).powi(2);
+            }
+        }
+        energy.iter_mut().for_each(|e| *e /= blocks.len() as f64);
+        (counts, energy)
+    };
+    let (before, energy_before) = histogram(&before);
+    let (after, energy_after) = histogram(&after);
+    Ok(CoefficientHistogram {
+        bins: bins as u32,
+        bin_width,
+        min,
+        before,
+        after,
+        energy_before,
+        energy_after,
+    })
+}
+
+/// Quality of one partial pipeline of `analyze_stages` against the input.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StageQuality {
+    /// Combined RGB PSNR in decibels.
+    pub psnr: f64,
+    /// Luma SSIM (gaussian window).
+    pub ssim: f64,
+}
+
+/// Output of `analyze_stages`: the quality of partial pipelines that each
+/// leave out some of the lossy stages. The drop from one to the next is what
+/// the added stage costs.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StageAnalysis {
+    /// Color conversion and 8-bit rounding only: no subsampling, no
+    /// quantization. The floor every other stage starts from.
+    pub rounding: StageQuality,
+    /// Rounding plus chroma subsampling, without quantization.
+    pub subsampling: StageQuality,
+    /// Rounding plus quantization, with chroma at full resolution.
+    pub quantization: StageQuality,
+    /// The full pipeline, as `compress_jpeg_with_options` runs it.
+    pub total: StageQuality,
+}
+
+/// Compresses an RGBA buffer with subsampling and quantization each switched
+/// on and off and measures every result against the input.
+pub(crate) fn analyze_stages(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    options: &CompressOptions,
+) -> Result<StageAnalysis, Error> {
+    let frame = Frame::from_pixels(data, width, height, PixelFormat::Rgba)?;
+    let measure = |subsample: bool, quantize: bool| {
+        let options = CompressOptions {
+            subsampling: if subsample { options.subsampling } else { ChromaSubsampling::Yuv444 },
+            compression: if quantize { options.compression } else { 0.0 },
+            ..options.clone()
+        };
+        let output = pipeline::compress_frame(frame.clone(), &options).to_pixels(PixelFormat::Rgba);
+        StageQuality {
+            psnr: metrics::psnr(data, &output).combined,
+            ssim: metrics::ssim(data, &output, width, height, SsimWindow::Gaussian),
+        }
+    };
+    Ok(StageAnalysis {
+        rounding: measure(false, false),
+        subsampling: measure(true, false),
+        quantization: measure(false, true),
+        total: measure(true, true),
+    })
+}
+
+/// Rounded pixels and DCT coefficients of every 8x8 luma block in raster order.
+///
+/// Blocks overhanging the right or bottom edge repeat the last column or row,
+/// as the block pass does.
+fn luma_blocks(planes: &Planes) -> Vec<([u8; 64], [[f32; 8]; 8])> {
+    let (width, height) = (planes.width, planes.height);
+    let blocks_x = width.div_ceil(8);
+    dct::forward_blocks(&planes.y, width, height)
+        .into_iter()
+        .enumerate()
+        .map(|(i, block)| {
+            let (bx, by) = (i % blocks_x * 8, i / blocks_x * 8);
+            let mut pixels = [0u8; 64];
+            for (j, p) in pixels.iter_mut().enumerate() {
+                let x = (bx + j % 8).min(width - 1);
+                let y = (by + j / 8).min(height - 1);
+                *p = planes.y[y * width + x].round().clamp(0.0, 255.0) as u8;
+            }
+            (pixels, block)
+        })
+        .collect()
+}
+
+/// Lowest and highest compression [`auto_compression`] picks, on the linear scale.
+const AUTO_MIN: f32 = 0.01;
+const AUTO_MAX: f32 = 0.2;
+/// Luma noise (standard deviation) at which masking stops helping further.
+const AUTO_NOISE_CEILING: f32 = 6.0;
+/// Block variance below which a block counts as flat.
+const FLAT_VARIANCE: f32 = 2.0;
+/// Block variance above which a block counts as a sharp edge when it is also
+/// synthetic.
+const EDGE_VARIANCE: f32 = 100.0;
+/// Histogram entropy (bits) below which a block counts as synthetic: text, line
+/// art and UI use a handful of levels, while camera noise spreads over many.
+const SYNTHETIC_ENTROPY: f32 = 3.5;
+
+/// Content measurements [`auto_compression`] bases its choice on.
+struct ContentProfile {
+    /// Estimated luma noise standard deviation in sample levels.
+    noise: f32,
+    /// Share of luma blocks dominated by sharp edges (text, line art, UI).
+    edges: f32,
+    /// Share of luma blocks that are nearly flat, where blocking shows first.
+    flat: f32,
+    /// Mean chroma block variance relative to the luma one; 0 for grayscale.
+    chroma: f32,
+}
+
+impl ContentProfile {
+    fn measure(planes: &Planes) -> ContentProfile {
+        let luma = luma_blocks(planes);
+        let count = luma.len() as f32;
+
+        // Noise spreads evenly over all frequencies while natural content
+        // concentrates in the low ones, so the typical high-frequency energy
+        // per coefficient of the non-synthetic blocks estimates the noise variance.
+        let mut energies: Vec<f32> = luma
+            .iter()
+            .filter(|(pixels, _)| histogram_entropy(pixels) >= SYNTHETIC_ENTROPY)
+            .map(|(_, b)| ac_energy(b, HIGH_FREQUENCY_START) / (64 - HIGH_FREQUENCY_START) as f32)
+            .collect();
+        energies.sort_by(f32::total_cmp);
+        let noise_variance = energies.get(energies.len() / 2).copied().unwrap_or(0.0);
+
+        let edges = luma
+            .iter()
+            .filter(|(pixels, b)| {
+                block_variance(b) > EDGE_VARIANCE && histogram_entropy(pixels) < SYNTHETIC_ENTROPY
+            })
+            .count() as f32;
+        let flat = luma
+            .iter()
+            .filter(|(_, b)| block_variance(b) < FLAT_VARIANCE)
+            .count() as f32;
+        let luma_variance = luma.iter().map(|(_, b)| block_variance(b)).sum::<f32>() / count;
+
+        let chroma = planes.chroma.as_ref().map_or(0.0, |c| {
+            let blocks: Vec<_> = [&c.cb, &c.cr]
+                .iter()
+                .flat_map(|p| dct::forward_blocks(p, c.width, c.height))
+                .collect();
+            let chroma_variance =
+                blocks.iter().map(block_variance).sum::<f32>() / blocks.len() as f32;
+            chroma_variance / (luma_variance + 1.0)
+        });
+
+        ContentProfile {
+            noise: noise_variance.sqrt(),
+            edges: edges / count,
+            flat: flat / count,
+            chroma,
+        }
+    }
+}
+
+/// Picks a compression level that should look close to the original for this
+/// content.
+///
+/// Noise and texture mask quantization error, so noisy photos get more;
+/// sharp edges ring and flat areas block, so screenshots, text and smooth
+/// gradients get less; busy chroma gets less when it is subsampled.
+pub(crate) fn auto_compression(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    options: &CompressOptions,
+) -> Result<f32, Error> {
+    let mut frame = Frame::from_pixels(data, width, height, PixelFormat::Rgba)?;
+    let planes = pipeline::prepare_planes(&mut frame, options);
+    let profile = ContentProfile::measure(&planes);
+
+    let masking = (profile.noise / AUTO_NOISE_CEILING).min(1.0) * (1.0 - profile.edges);
+    let subsampled = planes.chroma.as_ref().is_some_and(|c| c.fx > 1 || c.fy > 1);
+    let chroma_weight = if subsampled { 0.03 } else { 0.01 };
+    let compression = 0.06 + 0.12 * masking
+        - 0.05 * profile.edges
+        - 0.03 * profile.flat
+        - chroma_weight * profile.chroma.min(1.0);
+    let scale = CompressionScale::Linear.table_scale(compression.clamp(AUTO_MIN, AUTO_MAX));
+    Ok(options.compression_scale.compression(scale))
+}
@@ -0,0 +1,325 @@
+use wasm_bindgen::prelude::*;
+
+use crate::dct;
+use crate::metrics;
+use crate::options::CompressionScale;
+use crate::quant;
+
+/// Signs of earlier JPEG-style compression in an image, from `artifact_score`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ArtifactScore {
+    /// How much stronger luma steps are across the 8x8 grid lines than inside
+    /// blocks: 0 for a clean image, around 0.5 and above for visible blocking.
+    pub blockiness: f64,
+    /// How much busier flat areas are inside blocks that contain a sharp edge
+    /// than elsewhere: 0 for a clean image, rising with mosquito noise.
+    pub ringing: f64,
+}
+
+/// Evidence of an earlier JPEG encode, from `detect_grid`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridDetection {
+    /// How sure the detector is that the image was JPEG compressed before, 0–1.
+    pub confidence: f64,
+    /// Estimated `canvas.toBlob` quality (0.01–1.0) of that encode; unset when
+    /// its quantization steps cannot be recovered (very high quality, or
+    /// the image was resized or filtered since).
+    pub quality: Option<f64>,
+    /// Column (0–7) where the blocks of the earlier encode start.
+    pub dx: u32,
+    /// Row (0–7) where the blocks of the earlier encode start.
+    pub dy: u32,
+}
+
+/// Zigzag positions whose quantization steps [`detect_grid`] recovers: DC and
+/// the five lowest AC frequencies, which survive quantization most often.
+const PROBE_POSITIONS: usize = 6;
+/// Fewest coefficients a step estimate may rest on.
+const MIN_PROBE_SAMPLES: usize = 24;
+/// Largest mean distance to the nearest multiple (in steps) that still counts
+/// as quantized, before allowing for rounding noise.
+const MAX_PROBE_RESIDUE: f64 = 0.08;
+/// Mean absolute rounding noise of a decoded coefficient: the 8-bit RGB output
+/// of the earlier decode blurs every multiple by about this much.
+const PROBE_NOISE: f64 = 0.35;
+/// Mean and standard deviation of the residue of values spread evenly around
+/// the multiples; a fit must beat the mean by [`PROBE_SIGMAS`] deviations.
+const RANDOM_RESIDUE: f64 = 0.25;
+const RANDOM_RESIDUE_DEVIATION: f64 = 0.144;
+const PROBE_SIGMAS: f64 = 6.0;
+
+/// Finds the 8x8 grid of an earlier JPEG encode in an RGBA buffer, and the
+/// quality it was encoded at.
+///
+/// The grid phase is where luma steps are strongest; the quality comes from
+/// the luma DCT coefficients at that phase, which cluster on multiples of the
+/// quantization steps of the earlier encode.
+pub(crate) fn detect_grid(data: &[u8], width: usize, height: usize) -> GridDetection {
+    let luma = metrics::luma(data);
+    let profile = StepProfile::new(&luma, width, height);
+    let (dx, strength_x) = StepProfile::strongest_phase(&profile.horizontal);
+    let (dy, strength_y) = StepProfile::strongest_phase(&profile.vertical);
+    let grid = (strength_x * strength_y).sqrt();
+
+    let mut steps = probe_steps(&luma, width, height, dx, dy);
+    let (mut dx, mut dy) = (dx, dy);
+    if steps.len() < 2 {
+        // Mild encodes leave too little blocking to place the grid; look for the
+        // phase whose block means are quantized instead.
+        if let Some((x, y)) = quantized_dc_phase(&luma, width, height) {
+            (dx, dy) = (x, y);
+            steps = probe_steps(&luma, width, height, dx, dy);
+        }
+    }
+
+    let (quality, confidence) = if steps.len() >= 2 {
+        let mut scales: Vec<f64> = steps.iter().map(|&(scale, _)| scale).collect();
+        scales.sort_by(f64::total_cmp);
+        let scale = scales[scales.len() / 2] as f32;
+        let quality = 1.0 - CompressionScale::Browser.compression(scale) as f64;
+        let residue = steps.iter().map(|&(_, r)| r).sum::<f64>() / steps.len() as f64;
+        let found = steps.len() as f64 / PROBE_POSITIONS as f64;
+        (
+            Some(quality),
+            found * (1.0 - residue / 0.25).clamp(0.0, 1.0),
+        )
+    } else {
+        (None, (2.0 * grid).min(1.0) * 0.5)
+    };
+
+    GridDetection {
+        confidence,
+        quality,
+        dx: dx as u32,
+        dy: dy as u32,
+    }
+}
+
+/// Quantization step of each probe position for the blocks at (`dx`, `dy`), as
+/// a multiple of the standard table entry, with its residue.
+///
+/// Positions without a clear step are left out, and so are positions whose
+/// step fits just as well half a block away: there the regularity comes from
+/// the content (flat areas, synthetic gradients) rather than from a grid.
+fn probe_steps(luma: &[f64], w: usize, h: usize, dx: usize, dy: usize) -> Vec<(f64, f64)> {
+    let blocks = aligned_blocks(luma, w, h, dx, dy);
+    let control = aligned_blocks(luma, w, h, (dx + 4) % 8, (dy + 4) % 8);
+    dct::ZIGZAG[..PROBE_POSITIONS]
+        .iter()
+        .filter_map(|&(u, v)| {
+            // The encoder level-shifts samples by 128, which moves DC by 1024.
+            let shift = if (u, v) == (0, 0) { 1024.0 } else { 0.0 };
+            let values = |blocks: &[[[f32; 8]; 8]]| -> Vec<f64> {
+                blocks.iter().map(|b| b[u][v] as f64 - shift).collect()
+            };
+            let (step, residue) = quantization_step(&values(&blocks), 255)?;
+            if quantization_step(&values(&control), step).is_some_and(|(s, _)| s == step) {
+                return None;
+            }
+            Some((step as f64 / quant::STD_QUANT[u][v] as f64, residue))
+        })
+        .collect()
+}
+
+/// Largest DC step the phase search of [`quantized_dc_phase`] tries; anything
+/// coarser leaves enough blocking for the step profile to find the grid.
+const MAX_PHASE_DC_STEP: u32 = 16;
+
+/// Grid phase whose block means (DC coefficients) sit best on multiples of a
+/// common step, if any does.
+fn quantized_dc_phase(luma: &[f64], w: usize, h: usize) -> Option<(usize, usize)> {
+    let mut best: Option<((usize, usize), f64)> = None;
+    for dy in 0..8 {
+        for dx in 0..8 {
+            let mut dc = Vec::new();
+            for by in (dy..h.saturating_sub(7)).step_by(8) {
+                for bx in (dx..w.saturating_sub(7)).step_by(8) {
+                    let sum: f64 = (by..by + 8)
+                        .map(|y| luma[y * w + bx..y * w + bx + 8].iter().sum::<f64>())
+                        .sum();
+                    dc.push(sum / 8.0 - 1024.0);
+                }
+            }
+            if let Some((_, residue)) = quantization_step(&dc, MAX_PHASE_DC_STEP) {
+                if best.is_none_or(|(_, r)| residue < r) {
+                    best = Some(((dx, dy), residue));
+                }
+            }
+        }
+    }
+    best.map(|(phase, _)| phase)
+}
+
+/// DCT of every 8x8 luma block starting at (`dx`, `dy`) that lies fully inside
+/// the plane.
+fn aligned_blocks(luma: &[f64], w: usize, h: usize, dx: usize, dy: usize) -> Vec<[[f32; 8]; 8]> {
+    let mut blocks = Vec::new();
+    for by in (dy..h.saturating_sub(7)).step_by(8) {
+        for bx in (dx..w.saturating_sub(7)).step_by(8) {
+            let mut block = [[0.0; 8]; 8];
+            for (u, row) in block.iter_mut().enumerate() {
+                for (v, sample) in row.iter_mut().enumerate() {
+                    *sample = luma[(by + u) * w + bx + v] as f32;
+                }
+            }
+            blocks.push(dct::dct2d(block));
+        }
+    }
+    blocks
+}
+
+/// The largest step (2–`max_step`) that `values` sit on multiples of, with the
+/// mean distance to the nearest multiple in steps.
+///
+/// Divisors of the true step fit as well, so the search runs downward and
+/// stops at the first fit; values below half a step carry no information and
+/// are skipped.
+fn quantization_step(values: &[f64], max_step: u32) -> Option<(u32, f64)> {
+    (2..=max_step).rev().find_map(|step| {
+        let q = step as f64;
+        let (mut residue, mut n) = (0.0, 0);
+        for &c in values.iter().filter(|c| c.abs() >= q / 2.0) {
+            residue += (c / q - (c / q).round()).abs();
+            n += 1;
+        }
+        if n < MIN_PROBE_SAMPLES {
+            return None;
+        }
+        let residue = residue / n as f64;
+        let chance = RANDOM_RESIDUE - PROBE_SIGMAS * RANDOM_RESIDUE_DEVIATION / (n as f64).sqrt();
+        (residue < (MAX_PROBE_RESIDUE + PROBE_NOISE / q).min(chance)).then_some((step, residue))
+    })
+}
+
+/// Luma steps at least this large (in levels) mark an edge pixel.
+const EDGE_GRADIENT: f64 = 20.0;
+/// Luma steps below this count as a flat pixel.
+const FLAT_GRADIENT: f64 = 8.0;
+
+/// Scores blocking and ringing in an RGBA buffer on the 8x8 grid anchored at
+/// the top-left corner.
+pub(crate) fn artifact_score(data: &[u8], width: usize, height: usize) -> ArtifactScore {
+    let luma = metrics::luma(data);
+    ArtifactScore {
+        blockiness: StepProfile::new(&luma, width, height).blockiness(0, 0),
+        ringing: ringing(&luma, width, height),
+    }
+}
+
+/// Sums and counts of absolute luma steps by grid phase: entry `k` of the
+/// horizontal profile covers steps from column `x` to `x + 1` with
+/// `(x + 1) % 8 == k`, the vertical profile likewise for rows.
+struct StepProfile {
+    horizontal: [(f64, usize); 8],
+    vertical: [(f64, usize); 8],
+}
+
+impl StepProfile {
+    fn new(luma: &[f64], w: usize, h: usize) -> StepProfile {
+        let mut profile = StepProfile {
+            horizontal: [(0.0, 0); 8],
+            vertical: [(0.0, 0); 8],
+        };
+        for y in 0..h {
+            for x in 0..w {
+                let p = luma[y * w + x];
+                if x + 1 < w {
+                    let bin = &mut profile.horizontal[(x + 1) % 8];
+                    bin.0 += (luma[y * w + x + 1] - p).abs();
+                    bin.1 += 1;
+                }
+                if y + 1 < h {
+                    let bin = &mut profile.vertical[(y + 1) % 8];
+                    bin.0 += (luma[(y + 1) * w + x] - p).abs();
+                    bin.1 += 1;
+                }
+            }
+        }
+        profile
+    }
+
+    /// Ratio of the mean step across the grid lines at offset (`dx`, `dy`) to
+    /// the mean step elsewhere, minus one.
+    fn blockiness(&self, dx: usize, dy: usize) -> f64 {
+        let (mut boundary, mut boundary_n) = (0.0, 0);
+        let (mut interior, mut interior_n) = (0.0, 0);
+        for (bins, offset) in [(&self.horizontal, dx), (&self.vertical, dy)] {
+            for (k, &(sum, n)) in bins.iter().enumerate() {
+                if k == offset {
+                    boundary += sum;
+                    boundary_n += n;
+                } else {
+                    interior += sum;
+                    interior_n += n;
+                }
+            }
+        }
+        if boundary_n == 0 || interior_n == 0 {
+            return 0.0;
+        }
+        let boundary = boundary / boundary_n as f64;
+        let interior = interior / interior_n as f64;
+        ((boundary + 0.5) / (interior + 0.5) - 1.0).max(0.0)
+    }
+
+    /// Grid phase along one axis with the strongest steps, and how far they stand
+    /// out from the other phases (0 when they don't, approaching 1).
+    fn strongest_phase(bins: &[(f64, usize); 8]) -> (usize, f64) {
+        let means = bins.map(|(sum, n)| if n > 0 { sum / n as f64 } else { 0.0 });
+        let best = (0..8)
+            .max_by(|&a, &b| means[a].total_cmp(&means[b]))
+            .unwrap_or(0);
+        let others = (means.iter().sum::<f64>() - means[best]) / 7.0;
+        (
+            best,
+            ((means[best] - others) / (means[best] + 0.5)).max(0.0),
+        )
+    }
+}
+
+/// Mean absolute Laplacian of the flat pixels in blocks holding an edge,
+/// relative to that of the flat pixels in blocks without one, minus one.
+fn ringing(luma: &[f64], w: usize, h: usize) -> f64 {
+    if w < 3 || h < 3 {
+        return 0.0;
+    }
+    let at = |x: usize, y: usize| luma[y * w + x];
+    let mut gradient = vec![0.0; w * h];
+    let mut laplacian = vec![0.0; w * h];
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let gx = (at(x + 1, y) - at(x - 1, y)) / 2.0;
+            let gy = (at(x, y + 1) - at(x, y - 1)) / 2.0;
+            gradient[y * w + x] = gx.abs().max(gy.abs());
+            laplacian[y * w + x] =
+                (4.0 * at(x, y) - at(x - 1, y) - at(x + 1, y) - at(x, y - 1) - at(x, y + 1)).abs();
+        }
+    }
+
+    let (mut near, mut near_n) = (0.0, 0usize);
+    let (mut far, mut far_n) = (0.0, 0usize);
+    for by in (0..h).step_by(8) {
+        for bx in (0..w).step_by(8) {
+            let pixels = || {
+                (by.max(1)..(by + 8).min(h - 1))
+                    .flat_map(move |y| (bx.max(1)..(bx + 8).min(w - 1)).map(move |x| y * w + x))
+            };
+            let has_edge = pixels().any(|i| gradient[i] >= EDGE_GRADIENT);
+            for i in pixels().filter(|&i| gradient[i] < FLAT_GRADIENT) {
+                if has_edge {
+                    near += laplacian[i];
+                    near_n += 1;
+                } else {
+                    far += laplacian[i];
+                    far_n += 1;
+                }
+            }
+        }
+    }
+    if near_n == 0 || far_n == 0 {
+        return 0.0;
+    }
+    ((near / near_n as f64 + 2.0) / (far / far_n as f64 + 2.0) - 1.0).max(0.0)
+}
@@ -0,0 +1,447 @@
+//! Content-addressed cache of compressed images, keyed by a SHA-256 digest of
+//! the pixels and the options, with an in-memory store and an optional
+//! persistent one behind it.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use js_sys::{Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::ImageData as BrowserImageData;
+
+use crate::error::Error;
+use crate::options::{CompressOptions, PixelFormat};
+use crate::pipeline;
+use crate::web;
+
+/// SHA-256 round constants (FIPS 180-4, section 4.2.2).
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 initial hash value (FIPS 180-4, section 5.3.3).
+const SHA256_H: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// Incremental SHA-256, so the pixels are hashed without being copied next
+/// to the rest of the key.
+struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    filled: usize,
+    len: u64,
+}
+
+impl Sha256 {
+    fn new() -> Sha256 {
+        Sha256 {
+            state: SHA256_H,
+            block: [0; 64],
+            filled: 0,
+            len: 0,
+        }
+    }
+
+    fn update(&mut self, mut bytes: &[u8]) {
+        self.len += bytes.len() as u64;
+        while !bytes.is_empty() {
+            let take = (64 - self.filled).min(bytes.len());
+            self.block[self.filled..self.filled + take].copy_from_slice(&bytes[..take]);
+            self.filled += take;
+            bytes = &bytes[take..];
+            if self.filled == 64 {
+                self.compress();
+                self.filled = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> [u8; 32] {
+        let bits = self.len * 8;
+        self.update(&[0x80]);
+        while self.filled != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bits.to_be_bytes());
+        let mut digest = [0; 32];
+        for (out, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            out.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    /// Runs the compression function on the full block.
+    fn compress(&mut self) {
+        let mut w = [0u32; 64];
+        for (i, word) in self.block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            (h, g, f, e) = (g, f, e, d.wrapping_add(t1));
+            (d, c, b, a) = (c, b, a, t1.wrapping_add(t2));
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+/// Key of the result of compressing `data` with `options`: the hex SHA-256
+/// of the crate version, the dimensions, the color space, every option and
+/// the pixels. The version makes results of an older pipeline miss.
+fn cache_key(
+    data: &[u8],
+    (width, height): (u32, u32),
+    color_space: Option<&str>,
+    options: &CompressOptions,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.update(&width.to_le_bytes());
+    hasher.update(&height.to_le_bytes());
+    hasher.update(color_space.unwrap_or("srgb").as_bytes());
+    hasher.update(format!("{options:?}").as_bytes());
+    hasher.update(data);
+    hasher.finish().iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// A compressed image as the cache holds it.
+struct CachedImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl CachedImage {
+    /// Compresses RGBA `data` like `compress_jpeg_with_options`.
+    fn compress(
+        data: &[u8],
+        size: (usize, usize),
+        options: &CompressOptions,
+    ) -> Result<CachedImage, Error> {
+        let (data, width, height) = pipeline::transform_input(data, size, 4, options)?;
+        let pixels = pipeline::compress(&data, width, height, PixelFormat::Rgba, options)?;
+        Ok(CachedImage {
+            width: width as u32,
+            height: height as u32,
+            pixels,
+        })
+    }
+
+    /// The form a persistent store keeps: width and height as little-endian
+    /// `u32`, then the RGBA pixels.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + self.pixels.len());
+        bytes.extend_from_slice(&self.width.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        bytes.extend_from_slice(&self.pixels);
+        bytes
+    }
+
+    /// Reads [`CachedImage::to_bytes`], or `None` when the bytes don't fit
+    /// their dimensions.
+    fn from_bytes(bytes: &[u8]) -> Option<CachedImage> {
+        let (width, height) = (bytes.get(..4)?, bytes.get(4..8)?);
+        let width = u32::from_le_bytes(width.try_into().ok()?);
+        let height = u32::from_le_bytes(height.try_into().ok()?);
+        // Sizes no buffer could hold are a miss, like any other bad entry.
+        let len = (width as usize).checked_mul(height as usize)?.checked_mul(4)?;
+        let pixels = &bytes[8..];
+        (pixels.len() == len).then(|| CachedImage {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        })
+    }
+
+    fn to_image_data(&self, color_space: Option<&str>) -> Result<BrowserImageData, JsValue> {
+        web::new_image_data(&self.pixels, self.width, self.height, color_space)
+    }
+}
+
+/// The in-memory store: the most recently used results, newest last.
+struct MemoryStore {
+    capacity: usize,
+    entries: VecDeque<(String, Rc<CachedImage>)>,
+    hits: u32,
+    misses: u32,
+}
+
+impl MemoryStore {
+    /// The result under `key`, which becomes the most recently used.
+    fn get(&mut self, key: &str) -> Option<Rc<CachedImage>> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(index)?;
+        let image = Rc::clone(&entry.1);
+        self.entries.push_back(entry);
+        Some(image)
+    }
+
+    /// Stores `image` under `key`, evicting the least recently used result
+    /// when full.
+    fn insert(&mut self, key: String, image: Rc<CachedImage>) {
+        self.entries.retain(|(k, _)| *k != key);
+        self.entries.push_back((key, image));
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+}
+
+/// Remembers compressed images so that compressing the same pixels with the
+/// same options again costs a hash instead of the pipeline, e.g. when
+/// settings are toggled back and forth in an editor.
+///
+/// Results are keyed by a SHA-256 digest of the pixels, the dimensions, the
+/// color space and every option. The last `capacity` results stay in memory;
+/// `set_store` adds a persistent store behind them for `compress_async`.
+#[wasm_bindgen]
+pub struct CompressionCache {
+    memory: Rc<RefCell<MemoryStore>>,
+    store: Option<JsValue>,
+}
+
+#[wasm_bindgen]
+impl CompressionCache {
+    /// A cache that keeps the last `capacity` results in memory.
+    #[wasm_bindgen(constructor)]
+    pub fn new(capacity: u32) -> CompressionCache {
+        CompressionCache {
+            memory: Rc::new(RefCell::new(MemoryStore {
+                capacity: capacity as usize,
+                entries: VecDeque::new(),
+                hits: 0,
+                misses: 0,
+            })),
+            store: None,
+        }
+    }
+
+    /// Puts a persistent store behind the in-memory one, for
+    /// `compress_async`: an `IndexedDbStore`, or any object with
+    /// `get(key)` resolving to a `Uint8Array` or `undefined` and
+    /// `set(key, bytes)`.
+    pub fn set_store(&mut self, store: JsValue) {
+        self.store = Some(store);
+    }
+
+    /// Compress an ImageData like `compress_jpeg_with_options`, or return the
+    /// remembered result. Only the in-memory store is consulted.
+    pub fn compress(
+        &self,
+        image_data: BrowserImageData,
+        options: &CompressOptions,
+    ) -> Result<BrowserImageData, JsValue> {
+        let size = (image_data.width(), image_data.height());
+        let data = image_data.data();
+        let color_space = web::color_space(&image_data);
+        let key = cache_key(&data, size, color_space.as_deref(), options);
+        let mut memory = self.memory.borrow_mut();
+        if let Some(image) = memory.get(&key) {
+            memory.hits += 1;
+            return image.to_image_data(color_space.as_deref());
+        }
+        memory.misses += 1;
+        let image = CachedImage::compress(&data, (size.0 as usize, size.1 as usize), options)?;
+        let output = image.to_image_data(color_space.as_deref());
+        memory.insert(key, Rc::new(image));
+        output
+    }
+
+    /// Like `compress`, also looking in the persistent store on a miss in
+    /// memory and saving new results to it. Saving isn't waited for.
+    ///
+    /// **Returns:**
+    /// A `Promise` of the compressed `ImageData`.
+    pub fn compress_async(
+        &self,
+        image_data: BrowserImageData,
+        options: &CompressOptions,
+    ) -> Result<Promise, JsValue> {
+        let Some(store) = self.store.clone() else {
+            let output = self.compress(image_data, options)?;
+            return Ok(Promise::resolve(&JsValue::from(output)));
+        };
+        let size = (image_data.width(), image_data.height());
+        let data = image_data.data();
+        let color_space = web::color_space(&image_data);
+        let key = cache_key(&data, size, color_space.as_deref(), options);
+        let remembered = self.memory.borrow_mut().get(&key);
+        if let Some(image) = remembered {
+            self.memory.borrow_mut().hits += 1;
+            let output = image.to_image_data(color_space.as_deref())?;
+            return Ok(Promise::resolve(&JsValue::from(output)));
+        }
+
+        let stored = web::call(&store, "get", &[&key.as_str().into()])?;
+        let memory = Rc::clone(&self.memory);
+        let options = options.clone();
+        let output = web::then(&Promise::resolve(&stored), move |stored| {
+            let stored = stored.dyn_ref::<Uint8Array>().map(|bytes| bytes.to_vec());
+            let mut memory = memory.borrow_mut();
+            let image = match stored.as_deref().and_then(CachedImage::from_bytes) {
+                Some(image) => {
+                    memory.hits += 1;
+                    image
+                }
+                None => {
+                    memory.misses += 1;
+                    let size = (size.0 as usize, size.1 as usize);
+                    let image = CachedImage::compress(&data, size, &options)?;
+                    let bytes = Uint8Array::from(&image.to_bytes()[..]);
+                    web::call(&store, "set", &[&key.as_str().into(), &bytes])?;
+                    image
+                }
+            };
+            let output = image.to_image_data(color_space.as_deref())?;
+            memory.insert(key, Rc::new(image));
+            Ok(output.into())
+        })?;
+        Ok(output.unchecked_into())
+    }
+
+    /// Forgets every result held in memory; the persistent store is kept.
+    pub fn clear(&self) {
+        self.memory.borrow_mut().entries.clear();
+    }
+
+    /// Results found in memory or in the persistent store.
+    #[wasm_bindgen(getter)]
+    pub fn hits(&self) -> u32 {
+        self.memory.borrow().hits
+    }
+
+    /// Results that had to be compressed.
+    #[wasm_bindgen(getter)]
+    pub fn misses(&self) -> u32 {
+        self.memory.borrow().misses
+    }
+}
+
+/// Name of the object store inside an `IndexedDbStore` database.
+const OBJECT_STORE: &str = "results";
+
+/// A persistent store for `CompressionCache` in an IndexedDB database.
+#[wasm_bindgen]
+pub struct IndexedDbStore {
+    db: JsValue,
+}
+
+impl IndexedDbStore {
+    /// A request on the object store, made in a new transaction in `mode`.
+    fn request(&self, mode: &str, method: &str, args: &[&JsValue]) -> Result<Promise, JsValue> {
+        let store = OBJECT_STORE.into();
+        let transaction = web::call(&self.db, "transaction", &[&store, &mode.into()])?;
+        let objects = web::call(&transaction, "objectStore", &[&store])?;
+        Ok(web::request(&web::call(&objects, method, args)?))
+    }
+}
+
+#[wasm_bindgen]
+impl IndexedDbStore {
+    /// Opens the database `name`, creating it on first use.
+    ///
+    /// **Returns:**
+    /// A `Promise` of the `IndexedDbStore`.
+    pub fn open(name: &str) -> Result<Promise, JsValue> {
+        let factory = Reflect::get(&js_sys::global(), &"indexedDB".into())?;
+        let request = web::call(&factory, "open", &[&name.into(), &1.into()])?;
+        let target = request.clone();
+        let upgrade = Closure::once_into_js(move || {
+            let db = Reflect::get(&target, &"result".into())?;
+            web::call(&db, "createObjectStore", &[&OBJECT_STORE.into()])
+        });
+        Reflect::set(&request, &"onupgradeneeded".into(), &upgrade)?;
+        let opened = web::then(&web::request(&request), |db| Ok(IndexedDbStore { db }.into()))?;
+        Ok(opened.unchecked_into())
+    }
+
+    /// A `Promise` of the bytes stored under `key`, or of `undefined`.
+    pub fn get(&self, key: &str) -> Result<Promise, JsValue> {
+        self.request("readonly", "get", &[&key.into()])
+    }
+
+    /// Stores `value` under `key`, resolving once it is written.
+    pub fn set(&self, key: &str, value: Uint8Array) -> Result<Promise, JsValue> {
+        self.request("readwrite", "put", &[&value, &key.into()])
+    }
+
+    /// Deletes every stored result, resolving once done.
+    pub fn clear(&self) -> Result<Promise, JsValue> {
+        self.request("readwrite", "clear", &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sha256(chunks: &[&[u8]]) -> String {
+        let mut hasher = Sha256::new();
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        hasher.finish().iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn sha256_matches_fips_180_vectors() {
+        assert_eq!(
+            sha256(&[]),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256(&[b"abc"]),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let two_blocks = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        assert_eq!(
+            sha256(&[two_blocks]),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        // Split across calls, and across a block boundary.
+        assert_eq!(sha256(&[&two_blocks[..3], &two_blocks[3..]]), sha256(&[two_blocks]));
+        assert_eq!(
+            sha256(&[&[b'a'; 1_000_000]]),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    #[test]
+    fn cached_images_must_hold_their_pixels() {
+        let entry = |width: u32, height: u32, len: usize| {
+            let mut bytes = [width.to_le_bytes(), height.to_le_bytes()].concat();
+            bytes.resize(8 + len, 0);
+            CachedImage::from_bytes(&bytes).map(|image| image.pixels.len())
+        };
+        assert_eq!(entry(3, 2, 24), Some(24));
+        assert_eq!(entry(3, 2, 20), None);
+        assert_eq!(entry(u32::MAX, u32::MAX, 16), None);
+    }
+}
@@ -0,0 +1,180 @@
+//! The `compress-jpeg` command of the `cli` feature, which runs the pipeline
+//! on image files.
+
+use std::error::Error as StdError;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use image::{ImageFormat, RgbaImage};
+
+use crate::metrics::{self, SsimWindow};
+use crate::options::{
+    ChromaDownsampling, ChromaSubsampling, CompressOptions, CompressionScale, PixelFormat,
+};
+use crate::pipeline;
+
+const USAGE: &str = "\
+Usage: compress-jpeg [OPTIONS] <INPUT> <OUTPUT>
+
+Reads a PNG, JPEG or WebP file and compresses it. A .jpg or .jpeg OUTPUT is a
+baseline JPEG file; a .png or .webp OUTPUT holds the compressed pixels
+losslessly, as compress_jpeg returns them in the browser.
+
+Options:
+  --quality <0-100>                 Quality of canvas.toBlob, times 100
+  --compression <0.0-1.0>           Compression on the linear scale [default: 0.5]
+  --subsampling <444|422|420>       Chroma subsampling [default: 420]
+  --chroma-downsampling <nearest|box|triangle>
+                                    Chroma downsampling filter [default: box]
+  --deblock                         Smooth block edges after decoding
+  --dering                          Suppress ringing after decoding
+  --metrics                         Print PSNR, SSIM and MS-SSIM of the output
+  -h, --help                        Print this help
+  -V, --version                     Print the version";
+
+/// What the command line asks for.
+struct Args {
+    input: PathBuf,
+    output: PathBuf,
+    options: CompressOptions,
+    metrics: bool,
+}
+
+/// Reads the arguments after the program name. `Ok(None)` means help or the
+/// version was printed.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut args = args.into_iter();
+    let mut options = CompressOptions::default();
+    let mut metrics = false;
+    let mut paths = Vec::new();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+            _ => (arg.clone(), None),
+        };
+        let inline = inline.map(str::to_string);
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{flag} needs a value"))
+        };
+        match flag.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(None);
+            }
+            "-V" | "--version" => {
+                println!("compress-jpeg {}", env!("CARGO_PKG_VERSION"));
+                return Ok(None);
+            }
+            "--quality" => {
+                let quality: f32 = number(&flag, &value()?)?;
+                if !(0.0..=100.0).contains(&quality) {
+                    return Err("--quality must be between 0 and 100".into());
+                }
+                options.compression_scale = CompressionScale::Browser;
+                options.compression = 1.0 - quality / 100.0;
+            }
+            "--compression" => {
+                options.compression_scale = CompressionScale::Linear;
+                options.compression = number(&flag, &value()?)?;
+            }
+            "--subsampling" => {
+                options.subsampling = match value()?.as_str() {
+                    "444" => ChromaSubsampling::Yuv444,
+                    "422" => ChromaSubsampling::Yuv422,
+                    "420" => ChromaSubsampling::Yuv420,
+                    other => return Err(format!("unknown subsampling '{other}'")),
+                };
+            }
+            "--chroma-downsampling" => {
+                options.chroma_downsampling = match value()?.as_str() {
+                    "nearest" => ChromaDownsampling::Nearest,
+                    "box" => ChromaDownsampling::Box,
+                    "triangle" => ChromaDownsampling::Triangle,
+                    other => return Err(format!("unknown chroma downsampling '{other}'")),
+                };
+            }
+            "--deblock" => options.deblock = true,
+            "--dering" => options.dering = true,
+            "--metrics" => metrics = true,
+            _ if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option '{flag}'"));
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let [input, output] = <[PathBuf; 2]>::try_from(paths)
+        .map_err(|_| "expected an INPUT and an OUTPUT file".to_string())?;
+    let format = ImageFormat::from_path(&output).ok();
+    if !matches!(format, Some(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP)) {
+        return Err("OUTPUT must be a .jpg, .jpeg, .png or .webp file".into());
+    }
+    Ok(Some(Args {
+        input,
+        output,
+        options,
+        metrics,
+    }))
+}
+
+fn number(flag: &str, value: &str) -> Result<f32, String> {
+    value.parse().map_err(|_| format!("{flag} takes a number, not '{value}'"))
+}
+
+/// Whether `path` names a JPEG file.
+fn is_jpeg(path: &Path) -> bool {
+    ImageFormat::from_path(path).is_ok_and(|format| format == ImageFormat::Jpeg)
+}
+
+/// Prefixes the errors of reading or writing `path` with it.
+fn in_file<T, E: StdError>(path: &Path, result: Result<T, E>) -> Result<T, String> {
+    result.map_err(|err| format!("{}: {err}", path.display()))
+}
+
+fn run(args: Args) -> Result<(), Box<dyn StdError>> {
+    let input = in_file(&args.input, image::open(&args.input))?.into_rgba8();
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    let rgba = PixelFormat::Rgba;
+    let options = &args.options;
+
+    let output = if is_jpeg(&args.output) {
+        let jpeg = pipeline::encode_jpeg(&input, width, height, rgba, options)?;
+        in_file(&args.output, std::fs::write(&args.output, &jpeg))?;
+        pipeline::decode_jpeg(&jpeg)?.to_pixels(rgba)
+    } else {
+        let pixels = pipeline::compress(&input, width, height, rgba, options)?;
+        let image = RgbaImage::from_raw(width as u32, height as u32, pixels)
+            .ok_or("compressed pixels don't fill the image")?;
+        in_file(&args.output, image.save(&args.output))?;
+        image.into_raw()
+    };
+
+    if args.metrics {
+        let psnr = metrics::psnr(&input, &output).combined;
+        let ssim = metrics::ssim(&input, &output, width, height, SsimWindow::Gaussian);
+        let ms_ssim = metrics::ms_ssim(&input, &output, width, height);
+        println!("PSNR {psnr:.2} dB, SSIM {ssim:.4}, MS-SSIM {ms_ssim:.4}");
+    }
+    Ok(())
+}
+
+/// Runs the command with the process arguments.
+pub fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => return ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("compress-jpeg: {message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("compress-jpeg: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
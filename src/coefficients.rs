@@ -0,0 +1,240 @@
+//! Binary dump of the quantized coefficients a baseline JPEG encode writes.
+//!
+//! All values are little-endian, and every 16-bit field starts at an even
+//! offset, so the levels can be read in place as an `Int16Array`:
+//!
+//! - the magic bytes `JDCT`, the format version (1) and the plane count (1
+//!   for grayscale, 3 for YCbCr), one byte each after the magic;
+//! - the image width and height as `u16`;
+//! - the horizontal and vertical chroma subsampling factors as `u8` (1 and 1
+//!   for grayscale);
+//! - the quantization table as 64 `u16` steps in zigzag order;
+//! - per plane (Y, then Cb and Cr): its width and height in blocks as `u16`,
+//!   then 64 `i16` levels per block in zigzag order, blocks in raster order.
+//!
+//! Levels are the coefficients divided by their step, with the DC of samples
+//! shifted to -128–127 as in a JPEG file.
+
+use crate::dct::{self, ZIGZAG};
+use crate::error::Error;
+use crate::pipeline::{ChromaPlanes, Planes};
+use crate::sampling::subsampled_size;
+
+const MAGIC: &[u8; 4] = b"JDCT";
+const VERSION: u8 = 1;
+
+/// The quantized levels of one plane.
+#[derive(Clone, Debug)]
+pub(crate) struct PlaneLevels {
+    pub(crate) blocks_x: usize,
+    pub(crate) blocks_y: usize,
+    pub(crate) levels: Vec<[[i32; 8]; 8]>,
+}
+
+/// Everything the dump holds.
+#[derive(Clone, Debug)]
+pub(crate) struct CoefficientDump {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    /// Chroma subsampling factors.
+    pub(crate) fx: usize,
+    pub(crate) fy: usize,
+    pub(crate) table: [[u32; 8]; 8],
+    pub(crate) planes: Vec<PlaneLevels>,
+}
+
+impl CoefficientDump {
+    /// Dump of `levels`, one set per plane in the order of a JPEG file, coded
+    /// from `planes` with `table`.
+    pub(crate) fn new(
+        planes: &Planes,
+        levels: Vec<Vec<[[i32; 8]; 8]>>,
+        table: [[u32; 8]; 8],
+    ) -> CoefficientDump {
+        let (w, h) = (planes.width, planes.height);
+        let chroma = planes.chroma.as_ref();
+        let (fx, fy) = chroma.map_or((1, 1), |c| (c.fx, c.fy));
+        let (cw, ch) = chroma.map_or((w, h), |c| (c.width, c.height));
+        let planes = levels
+            .into_iter()
+            .enumerate()
+            .map(|(i, levels)| {
+                let (pw, ph) = if i == 0 { (w, h) } else { (cw, ch) };
+                PlaneLevels {
+                    blocks_x: pw.div_ceil(8),
+                    blocks_y: ph.div_ceil(8),
+                    levels,
+                }
+            })
+            .collect();
+        CoefficientDump {
+            width: w,
+            height: h,
+            fx,
+            fy,
+            table,
+            planes,
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let u16_bytes = |value: usize| (value as u16).to_le_bytes();
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&[VERSION, self.planes.len() as u8]);
+        out.extend_from_slice(&u16_bytes(self.width));
+        out.extend_from_slice(&u16_bytes(self.height));
+        out.extend_from_slice(&[self.fx as u8, self.fy as u8]);
+        for &(u, v) in &ZIGZAG {
+            out.extend_from_slice(&u16_bytes(self.table[u][v] as usize));
+        }
+        for plane in &self.planes {
+            out.extend_from_slice(&u16_bytes(plane.blocks_x));
+            out.extend_from_slice(&u16_bytes(plane.blocks_y));
+            for block in &plane.levels {
+                for &(u, v) in &ZIGZAG {
+                    out.extend_from_slice(&(block[u][v] as i16).to_le_bytes());
+                }
+            }
+        }
+        out
+    }
+
+    /// Reads a dump written by [`CoefficientDump::to_bytes`], possibly edited
+    /// since. Only the levels may change: the layout must still fit the
+    /// dimensions in the header.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<CoefficientDump, Error> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(4)? != MAGIC || reader.u8()? != VERSION as usize {
+            return Err(Error::InvalidCoefficients);
+        }
+        let count = reader.u8()?;
+        let (width, height) = (reader.u16()?, reader.u16()?);
+        let (fx, fy) = (reader.u8()?, reader.u8()?);
+        let grayscale = count == 1 && (fx, fy) == (1, 1);
+        let color = count == 3 && (1..=2).contains(&fx) && (1..=2).contains(&fy);
+        if width == 0 || height == 0 || !(grayscale || color) {
+            return Err(Error::InvalidCoefficients);
+        }
+        let mut table = [[0; 8]; 8];
+        for &(u, v) in &ZIGZAG {
+            table[u][v] = reader.u16()? as u32;
+        }
+        let (cw, ch) = subsampled_size(width, height, fx, fy);
+        let planes = (0..count)
+            .map(|i| {
+                let (pw, ph) = if i == 0 { (width, height) } else { (cw, ch) };
+                let (blocks_x, blocks_y) = (reader.u16()?, reader.u16()?);
+                if (blocks_x, blocks_y) != (pw.div_ceil(8), ph.div_ceil(8)) {
+                    return Err(Error::InvalidCoefficients);
+                }
+                let levels = (0..blocks_x * blocks_y)
+                    .map(|_| {
+                        let mut block = [[0; 8]; 8];
+                        for &(u, v) in &ZIGZAG {
+                            block[u][v] = reader.i16()? as i32;
+                        }
+                        Ok(block)
+                    })
+                    .collect::<Result<_, Error>>()?;
+                Ok(PlaneLevels {
+                    blocks_x,
+                    blocks_y,
+                    levels,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        if reader.pos != bytes.len() {
+            return Err(Error::InvalidCoefficients);
+        }
+        Ok(CoefficientDump {
+            width,
+            height,
+            fx,
+            fy,
+            table,
+            planes,
+        })
+    }
+
+    /// Dequantizes the levels and transforms them back into YCbCr planes.
+    pub(crate) fn to_planes(&self) -> Planes {
+        let plane = |levels: &PlaneLevels, w: usize, h: usize| {
+            dequantize(&levels.levels, &self.table, w, h)
+        };
+        let (w, h) = (self.width, self.height);
+        let chroma = match &self.planes[..] {
+            [_, cb, cr] => {
+                let (cw, ch) = subsampled_size(w, h, self.fx, self.fy);
+                Some(ChromaPlanes {
+                    cb: plane(cb, cw, ch),
+                    cr: plane(cr, cw, ch),
+                    width: cw,
+                    height: ch,
+                    fx: self.fx,
+                    fy: self.fy,
+                })
+            }
+            _ => None,
+        };
+        Planes {
+            width: w,
+            height: h,
+            y: plane(&self.planes[0], w, h),
+            chroma,
+            block_stats: Default::default(),
+        }
+    }
+}
+
+/// Multiplies the levels of a `w`x`h` plane's blocks by their steps in
+/// `table` and transforms them back into samples.
+pub(crate) fn dequantize(
+    levels: &[[[i32; 8]; 8]],
+    table: &[[u32; 8]; 8],
+    w: usize,
+    h: usize,
+) -> Vec<f32> {
+    let blocks: Vec<[[f32; 8]; 8]> = levels
+        .iter()
+        .map(|block| {
+            let mut coeffs = [[0.0; 8]; 8];
+            for u in 0..8 {
+                for v in 0..8 {
+                    coeffs[u][v] = block[u][v] as f32 * table[u][v] as f32;
+                }
+            }
+            // Undo the shift of the samples to -128–127.
+            coeffs[0][0] += 1024.0;
+            coeffs
+        })
+        .collect();
+    dct::inverse_blocks(&blocks, w, h)
+}
+
+/// Reads the little-endian fields of a dump, failing at its end.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let bytes = self.bytes.get(self.pos..self.pos + len);
+        self.pos += len;
+        bytes.ok_or(Error::InvalidCoefficients)
+    }
+
+    fn u8(&mut self) -> Result<usize, Error> {
+        Ok(self.take(1)?[0] as usize)
+    }
+
+    fn u16(&mut self) -> Result<usize, Error> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+    }
+
+    fn i16(&mut self) -> Result<i16, Error> {
+        let bytes = self.take(2)?;
+        Ok(i16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
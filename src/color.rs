@@ -0,0 +1,150 @@
+use crate::options::{ColorMatrix, ColorRange, ColorTransform, CompressOptions};
+
+/// Luma excursion of studio-swing video (16–235).
+const LUMA_SPAN: f32 = 219.0;
+/// Chroma excursion of studio-swing video (16–240).
+const CHROMA_SPAN: f32 = 224.0;
+
+/// Converts between RGB and the three planes the codec operates on.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ColorConverter {
+    transform: ColorTransform,
+    ycbcr: YCbCr,
+}
+
+impl ColorConverter {
+    pub(crate) fn new(options: &CompressOptions) -> Self {
+        ColorConverter {
+            transform: options.color_transform,
+            ycbcr: YCbCr::new(options.color_matrix, options.color_range),
+        }
+    }
+
+    /// Converts 0–255 RGB to a luma plane value and two chroma values centered on 128.
+    pub(crate) fn forward(&self, r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+        match self.transform {
+            ColorTransform::YCbCr => self.ycbcr.forward(r, g, b),
+            ColorTransform::YCoCg => ycocg_forward(r, g, b),
+            ColorTransform::YCoCgR => ycocg_r_forward(r, g, b),
+            ColorTransform::Rct => rct_forward(r, g, b),
+        }
+    }
+
+    /// Inverse of [`ColorConverter::forward`]; the RGB result is not clamped.
+    pub(crate) fn inverse(&self, y: f32, c1: f32, c2: f32) -> (f32, f32, f32) {
+        match self.transform {
+            ColorTransform::YCbCr => self.ycbcr.inverse(y, c1, c2),
+            ColorTransform::YCoCg => ycocg_inverse(y, c1, c2),
+            ColorTransform::YCoCgR => ycocg_r_inverse(y, c1, c2),
+            ColorTransform::Rct => rct_inverse(y, c1, c2),
+        }
+    }
+}
+
+fn ycocg_forward(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let y = 0.25 * r + 0.5 * g + 0.25 * b;
+    let co = 0.5 * r - 0.5 * b;
+    let cg = -0.25 * r + 0.5 * g - 0.25 * b;
+    (y, co + 128.0, cg + 128.0)
+}
+
+fn ycocg_inverse(y: f32, co: f32, cg: f32) -> (f32, f32, f32) {
+    let co = co - 128.0;
+    let cg = cg - 128.0;
+    let t = y - cg;
+    (t + co, y + cg, t - co)
+}
+
+/// Lossless lifting form of YCoCg. Operates on integers, so the chroma
+/// planes carry one extra bit of range (-255..=255 before centering).
+fn ycocg_r_forward(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let (r, g, b) = (r.round() as i32, g.round() as i32, b.round() as i32);
+    let co = r - b;
+    let t = b + (co >> 1);
+    let cg = g - t;
+    let y = t + (cg >> 1);
+    (y as f32, co as f32 + 128.0, cg as f32 + 128.0)
+}
+
+fn ycocg_r_inverse(y: f32, co: f32, cg: f32) -> (f32, f32, f32) {
+    let y = y.round() as i32;
+    let co = (co - 128.0).round() as i32;
+    let cg = (cg - 128.0).round() as i32;
+    let t = y - (cg >> 1);
+    let g = cg + t;
+    let b = t - (co >> 1);
+    let r = b + co;
+    (r as f32, g as f32, b as f32)
+}
+
+/// JPEG 2000 reversible color transform. Integer-exact like YCoCg-R, with
+/// chroma as plain B-G / R-G differences.
+fn rct_forward(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let (r, g, b) = (r.round() as i32, g.round() as i32, b.round() as i32);
+    let y = (r + 2 * g + b) >> 2;
+    (y as f32, (b - g) as f32 + 128.0, (r - g) as f32 + 128.0)
+}
+
+fn rct_inverse(y: f32, cb: f32, cr: f32) -> (f32, f32, f32) {
+    let y = y.round() as i32;
+    let cb = (cb - 128.0).round() as i32;
+    let cr = (cr - 128.0).round() as i32;
+    let g = y - ((cb + cr) >> 2);
+    ((cr + g) as f32, g as f32, (cb + g) as f32)
+}
+
+/// RGB ⇄ YCbCr conversion for a given set of luma coefficients and range.
+#[derive(Clone, Copy, Debug)]
+struct YCbCr {
+    kr: f32,
+    kg: f32,
+    kb: f32,
+    range: ColorRange,
+}
+
+impl YCbCr {
+    fn new(matrix: ColorMatrix, range: ColorRange) -> Self {
+        let (kr, kb) = match matrix {
+            ColorMatrix::Bt601 => (0.299, 0.114),
+            ColorMatrix::Bt709 => (0.2126, 0.0722),
+            ColorMatrix::Bt2020 => (0.2627, 0.0593),
+        };
+        YCbCr { kr, kg: 1.0 - kr - kb, kb, range }
+    }
+
+    /// Converts 0–255 RGB to YCbCr with chroma centered on 128.
+    ///
+    /// Limited range maps luma to 16–235 and chroma to 16–240.
+    fn forward(&self, r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+        let y = self.kr * r + self.kg * g + self.kb * b;
+        let cb = (b - y) / (2.0 * (1.0 - self.kb));
+        let cr = (r - y) / (2.0 * (1.0 - self.kr));
+        match self.range {
+            ColorRange::Full => (y, cb + 128.0, cr + 128.0),
+            ColorRange::Limited => (
+                16.0 + y * LUMA_SPAN / 255.0,
+                128.0 + cb * CHROMA_SPAN / 255.0,
+                128.0 + cr * CHROMA_SPAN / 255.0,
+            ),
+        }
+    }
+
+    /// Inverse of [`YCbCr::forward`]; the RGB result is not clamped.
+    ///
+    /// Limited-range input is clamped to its nominal range first, so
+    /// quantization overshoot into the foot/headroom doesn't leak into RGB.
+    fn inverse(&self, y: f32, cb: f32, cr: f32) -> (f32, f32, f32) {
+        let (y, cb, cr) = match self.range {
+            ColorRange::Full => (y, cb - 128.0, cr - 128.0),
+            ColorRange::Limited => (
+                (y.clamp(16.0, 235.0) - 16.0) * 255.0 / LUMA_SPAN,
+                (cb.clamp(16.0, 240.0) - 128.0) * 255.0 / CHROMA_SPAN,
+                (cr.clamp(16.0, 240.0) - 128.0) * 255.0 / CHROMA_SPAN,
+            ),
+        };
+        let r = y + 2.0 * (1.0 - self.kr) * cr;
+        let b = y + 2.0 * (1.0 - self.kb) * cb;
+        let g = (y - self.kr * r - self.kb * b) / self.kg;
+        (r, g, b)
+    }
+}
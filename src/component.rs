@@ -0,0 +1,108 @@
+//! The `codec` interface of `wit/compress-jpeg.wit` for component-model hosts
+//! (Wasmtime, Spin, jco). Built with the `component` feature for
+//! `wasm32-wasip2`, where the wasm-bindgen exports compile away.
+
+wit_bindgen::generate!({ path: "wit" });
+
+use exports::ganemedelabs::compress_jpeg::codec::{
+    self, ChromaDownsampling as WitDownsampling, ChromaSubsampling as WitSubsampling, Guest,
+    Image, Options,
+};
+
+use crate::error::Error;
+use crate::metrics::{self, SsimWindow};
+use crate::options::{ChromaDownsampling, ChromaSubsampling, CompressOptions, PixelFormat};
+use crate::pipeline;
+
+struct Codec;
+
+export!(Codec);
+
+impl From<Error> for codec::Error {
+    fn from(err: Error) -> codec::Error {
+        match err {
+            Error::InvalidDimensions => codec::Error::InvalidDimensions,
+            Error::BufferMismatch => codec::Error::BufferMismatch,
+            Error::DimensionMismatch => codec::Error::DimensionMismatch,
+            Error::PayloadTooLarge => codec::Error::PayloadTooLarge,
+            Error::InvalidJpeg => codec::Error::InvalidJpeg,
+            Error::NoPayload => codec::Error::NoPayload,
+            Error::InvalidCoefficients => codec::Error::InvalidCoefficients,
+            Error::InvalidThumbHash => codec::Error::InvalidThumbhash,
+            Error::FetchFailed => codec::Error::FetchFailed,
+        }
+    }
+}
+
+impl From<Options> for CompressOptions {
+    fn from(options: Options) -> CompressOptions {
+        CompressOptions {
+            compression: options.compression,
+            subsampling: match options.subsampling {
+                WitSubsampling::Yuv444 => ChromaSubsampling::Yuv444,
+                WitSubsampling::Yuv422 => ChromaSubsampling::Yuv422,
+                WitSubsampling::Yuv420 => ChromaSubsampling::Yuv420,
+            },
+            chroma_downsampling: match options.chroma_downsampling {
+                WitDownsampling::Nearest => ChromaDownsampling::Nearest,
+                WitDownsampling::Box => ChromaDownsampling::Box,
+                WitDownsampling::Triangle => ChromaDownsampling::Triangle,
+            },
+            deblock: options.deblock,
+            dering: options.dering,
+            ..CompressOptions::default()
+        }
+    }
+}
+
+/// The dimensions of two images, which must be the same.
+fn pair_size(original: &Image, compressed: &Image) -> Result<(usize, usize), Error> {
+    let (width, height) = (original.width as usize, original.height as usize);
+    if (original.width, original.height) != (compressed.width, compressed.height) {
+        return Err(Error::DimensionMismatch);
+    }
+    pipeline::check_dimensions(width, height, original.pixels.len(), 4)?;
+    pipeline::check_dimensions(width, height, compressed.pixels.len(), 4)?;
+    Ok((width, height))
+}
+
+impl Guest for Codec {
+    fn compress(image: Image, options: Options) -> Result<Image, codec::Error> {
+        let (width, height) = (image.width as usize, image.height as usize);
+        let options = CompressOptions::from(options);
+        let pixels = pipeline::compress(&image.pixels, width, height, PixelFormat::Rgba, &options)?;
+        Ok(Image { pixels, ..image })
+    }
+
+    fn encode_jpeg(image: Image, options: Options) -> Result<Vec<u8>, codec::Error> {
+        let (width, height) = (image.width as usize, image.height as usize);
+        let options = CompressOptions::from(options);
+        let rgba = PixelFormat::Rgba;
+        Ok(pipeline::encode_jpeg(&image.pixels, width, height, rgba, &options)?)
+    }
+
+    fn decode_jpeg(jpeg: Vec<u8>) -> Result<Image, codec::Error> {
+        let frame = pipeline::decode_jpeg(&jpeg)?;
+        Ok(Image {
+            width: frame.width as u32,
+            height: frame.height as u32,
+            pixels: frame.to_pixels(PixelFormat::Rgba),
+        })
+    }
+
+    fn psnr(original: Image, compressed: Image) -> Result<f64, codec::Error> {
+        pair_size(&original, &compressed)?;
+        Ok(metrics::psnr(&original.pixels, &compressed.pixels).combined)
+    }
+
+    fn ssim(original: Image, compressed: Image) -> Result<f64, codec::Error> {
+        let (width, height) = pair_size(&original, &compressed)?;
+        let (original, compressed) = (&original.pixels, &compressed.pixels);
+        Ok(metrics::ssim(original, compressed, width, height, SsimWindow::Gaussian))
+    }
+
+    fn ms_ssim(original: Image, compressed: Image) -> Result<f64, codec::Error> {
+        let (width, height) = pair_size(&original, &compressed)?;
+        Ok(metrics::ms_ssim(&original.pixels, &compressed.pixels, width, height))
+    }
+}
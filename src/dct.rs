@@ -0,0 +1,432 @@
+use std::f32::consts::PI;
+
+use crate::quant::Quantizer;
+
+/// Quantization results of a single 8x8 block.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct BlockStats {
+    /// Mean squared error of the reconstructed block against the input.
+    pub(crate) error: f32,
+    /// Number of coefficients that survived quantization.
+    pub(crate) nonzero: u32,
+    /// Rough size of the block once entropy coded, in bits.
+    pub(crate) bits: f32,
+}
+
+/// Runs the block pass at the quantizer's block size, lapped if it asks for
+/// it.
+pub(crate) fn process_blocks(input: &[f32], w: usize, h: usize, q: &Quantizer) -> Vec<f32> {
+    let aligned = aligned_blocks(input, w, h, q);
+    if q.overlap {
+        lapped_blocks(aligned, input, w, h, q)
+    } else {
+        aligned
+    }
+}
+
+/// One block pass on the grid starting at the top-left corner.
+fn aligned_blocks(input: &[f32], w: usize, h: usize, q: &Quantizer) -> Vec<f32> {
+    match q.block_size {
+        8 => process_blocks_with_stats(input, w, h, q).0,
+        n => process_sized_blocks(input, w, h, n, q),
+    }
+}
+
+/// Blends `aligned`, the output of the block pass on the usual grid, with the
+/// passes on the grids offset by half a block horizontally, vertically and
+/// both.
+///
+/// Each pass is weighted by a squared sine window over its blocks, which
+/// falls to nearly zero at the block edges; the windows of the four grids sum
+/// to one everywhere, so every edge is covered by the middle of another
+/// grid's block.
+pub(crate) fn lapped_blocks(
+    aligned: Vec<f32>,
+    input: &[f32],
+    w: usize,
+    h: usize,
+    q: &Quantizer,
+) -> Vec<f32> {
+    let n = q.block_size;
+    let half = n / 2;
+    let window: Vec<f32> = (0..n)
+        .map(|t| libm::sinf(PI * (t as f32 + 0.5) / n as f32).powi(2))
+        .collect();
+    let mut out = vec![0.0; w * h];
+    for (ox, oy) in [(0, 0), (half, 0), (0, half), (half, half)] {
+        let pass = if (ox, oy) == (0, 0) {
+            aligned.clone()
+        } else {
+            // Replicate the top/left edge so the grid starts `ox`, `oy` early.
+            let (pw, ph) = (w + ox, h + oy);
+            let padded: Vec<f32> = (0..pw * ph)
+                .map(|i| input[(i / pw).saturating_sub(oy) * w + (i % pw).saturating_sub(ox)])
+                .collect();
+            let processed = aligned_blocks(&padded, pw, ph, q);
+            (0..w * h)
+                .map(|i| processed[(i / w + oy) * pw + i % w + ox])
+                .collect()
+        };
+        for (i, value) in pass.into_iter().enumerate() {
+            let (x, y) = (i % w + ox, i / w + oy);
+            out[i] += window[x % n] * window[y % n] * value;
+        }
+    }
+    out
+}
+
+/// Runs the block pass and also reports per-block statistics in raster order.
+pub(crate) fn process_blocks_with_stats(
+    input: &[f32],
+    w: usize,
+    h: usize,
+    q: &Quantizer,
+) -> (Vec<f32>, Vec<BlockStats>) {
+    let blocks = if q.dc_only() {
+        forward_dc_blocks(input, w, h)
+    } else {
+        forward_blocks(input, w, h)
+    };
+    reconstruct_blocks(&blocks, input, w, h, q)
+}
+
+/// Forward DCT of every 8x8 block of a `w`x`h` plane in raster order.
+///
+/// Partial blocks at the right/bottom edge are padded by edge replication. The
+/// result can be quantized repeatedly with [`reconstruct_blocks`].
+pub(crate) fn forward_blocks(input: &[f32], w: usize, h: usize) -> Vec<[[f32; 8]; 8]> {
+    gather_blocks(input, w, h).map(dct2d).collect()
+}
+
+/// Inverse of [`forward_blocks`]: transforms the blocks back into a `w`x`h`
+/// plane, dropping the samples that pad the right/bottom edge.
+pub(crate) fn inverse_blocks(blocks: &[[[f32; 8]; 8]], w: usize, h: usize) -> Vec<f32> {
+    let mut out = vec![0.0; w * h];
+    let blocks_x = w.div_ceil(8);
+    for (b_i, &block) in blocks.iter().enumerate() {
+        let (bx, by) = (b_i % blocks_x * 8, b_i / blocks_x * 8);
+        let pixels = idct2d(block);
+        for u in 0..8.min(h - by) {
+            for v in 0..8.min(w - bx) {
+                out[(by + u) * w + bx + v] = pixels[u][v];
+            }
+        }
+    }
+    out
+}
+
+/// Like [`forward_blocks`], but computes only the DC coefficient of each block
+/// (eight times its mean) and leaves the AC coefficients at zero.
+pub(crate) fn forward_dc_blocks(input: &[f32], w: usize, h: usize) -> Vec<[[f32; 8]; 8]> {
+    gather_blocks(input, w, h)
+        .map(|block| {
+            let mut dct = [[0.0; 8]; 8];
+            dct[0][0] = block.iter().flatten().sum::<f32>() / 8.0;
+            dct
+        })
+        .collect()
+}
+
+/// The 8x8 pixel blocks of a `w`x`h` plane in raster order, padded by edge
+/// replication.
+#[allow(clippy::needless_range_loop)]
+fn gather_blocks(input: &[f32], w: usize, h: usize) -> impl Iterator<Item = [[f32; 8]; 8]> + '_ {
+    (0..h).step_by(8).flat_map(move |by| {
+        (0..w).step_by(8).map(move |bx| {
+            let mut block = [[0.0; 8]; 8];
+            for u in 0..8 {
+                for v in 0..8 {
+                    let py = (by + u).min(h - 1);
+                    let px = (bx + v).min(w - 1);
+                    block[u][v] = input[py * w + px];
+                }
+            }
+            block
+        })
+    })
+}
+
+/// Quantizes and dequantizes the coefficients from [`forward_blocks`] of a
+/// `w`x`h` plane, with error diffusion and block shuffle.
+pub(crate) fn quantize_blocks(
+    blocks: &[[[f32; 8]; 8]],
+    w: usize,
+    h: usize,
+    q: &Quantizer,
+) -> Vec<[[f32; 8]; 8]> {
+    let blocks_x = w.div_ceil(8);
+    let q = &q.on_grid(blocks_x, h.div_ceil(8));
+    let mut dc_error = vec![0.0; blocks.len()];
+    let mut quantized_blocks = Vec::with_capacity(blocks.len());
+    for (b_i, &block) in blocks.iter().enumerate() {
+        let (bxi, byi) = (b_i % blocks_x, b_i / blocks_x);
+        let mut coeffs = block;
+        if q.error_diffusion {
+            coeffs[0][0] += dc_error[b_i];
+        }
+        let quantized = q.quantize(coeffs, bxi, byi);
+        if q.error_diffusion {
+            diffuse_dc_error(&mut dc_error, blocks_x, bxi, byi, coeffs[0][0] - quantized[0][0]);
+        }
+        quantized_blocks.push(quantized);
+    }
+    q.displace(&mut quantized_blocks, blocks_x);
+    quantized_blocks
+}
+
+/// Quantizes the coefficients from [`forward_blocks`], transforms them back and
+/// reports per-block statistics measured against the original `input` plane.
+#[allow(clippy::needless_range_loop)]
+pub(crate) fn reconstruct_blocks(
+    blocks: &[[[f32; 8]; 8]],
+    input: &[f32],
+    w: usize,
+    h: usize,
+    q: &Quantizer,
+) -> (Vec<f32>, Vec<BlockStats>) {
+    let mut out = vec![0.0; w * h];
+    let mut stats = Vec::with_capacity(blocks.len());
+    let mut prev_dc = 0;
+    let blocks_x = w.div_ceil(8);
+    let quantized_blocks = quantize_blocks(blocks, w, h, q);
+
+    for (b_i, &quantized) in quantized_blocks.iter().enumerate() {
+        let (bx, by) = (b_i % blocks_x * 8, b_i / blocks_x * 8);
+        // Blocks quantized down to their DC are flat; skip the full transform.
+        let processed = if quantized.iter().flatten().skip(1).all(|&c| c == 0.0) {
+            [[quantized[0][0] / 8.0; 8]; 8]
+        } else {
+            idct2d(quantized)
+        };
+
+        let mut levels = [[0i32; 8]; 8];
+        for u in 0..8 {
+            for v in 0..8 {
+                levels[u][v] = (quantized[u][v] / q.table[u][v] as f32).round() as i32;
+            }
+        }
+        let mut squared_error = 0.0;
+        let mut pixels = 0;
+        for u in 0..8 {
+            for v in 0..8 {
+                let py = by + u;
+                let px = bx + v;
+                if py < h && px < w {
+                    out[py * w + px] = processed[u][v];
+                    squared_error += (processed[u][v] - input[py * w + px]).powi(2);
+                    pixels += 1;
+                }
+            }
+        }
+        stats.push(BlockStats {
+            error: squared_error / pixels as f32,
+            nonzero: levels.iter().flatten().filter(|&&l| l != 0).count() as u32,
+            bits: estimate_bits(&levels, prev_dc),
+        });
+        prev_dc = levels[0][0];
+    }
+    (out, stats)
+}
+
+/// Bytes of a color baseline JFIF file written by `encoder` outside the
+/// entropy-coded data: markers, headers, one quantization table and the
+/// standard Huffman tables.
+const JPEG_OVERHEAD_BYTES: usize = 544;
+
+/// Estimated size in bytes of a baseline JPEG whose blocks produced `stats`.
+pub(crate) fn estimated_bytes<'a>(stats: impl IntoIterator<Item = &'a BlockStats>) -> usize {
+    let bits: f32 = stats.into_iter().map(|s| s.bits).sum();
+    JPEG_OVERHEAD_BYTES + (bits / 8.0).ceil() as usize
+}
+
+/// Zigzag scan order of an 8x8 block as (row, column) pairs.
+pub(crate) const ZIGZAG: [(usize, usize); 64] = {
+    let mut order = [(0, 0); 64];
+    let (mut u, mut v) = (0, 0);
+    let mut i = 0;
+    while i < 64 {
+        order[i] = (u, v);
+        if (u + v) % 2 == 0 {
+            if v == 7 {
+                u += 1;
+            } else if u == 0 {
+                v += 1;
+            } else {
+                u -= 1;
+                v += 1;
+            }
+        } else if u == 7 {
+            v += 1;
+        } else if v == 0 {
+            u += 1;
+        } else {
+            u += 1;
+            v -= 1;
+        }
+        i += 1;
+    }
+    order
+};
+
+/// Number of magnitude bits JPEG uses for a coefficient value (its "category").
+pub(crate) fn category(value: i32) -> u32 {
+    32 - value.unsigned_abs().leading_zeros()
+}
+
+/// Heuristic entropy-coded size of a block, assuming typical baseline Huffman
+/// code lengths: the DC difference and each run/size symbol cost a short code
+/// plus their magnitude bits.
+fn estimate_bits(levels: &[[i32; 8]; 8], prev_dc: i32) -> f32 {
+    let mut bits = 3.0 + category(levels[0][0] - prev_dc) as f32;
+    let mut run = 0;
+    for &(u, v) in &ZIGZAG[1..] {
+        let level = levels[u][v];
+        if level == 0 {
+            run += 1;
+            continue;
+        }
+        // Runs of 16+ zeros need a ZRL symbol each.
+        bits += (run / 16) as f32 * 11.0;
+        bits += 4.0 + (run % 16) as f32 / 2.0 + category(level) as f32;
+        run = 0;
+    }
+    if run > 0 {
+        bits += 4.0; // end of block
+    }
+    bits
+}
+
+/// Spreads a block's DC rounding error over the blocks not yet visited
+/// (Floyd–Steinberg weights on the block grid), so the average intensity of
+/// smooth gradients survives coarse DC quantization.
+pub(crate) fn diffuse_dc_error(errors: &mut [f32], blocks_x: usize, bx: usize, by: usize, error: f32) {
+    let blocks_y = errors.len() / blocks_x;
+    let mut spread = |x: isize, y: usize, weight: f32| {
+        if x >= 0 && (x as usize) < blocks_x && y < blocks_y {
+            errors[y * blocks_x + x as usize] += error * weight;
+        }
+    };
+    let x = bx as isize;
+    spread(x + 1, by, 7.0 / 16.0);
+    spread(x - 1, by + 1, 3.0 / 16.0);
+    spread(x, by + 1, 5.0 / 16.0);
+    spread(x + 1, by + 1, 1.0 / 16.0);
+}
+
+/// Runs the block pass with `n`x`n` blocks (any size but the 8x8 the other
+/// functions use), quantizing with [`Quantizer::quantize_sized`].
+///
+/// Partial blocks at the right/bottom edge are padded by edge replication.
+pub(crate) fn process_sized_blocks(
+    input: &[f32],
+    w: usize,
+    h: usize,
+    n: usize,
+    q: &Quantizer,
+) -> Vec<f32> {
+    let q = &q.on_grid(w.div_ceil(n), h.div_ceil(n));
+    let basis = dct_basis(n);
+    let mut out = vec![0.0; w * h];
+    let mut block = vec![0.0; n * n];
+    for (byi, by) in (0..h).step_by(n).enumerate() {
+        for (bxi, bx) in (0..w).step_by(n).enumerate() {
+            for u in 0..n {
+                for v in 0..n {
+                    block[u * n + v] = input[(by + u).min(h - 1) * w + (bx + v).min(w - 1)];
+                }
+            }
+            let mut coeffs = transform(&block, n, &basis, false);
+            q.quantize_sized(&mut coeffs, n, bxi, byi);
+            let processed = transform(&coeffs, n, &basis, true);
+            for u in 0..n.min(h - by) {
+                for v in 0..n.min(w - bx) {
+                    out[(by + u) * w + bx + v] = processed[u * n + v];
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Orthonormal DCT-II basis of size `n`, `basis[k * n + x]` being frequency
+/// `k` at sample `x`. At `n` = 8 it matches [`dct2d`].
+fn dct_basis(n: usize) -> Vec<f32> {
+    let mut basis = vec![0.0; n * n];
+    for k in 0..n {
+        let c = if k == 0 {
+            (1.0 / n as f32).sqrt()
+        } else {
+            (2.0 / n as f32).sqrt()
+        };
+        for x in 0..n {
+            basis[k * n + x] = c * libm::cosf((2 * x + 1) as f32 * k as f32 * PI / (2 * n) as f32);
+        }
+    }
+    basis
+}
+
+/// Separable 2D DCT (or its inverse) of an `n`x`n` block in row-major order.
+fn transform(block: &[f32], n: usize, basis: &[f32], inverse: bool) -> Vec<f32> {
+    let weight = |k: usize, x: usize| {
+        if inverse {
+            basis[x * n + k]
+        } else {
+            basis[k * n + x]
+        }
+    };
+    let mut rows = vec![0.0; n * n];
+    for r in 0..n {
+        for k in 0..n {
+            rows[r * n + k] = (0..n).map(|x| weight(k, x) * block[r * n + x]).sum();
+        }
+    }
+    let mut out = vec![0.0; n * n];
+    for c in 0..n {
+        for k in 0..n {
+            out[k * n + c] = (0..n).map(|x| weight(k, x) * rows[x * n + c]).sum();
+        }
+    }
+    out
+}
+
+#[allow(clippy::needless_range_loop)]
+pub(crate) fn dct2d(block: [[f32; 8]; 8]) -> [[f32; 8]; 8] {
+    let mut dct = [[0.0; 8]; 8];
+    for u in 0..8 {
+        for v in 0..8 {
+            let mut sum = 0.0;
+            for x in 0..8 {
+                for y in 0..8 {
+                    sum += block[x][y]
+                        * libm::cosf((2 * x + 1) as f32 * u as f32 * PI / 16.0)
+                        * libm::cosf((2 * y + 1) as f32 * v as f32 * PI / 16.0);
+                }
+            }
+            let cu = if u == 0 { 1.0 / 2.0_f32.sqrt() } else { 1.0 };
+            let cv = if v == 0 { 1.0 / 2.0_f32.sqrt() } else { 1.0 };
+            dct[u][v] = 0.25 * cu * cv * sum;
+        }
+    }
+    dct
+}
+
+#[allow(clippy::needless_range_loop)]
+fn idct2d(dct: [[f32; 8]; 8]) -> [[f32; 8]; 8] {
+    let mut block = [[0.0; 8]; 8];
+    for x in 0..8 {
+        for y in 0..8 {
+            let mut sum = 0.0;
+            for u in 0..8 {
+                for v in 0..8 {
+                    let cu = if u == 0 { 1.0 / 2.0_f32.sqrt() } else { 1.0 };
+                    let cv = if v == 0 { 1.0 / 2.0_f32.sqrt() } else { 1.0 };
+                    sum += cu * cv * dct[u][v]
+                        * libm::cosf((2 * x + 1) as f32 * u as f32 * PI / 16.0)
+                        * libm::cosf((2 * y + 1) as f32 * v as f32 * PI / 16.0);
+                }
+            }
+            block[x][y] = 0.25 * sum;
+        }
+    }
+    block
+}
\ No newline at end of file
@@ -0,0 +1,496 @@
+//! Baseline JFIF reader, as far as recovering the quantized coefficients and
+//! the tables to dequantize them with.
+//!
+//! Only sequential Huffman-coded files (SOF0/SOF1) are understood; nothing is
+//! dequantized or transformed back here.
+
+use crate::dct::ZIGZAG;
+use crate::error::Error;
+
+/// Canonical Huffman decoding table (Annex F.2.2.3): the largest code and
+/// the index of the first symbol for every code length.
+#[derive(Clone, Default)]
+struct HuffmanTable {
+    max_code: [i32; 17],
+    offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(bits: &[u8], values: &[u8]) -> HuffmanTable {
+        let mut table = HuffmanTable {
+            max_code: [-1; 17],
+            offset: [0; 17],
+            values: values.to_vec(),
+        };
+        let (mut code, mut k) = (0i32, 0i32);
+        for len in 1..=16 {
+            let count = bits[len - 1] as i32;
+            table.offset[len] = k - code;
+            if count > 0 {
+                code += count;
+                k += count;
+                table.max_code[len] = code - 1;
+            }
+            code <<= 1;
+        }
+        table
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u8, Error> {
+        let mut code = 0;
+        for len in 1..=16 {
+            code = code << 1 | reader.bit() as i32;
+            if code <= self.max_code[len] {
+                let index = (code + self.offset[len]) as usize;
+                return self.values.get(index).copied().ok_or(Error::InvalidJpeg);
+            }
+        }
+        Err(Error::InvalidJpeg)
+    }
+}
+
+/// Reads entropy-coded bits, dropping the zero byte stuffed after every
+/// `0xFF`. At a marker it stops advancing and yields one bits.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u8,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> u32 {
+        if self.count == 0 {
+            match (self.data.get(self.pos), self.data.get(self.pos + 1)) {
+                (Some(0xFF), Some(0)) => {
+                    self.buffer = 0xFF;
+                    self.pos += 2;
+                }
+                (Some(0xFF), _) | (None, _) => return 1,
+                (Some(&byte), _) => {
+                    self.buffer = byte;
+                    self.pos += 1;
+                }
+            }
+            self.count = 8;
+        }
+        self.count -= 1;
+        (self.buffer >> self.count & 1) as u32
+    }
+
+    fn bits(&mut self, len: u32) -> u32 {
+        (0..len).fold(0, |value, _| value << 1 | self.bit())
+    }
+
+    /// Skips to the next byte and over the restart marker expected there.
+    fn restart(&mut self) -> Result<(), Error> {
+        self.count = 0;
+        match self.data.get(self.pos..self.pos + 2) {
+            Some([0xFF, 0xD0..=0xD7]) => {
+                self.pos += 2;
+                Ok(())
+            }
+            _ => Err(Error::InvalidJpeg),
+        }
+    }
+}
+
+/// Value of a `size`-bit magnitude field (Annex F.2.2.1, EXTEND).
+fn extend(bits: u32, size: u32) -> i32 {
+    if size == 0 {
+        0
+    } else if bits < 1 << (size - 1) {
+        bits as i32 - (1 << size) + 1
+    } else {
+        bits as i32
+    }
+}
+
+/// A component declared in the frame header.
+struct FrameComponent {
+    id: u8,
+    h: usize,
+    v: usize,
+    /// Quantization table selector.
+    table: usize,
+    blocks_x: usize,
+    blocks_y: usize,
+    levels: Vec<[[i32; 8]; 8]>,
+}
+
+/// The frame header: its components and the size of the interleaved MCU grid.
+#[derive(Default)]
+struct FrameHeader {
+    width: usize,
+    height: usize,
+    mcus_x: usize,
+    mcus_y: usize,
+    /// Sample precision in bits, 8 or 12.
+    precision: u8,
+    /// Largest DC difference category the sample precision allows: 11 for
+    /// 8-bit samples, 15 for 12-bit ones (Table F.1).
+    max_dc_size: u32,
+    /// Components, whose levels are allocated by their first scan.
+    components: Vec<FrameComponent>,
+}
+
+/// A component of a decoded file.
+pub(crate) struct DecodedComponent {
+    /// Horizontal and vertical sampling factors.
+    pub(crate) h: usize,
+    pub(crate) v: usize,
+    /// Quantization steps in natural (not zigzag) order.
+    pub(crate) table: [[u32; 8]; 8],
+    /// Levels of the component's blocks in raster order, without the blocks
+    /// that only pad the MCU grid.
+    pub(crate) levels: Vec<[[i32; 8]; 8]>,
+}
+
+/// The dimensions and the components, in frame header order, of a baseline
+/// JPEG file.
+pub(crate) struct DecodedJpeg {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    /// Sample precision in bits, 8 or 12.
+    pub(crate) precision: u8,
+    pub(crate) components: Vec<DecodedComponent>,
+}
+
+/// Quantized coefficient levels of every component of a baseline JPEG file,
+/// in frame header order, each with its blocks in raster order. Blocks that
+/// only pad the MCU grid are left out.
+pub(crate) fn read_levels(jpeg: &[u8]) -> Result<Vec<Vec<[[i32; 8]; 8]>>, Error> {
+    Ok(read_jpeg(jpeg)?.components.into_iter().map(|c| c.levels).collect())
+}
+
+/// Reads a baseline JPEG file as far as its quantized levels and tables.
+pub(crate) fn read_jpeg(jpeg: &[u8]) -> Result<DecodedJpeg, Error> {
+    if jpeg.get(..2) != Some(&[0xFF, 0xD8]) {
+        return Err(Error::InvalidJpeg);
+    }
+    let mut quant_tables = [None; 4];
+    let mut dc_tables = vec![HuffmanTable::default(); 4];
+    let mut ac_tables = vec![HuffmanTable::default(); 4];
+    let mut frame = FrameHeader::default();
+    let mut restart_interval = 0;
+    let mut pos = 2;
+    loop {
+        // Fill bytes may precede a marker.
+        while jpeg.get(pos) == Some(&0xFF) && jpeg.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        let marker = match jpeg.get(pos..pos + 2) {
+            Some(&[0xFF, marker]) => marker,
+            _ => return Err(Error::InvalidJpeg),
+        };
+        if marker == 0xD9 {
+            break;
+        }
+        let len = match jpeg.get(pos + 2..pos + 4) {
+            Some(&[hi, lo]) => u16::from_be_bytes([hi, lo]) as usize,
+            _ => return Err(Error::InvalidJpeg),
+        };
+        let body = jpeg.get(pos + 4..pos + 2 + len).ok_or(Error::InvalidJpeg)?;
+        pos += 2 + len;
+        match marker {
+            0xC0 | 0xC1 => frame = read_frame(body)?,
+            // Progressive, lossless and arithmetic-coded frames.
+            0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                return Err(Error::InvalidJpeg)
+            }
+            0xC4 => read_huffman_tables(body, &mut dc_tables, &mut ac_tables)?,
+            0xDB => read_quant_tables(body, &mut quant_tables)?,
+            0xDD => {
+                let bytes = body.get(..2).ok_or(Error::InvalidJpeg)?;
+                restart_interval = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+            }
+            0xDA => {
+                let scan = read_scan_header(body, &frame.components)?;
+                let mut reader = BitReader {
+                    data: jpeg,
+                    pos,
+                    buffer: 0,
+                    count: 0,
+                };
+                let tables = (&dc_tables[..], &ac_tables[..]);
+                decode_scan(&mut reader, &mut frame, &scan, tables, restart_interval)?;
+                pos = next_marker(jpeg, reader.pos);
+            }
+            _ => {}
+        }
+    }
+    let components = frame
+        .components
+        .into_iter()
+        .map(|c| {
+            // Every component is coded by some scan.
+            if c.levels.is_empty() {
+                return Err(Error::InvalidJpeg);
+            }
+            Ok(DecodedComponent {
+                h: c.h,
+                v: c.v,
+                table: quant_tables[c.table].ok_or(Error::InvalidJpeg)?,
+                levels: c.levels,
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+    Ok(DecodedJpeg {
+        width: frame.width,
+        height: frame.height,
+        precision: frame.precision,
+        components,
+    })
+}
+
+fn read_frame(body: &[u8]) -> Result<FrameHeader, Error> {
+    let header = body.get(..6).ok_or(Error::InvalidJpeg)?;
+    let height = u16::from_be_bytes([header[1], header[2]]) as usize;
+    let width = u16::from_be_bytes([header[3], header[4]]) as usize;
+    let count = header[5] as usize;
+    let specs = body.get(6..6 + 3 * count).ok_or(Error::InvalidJpeg)?;
+    let max_dc_size = match header[0] {
+        8 => 11,
+        12 => 15,
+        _ => return Err(Error::InvalidJpeg),
+    };
+    if width == 0 || height == 0 || count == 0 {
+        return Err(Error::InvalidJpeg);
+    }
+    let sampling = |spec: &[u8]| ((spec[1] >> 4) as usize, (spec[1] & 15) as usize);
+    let h_max = specs.chunks(3).map(|s| sampling(s).0).max().unwrap_or(1);
+    let v_max = specs.chunks(3).map(|s| sampling(s).1).max().unwrap_or(1);
+    let components = specs
+        .chunks(3)
+        .map(|spec| {
+            let (h, v) = sampling(spec);
+            if h == 0 || v == 0 || spec[2] > 3 {
+                return Err(Error::InvalidJpeg);
+            }
+            let blocks_x = (width * h).div_ceil(h_max).div_ceil(8);
+            let blocks_y = (height * v).div_ceil(v_max).div_ceil(8);
+            Ok(FrameComponent {
+                id: spec[0],
+                h,
+                v,
+                table: spec[2] as usize,
+                blocks_x,
+                blocks_y,
+                levels: Vec::new(),
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(FrameHeader {
+        width,
+        height,
+        mcus_x: width.div_ceil(8 * h_max),
+        mcus_y: height.div_ceil(8 * v_max),
+        precision: header[0],
+        max_dc_size,
+        components,
+    })
+}
+
+fn read_huffman_tables(
+    mut body: &[u8],
+    dc_tables: &mut [HuffmanTable],
+    ac_tables: &mut [HuffmanTable],
+) -> Result<(), Error> {
+    while !body.is_empty() {
+        let bits = body.get(1..17).ok_or(Error::InvalidJpeg)?;
+        let count = bits.iter().map(|&b| b as usize).sum::<usize>();
+        let values = body.get(17..17 + count).ok_or(Error::InvalidJpeg)?;
+        let (class, id) = (body[0] >> 4, (body[0] & 15) as usize);
+        let tables = if class == 0 { &mut *dc_tables } else { &mut *ac_tables };
+        *tables.get_mut(id).ok_or(Error::InvalidJpeg)? = HuffmanTable::new(bits, values);
+        body = &body[17 + count..];
+    }
+    Ok(())
+}
+
+fn read_quant_tables(
+    mut body: &[u8],
+    tables: &mut [Option<[[u32; 8]; 8]>; 4],
+) -> Result<(), Error> {
+    while let Some(&spec) = body.first() {
+        // 8-bit steps, or 16-bit ones for 12-bit files.
+        let (wide, id) = (spec >> 4 != 0, (spec & 15) as usize);
+        let len = if wide { 128 } else { 64 };
+        let steps = body.get(1..1 + len).ok_or(Error::InvalidJpeg)?;
+        let mut table = [[0; 8]; 8];
+        for (k, &(u, v)) in ZIGZAG.iter().enumerate() {
+            table[u][v] = if wide {
+                u16::from_be_bytes([steps[2 * k], steps[2 * k + 1]]) as u32
+            } else {
+                steps[k] as u32
+            };
+        }
+        *tables.get_mut(id).ok_or(Error::InvalidJpeg)? = Some(table);
+        body = &body[1 + len..];
+    }
+    Ok(())
+}
+
+/// Components of a scan: index into the frame components, DC and AC table.
+type ScanComponents = Vec<(usize, usize, usize)>;
+
+fn read_scan_header(body: &[u8], components: &[FrameComponent]) -> Result<ScanComponents, Error> {
+    let count = *body.first().ok_or(Error::InvalidJpeg)? as usize;
+    let specs = body.get(1..1 + 2 * count).ok_or(Error::InvalidJpeg)?;
+    specs
+        .chunks(2)
+        .map(|spec| {
+            let index = components
+                .iter()
+                .position(|c| c.id == spec[0])
+                .ok_or(Error::InvalidJpeg)?;
+            let (dc, ac) = ((spec[1] >> 4) as usize, (spec[1] & 15) as usize);
+            if dc > 3 || ac > 3 {
+                return Err(Error::InvalidJpeg);
+            }
+            Ok((index, dc, ac))
+        })
+        .collect()
+}
+
+fn decode_scan(
+    reader: &mut BitReader,
+    frame: &mut FrameHeader,
+    scan: &ScanComponents,
+    (dc_tables, ac_tables): (&[HuffmanTable], &[HuffmanTable]),
+    restart_interval: usize,
+) -> Result<(), Error> {
+    // A single-component scan codes the component's own blocks one at a time;
+    // an interleaved one codes h x v blocks of each component per MCU.
+    let single = scan.len() == 1;
+    let (mcus_x, mcus_y) = if single {
+        let c = &frame.components[scan[0].0];
+        (c.blocks_x, c.blocks_y)
+    } else {
+        (frame.mcus_x, frame.mcus_y)
+    };
+
+    // Every block takes at least a DC and an AC code of a bit or more, so the
+    // header can't ask for more blocks than the rest of the file can hold.
+    let per_mcu = scan.iter().map(|&(index, _, _)| match single {
+        true => 1,
+        false => frame.components[index].h * frame.components[index].v,
+    });
+    let blocks = per_mcu.sum::<usize>().checked_mul(mcus_x * mcus_y);
+    let room = reader.data.len().saturating_sub(reader.pos).saturating_mul(4);
+    if blocks.is_none_or(|blocks| blocks > room) {
+        return Err(Error::InvalidJpeg);
+    }
+    for &(index, _, _) in scan {
+        let c = &mut frame.components[index];
+        if c.levels.is_empty() {
+            let len = c.blocks_x * c.blocks_y;
+            c.levels.try_reserve_exact(len).map_err(|_| Error::InvalidJpeg)?;
+            c.levels.resize(len, [[0; 8]; 8]);
+        }
+    }
+
+    let mut prev_dc = vec![0i32; scan.len()];
+    for mcu in 0..mcus_x * mcus_y {
+        if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+            reader.restart()?;
+            prev_dc.iter_mut().for_each(|dc| *dc = 0);
+        }
+        let (mx, my) = (mcu % mcus_x, mcu / mcus_x);
+        for (s_i, &(index, dc, ac)) in scan.iter().enumerate() {
+            let c = &mut frame.components[index];
+            let (ch, cv) = if single { (1, 1) } else { (c.h, c.v) };
+            for v in 0..cv {
+                for u in 0..ch {
+                    let mut levels = [[0; 8]; 8];
+                    let size = dc_tables[dc].decode(reader)? as u32;
+                    if size > frame.max_dc_size {
+                        return Err(Error::InvalidJpeg);
+                    }
+                    prev_dc[s_i] = prev_dc[s_i].wrapping_add(extend(reader.bits(size), size));
+                    levels[0][0] = prev_dc[s_i];
+                    let mut k = 1;
+                    while k < 64 {
+                        let rs = ac_tables[ac].decode(reader)?;
+                        let (run, size) = ((rs >> 4) as usize, (rs & 15) as u32);
+                        if size == 0 {
+                            if run != 15 {
+                                break;
+                            }
+                            k += 16;
+                            continue;
+                        }
+                        k += run;
+                        let &(zu, zv) = ZIGZAG.get(k).ok_or(Error::InvalidJpeg)?;
+                        levels[zu][zv] = extend(reader.bits(size), size);
+                        k += 1;
+                    }
+                    let (bx, by) = (mx * ch + u, my * cv + v);
+                    if bx < c.blocks_x && by < c.blocks_y {
+                        c.levels[by * c.blocks_x + bx] = levels;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Position of the first marker at or after `pos`, skipping stuffed bytes and
+/// restart markers.
+fn next_marker(jpeg: &[u8], mut pos: usize) -> usize {
+    while pos + 1 < jpeg.len() {
+        if jpeg[pos] == 0xFF && !matches!(jpeg[pos + 1], 0x00 | 0xD0..=0xD7) {
+            return pos;
+        }
+        pos += 1;
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::{CompressOptions, PixelFormat};
+    use crate::pipeline;
+
+    fn jpeg(width: usize, height: usize) -> Vec<u8> {
+        let data: Vec<u8> = (0..width * height * 4).map(|i| (i * 37 % 251) as u8).collect();
+        let options = CompressOptions::default();
+        pipeline::encode_jpeg(&data, width, height, PixelFormat::Rgba, &options).unwrap()
+    }
+
+    #[test]
+    fn reads_the_frame_of_an_encoded_file() {
+        let file = read_jpeg(&jpeg(45, 29)).unwrap();
+        assert_eq!((file.width, file.height, file.precision), (45, 29, 8));
+        assert_eq!(file.components.len(), 3);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let file = jpeg(45, 29);
+        for len in 0..file.len() {
+            assert_eq!(read_jpeg(&file[..len]).err(), Some(Error::InvalidJpeg), "{len}");
+        }
+    }
+
+    #[test]
+    fn rejects_headers_the_data_cannot_back() {
+        let mut file = jpeg(45, 29);
+        let sof = file.windows(2).position(|m| m == [0xFF, 0xC0]).unwrap();
+        // Height and width follow the length and the sample precision.
+        file[sof + 5..sof + 9].copy_from_slice(&[0xFF; 4]);
+        assert_eq!(read_jpeg(&file).err(), Some(Error::InvalidJpeg));
+    }
+
+    #[test]
+    fn rejects_12_bit_samples_when_decoding() {
+        let mut file = jpeg(45, 29);
+        let sof = file.windows(2).position(|m| m == [0xFF, 0xC0]).unwrap();
+        file[sof + 4] = 12;
+        assert_eq!(read_jpeg(&file).map(|file| file.precision).ok(), Some(12));
+        assert_eq!(pipeline::decode_jpeg(&file).err(), Some(Error::InvalidJpeg));
+    }
+}
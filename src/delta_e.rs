@@ -0,0 +1,121 @@
+use wasm_bindgen::prelude::*;
+
+use crate::options::TransferFunction;
+
+/// CIEDE2000 color-difference statistics between two images.
+///
+/// A ΔE of about 1 is the smallest difference most observers notice; brand
+/// color tolerances are typically 2–3.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeltaE {
+    /// Mean ΔE over all pixels.
+    pub mean: f64,
+    /// Median ΔE.
+    pub median: f64,
+    /// 95th-percentile ΔE.
+    pub p95: f64,
+    /// 99th-percentile ΔE.
+    pub p99: f64,
+    /// Largest ΔE.
+    pub max: f64,
+}
+
+/// Computes ΔE2000 statistics for two sRGB RGBA buffers of equal length.
+pub(crate) fn delta_e_2000(original: &[u8], compressed: &[u8]) -> DeltaE {
+    let mut values: Vec<f64> = original
+        .chunks_exact(4)
+        .zip(compressed.chunks_exact(4))
+        .map(|(a, b)| ciede2000(lab(a), lab(b)))
+        .collect();
+    values.sort_unstable_by(f64::total_cmp);
+
+    let percentile = |p: f64| {
+        let rank = (p * values.len() as f64).ceil() as usize;
+        values[rank.clamp(1, values.len()) - 1]
+    };
+    DeltaE {
+        mean: values.iter().sum::<f64>() / values.len() as f64,
+        median: percentile(0.5),
+        p95: percentile(0.95),
+        p99: percentile(0.99),
+        max: values[values.len() - 1],
+    }
+}
+
+/// Converts an sRGB pixel to CIE L*a*b* (D65 white).
+fn lab(px: &[u8]) -> [f64; 3] {
+    let [r, g, b] =
+        [px[0], px[1], px[2]].map(|v| TransferFunction::Srgb.decode(v as f32 / 255.0) as f64);
+    let x = (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047;
+    let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+    let z = (0.0193339 * r + 0.1191920 * g + 0.9503041 * b) / 1.08883;
+
+    let f = |t: f64| {
+        if t > 216.0 / 24389.0 {
+            libm::cbrt(t)
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// CIEDE2000 difference between two L*a*b* colors (Sharma, Wu & Dalal, 2005).
+fn ciede2000([l1, a1, b1]: [f64; 3], [l2, a2, b2]: [f64; 3]) -> f64 {
+    let c_bar = (libm::hypot(a1, b1) + libm::hypot(a2, b2)) / 2.0;
+    let c_bar7 = c_bar.powi(7);
+    let g = 0.5 * (1.0 - (c_bar7 / (c_bar7 + 25f64.powi(7))).sqrt());
+    let (a1p, a2p) = ((1.0 + g) * a1, (1.0 + g) * a2);
+    let (c1p, c2p) = (libm::hypot(a1p, b1), libm::hypot(a2p, b2));
+    let hue = |b: f64, a: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            libm::atan2(b, a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let (h1p, h2p) = (hue(b1, a1p), hue(b2, a2p));
+
+    let dl = l2 - l1;
+    let dc = c2p - c1p;
+    let dh = if c1p * c2p == 0.0 {
+        0.0
+    } else if (h2p - h1p).abs() <= 180.0 {
+        h2p - h1p
+    } else if h2p - h1p > 180.0 {
+        h2p - h1p - 360.0
+    } else {
+        h2p - h1p + 360.0
+    };
+    let dh_big = 2.0 * (c1p * c2p).sqrt() * libm::sin((dh / 2.0).to_radians());
+
+    let l_bar = (l1 + l2) / 2.0;
+    let c_bar_p = (c1p + c2p) / 2.0;
+    let h_bar = if c1p * c2p == 0.0 {
+        h1p + h2p
+    } else if (h1p - h2p).abs() <= 180.0 {
+        (h1p + h2p) / 2.0
+    } else if h1p + h2p < 360.0 {
+        (h1p + h2p + 360.0) / 2.0
+    } else {
+        (h1p + h2p - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * libm::cos((h_bar - 30.0).to_radians())
+        + 0.24 * libm::cos((2.0 * h_bar).to_radians())
+        + 0.32 * libm::cos((3.0 * h_bar + 6.0).to_radians())
+        - 0.20 * libm::cos((4.0 * h_bar - 63.0).to_radians());
+    let d_theta = 30.0 * libm::exp(-((h_bar - 275.0) / 25.0).powi(2));
+    let c_bar_p7 = c_bar_p.powi(7);
+    let r_c = 2.0 * (c_bar_p7 / (c_bar_p7 + 25f64.powi(7))).sqrt();
+    let l50 = (l_bar - 50.0).powi(2);
+    let s_l = 1.0 + 0.015 * l50 / (20.0 + l50).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar_p;
+    let s_h = 1.0 + 0.015 * c_bar_p * t;
+    let r_t = -libm::sin((2.0 * d_theta).to_radians()) * r_c;
+
+    let (tl, tc, th) = (dl / s_l, dc / s_c, dh_big / s_h);
+    (tl * tl + tc * tc + th * th + r_t * tc * th).sqrt()
+}
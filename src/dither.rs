@@ -0,0 +1,29 @@
+use crate::options::Dither;
+
+/// 8x8 Bayer threshold matrix.
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+impl Dither {
+    /// Zero-mean dither offset in -0.5..0.5 for position (`x`, `y`).
+    pub(crate) fn offset(self, x: usize, y: usize) -> f32 {
+        match self {
+            Dither::None => 0.0,
+            Dither::Ordered => (BAYER[y % 8][x % 8] as f32 + 0.5) / 64.0 - 0.5,
+            // Interleaved gradient noise: a cheap, tileless pattern with
+            // blue-noise-like spectral properties.
+            Dither::BlueNoise => {
+                let t = (0.06711056 * x as f32 + 0.00583715 * y as f32).fract();
+                (52.982_918 * t).fract() - 0.5
+            }
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use crate::dct::ZIGZAG;
+use crate::options::{
+    BlockShuffle, CoefficientEffect, CompressOptions, FrequencyFilter, FrequencyMeasure,
+    PlaneSource,
+};
+use crate::sampling::clamp_index;
+
+/// Largest number of quantization steps a glitched coefficient moves by.
+const GLITCH_MAX_OFFSET: u32 = 16;
+
+/// Deterministic 32-bit hash of `values` under `seed` (a chained
+/// SplitMix-style finalizer); the only randomness the effects use, so equal
+/// seeds always give equal output.
+pub(crate) fn hash(seed: u32, values: &[u32]) -> u32 {
+    let mut h = seed ^ 0x9E37_79B9;
+    for &value in values {
+        h = (h ^ value).wrapping_mul(0x85EB_CA6B);
+        h ^= h >> 13;
+        h = h.wrapping_mul(0xC2B2_AE35);
+        h ^= h >> 16;
+    }
+    h
+}
+
+/// Per-coefficient gains of the configured frequency filter: 1 for passed
+/// frequencies, `frequency_attenuation` for rejected ones. DC always passes.
+pub(crate) fn frequency_gains(options: &CompressOptions) -> [[f32; 8]; 8] {
+    let mut gains = [[1.0; 8]; 8];
+    if options.frequency_filter == FrequencyFilter::None {
+        return gains;
+    }
+    let (min, max) = (options.frequency_min, options.frequency_max);
+    let rejected = options.frequency_attenuation.clamp(0.0, 1.0);
+    for (k, &(u, v)) in ZIGZAG.iter().enumerate().skip(1) {
+        let f = match options.frequency_measure {
+            FrequencyMeasure::Zigzag => k as f32,
+            FrequencyMeasure::Radial => ((u * u + v * v) as f32).sqrt(),
+        };
+        let passed = match options.frequency_filter {
+            FrequencyFilter::None => true,
+            FrequencyFilter::LowPass => f <= max,
+            FrequencyFilter::HighPass => f >= min,
+            FrequencyFilter::BandPass => f >= min && f <= max,
+            FrequencyFilter::BandStop => f < min || f > max,
+        };
+        if !passed {
+            gains[u][v] = rejected;
+        }
+    }
+    gains
+}
+
+/// Seeded corruption of quantized DCT coefficients, like flipping bits in
+/// the entropy-coded data of a JPEG file but confined to a frequency band.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Glitch {
+    /// Fraction (0–1) of the coefficients in the band that are perturbed.
+    amount: f32,
+    /// First and last zigzag index (0–63) of the band.
+    band: (usize, usize),
+    seed: u32,
+}
+
+impl Glitch {
+    pub(crate) fn new(options: &CompressOptions) -> Self {
+        let start = options.glitch_band_start.min(63) as usize;
+        Glitch {
+            amount: options.glitch_amount.clamp(0.0, 1.0),
+            band: (start, (options.glitch_band_end.min(63) as usize).max(start)),
+            seed: options.glitch_seed,
+        }
+    }
+
+    /// Moves the picked levels of the block at (`bx`, `by`) of `plane` by 1 to
+    /// [`GLITCH_MAX_OFFSET`] steps in either direction.
+    ///
+    /// Which coefficients are picked, and by how much they move, depends only
+    /// on the seed and the coefficient's position, never on the image.
+    #[allow(clippy::needless_range_loop)]
+    pub(crate) fn corrupt(&self, levels: &mut [[f32; 8]; 8], plane: u32, bx: usize, by: usize) {
+        if self.amount <= 0.0 {
+            return;
+        }
+        // Hashes are uniform in 0..2^32, so this picks `amount` of them.
+        let threshold = self.amount as f64 * 4_294_967_296.0;
+        for k in self.band.0..=self.band.1 {
+            let h = hash(self.seed, &[plane, bx as u32, by as u32, k as u32]);
+            if h as f64 >= threshold {
+                continue;
+            }
+            let r = hash(h, &[k as u32]);
+            let offset = (r % GLITCH_MAX_OFFSET + 1) as f32;
+            let sign = if r >> 31 == 1 { -1.0 } else { 1.0 };
+            let (u, v) = ZIGZAG[k];
+            levels[u][v] += sign * offset;
+        }
+    }
+}
+
+/// Seeded sign flips or swaps of quantized AC coefficients. The DC and the
+/// coefficient magnitudes are kept, so the image structure survives under
+/// ringing in the wrong phase or orientation.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Scramble {
+    effect: CoefficientEffect,
+    /// Fraction (0–1) of the AC coefficients that are picked.
+    amount: f32,
+    seed: u32,
+}
+
+impl Scramble {
+    pub(crate) fn new(options: &CompressOptions) -> Self {
+        Scramble {
+            effect: options.coefficient_effect,
+            amount: options.coefficient_effect_amount.clamp(0.0, 1.0),
+            seed: options.coefficient_effect_seed,
+        }
+    }
+
+    /// Applies the effect to the picked levels of the block at (`bx`, `by`)
+    /// of `plane`; like [`Glitch::corrupt`], the picks depend only on the seed
+    /// and the coefficient's position.
+    #[allow(clippy::needless_range_loop)]
+    pub(crate) fn apply(&self, levels: &mut [[f32; 8]; 8], plane: u32, bx: usize, by: usize) {
+        if self.effect == CoefficientEffect::None || self.amount <= 0.0 {
+            return;
+        }
+        let threshold = self.amount as f64 * 4_294_967_296.0;
+        let picked = |u: usize, v: usize| {
+            let index = (u * 8 + v) as u32;
+            (hash(self.seed, &[plane, bx as u32, by as u32, index]) as f64) < threshold
+        };
+        for u in 0..8 {
+            for v in 0..8 {
+                match self.effect {
+                    CoefficientEffect::FlipSigns if (u, v) != (0, 0) && picked(u, v) => {
+                        levels[u][v] = -levels[u][v];
+                    }
+                    // Each pair is visited once, from its upper triangle.
+                    CoefficientEffect::Swap if u < v && picked(u, v) => {
+                        (levels[u][v], levels[v][u]) = (levels[v][u], levels[u][v]);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Seeded displacement of whole 8x8 blocks within a plane, like the block
+/// smearing of a datamoshed video.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Displacement {
+    shuffle: BlockShuffle,
+    /// Fraction (0–1) of the blocks that are moved.
+    amount: f32,
+    /// Largest horizontal and vertical distance, in blocks, a block moves.
+    radius: usize,
+    seed: u32,
+}
+
+impl Displacement {
+    pub(crate) fn new(options: &CompressOptions) -> Self {
+        Displacement {
+            shuffle: options.block_shuffle,
+            amount: options.block_shuffle_amount.clamp(0.0, 1.0),
+            radius: options.block_shuffle_radius as usize,
+            seed: options.block_shuffle_seed,
+        }
+    }
+
+    /// Whether [`Displacement::apply`] moves any block at all.
+    pub(crate) fn moves_blocks(&self) -> bool {
+        self.shuffle != BlockShuffle::None && self.amount > 0.0 && self.radius > 0
+    }
+
+    /// Moves the picked blocks of `plane`, stored in raster order `blocks_x`
+    /// to a row, to a seeded position at most `radius` blocks away.
+    pub(crate) fn apply<T: Copy>(&self, blocks: &mut [T], blocks_x: usize, plane: u32) {
+        if !self.moves_blocks() {
+            return;
+        }
+        let blocks_y = blocks.len() / blocks_x;
+        let threshold = self.amount as f64 * 4_294_967_296.0;
+        // In 64 bits, where twice any `u32` radius fits.
+        let (radius, span) = (self.radius as i64, 2 * self.radius as u64 + 1);
+        // Displaced blocks copy from the untouched plane, so moves don't chain.
+        let original = blocks.to_vec();
+        for i in 0..blocks.len() {
+            let h = hash(self.seed, &[plane, i as u32]);
+            if h as f64 >= threshold {
+                continue;
+            }
+            let r = hash(h, &[plane]) as u64;
+            let dx = (r % span) as i64 - radius;
+            let dy = (r / span % span) as i64 - radius;
+            let x = (i % blocks_x) as i64 + dx;
+            let y = (i / blocks_x) as i64 + dy;
+            let x = x.clamp(0, blocks_x as i64 - 1) as usize;
+            let y = y.clamp(0, blocks_y as i64 - 1) as usize;
+            let j = y * blocks_x + x;
+            match self.shuffle {
+                BlockShuffle::Swap => blocks.swap(i, j),
+                _ => blocks[i] = original[j],
+            }
+        }
+    }
+}
+
+/// Moves a `w`x`h` plane right by `dx` and down by `dy` samples, repeating
+/// the edge into the uncovered side.
+pub(crate) fn shift_plane(plane: &[f32], w: usize, h: usize, dx: i32, dy: i32) -> Vec<f32> {
+    if dx == 0 && dy == 0 {
+        return plane.to_vec();
+    }
+    (0..w * h)
+        .map(|i| {
+            let x = clamp_index((i % w) as isize - dx as isize, w);
+            let y = clamp_index((i / w) as isize - dy as isize, h);
+            plane[y * w + x]
+        })
+        .collect()
+}
+
+/// Reassigns the full-resolution Y, Cb and Cr planes to the slots named by
+/// `y_source`, `cb_source` and `cr_source`. A `Zero` slot gets 0 for luma and
+/// the neutral 128 for chroma.
+pub(crate) fn remap_planes(planes: [Vec<f32>; 3], options: &CompressOptions) -> [Vec<f32>; 3] {
+    let sources = [options.y_source, options.cb_source, options.cr_source];
+    if sources == [PlaneSource::Y, PlaneSource::Cb, PlaneSource::Cr] {
+        return planes;
+    }
+    let n = planes[0].len();
+    std::array::from_fn(|slot| match sources[slot] {
+        PlaneSource::Y => planes[0].clone(),
+        PlaneSource::Cb => planes[1].clone(),
+        PlaneSource::Cr => planes[2].clone(),
+        PlaneSource::Zero => vec![if slot == 0 { 0.0 } else { 128.0 }; n],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displacement_keeps_blocks_in_the_grid_for_any_radius() {
+        for radius in [1, u32::MAX / 2 + 1, u32::MAX] {
+            let options = CompressOptions {
+                block_shuffle: BlockShuffle::Swap,
+                block_shuffle_amount: 1.0,
+                block_shuffle_radius: radius,
+                ..Default::default()
+            };
+            let mut blocks: Vec<usize> = (0..35).collect();
+            Displacement::new(&options).apply(&mut blocks, 7, 0);
+            blocks.sort_unstable();
+            assert_eq!(blocks, (0..35).collect::<Vec<_>>());
+        }
+    }
+}
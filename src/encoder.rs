@@ -0,0 +1,631 @@
+//! Baseline (sequential, Huffman-coded) JFIF writer.
+
+use std::sync::OnceLock;
+
+use crate::dct::{self, ZIGZAG};
+use crate::error::Error;
+use crate::pipeline::Planes;
+use crate::quant::Quantizer;
+use crate::stego;
+
+/// Standard Huffman tables from ITU-T T.81 Annex K.3: code counts per length
+/// (1–16 bits) followed by the symbols in code order.
+struct HuffmanSpec {
+    bits: [u8; 16],
+    values: &'static [u8],
+}
+
+const DC_LUMA: HuffmanSpec = HuffmanSpec {
+    bits: [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+    values: &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+};
+
+const DC_CHROMA: HuffmanSpec = HuffmanSpec {
+    bits: [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0],
+    values: &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+};
+
+const AC_LUMA: HuffmanSpec = HuffmanSpec {
+    bits: [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d],
+    values: &[
+        0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61,
+        0x07, 0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52,
+        0xd1, 0xf0, 0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25,
+        0x26, 0x27, 0x28, 0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45,
+        0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64,
+        0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83,
+        0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99,
+        0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6,
+        0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3,
+        0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8,
+        0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa,
+    ],
+};
+
+const AC_CHROMA: HuffmanSpec = HuffmanSpec {
+    bits: [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77],
+    values: &[
+        0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61,
+        0x71, 0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33,
+        0x52, 0xf0, 0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18,
+        0x19, 0x1a, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44,
+        0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63,
+        0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a,
+        0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97,
+        0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4,
+        0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca,
+        0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7,
+        0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa,
+    ],
+};
+
+/// Code and length for every symbol of a Huffman table (Annex C).
+struct HuffmanCodes([(u16, u8); 256]);
+
+impl HuffmanCodes {
+    fn new(spec: &HuffmanSpec) -> HuffmanCodes {
+        let mut codes = [(0, 0); 256];
+        let mut code = 0u16;
+        let mut k = 0;
+        for (len, &count) in spec.bits.iter().enumerate() {
+            for _ in 0..count {
+                codes[spec.values[k] as usize] = (code, len as u8 + 1);
+                code += 1;
+                k += 1;
+            }
+            code <<= 1;
+        }
+        HuffmanCodes(codes)
+    }
+}
+
+/// Destination of entropy-coded bits.
+trait BitSink {
+    /// Appends the low `len` bits of `bits`, most significant first.
+    fn write(&mut self, bits: u32, len: u32);
+}
+
+/// Counts entropy-coded bits without producing them.
+struct BitCounter(u64);
+
+impl BitSink for BitCounter {
+    fn write(&mut self, _bits: u32, len: u32) {
+        self.0 += len as u64;
+    }
+}
+
+/// Accumulates entropy-coded bits, stuffing a zero byte after every `0xFF`.
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitSink for BitWriter {
+    fn write(&mut self, bits: u32, len: u32) {
+        if len == 0 {
+            return;
+        }
+        self.buffer = (self.buffer << len) | (bits & ((1 << len) - 1));
+        self.count += len;
+        while self.count >= 8 {
+            let byte = (self.buffer >> (self.count - 8)) as u8;
+            self.out.push(byte);
+            if byte == 0xFF {
+                self.out.push(0);
+            }
+            self.count -= 8;
+        }
+        self.buffer &= (1 << self.count) - 1;
+    }
+}
+
+impl BitWriter {
+    /// Pads the final byte with one bits, as the standard requires.
+    fn flush(&mut self) {
+        let pad = (8 - self.count % 8) % 8;
+        self.write((1 << pad) - 1, pad);
+    }
+}
+
+/// One color component: its quantized blocks in raster order and coding setup.
+struct Component<'a> {
+    id: u8,
+    /// Levels of the block rows from `first_row` on (all of them, unless the
+    /// component is written a few rows at a time).
+    levels: Vec<[[i32; 8]; 8]>,
+    first_row: usize,
+    blocks_x: usize,
+    blocks_y: usize,
+    /// Horizontal and vertical sampling factors.
+    h: usize,
+    v: usize,
+    table: u8,
+    dc: &'a HuffmanCodes,
+    ac: &'a HuffmanCodes,
+}
+
+/// The standard Huffman tables in the order components refer to them:
+/// luma DC, luma AC, chroma DC, chroma AC.
+fn standard_tables() -> &'static [HuffmanCodes; 4] {
+    static TABLES: OnceLock<[HuffmanCodes; 4]> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        [
+            HuffmanCodes::new(&DC_LUMA),
+            HuffmanCodes::new(&AC_LUMA),
+            HuffmanCodes::new(&DC_CHROMA),
+            HuffmanCodes::new(&AC_CHROMA),
+        ]
+    })
+}
+
+/// Writes a baseline JFIF file from planes and their forward DCT
+/// (see [`Planes::forward`]), quantized with `q`.
+///
+/// The planes must hold full-range BT.601 YCbCr. Grayscale planes (no chroma)
+/// produce a single-component file.
+pub(crate) fn encode(
+    planes: &Planes,
+    coefficients: &[Vec<[[f32; 8]; 8]>; 3],
+    q: &Quantizer,
+) -> Vec<u8> {
+    let tables = standard_tables();
+    let components = components(planes, coefficients, q, tables);
+    write_file(planes.width, planes.height, &components, q)
+}
+
+/// Like [`encode`], with `payload` hidden in the quantized coefficients
+/// (see [`stego::embed`]).
+pub(crate) fn encode_with_payload(
+    planes: &Planes,
+    coefficients: &[Vec<[[f32; 8]; 8]>; 3],
+    q: &Quantizer,
+    payload: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let tables = standard_tables();
+    let mut components = components(planes, coefficients, q, tables);
+    stego::embed(components.iter_mut().flat_map(|c| c.levels.iter_mut()), payload)?;
+    Ok(write_file(planes.width, planes.height, &components, q))
+}
+
+/// Largest payload in bytes [`encode_with_payload`] can hide with these settings.
+pub(crate) fn payload_capacity(
+    planes: &Planes,
+    coefficients: &[Vec<[[f32; 8]; 8]>; 3],
+    q: &Quantizer,
+) -> usize {
+    let tables = standard_tables();
+    let components = components(planes, coefficients, q, tables);
+    stego::capacity(components.iter().flat_map(|c| c.levels.iter()))
+}
+
+/// Quantized levels of every component [`encode`] would write, in file
+/// order, each with its blocks in raster order.
+pub(crate) fn levels(
+    planes: &Planes,
+    coefficients: &[Vec<[[f32; 8]; 8]>; 3],
+    q: &Quantizer,
+) -> Vec<Vec<[[i32; 8]; 8]>> {
+    let tables = standard_tables();
+    let components = components(planes, coefficients, q, tables);
+    components.into_iter().map(|c| c.levels).collect()
+}
+
+fn write_file(w: usize, h: usize, components: &[Component], q: &Quantizer) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_headers(&mut out, w, h, components, q);
+    let mut writer = BitWriter {
+        out,
+        buffer: 0,
+        count: 0,
+    };
+    write_scan(&mut writer, w, h, components);
+    writer.flush();
+    let mut out = writer.out;
+    out.extend_from_slice(&[0xFF, 0xD9]);
+    out
+}
+
+/// Size in bytes of the file [`encode`] would write, computed from the Huffman
+/// code lengths of the quantized, zigzagged coefficients without emitting any
+/// bits. Only the zero bytes stuffed after `0xFF` in the scan are not counted,
+/// typically well under 1% of the file.
+pub(crate) fn encoded_size(
+    planes: &Planes,
+    coefficients: &[Vec<[[f32; 8]; 8]>; 3],
+    q: &Quantizer,
+) -> usize {
+    let tables = standard_tables();
+    let components = components(planes, coefficients, q, tables);
+    let (w, h) = (planes.width, planes.height);
+
+    let mut headers = Vec::new();
+    write_headers(&mut headers, w, h, &components, q);
+    let mut counter = BitCounter(0);
+    write_scan(&mut counter, w, h, &components);
+    headers.len() + counter.0.div_ceil(8) as usize + 2
+}
+
+/// Writes the file [`encode`] would, a few MCU rows at a time, so it can be
+/// handed on in pieces without ever being held whole.
+///
+/// Only the planes are kept: each MCU row is transformed and quantized when
+/// it is written, and its levels dropped after. A block shuffle moves levels
+/// across the whole plane, so with one the planes are quantized up front.
+pub(crate) struct ChunkedEncoder {
+    components: Vec<Component<'static>>,
+    /// The planes and a quantizer per component, while rows are quantized
+    /// as they are written.
+    rows: Option<(Planes, Vec<RowQuantizer>)>,
+    width: usize,
+    height: usize,
+    next_row: usize,
+    prev_dc: Vec<i32>,
+    writer: BitWriter,
+    done: bool,
+}
+
+impl ChunkedEncoder {
+    pub(crate) fn new(planes: Planes, q: &Quantizer) -> ChunkedEncoder {
+        let tables = standard_tables();
+        let (w, h) = (planes.width, planes.height);
+        let (components, rows) = if q.displaces() {
+            (components(&planes, &planes.forward(), q, tables), None)
+        } else {
+            let components = layout(&planes, tables);
+            let quantizers = components
+                .iter()
+                .map(|c| RowQuantizer::new(&plane_quantizer(q, c), c.blocks_x, c.blocks_y))
+                .collect();
+            (components, Some((planes, quantizers)))
+        };
+        let mut out = Vec::new();
+        write_headers(&mut out, w, h, &components, q);
+        ChunkedEncoder {
+            prev_dc: vec![0; components.len()],
+            components,
+            rows,
+            width: w,
+            height: h,
+            next_row: 0,
+            writer: BitWriter {
+                out,
+                buffer: 0,
+                count: 0,
+            },
+            done: false,
+        }
+    }
+
+    /// The next piece of the file, at least `min_len` bytes unless it is the
+    /// last one, or `None` once the whole file has been returned.
+    pub(crate) fn next_chunk(&mut self, min_len: usize) -> Option<Vec<u8>> {
+        if self.done {
+            return None;
+        }
+        let (w, h) = (self.width, self.height);
+        let mcus_y = mcu_grid(w, h, &self.components).1;
+        while self.writer.out.len() < min_len.max(1) && self.next_row < mcus_y {
+            let my = self.next_row;
+            if let Some((planes, quantizers)) = &mut self.rows {
+                quantize_mcu_row(&mut self.components, planes, quantizers, my);
+            }
+            let rows = my..my + 1;
+            write_scan_rows(&mut self.writer, w, h, &self.components, rows, &mut self.prev_dc);
+            self.next_row += 1;
+        }
+        if self.next_row == mcus_y {
+            self.writer.flush();
+            self.writer.out.extend_from_slice(&[0xFF, 0xD9]);
+            self.components.iter_mut().for_each(|c| c.levels = Vec::new());
+            self.rows = None;
+            self.done = true;
+        }
+        Some(std::mem::take(&mut self.writer.out))
+    }
+}
+
+/// Replaces the levels of every component with those of the block rows MCU
+/// row `my` covers, transformed and quantized from the planes.
+fn quantize_mcu_row(
+    components: &mut [Component],
+    planes: &Planes,
+    quantizers: &mut [RowQuantizer],
+    my: usize,
+) {
+    let single = components.len() == 1;
+    for (c_i, (c, quantizer)) in components.iter_mut().zip(quantizers).enumerate() {
+        let (plane, pw, ph) = match (c_i, &planes.chroma) {
+            (0, _) => (&planes.y, planes.width, planes.height),
+            (1, Some(chroma)) => (&chroma.cb, chroma.width, chroma.height),
+            (_, Some(chroma)) => (&chroma.cr, chroma.width, chroma.height),
+            (_, None) => unreachable!("chroma components need chroma planes"),
+        };
+        let cv = if single || c_i > 0 { 1 } else { c.v };
+        c.first_row = my * cv;
+        c.levels = (c.first_row..(c.first_row + cv).min(c.blocks_y))
+            .flat_map(|by| {
+                let samples = &plane[by * 8 * pw..((by + 1) * 8).min(ph) * pw];
+                let rows = samples.len() / pw;
+                quantizer.row(&dct::forward_blocks(samples, pw, rows), by)
+            })
+            .collect();
+    }
+}
+
+/// The components of a JFIF file for `planes`, without levels.
+fn layout<'a>(planes: &Planes, tables: &'a [HuffmanCodes; 4]) -> Vec<Component<'a>> {
+    let (w, h) = (planes.width, planes.height);
+    let (fx, fy) = planes.chroma.as_ref().map_or((1, 1), |c| (c.fx, c.fy));
+
+    let mut components = vec![Component {
+        id: 1,
+        levels: Vec::new(),
+        first_row: 0,
+        blocks_x: w.div_ceil(8),
+        blocks_y: h.div_ceil(8),
+        h: fx,
+        v: fy,
+        table: 0,
+        dc: &tables[0],
+        ac: &tables[1],
+    }];
+    if let Some(c) = &planes.chroma {
+        for id in [2, 3] {
+            components.push(Component {
+                id,
+                levels: Vec::new(),
+                first_row: 0,
+                blocks_x: c.width.div_ceil(8),
+                blocks_y: c.height.div_ceil(8),
+                h: 1,
+                v: 1,
+                table: 1,
+                dc: &tables[2],
+                ac: &tables[3],
+            });
+        }
+    }
+    components
+}
+
+/// Quantizes the planes into the components of a JFIF file.
+fn components<'a>(
+    planes: &Planes,
+    coefficients: &[Vec<[[f32; 8]; 8]>; 3],
+    q: &Quantizer,
+    tables: &'a [HuffmanCodes; 4],
+) -> Vec<Component<'a>> {
+    let mut components = layout(planes, tables);
+    for (c, blocks) in components.iter_mut().zip(coefficients) {
+        c.levels = quantize_levels(blocks, c.blocks_x, &plane_quantizer(q, c));
+    }
+    components
+}
+
+/// The quantizer of component `c`.
+fn plane_quantizer(q: &Quantizer, c: &Component) -> Quantizer {
+    q.plane(c.id as u32 - 1)
+}
+
+/// Quantization levels of every block, with the JPEG level shift applied to DC
+/// and the quantizer's dither, error diffusion and block shuffle honored.
+fn quantize_levels(blocks: &[[[f32; 8]; 8]], blocks_x: usize, q: &Quantizer) -> Vec<[[i32; 8]; 8]> {
+    let mut rows = RowQuantizer::new(q, blocks_x, blocks.len() / blocks_x);
+    let mut levels: Vec<[[i32; 8]; 8]> = blocks
+        .chunks(blocks_x)
+        .enumerate()
+        .flat_map(|(by, row)| rows.row(row, by))
+        .collect();
+    q.displace(&mut levels, blocks_x);
+    levels
+}
+
+/// Quantizes the blocks of a plane one block row at a time, in order, for
+/// [`quantize_levels`] and [`ChunkedEncoder`]. Only the DC error diffused
+/// into the next row is carried over.
+struct RowQuantizer {
+    q: Quantizer,
+    blocks_x: usize,
+    /// Diffused DC error of the current block row, then of the next.
+    dc_error: Vec<f32>,
+}
+
+impl RowQuantizer {
+    fn new(q: &Quantizer, blocks_x: usize, blocks_y: usize) -> RowQuantizer {
+        RowQuantizer {
+            q: q.on_grid(blocks_x, blocks_y),
+            blocks_x,
+            dc_error: vec![0.0; 2 * blocks_x],
+        }
+    }
+
+    /// Levels of block row `by` from its forward DCT `blocks`, without the
+    /// block shuffle.
+    fn row(&mut self, blocks: &[[[f32; 8]; 8]], by: usize) -> Vec<[[i32; 8]; 8]> {
+        let (q, dc_error, blocks_x) = (&self.q, &mut self.dc_error, self.blocks_x);
+        let levels = blocks
+            .iter()
+            .enumerate()
+            .map(|(bx, &block)| {
+                let mut coeffs = block;
+                // Samples are 0–255; JPEG codes them shifted to -128–127.
+                coeffs[0][0] -= 1024.0;
+                if q.error_diffusion {
+                    coeffs[0][0] += dc_error[bx];
+                }
+                let quantized = q.quantize(coeffs, bx, by);
+                if q.error_diffusion {
+                    let error = coeffs[0][0] - quantized[0][0];
+                    dct::diffuse_dc_error(dc_error, blocks_x, bx, 0, error);
+                }
+                let mut levels = [[0; 8]; 8];
+                for u in 0..8 {
+                    for v in 0..8 {
+                        levels[u][v] = (quantized[u][v] / q.table[u][v] as f32).round() as i32;
+                    }
+                }
+                // Keep magnitudes within the Huffman tables' categories (11 bits DC, 10 AC).
+                levels[0][0] = levels[0][0].clamp(-1024, 1023);
+                for level in levels.iter_mut().flatten().skip(1) {
+                    *level = (*level).clamp(-1023, 1023);
+                }
+                levels
+            })
+            .collect();
+        dc_error.copy_within(blocks_x.., 0);
+        dc_error[blocks_x..].fill(0.0);
+        levels
+    }
+}
+
+fn write_segment(out: &mut Vec<u8>, marker: u8, body: &[u8]) {
+    out.extend_from_slice(&[0xFF, marker]);
+    out.extend_from_slice(&(body.len() as u16 + 2).to_be_bytes());
+    out.extend_from_slice(body);
+}
+
+fn write_headers(out: &mut Vec<u8>, w: usize, h: usize, components: &[Component], q: &Quantizer) {
+    out.extend_from_slice(&[0xFF, 0xD8]);
+    // JFIF 1.01, no density information, no thumbnail.
+    write_segment(out, 0xE0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+
+    // The codec quantizes every plane with the same table.
+    let mut dqt = vec![0];
+    dqt.extend(ZIGZAG.iter().map(|&(u, v)| q.table[u][v] as u8));
+    write_segment(out, 0xDB, &dqt);
+
+    let mut sof = vec![8];
+    sof.extend_from_slice(&(h as u16).to_be_bytes());
+    sof.extend_from_slice(&(w as u16).to_be_bytes());
+    sof.push(components.len() as u8);
+    for c in components {
+        sof.extend_from_slice(&[c.id, (c.h << 4 | c.v) as u8, 0]);
+    }
+    write_segment(out, 0xC0, &sof);
+
+    let specs: &[(u8, &HuffmanSpec)] = if components.len() == 1 {
+        &[(0x00, &DC_LUMA), (0x10, &AC_LUMA)]
+    } else {
+        &[
+            (0x00, &DC_LUMA),
+            (0x10, &AC_LUMA),
+            (0x01, &DC_CHROMA),
+            (0x11, &AC_CHROMA),
+        ]
+    };
+    let mut dht = Vec::new();
+    for (class_id, spec) in specs {
+        dht.push(*class_id);
+        dht.extend_from_slice(&spec.bits);
+        dht.extend_from_slice(spec.values);
+    }
+    write_segment(out, 0xC4, &dht);
+
+    let mut sos = vec![components.len() as u8];
+    for c in components {
+        sos.extend_from_slice(&[c.id, c.table << 4 | c.table]);
+    }
+    sos.extend_from_slice(&[0, 63, 0]);
+    write_segment(out, 0xDA, &sos);
+}
+
+/// Writes the interleaved scan: each MCU holds `h`x`v` blocks of every component.
+///
+/// Blocks the MCU grid needs beyond a component's real blocks are coded as
+/// flat repeats of the previous DC, which decoders discard anyway.
+fn write_scan(sink: &mut impl BitSink, w: usize, h: usize, components: &[Component]) {
+    let mcus_y = mcu_grid(w, h, components).1;
+    let mut prev_dc = vec![0; components.len()];
+    write_scan_rows(sink, w, h, components, 0..mcus_y, &mut prev_dc);
+}
+
+/// Number of MCUs across and down the scan [`write_scan`] writes.
+fn mcu_grid(w: usize, h: usize, components: &[Component]) -> (usize, usize) {
+    if components.len() == 1 {
+        (components[0].blocks_x, components[0].blocks_y)
+    } else {
+        let (h_max, v_max) = (components[0].h, components[0].v);
+        (w.div_ceil(8 * h_max), h.div_ceil(8 * v_max))
+    }
+}
+
+/// Writes the MCU rows `rows` of the scan, carrying the DC predictions of
+/// every component in `prev_dc` from one call to the next.
+fn write_scan_rows(
+    sink: &mut impl BitSink,
+    w: usize,
+    h: usize,
+    components: &[Component],
+    rows: std::ops::Range<usize>,
+    prev_dc: &mut [i32],
+) {
+    let mcus_x = mcu_grid(w, h, components).0;
+    let (h_block, v_block) = if components.len() == 1 {
+        (1, 1)
+    } else {
+        (components[0].h, components[0].v)
+    };
+
+    for my in rows {
+        for mx in 0..mcus_x {
+            for (c_i, c) in components.iter().enumerate() {
+                let (ch, cv) = if c_i == 0 { (h_block, v_block) } else { (1, 1) };
+                for v in 0..cv {
+                    for u in 0..ch {
+                        let (bx, by) = (mx * ch + u, my * cv + v);
+                        let levels = if bx < c.blocks_x && by < c.blocks_y {
+                            c.levels[(by - c.first_row) * c.blocks_x + bx]
+                        } else {
+                            let mut dummy = [[0; 8]; 8];
+                            dummy[0][0] = prev_dc[c_i];
+                            dummy
+                        };
+                        write_block(sink, &levels, prev_dc[c_i], c);
+                        prev_dc[c_i] = levels[0][0];
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn write_block(writer: &mut impl BitSink, levels: &[[i32; 8]; 8], prev_dc: i32, c: &Component) {
+    let diff = levels[0][0] - prev_dc;
+    let size = dct::category(diff);
+    let (code, len) = c.dc.0[size as usize];
+    writer.write(code as u32, len as u32);
+    writer.write(magnitude_bits(diff, size), size);
+
+    let mut run = 0;
+    for &(u, v) in &ZIGZAG[1..] {
+        let level = levels[u][v];
+        if level == 0 {
+            run += 1;
+            continue;
+        }
+        while run >= 16 {
+            let (code, len) = c.ac.0[0xF0];
+            writer.write(code as u32, len as u32);
+            run -= 16;
+        }
+        let size = dct::category(level);
+        let (code, len) = c.ac.0[(run << 4 | size) as usize];
+        writer.write(code as u32, len as u32);
+        writer.write(magnitude_bits(level, size), size);
+        run = 0;
+    }
+    if run > 0 {
+        let (code, len) = c.ac.0[0x00];
+        writer.write(code as u32, len as u32);
+    }
+}
+
+/// Magnitude bits of a coefficient: the value itself, or its one's complement when negative.
+fn magnitude_bits(value: i32, size: u32) -> u32 {
+    if value < 0 {
+        (value - 1) as u32 & ((1 << size) - 1)
+    } else {
+        value as u32
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+
+use wasm_bindgen::JsValue;
+
+/// Errors reported by the compression pipeline.
+///
+/// On the JavaScript side these surface as the string returned by [`Error::code`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Width or height is zero, or too large for the output format.
+    InvalidDimensions,
+    /// The pixel buffer length doesn't match the declared dimensions.
+    BufferMismatch,
+    /// Two images that must be compared pixel for pixel have different sizes.
+    DimensionMismatch,
+    /// The payload doesn't fit in the coefficients available to hide it in.
+    PayloadTooLarge,
+    /// The data is not a baseline JPEG file this crate can read.
+    InvalidJpeg,
+    /// The JPEG file carries no payload written by `encode_jpeg_with_payload`.
+    NoPayload,
+    /// The data is not a coefficient dump written by `export_coefficients`,
+    /// or its planes don't fit its dimensions.
+    InvalidCoefficients,
+    /// The data is too short for the ThumbHash its header describes.
+    InvalidThumbHash,
+    /// A fetched URL answered with an error status.
+    FetchFailed,
+}
+
+impl Error {
+    /// Machine-readable code, e.g. `"BUFFER_MISMATCH"`.
+    pub fn code(self) -> &'static str {
+        match self {
+            Error::InvalidDimensions => "INVALID_DIMENSIONS",
+            Error::BufferMismatch => "BUFFER_MISMATCH",
+            Error::DimensionMismatch => "DIMENSION_MISMATCH",
+            Error::PayloadTooLarge => "PAYLOAD_TOO_LARGE",
+            Error::InvalidJpeg => "INVALID_JPEG",
+            Error::NoPayload => "NO_PAYLOAD",
+            Error::InvalidCoefficients => "INVALID_COEFFICIENTS",
+            Error::InvalidThumbHash => "INVALID_THUMBHASH",
+            Error::FetchFailed => "FETCH_FAILED",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for JsValue {
+    fn from(err: Error) -> JsValue {
+        JsValue::from_str(err.code())
+    }
+}
@@ -0,0 +1,65 @@
+//! The orientation tag of the Exif block in a JPEG file.
+//!
+//! Only the first IFD of the APP1 `Exif` segment is read, as far as tag
+//! 0x0112; everything else in the file is skipped.
+
+use crate::error::Error;
+use crate::options::Orientation;
+
+/// Tag number of the orientation in the TIFF structure of an Exif block.
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// The transform the Exif orientation tag of a JPEG file asks for to show
+/// the stored pixels upright; `Orientation::None` when the file has no tag or
+/// an unreadable one.
+pub(crate) fn orientation(jpeg: &[u8]) -> Result<Orientation, Error> {
+    if jpeg.get(..2) != Some(&[0xFF, 0xD8]) {
+        return Err(Error::InvalidJpeg);
+    }
+    let mut pos = 2;
+    // Exif must come before the image data, so stop at the first scan.
+    while let Some(&[0xFF, marker, hi, lo]) = jpeg.get(pos..pos + 4) {
+        if matches!(marker, 0xD9 | 0xDA) {
+            break;
+        }
+        let len = u16::from_be_bytes([hi, lo]) as usize;
+        let body = jpeg.get(pos + 4..pos + 2 + len).ok_or(Error::InvalidJpeg)?;
+        if let Some(tiff) = body.strip_prefix(b"Exif\0\0").filter(|_| marker == 0xE1) {
+            return Ok(read_orientation(tiff).unwrap_or(Orientation::None));
+        }
+        pos += 2 + len;
+    }
+    Ok(Orientation::None)
+}
+
+/// Reads the orientation entry of the first IFD of a TIFF structure.
+fn read_orientation(tiff: &[u8]) -> Option<Orientation> {
+    let big_endian = match tiff.get(..4)? {
+        b"II*\0" => false,
+        b"MM\0*" => true,
+        _ => return None,
+    };
+    let u16_at = |at: usize| {
+        let bytes = [*tiff.get(at)?, *tiff.get(at + 1)?];
+        Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    };
+    let u32_at = |at: usize| {
+        let (hi, lo) = (u16_at(at)? as u32, u16_at(at + 2)? as u32);
+        Some(if big_endian { hi << 16 | lo } else { lo << 16 | hi })
+    };
+    let ifd = u32_at(4)? as usize;
+    let value = (0..u16_at(ifd)? as usize)
+        .map(|i| ifd + 2 + 12 * i)
+        .find(|&entry| u16_at(entry) == Some(ORIENTATION_TAG))
+        .and_then(|entry| u16_at(entry + 8))?;
+    Some(match value {
+        2 => Orientation::FlipHorizontal,
+        3 => Orientation::Rotate180,
+        4 => Orientation::FlipVertical,
+        5 => Orientation::Transpose,
+        6 => Orientation::Rotate90,
+        7 => Orientation::Transverse,
+        8 => Orientation::Rotate270,
+        _ => Orientation::None,
+    })
+}
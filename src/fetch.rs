@@ -0,0 +1,100 @@
+//! Glue for images that arrive over the network, as `Response` bodies.
+
+use js_sys::{Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::ImageData as BrowserImageData;
+
+use crate::error::Error;
+use crate::options::{CompressOptions, PixelFormat};
+use crate::pipeline;
+use crate::web;
+
+/// Image types `compress_response` leaves alone: vector images, and GIFs,
+/// which may be animated.
+const PASSTHROUGH_TYPES: [&str; 2] = ["image/svg+xml", "image/gif"];
+
+/// Decodes the image in `blob` with `createImageBitmap`, resolving to its
+/// RGBA ImageData.
+pub(crate) fn decode_blob(blob: &JsValue) -> Result<JsValue, JsValue> {
+    let bitmap = web::call(&js_sys::global(), "createImageBitmap", &[blob])?;
+    web::then(&bitmap, |bitmap| {
+        let size = |name: &str| Reflect::get(&bitmap, &name.into());
+        let (width, height) = (size("width")?, size("height")?);
+        let canvas = web::construct("OffscreenCanvas", &[&width, &height])?;
+        let context = web::call(&canvas, "getContext", &[&"2d".into()])?;
+        let origin = JsValue::from(0);
+        web::call(&context, "drawImage", &[&bitmap, &origin, &origin])?;
+        web::call(&bitmap, "close", &[])?;
+        web::call(&context, "getImageData", &[&origin, &origin, &width, &height])
+    })
+}
+
+/// A promise of `response` with its image re-encoded as a baseline JPEG
+/// with `options`, or of `response` itself when it isn't a complete image
+/// (status 200 and an `image/*` type other than [`PASSTHROUGH_TYPES`]).
+/// Images the browser can't decode also come back unchanged.
+pub(crate) fn compress_response(
+    response: JsValue,
+    options: CompressOptions,
+) -> Result<Promise, JsValue> {
+    let status = Reflect::get(&response, &"status".into())?;
+    let headers = Reflect::get(&response, &"headers".into())?;
+    let content_type = web::call(&headers, "get", &[&"content-type".into()])?;
+    let content_type = content_type.as_string().unwrap_or_default().to_ascii_lowercase();
+    let is_image = content_type.starts_with("image/")
+        && !PASSTHROUGH_TYPES.iter().any(|t| content_type.starts_with(t));
+    if status.as_f64() != Some(200.0) || !is_image {
+        return Ok(Promise::resolve(&response));
+    }
+
+    // Read a copy, so the original is still whole if decoding fails.
+    let copy = web::call(&response, "clone", &[])?;
+    let blob = web::call(&copy, "blob", &[])?;
+    let image = web::then(&blob, |blob| decode_blob(&blob))?;
+    let original = response.clone();
+    let compressed = web::then(&image, move |image| {
+        let image: BrowserImageData = image.dyn_into()?;
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let data = image.data();
+        let jpeg = pipeline::encode_jpeg(&data, width, height, PixelFormat::Rgba, &options)?;
+
+        let headers = web::construct("Headers", &[&headers])?;
+        web::call(&headers, "set", &[&"content-type".into(), &"image/jpeg".into()])?;
+        web::call(&headers, "delete", &[&"content-length".into()])?;
+        let status_text = Reflect::get(&original, &"statusText".into())?;
+        let init = web::object(&[
+            ("status", &status),
+            ("statusText", &status_text),
+            ("headers", &headers),
+        ])?;
+        web::construct("Response", &[&Uint8Array::from(&jpeg[..]), &init])
+    })?;
+    let fallback = Closure::once_into_js(move |_: JsValue| response);
+    Ok(web::call(&compressed, "catch", &[&fallback])?.unchecked_into())
+}
+
+/// A promise of the image at `url`, fetched, decoded and compressed with
+/// `options` like `compress_jpeg_with_options`.
+pub(crate) fn compress_url(url: &str, options: CompressOptions) -> Result<Promise, JsValue> {
+    let response = web::call(&js_sys::global(), "fetch", &[&url.into()])?;
+    let blob = web::then(&response, |response| {
+        if Reflect::get(&response, &"ok".into())?.as_bool() != Some(true) {
+            return Err(Error::FetchFailed.into());
+        }
+        web::call(&response, "blob", &[])
+    })?;
+    let image = web::then(&blob, |blob| decode_blob(&blob))?;
+    let compressed = web::then(&image, move |image| {
+        let image: BrowserImageData = image.dyn_into()?;
+        let size = (image.width() as usize, image.height() as usize);
+        let data = image.data();
+        let (data, width, height) = pipeline::transform_input(&data, size, 4, &options)?;
+        let output = pipeline::compress(&data, width, height, PixelFormat::Rgba, &options)?;
+        let color_space = web::color_space(&image);
+        let output =
+            web::new_image_data(&output, width as u32, height as u32, color_space.as_deref())?;
+        Ok(output.into())
+    })?;
+    Ok(compressed.unchecked_into())
+}
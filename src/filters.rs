@@ -0,0 +1,154 @@
+use crate::sampling::{self, Taps};
+
+/// Blurs a `w`x`h` plane with a separable gaussian of standard deviation `sigma`
+/// pixels. Edges are replicated; a non-positive `sigma` returns the plane unchanged.
+pub(crate) fn gaussian_blur(input: &[f32], w: usize, h: usize, sigma: f32) -> Vec<f32> {
+    if sigma <= 0.0 {
+        return input.to_vec();
+    }
+    let horizontal = sampling::apply_taps(input, w, h, &gaussian_taps(w, sigma), true);
+    sampling::apply_taps(&horizontal, w, h, &gaussian_taps(h, sigma), false)
+}
+
+/// Normalized gaussian taps for every sample along an axis of `len` pixels,
+/// truncated at three standard deviations.
+fn gaussian_taps(len: usize, sigma: f32) -> Taps {
+    let radius = (3.0 * sigma).ceil() as isize;
+    let weights: Vec<f32> = (-radius..=radius)
+        .map(|d| libm::expf(-(d * d) as f32 / (2.0 * sigma * sigma)))
+        .collect();
+    let total: f32 = weights.iter().sum();
+
+    (0..len as isize)
+        .map(|i| {
+            (-radius..=radius)
+                .zip(&weights)
+                .map(|(d, &wt)| (sampling::clamp_index(i + d, len), wt / total))
+                .collect()
+        })
+        .collect()
+}
+
+/// libjpeg-style smoothing (cjpeg `-smooth`): every pixel becomes a weighted
+/// average of itself and its eight neighbors. `factor` runs from 0 (off) to 100,
+/// each neighbor contributing `factor / 1024` of the result.
+pub(crate) fn smooth(input: &[f32], w: usize, h: usize, factor: u32) -> Vec<f32> {
+    let factor = factor.min(100);
+    if factor == 0 {
+        return input.to_vec();
+    }
+    let neighbor = factor as f32 / 1024.0;
+    let member = 1.0 - 8.0 * neighbor;
+
+    let mut out = vec![0.0; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let mut neighbors = 0.0;
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if dx != 0 || dy != 0 {
+                        let sy = sampling::clamp_index(y as isize + dy, h);
+                        let sx = sampling::clamp_index(x as isize + dx, w);
+                        neighbors += input[sy * w + sx];
+                    }
+                }
+            }
+            out[y * w + x] = member * input[y * w + x] + neighbor * neighbors;
+        }
+    }
+    out
+}
+
+/// Unsharp mask: adds `amount` times the difference between the plane and a
+/// gaussian blur of `radius` pixels. Differences at or below `threshold` (in
+/// sample levels) are left alone so flat areas and noise aren't amplified.
+pub(crate) fn unsharp_mask(
+    input: &[f32],
+    w: usize,
+    h: usize,
+    amount: f32,
+    radius: f32,
+    threshold: f32,
+) -> Vec<f32> {
+    if amount <= 0.0 || radius <= 0.0 {
+        return input.to_vec();
+    }
+    let blurred = gaussian_blur(input, w, h, radius);
+    input
+        .iter()
+        .zip(&blurred)
+        .map(|(&v, &b)| {
+            let detail = v - b;
+            if detail.abs() > threshold {
+                v + amount * detail
+            } else {
+                v
+            }
+        })
+        .collect()
+}
+
+/// Estimates the standard deviation of additive gaussian noise in a plane
+/// (Immerkær, "Fast Noise Variance Estimation", 1996).
+///
+/// Planes smaller than 3x3 report no noise.
+pub(crate) fn estimate_noise(input: &[f32], w: usize, h: usize) -> f32 {
+    if w < 3 || h < 3 {
+        return 0.0;
+    }
+    const MASK: [[f32; 3]; 3] = [[1.0, -2.0, 1.0], [-2.0, 4.0, -2.0], [1.0, -2.0, 1.0]];
+
+    let mut sum = 0.0f64;
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let mut response = 0.0;
+            for (dy, row) in MASK.iter().enumerate() {
+                for (dx, &m) in row.iter().enumerate() {
+                    response += m * input[(y + dy - 1) * w + x + dx - 1];
+                }
+            }
+            sum += response.abs() as f64;
+        }
+    }
+    let count = ((w - 2) * (h - 2)) as f64;
+    ((std::f64::consts::FRAC_PI_2).sqrt() * sum / (6.0 * count)) as f32
+}
+
+/// Radius of the bilateral filter window (5x5).
+const BILATERAL_RADIUS: isize = 2;
+/// Spatial standard deviation of the bilateral filter, in pixels.
+const BILATERAL_SPATIAL_SIGMA: f32 = 1.5;
+
+/// Edge-preserving bilateral filter: neighbors are weighted by distance and by
+/// how close their value is to the center pixel, with `range_sigma` (in sample
+/// levels) controlling how large a difference still counts as noise.
+pub(crate) fn bilateral(input: &[f32], w: usize, h: usize, range_sigma: f32) -> Vec<f32> {
+    if range_sigma <= 0.0 {
+        return input.to_vec();
+    }
+    let spatial = |d: isize| {
+        libm::expf(-((d * d) as f32) / (2.0 * BILATERAL_SPATIAL_SIGMA * BILATERAL_SPATIAL_SIGMA))
+    };
+    let range_scale = -1.0 / (2.0 * range_sigma * range_sigma);
+
+    let mut out = vec![0.0; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let center = input[y * w + x];
+            let (mut total, mut weight) = (0.0, 0.0);
+            for dy in -BILATERAL_RADIUS..=BILATERAL_RADIUS {
+                let sy = sampling::clamp_index(y as isize + dy, h);
+                for dx in -BILATERAL_RADIUS..=BILATERAL_RADIUS {
+                    let sx = sampling::clamp_index(x as isize + dx, w);
+                    let v = input[sy * w + sx];
+                    let diff = v - center;
+                    let wt = spatial(dx) * spatial(dy) * libm::expf(diff * diff * range_scale);
+                    total += v * wt;
+                    weight += wt;
+                }
+            }
+            out[y * w + x] = total / weight;
+        }
+    }
+    out
+}
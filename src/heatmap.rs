@@ -0,0 +1,221 @@
+use wasm_bindgen::prelude::*;
+
+use crate::metrics;
+
+/// Quantity visualized by `error_heatmap`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeatmapSource {
+    /// Mean absolute RGB error per pixel, normalized to the largest error in the image.
+    AbsoluteError,
+    /// Local loss of structural similarity, `1 - SSIM` (gaussian window).
+    Ssim,
+}
+
+/// One of the planes the block pass works on.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImagePlane {
+    /// Luma.
+    Y,
+    /// Blue-difference chroma.
+    Cb,
+    /// Red-difference chroma.
+    Cr,
+}
+
+/// Cells outlined by `block_grid_overlay`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GridCell {
+    /// The 8x8 blocks the DCT works on.
+    Block,
+    /// Minimum coded units: the blocks covered by one chroma block, 16x16 at
+    /// 4:2:0, 16x8 at 4:2:2 and 8x8 at 4:4:4.
+    Mcu,
+}
+
+/// Color ramp used to paint a heatmap, from low to high values.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colormap {
+    /// Black to white.
+    Grayscale,
+    /// Perceptually uniform dark blue → green → yellow.
+    Viridis,
+    /// Perceptually uniform black → purple → orange → pale yellow.
+    Inferno,
+    /// High-contrast rainbow (improved jet).
+    Turbo,
+}
+
+impl Colormap {
+    /// Maps a value in 0.0–1.0 to an RGB color.
+    pub(crate) fn color(self, t: f32) -> [u8; 3] {
+        let t = t.clamp(0.0, 1.0);
+        let rgb = match self {
+            Colormap::Grayscale => [t; 3],
+            Colormap::Viridis => polynomial(&VIRIDIS, t),
+            Colormap::Inferno => polynomial(&INFERNO, t),
+            Colormap::Turbo => polynomial(&TURBO, t),
+        };
+        rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+}
+
+/// Polynomial fits of the matplotlib colormaps, lowest order first.
+const VIRIDIS: [[f32; 3]; 7] = [
+    [0.277_727_33, 0.005_407_344_5, 0.334_099_8],
+    [0.105_093_04, 1.404_613_5, 1.384_590_2],
+    [-0.330_861_83, 0.214_847_56, 0.095_095_16],
+    [-4.634_230_6, -5.799_101, -19.332_441],
+    [6.228_27, 14.179_934, 56.690_55],
+    [4.776_385, -13.745_146, -65.353_03],
+    [-5.435_456, 4.645_852_6, 26.312_435],
+];
+const INFERNO: [[f32; 3]; 7] = [
+    [0.000_218_940_37, 0.001_651_004_6, -0.019_480_899],
+    [0.106_513_42, 0.563_956_45, 3.932_712_4],
+    [11.602_493, -3.972_854, -15.942_394],
+    [-41.703_995, 17.436_4, 44.354_145],
+    [77.162_94, -33.402_36, -81.807_31],
+    [-71.319_43, 32.626_064, 73.209_52],
+    [25.131_126, -12.242_669, -23.070_325],
+];
+/// Polynomial fit of Google's Turbo colormap.
+const TURBO: [[f32; 3]; 6] = [
+    [0.135_721_38, 0.091_402_61, 0.106_673_3],
+    [4.615_392_6, 2.194_188_4, 12.641_946],
+    [-42.660_324, 4.842_966_6, -60.582_05],
+    [132.131_08, -14.185_033, 110.362_77],
+    [-152.942_4, 4.277_298_5, -89.903_11],
+    [59.286_38, 2.829_566, 27.348_25],
+];
+
+fn polynomial(coeffs: &[[f32; 3]], t: f32) -> [f32; 3] {
+    let mut rgb = [0.0; 3];
+    for c in coeffs.iter().rev() {
+        for i in 0..3 {
+            rgb[i] = rgb[i] * t + c[i];
+        }
+    }
+    rgb
+}
+
+/// Paints the per-pixel difference of two RGBA buffers as an opaque RGBA heatmap.
+pub(crate) fn error_heatmap(
+    original: &[u8],
+    compressed: &[u8],
+    width: usize,
+    height: usize,
+    source: HeatmapSource,
+    colormap: Colormap,
+) -> Vec<u8> {
+    let values: Vec<f32> = match source {
+        HeatmapSource::AbsoluteError => {
+            let errors: Vec<f32> = original
+                .chunks_exact(4)
+                .zip(compressed.chunks_exact(4))
+                .map(|(a, b)| (0..3).map(|c| a[c].abs_diff(b[c]) as f32).sum::<f32>() / 3.0)
+                .collect();
+            normalize(&errors)
+        }
+        HeatmapSource::Ssim => metrics::ssim_pixel_map(original, compressed, width, height)
+            .into_iter()
+            .map(|s| (1.0 - s) as f32)
+            .collect(),
+    };
+
+    paint_unit(&values, colormap)
+}
+
+/// Draws the `cell_w`x`cell_h` grid over an RGBA image `width` pixels wide,
+/// first tinting every 8x8 block with `colormap` by its value in
+/// `block_values` (normalized to the largest, `ceil(width / 8)` blocks per
+/// row) when given. Lines run along the top and left edge of every cell and
+/// are darkened on light pixels and lightened on dark ones, so they show on
+/// any content.
+pub(crate) fn grid_overlay(
+    pixels: &[u8],
+    width: usize,
+    (cell_w, cell_h): (usize, usize),
+    block_values: Option<&[f32]>,
+    colormap: Colormap,
+) -> Vec<u8> {
+    let blocks_x = width.div_ceil(8);
+    let tints = block_values.map(|values| {
+        normalize(values).into_iter().map(|t| colormap.color(t)).collect::<Vec<_>>()
+    });
+    let mut output = pixels.to_vec();
+    for (i, px) in output.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i % width, i / width);
+        if let Some(tints) = &tints {
+            let tint = tints[y / 8 * blocks_x + x / 8];
+            for c in 0..3 {
+                px[c] = ((px[c] as u16 + tint[c] as u16) / 2) as u8;
+            }
+        }
+        if x % cell_w == 0 || y % cell_h == 0 {
+            // BT.601 luma, in thousandths.
+            let luma = 299 * px[0] as u32 + 587 * px[1] as u32 + 114 * px[2] as u32;
+            let line = if luma >= 128_000 { 0 } else { 255 };
+            for c in px.iter_mut().take(3) {
+                *c = ((*c as u16 + line) / 2) as u8;
+            }
+            px[3] = 255;
+        }
+    }
+    output
+}
+
+/// Paints arbitrary non-negative values, normalized to the largest one, as opaque RGBA.
+pub(crate) fn paint(values: &[f32], colormap: Colormap) -> Vec<u8> {
+    paint_unit(&normalize(values), colormap)
+}
+
+/// Largest DCT coefficient magnitude of 8-bit samples: the DC of a block of 255s.
+pub(crate) const MAX_COEFFICIENT: f32 = 8.0 * 255.0;
+
+/// Log-scaled magnitudes (0.0–1.0) of the 8x8 DCT `blocks` of a `w`x`h`
+/// plane, laid out as the blocks are: each block shows as an 8x8 tile with
+/// the DC at its top left and frequencies rising to the right and down.
+pub(crate) fn coefficient_magnitudes(blocks: &[[[f32; 8]; 8]], w: usize, h: usize) -> Vec<f32> {
+    let blocks_x = w.div_ceil(8);
+    let scale = 1.0 / libm::log1pf(MAX_COEFFICIENT);
+    (0..w * h)
+        .map(|i| {
+            let (x, y) = (i % w, i / w);
+            let block = &blocks[y / 8 * blocks_x + x / 8];
+            (libm::log1pf(block[y % 8][x % 8].abs()) * scale).min(1.0)
+        })
+        .collect()
+}
+
+/// Largest quantization step: the standard table's 121 at the strongest
+/// table scale, 21.
+const MAX_STEP: f32 = 121.0 * 21.0;
+
+/// Log-scaled steps (0.0–1.0) of a quantization table in row-major order, on
+/// a fixed scale from 1 to [`MAX_STEP`].
+pub(crate) fn table_steps(table: &[[u32; 8]; 8]) -> Vec<f32> {
+    let scale = 1.0 / libm::logf(MAX_STEP);
+    table.iter().flatten().map(|&q| (libm::logf(q as f32) * scale).clamp(0.0, 1.0)).collect()
+}
+
+/// Paints values in 0.0–1.0 as opaque RGBA.
+pub(crate) fn paint_unit(values: &[f32], colormap: Colormap) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|&t| {
+            let [r, g, b] = colormap.color(t);
+            [r, g, b, 255]
+        })
+        .collect()
+}
+
+/// Scales values so the largest becomes 1.0 (all zeros stay zero).
+fn normalize(values: &[f32]) -> Vec<f32> {
+    let peak = values.iter().fold(0.0f32, |m, &v| m.max(v));
+    let scale = if peak > 0.0 { 1.0 / peak } else { 0.0 };
+    values.iter().map(|v| v * scale).collect()
+}
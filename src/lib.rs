@@ -1,6 +1,78 @@
 use wasm_bindgen::prelude::*;
 use web_sys::ImageData as BrowserImageData;
-use std::f32::consts::PI;
+
+mod analysis;
+mod artifacts;
+mod cache;
+#[cfg(feature = "cli")]
+pub mod cli;
+mod coefficients;
+mod color;
+#[cfg(feature = "component")]
+mod component;
+mod dct;
+mod decoder;
+mod delta_e;
+mod dither;
+mod effects;
+mod encoder;
+mod error;
+mod estimate;
+mod exif;
+mod fetch;
+mod filters;
+mod heatmap;
+mod media;
+mod metrics;
+mod options;
+mod perceptual;
+mod pipeline;
+mod placeholder;
+mod posterize;
+mod postfilter;
+#[cfg(feature = "python")]
+mod python;
+mod quant;
+mod regions;
+mod result;
+mod sampling;
+mod search;
+mod stego;
+mod stream;
+#[cfg(feature = "threads")]
+mod threads;
+mod transfer;
+mod web;
+mod worker;
+
+pub use analysis::{
+    BlockComplexity, BlockMetric, CoefficientHistogram, StageAnalysis, StageQuality,
+};
+pub use artifacts::{ArtifactScore, GridDetection};
+pub use cache::{CompressionCache, IndexedDbStore};
+pub use delta_e::DeltaE;
+pub use error::Error;
+pub use estimate::SizeEstimator;
+pub use heatmap::{Colormap, GridCell, HeatmapSource, ImagePlane};
+pub use media::CompressedTrack;
+pub use metrics::{ChannelDifference, Psnr, SsimWindow};
+pub use options::{
+    AlphaMode, BlockShuffle, BlockSize, ChromaDownsampling, ChromaSiting, ChromaSubsampling,
+    ChromaUpsampling, CoefficientEffect, ColorMatrix, ColorRange, ColorTransform, CompressOptions,
+    CompressionScale, Degradation, Denoise, Dither, FrequencyFilter, FrequencyMeasure, Orientation,
+    PixelFormat, PlaneSource, Posterize, QualityGradient, ResizeFilter, ToneMapping,
+    TransferFunction, YuvLayout,
+};
+pub use perceptual::PerceptualDistance;
+pub use placeholder::ImageColors;
+pub use regions::QualityRegion;
+pub use result::{
+    ChromaStages, CompressionResult, CompressionStages, CompressionStats, EncodedJpeg,
+    ImageComparison, Tile,
+};
+pub use search::QualityMetric;
+pub use stream::{CompressedFrame, FrameCompressor};
+pub use worker::{CompressorRequest, CompressorResponse, CompressorWorkerApi};
 
 /// Compress an ImageData using a simplified JPEG-style pipeline.
 ///
@@ -16,181 +88,1991 @@ use std::f32::consts::PI;
 pub fn compress_jpeg(
     image_data: BrowserImageData,
     compression: f32,
+) -> Result<BrowserImageData, JsValue> {
+    let options = CompressOptions {
+        compression,
+        ..CompressOptions::default()
+    };
+    compress_jpeg_with_options(image_data, &options)
+}
+
+/// Compress an ImageData with full control over the pipeline.
+///
+/// The result carries the same `colorSpace` as the input (e.g. `"display-p3"`).
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to compress.
+/// - `options`: A `CompressOptions` instance (see its fields for defaults).
+///
+/// **Returns:**
+/// A new `ImageData` object containing the visually compressed pixels, the
+/// size of the `crop_*` region when one is set and turned by `orientation`.
+#[wasm_bindgen]
+pub fn compress_jpeg_with_options(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
 ) -> Result<BrowserImageData, JsValue> {
     let width = image_data.width() as usize;
     let height = image_data.height() as usize;
-    
+
     if width == 0 || height == 0 {
-        return Err(JsValue::from_str("INVALID_DIMENSIONS"));
+        return Err(Error::InvalidDimensions.into());
     }
 
-    let c_factor = compression.clamp(0.0, 1.0);
-
-    if c_factor <= 0.0 {
+    let whole = options.crop_width == 0 || options.crop_height == 0;
+    if options.compression <= 0.0 && whole && options.orientation == Orientation::None {
         return Ok(image_data);
     }
 
-    let data_vec = image_data.data();
-    if data_vec.len() != width * height * 4 {
-        return Err(JsValue::from_str("BUFFER_MISMATCH"));
-    }
+    let data = image_data.data();
+    let (data, width, height) = pipeline::transform_input(&data, (width, height), 4, options)?;
+    let output = pipeline::compress(&data, width, height, PixelFormat::Rgba, options)?;
 
-    let mut y_matrix = vec![0.0; width * height];
-    let mut cb_matrix = vec![0.0; width * height];
-    let mut cr_matrix = vec![0.0; width * height];
+    // Keep the input's color space tag (e.g. "display-p3") so wide-gamut
+    // canvases don't come back interpreted as sRGB.
+    let color_space = web::color_space(&image_data);
+    web::new_image_data(&output, width as u32, height as u32, color_space.as_deref())
+}
 
-    for y in 0..height {
-        for x in 0..width {
-            let i = (y * width + x) * 4;
-            let r = *data_vec.get(i).unwrap_or(&0) as f32;
-            let g = *data_vec.get(i + 1).unwrap_or(&0) as f32;
-            let b = *data_vec.get(i + 2).unwrap_or(&0) as f32;
+/// Fetch an image, decode it and compress it, all in one call.
+///
+/// The image is decoded with `createImageBitmap`, so any format the browser
+/// displays works; cross-origin URLs need CORS headers.
+///
+/// **Parameters:**
+/// - `url`: The address of the image.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A `Promise` of the compressed `ImageData`, as `compress_jpeg_with_options`
+/// returns. It rejects with `"FETCH_FAILED"` when the server answers with an
+/// error status.
+#[wasm_bindgen]
+pub fn compress_url(url: &str, options: &CompressOptions) -> Result<js_sys::Promise, JsValue> {
+    fetch::compress_url(url, options.clone())
+}
 
-            let m_i = y * width + x;
-            y_matrix[m_i] = 0.299 * r + 0.587 * g + 0.114 * b;
-            cb_matrix[m_i] = -0.168736 * r - 0.331264 * g + 0.5 * b + 128.0;
-            cr_matrix[m_i] = 0.5 * r - 0.418688 * g - 0.081312 * b + 128.0;
-        }
+/// Compress an ImageData and return it together with optional measurements.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to compress.
+/// - `options`: A `CompressOptions` instance; `measure_ssim`,
+///   `measure_blurhash` and `measure_colors` enable the SSIM, BlurHash and
+///   color fields.
+///
+/// **Returns:**
+/// A `CompressionResult` holding the compressed `ImageData` and the requested metrics.
+#[wasm_bindgen]
+pub fn compress_jpeg_with_result(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+) -> Result<CompressionResult, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+
+    if width == 0 || height == 0 {
+        return Err(Error::InvalidDimensions.into());
     }
 
-    let sub_w = (width + 1) / 2;
-    let sub_h = (height + 1) / 2;
-    let mut cb_sub = vec![0.0; sub_w * sub_h];
-    let mut cr_sub = vec![0.0; sub_w * sub_h];
+    let data = image_data.data();
+    let (original, width, height) = pipeline::transform_input(&data, (width, height), 4, options)?;
+    let output = if options.compression <= 0.0 {
+        original.to_vec()
+    } else {
+        pipeline::compress(&original, width, height, PixelFormat::Rgba, options)?
+    };
 
-    for y in 0..sub_h {
-        for x in 0..sub_w {
-            let src_y = (y * 2).min(height - 1);
-            let src_x = (x * 2).min(width - 1);
-            let src_idx = src_y * width + src_x;
-            let dst_idx = y * sub_w + x;
-            cb_sub[dst_idx] = cb_matrix[src_idx];
-            cr_sub[dst_idx] = cr_matrix[src_idx];
-        }
+    let color_space = web::color_space(&image_data);
+    let output_image =
+        web::new_image_data(&output, width as u32, height as u32, color_space.as_deref())?;
+    let mut result = CompressionResult::new(output_image, options.compression);
+    if options.measure_ssim {
+        result.ssim = Some(metrics::ssim(&original, &output, width, height, SsimWindow::Gaussian));
     }
-
-    let std_quant: [[u32; 8]; 8] = [
-        [16, 11, 10, 16, 24, 40, 51, 61], [12, 12, 14, 19, 26, 58, 60, 55],
-        [14, 13, 16, 24, 40, 57, 69, 56], [14, 17, 22, 29, 51, 87, 80, 62],
-        [18, 22, 37, 56, 68, 109, 103, 77], [24, 35, 55, 64, 81, 104, 113, 92],
-        [49, 64, 78, 87, 103, 121, 120, 101], [72, 92, 95, 98, 112, 100, 103, 99],
-    ];
-    let scale = 1.0 + c_factor * 20.0;
-    let q_mat: [[u32; 8]; 8] = std_quant.map(|r| r.map(|v| (v as f32 * scale).floor().max(1.0) as u32));
-
-    let y_res = process_blocks(&y_matrix, width, height, &q_mat);
-    let cb_res = process_blocks(&cb_sub, sub_w, sub_h, &q_mat);
-    let cr_res = process_blocks(&cr_sub, sub_w, sub_h, &q_mat);
-
-    let mut output = vec![0u8; width * height * 4];
-    for y in 0..height {
-        for x in 0..width {
-            let sy = (y / 2).min(sub_h - 1);
-            let sx = (x / 2).min(sub_w - 1);
-
-            let y_v = y_res[y * width + x];
-            let cb = cb_res[sy * sub_w + sx] - 128.0;
-            let cr = cr_res[sy * sub_w + sx] - 128.0;
-
-            let r = (y_v + 1.402 * cr).clamp(0.0, 255.0) as u8;
-            let g = (y_v - 0.344136 * cb - 0.714136 * cr).clamp(0.0, 255.0) as u8;
-            let b = (y_v + 1.772 * cb).clamp(0.0, 255.0) as u8;
-
-            let idx = (y * width + x) * 4;
-            output[idx] = r;
-            output[idx + 1] = g;
-            output[idx + 2] = b;
-            output[idx + 3] = 255;
-        }
+    if options.measure_blurhash {
+        result.blurhash = Some(placeholder::blurhash(&original, width, height, 4, 3)?);
+    }
+    if options.measure_colors {
+        result.colors = Some(placeholder::colors(&original, width, height)?);
     }
+    Ok(result)
+}
 
-    BrowserImageData::new_with_u8_clamped_array_and_sh(
-        wasm_bindgen::Clamped(&output),
-        width as u32,
-        height as u32,
-    )
+/// Scale an ImageData to a new size and compress it, in one call.
+///
+/// The image is resampled with `options.resize_filter` (Lanczos3 by default),
+/// which looks the same in every browser, unlike canvas `drawImage` scaling.
+/// Color is weighted by alpha while filtering, so transparent edges stay
+/// clean.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to resize.
+/// - `target_width`, `target_height`: Size of the output in pixels.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A new `ImageData` of the target size with the compressed pixels.
+#[wasm_bindgen]
+pub fn resize_and_compress(
+    image_data: BrowserImageData,
+    target_width: u32,
+    target_height: u32,
+    options: &CompressOptions,
+) -> Result<BrowserImageData, JsValue> {
+    let size = (image_data.width() as usize, image_data.height() as usize);
+    let target = (target_width as usize, target_height as usize);
+    let data = image_data.data();
+    let output = pipeline::resize_and_compress(&data, size, target, PixelFormat::Rgba, options)?;
+    let color_space = web::color_space(&image_data);
+    web::new_image_data(&output, target_width, target_height, color_space.as_deref())
 }
 
-fn process_blocks(input: &[f32], w: usize, h: usize, q: &[[u32; 8]; 8]) -> Vec<f32> {
-    let mut out = vec![0.0; w * h];
-    for by in (0..h).step_by(8) {
-        for bx in (0..w).step_by(8) {
-            let mut block = [[0.0; 8]; 8];
-            for u in 0..8 {
-                for v in 0..8 {
-                    let py = by + u;
-                    let px = bx + v;
-                    if py < h && px < w {
-                        block[u][v] = input[py * w + px];
-                    } else {
-                        let py_edge = py.min(h - 1);
-                        let px_edge = px.min(w - 1);
-                        block[u][v] = input[py_edge * w + px_edge];
-                    }
-                }
-            }
-
-            let processed = idct2d(quantize(dct2d(block), q));
-
-            for u in 0..8 {
-                for v in 0..8 {
-                    let py = by + u;
-                    let px = bx + v;
-                    if py < h && px < w {
-                        out[py * w + px] = processed[u][v];
-                    }
-                }
-            }
-        }
+/// Pick a compression level that should look close to the original for this image.
+///
+/// Measures luma noise, the share of sharp-edged and flat blocks, and chroma
+/// activity: noisy photos hide artifacts and get stronger settings, while
+/// screenshots, text, smooth gradients and busy subsampled chroma get milder
+/// ones. The result stays within 0.01–0.2 on the `Linear` scale and is
+/// expressed in `options.compression_scale`.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to analyze.
+/// - `options`: A `CompressOptions` instance; `compression` is ignored.
+///
+/// **Returns:**
+/// The chosen `compression` value.
+#[wasm_bindgen]
+pub fn auto_compression(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+) -> Result<f32, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    Ok(analysis::auto_compression(&image_data.data(), width, height, options)?)
+}
+
+/// Compress an ImageData at the level `auto_compression` picks for its content.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to compress.
+/// - `options`: A `CompressOptions` instance; `compression` is ignored.
+///
+/// **Returns:**
+/// A `CompressionResult` whose `compression` is the chosen value; `measure_ssim`
+/// fills in `ssim` as in `compress_jpeg_with_result`.
+#[wasm_bindgen]
+pub fn compress_auto(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+) -> Result<CompressionResult, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let compression = analysis::auto_compression(&image_data.data(), width, height, options)?;
+    let options = CompressOptions {
+        compression,
+        ..options.clone()
+    };
+    compress_jpeg_with_result(image_data, &options)
+}
+
+/// Save an ImageData as JPEG `generations` times in a row, as repeated
+/// re-uploads do, and return the last generation.
+///
+/// Each generation is compressed from the 8-bit result of the previous one.
+/// `jitter` varies the compression per generation by up to that much and
+/// moves the 8x8 grid by a few pixels, like light edits or crops between
+/// saves; the variation is the same on every call.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to compress.
+/// - `options`: A `CompressOptions` instance used for every generation.
+/// - `generations`: The number of times to compress.
+/// - `jitter`: From 0.0 (identical saves) to 1.0.
+///
+/// **Returns:**
+/// A new `ImageData` with the final generation.
+#[wasm_bindgen]
+pub fn simulate_generations(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    generations: u32,
+    jitter: f32,
+) -> Result<BrowserImageData, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let frame = pipeline::Frame::from_pixels(&image_data.data(), width, height, PixelFormat::Rgba)?;
+    if options.compression <= 0.0 {
+        return Ok(image_data);
     }
-    out
+    let output = pipeline::simulate_generations(frame, options, generations, jitter, |_| {});
+
+    let color_space = web::color_space(&image_data);
+    let pixels = output.to_pixels(PixelFormat::Rgba);
+    web::new_image_data(&pixels, width as u32, height as u32, color_space.as_deref())
 }
 
-fn quantize(mut dct: [[f32; 8]; 8], q: &[[u32; 8]; 8]) -> [[f32; 8]; 8] {
-    for u in 0..8 {
-        for v in 0..8 {
-            dct[u][v] = (dct[u][v] / q[u][v] as f32).round() * q[u][v] as f32;
-        }
+/// Like `simulate_generations`, but return every generation.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to compress.
+/// - `options`: A `CompressOptions` instance used for every generation.
+/// - `generations`: The number of times to compress.
+/// - `jitter`: From 0.0 (identical saves) to 1.0.
+///
+/// **Returns:**
+/// An array of `generations` ImageData objects, first generation first.
+#[wasm_bindgen]
+pub fn simulate_generation_frames(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    generations: u32,
+    jitter: f32,
+) -> Result<js_sys::Array, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let original = image_data.data();
+    let frame = pipeline::Frame::from_pixels(&original, width, height, PixelFormat::Rgba)?;
+    let mut outputs = Vec::new();
+    if options.compression <= 0.0 {
+        outputs = vec![original.to_vec(); generations as usize];
+    } else {
+        pipeline::simulate_generations(frame, options, generations, jitter, |generation| {
+            outputs.push(generation.to_pixels(PixelFormat::Rgba));
+        });
     }
-    dct
-}
-
-fn dct2d(block: [[f32; 8]; 8]) -> [[f32; 8]; 8] {
-    let mut dct = [[0.0; 8]; 8];
-    for u in 0..8 {
-        for v in 0..8 {
-            let mut sum = 0.0;
-            for x in 0..8 {
-                for y in 0..8 {
-                    sum += block[x][y]
-                        * ((2 * x + 1) as f32 * u as f32 * PI / 16.0).cos()
-                        * ((2 * y + 1) as f32 * v as f32 * PI / 16.0).cos();
-                }
-            }
-            let cu = if u == 0 { 1.0 / 2.0_f32.sqrt() } else { 1.0 };
-            let cv = if v == 0 { 1.0 / 2.0_f32.sqrt() } else { 1.0 };
-            dct[u][v] = 0.25 * cu * cv * sum;
-        }
+
+    let color_space = web::color_space(&image_data);
+    let frames = js_sys::Array::new();
+    for pixels in &outputs {
+        let (w, h) = (width as u32, height as u32);
+        let image = web::new_image_data(pixels, w, h, color_space.as_deref())?;
+        frames.push(&image);
     }
-    dct
-}
-
-fn idct2d(dct: [[f32; 8]; 8]) -> [[f32; 8]; 8] {
-    let mut block = [[0.0; 8]; 8];
-    for x in 0..8 {
-        for y in 0..8 {
-            let mut sum = 0.0;
-            for u in 0..8 {
-                for v in 0..8 {
-                    let cu = if u == 0 { 1.0 / 2.0_f32.sqrt() } else { 1.0 };
-                    let cv = if v == 0 { 1.0 / 2.0_f32.sqrt() } else { 1.0 };
-                    sum += cu * cv * dct[u][v]
-                        * ((2 * x + 1) as f32 * u as f32 * PI / 16.0).cos()
-                        * ((2 * y + 1) as f32 * v as f32 * PI / 16.0).cos();
-                }
-            }
-            block[x][y] = 0.25 * sum;
-        }
+    Ok(frames)
+}
+
+/// Compress an ImageData at a sequence of evenly spaced compression levels,
+/// e.g. for a "watch it degrade" animation or a comparison scrubber.
+///
+/// The color conversion and forward DCT are computed once and shared by every
+/// frame, which makes this much faster than separate calls.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to compress.
+/// - `options`: A `CompressOptions` instance for every other setting.
+/// - `from`: The `compression` of the first frame.
+/// - `to`: The `compression` of the last frame; above `from` for falling quality.
+/// - `frames`: The number of frames, at least 1 (a single frame uses `from`).
+///
+/// **Returns:**
+/// An array of `frames` ImageData objects in sweep order.
+#[wasm_bindgen]
+pub fn compress_sweep(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    from: f32,
+    to: f32,
+    frames: u32,
+) -> Result<js_sys::Array, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let frame = pipeline::Frame::from_pixels(&image_data.data(), width, height, PixelFormat::Rgba)?;
+    let steps = frames.max(1);
+    let compressions: Vec<f32> = (0..steps)
+        .map(|i| from + (to - from) * i as f32 / (steps - 1).max(1) as f32)
+        .collect();
+
+    let color_space = web::color_space(&image_data);
+    let images = js_sys::Array::new();
+    for output in pipeline::compress_sweep(frame, options, &compressions) {
+        let pixels = output.to_pixels(PixelFormat::Rgba);
+        let (w, h) = (width as u32, height as u32);
+        let image = web::new_image_data(&pixels, w, h, color_space.as_deref())?;
+        images.push(&image);
+    }
+    Ok(images)
+}
+
+/// Compress an ImageData and measure the result against the original in the same pass.
+///
+/// PSNR, SSIM, the estimated file size and the block-pass statistics are
+/// computed from the buffers already in WASM memory, saving separate calls and copies.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to compress.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A `CompressionResult` with the compressed `ImageData` and its `psnr` and
+/// `ssim` filled in, and `estimated_bytes` and `stats` too unless
+/// `compression` is 0 or the blocks aren't 8x8.
+#[wasm_bindgen]
+pub fn compress_with_metrics(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+) -> Result<CompressionResult, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let original = image_data.data();
+    let frame = pipeline::Frame::from_pixels(&original, width, height, PixelFormat::Rgba)?;
+
+    let (output, block_stats) = if options.compression <= 0.0 {
+        (original.to_vec(), None)
+    } else {
+        let (frame, stats) = pipeline::compress_frame_with_stats(frame, options);
+        (frame.to_pixels(PixelFormat::Rgba), Some(stats))
+    };
+
+    let color_space = web::color_space(&image_data);
+    let output_image =
+        web::new_image_data(&output, width as u32, height as u32, color_space.as_deref())?;
+    let mut result = CompressionResult::new(output_image, options.compression);
+    result.psnr = Some(metrics::psnr(&original, &output).combined);
+    result.ssim = Some(metrics::ssim(&original, &output, width, height, SsimWindow::Gaussian));
+    // Block sizes other than 8x8 collect no statistics.
+    result.stats = block_stats
+        .filter(|stats| !stats[0].is_empty())
+        .map(|stats| CompressionStats::new(&stats, width, height, options.subsampling));
+    result.estimated_bytes = result.stats.map(|stats| stats.estimated_bytes);
+    Ok(result)
+}
+
+/// Compress as strongly as possible while staying within a quality target.
+///
+/// Bisects `compression` (to 1/256) for the strongest setting whose output meets
+/// `target`; the color conversion and forward DCT are computed once and reused
+/// for every attempt. `options.compression` is ignored.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to compress.
+/// - `options`: A `CompressOptions` instance for every other setting.
+/// - `metric`: `Ssim` (result ≥ `target`, e.g. 0.95) or `Butteraugli` (result ≤ `target`, e.g. 1.5).
+/// - `target`: The quality bound to respect.
+///
+/// **Returns:**
+/// A `CompressionResult` with the chosen `compression` and the achieved `ssim` or
+/// `butteraugli` score. If no setting meets the target, the input is returned
+/// uncompressed with `compression` 0.
+#[wasm_bindgen]
+pub fn compress_to_quality(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    metric: QualityMetric,
+    target: f64,
+) -> Result<CompressionResult, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let outcome =
+        search::search_quality(&image_data.data(), width, height, options, metric, target)?;
+
+    let color_space = web::color_space(&image_data);
+    let output_image = web::new_image_data(
+        &outcome.pixels,
+        width as u32,
+        height as u32,
+        color_space.as_deref(),
+    )?;
+    let mut result = CompressionResult::new(output_image, outcome.compression);
+    match metric {
+        QualityMetric::Ssim => result.ssim = Some(outcome.score),
+        QualityMetric::Butteraugli => result.butteraugli = Some(outcome.score),
     }
-    block
-}
\ No newline at end of file
+    Ok(result)
+}
+
+/// The `canvas.toBlob("image/jpeg", quality)` quality whose luma quantization
+/// table matches a `compression` value.
+///
+/// Chrome and Firefox scale the standard tables with libjpeg's quality formula;
+/// this inverts it. `Linear` and `Perceptual` only reach qualities up to 0.5,
+/// since their mildest lossy setting is the unscaled table (libjpeg quality 50).
+///
+/// **Parameters:**
+/// - `compression`: A value from 0.0–1.0 (0.0 maps to 1.0).
+/// - `scale`: The `CompressionScale` the value is expressed in.
+///
+/// **Returns:**
+/// The equivalent `toBlob` quality from 0.01–1.0.
+#[wasm_bindgen]
+pub fn to_blob_quality(compression: f32, scale: CompressionScale) -> f32 {
+    scale.to_blob_quality(compression)
+}
+
+/// The `compression` value whose luma quantization table matches
+/// `canvas.toBlob("image/jpeg", quality)`.
+///
+/// **Parameters:**
+/// - `quality`: A `toBlob` quality from 0.0–1.0.
+/// - `scale`: The `CompressionScale` to express the result in. Use `Browser` to
+///   cover the whole range; `Linear` and `Perceptual` return 0.0 (no
+///   compression) for qualities of 0.5 and above.
+///
+/// **Returns:**
+/// The equivalent `compression` from 0.0–1.0.
+#[wasm_bindgen]
+pub fn compression_for_to_blob_quality(quality: f32, scale: CompressionScale) -> f32 {
+    scale.compression_for_to_blob_quality(quality)
+}
+
+/// Report the quantization table the block pass uses at
+/// `options.compression`, after scaling and clamping.
+///
+/// Every plane is quantized with the same table. It follows
+/// `compression_scale`; `Degradation.Zigzag` has all steps at 1 and, with a
+/// `quality_gradient`, the table is that of the finest end of the ramp.
+///
+/// **Parameters:**
+/// - `options`: A `CompressOptions` instance.
+/// - `baseline`: Cap the steps at 255, giving the table `encode_jpeg` writes.
+///
+/// **Returns:**
+/// A `Uint32Array` of the 64 steps in row-major order, the DC step first and
+/// horizontal frequencies rising along each row.
+#[wasm_bindgen]
+pub fn quantization_table(options: &CompressOptions, baseline: bool) -> Vec<u32> {
+    quant::effective_table(options, baseline).concat()
+}
+
+/// Renders `quantization_table` as an 8x8 ImageData, one pixel per step.
+///
+/// Steps are log-scaled on a fixed range, from 1 to the largest step any
+/// setting produces, so images for different settings compare directly.
+///
+/// **Parameters:**
+/// - `options`: A `CompressOptions` instance.
+/// - `baseline`: Cap the steps at 255, giving the table `encode_jpeg` writes.
+/// - `colormap`: The color ramp to paint it with.
+///
+/// **Returns:**
+/// An opaque 8x8 `ImageData`.
+#[wasm_bindgen]
+pub fn quantization_table_image(
+    options: &CompressOptions,
+    baseline: bool,
+    colormap: Colormap,
+) -> Result<BrowserImageData, JsValue> {
+    let steps = heatmap::table_steps(&quant::effective_table(options, baseline));
+    web::new_image_data(&heatmap::paint_unit(&steps, colormap), 8, 8, None)
+}
+
+/// Encode an ImageData as a real baseline JPEG file.
+///
+/// Uses the same quantization as the in-place pipeline, except that table
+/// entries are capped at 255 as baseline JPEG requires, so the very strongest
+/// settings come out slightly milder. Colors are always stored as full-range
+/// BT.601 YCbCr; alpha and sharpening are not represented.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to encode.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A `Uint8Array` holding the `.jpg` file.
+#[wasm_bindgen]
+pub fn encode_jpeg(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+) -> Result<Vec<u8>, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let data = image_data.data();
+    Ok(pipeline::encode_jpeg(&data, width, height, PixelFormat::Rgba, options)?)
+}
+
+/// Re-encode the image in a fetch `Response` as a JPEG, e.g. in a service
+/// worker's `fetch` handler to shrink images on slow connections.
+///
+/// The body is decoded with `createImageBitmap` and encoded like
+/// `encode_jpeg`, so transparency is lost. The new response keeps the status
+/// and headers, with `Content-Type` set to `image/jpeg` and `Content-Length`
+/// dropped. Responses that aren't a complete image (status 200 with an
+/// `image/*` type), SVGs, GIFs and images the browser can't decode are
+/// returned unchanged.
+///
+/// **Parameters:**
+/// - `response`: The `Response` to transform; its body is not consumed.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A `Promise` of the new `Response`.
+#[wasm_bindgen]
+pub fn compress_response(
+    response: JsValue,
+    options: &CompressOptions,
+) -> Result<js_sys::Promise, JsValue> {
+    fetch::compress_response(response, options.clone())
+}
+
+/// Encode an ImageData as a baseline JPEG file, handing it to a sink in
+/// pieces instead of returning it whole.
+///
+/// The file is written a few MCU rows at a time, and each piece is only
+/// encoded once the sink has taken the previous one, so large files can go
+/// to OPFS, IndexedDB or the network without ever being held in full. Only
+/// the YCbCr planes are kept; each MCU row is transformed and quantized when
+/// it is written (all at once with a `block_shuffle`, which moves blocks
+/// across the image). The output is byte for byte what `encode_jpeg` returns.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to encode.
+/// - `options`: A `CompressOptions` instance.
+/// - `sink`: A `WritableStream`, which is closed after the last piece, or a
+///   function called with each piece as a `Uint8Array`; when it returns a
+///   promise, the next piece waits for it.
+/// - `chunk_size`: Smallest size in bytes of every piece but the last.
+///
+/// **Returns:**
+/// A `Promise` of the file size in bytes. It rejects when the sink throws or
+/// the stream fails.
+#[wasm_bindgen]
+pub fn encode_jpeg_to_sink(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    sink: JsValue,
+    chunk_size: u32,
+) -> Result<js_sys::Promise, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let data = image_data.data();
+    let encoder = pipeline::encode_jpeg_chunked(&data, width, height, PixelFormat::Rgba, options)?;
+    let written = stream::ChunkWriter::start(encoder, &sink, chunk_size as usize)?;
+    Ok(written.unchecked_into())
+}
+
+/// Encode an ImageData as a baseline JPEG file with a hidden payload.
+///
+/// The payload is written JSteg-style into the least significant bit of the
+/// quantized AC coefficients of magnitude 2 and up, which leaves the file
+/// size and look nearly unchanged. It survives only as long as the file is
+/// kept byte for byte; decoding and re-encoding destroys it.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to encode.
+/// - `options`: A `CompressOptions` instance.
+/// - `payload`: The bytes to hide, at most `payload_capacity` of them.
+///
+/// **Returns:**
+/// A `Uint8Array` holding the `.jpg` file. Throws `"PAYLOAD_TOO_LARGE"` when
+/// the payload doesn't fit.
+#[wasm_bindgen]
+pub fn encode_jpeg_with_payload(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    payload: &[u8],
+) -> Result<Vec<u8>, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let data = image_data.data();
+    Ok(pipeline::encode_jpeg_with_payload(
+        &data,
+        width,
+        height,
+        PixelFormat::Rgba,
+        options,
+        payload,
+    )?)
+}
+
+/// Report how many bytes `encode_jpeg_with_payload` can hide in an image.
+///
+/// Stronger compression leaves fewer coefficients large enough to carry a bit.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to encode.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// The capacity in bytes.
+#[wasm_bindgen]
+pub fn payload_capacity(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+) -> Result<u32, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let data = image_data.data();
+    let capacity = pipeline::payload_capacity(&data, width, height, PixelFormat::Rgba, options)?;
+    Ok(capacity as u32)
+}
+
+/// Read the Exif orientation tag of a JPEG file, such as a photo straight
+/// from a phone camera.
+///
+/// Set the result as `options.orientation` to turn pixels decoded without
+/// the tag upright during compression. Browsers apply the tag themselves
+/// when they draw the file onto a canvas, so pixels from there need nothing.
+///
+/// **Parameters:**
+/// - `jpeg`: The bytes of the `.jpg` file, e.g. from `await file.arrayBuffer()`.
+///
+/// **Returns:**
+/// The `Orientation` that shows the stored pixels upright; `None` without a
+/// tag. Throws `"INVALID_JPEG"` when the data is not a JPEG file.
+#[wasm_bindgen]
+pub fn exif_orientation(jpeg: &[u8]) -> Result<Orientation, JsValue> {
+    Ok(exif::orientation(jpeg)?)
+}
+
+/// Recover the payload hidden by `encode_jpeg_with_payload`.
+///
+/// **Parameters:**
+/// - `jpeg`: The bytes of the `.jpg` file.
+///
+/// **Returns:**
+/// A `Uint8Array` with the payload. Throws `"INVALID_JPEG"` for anything but a
+/// baseline JPEG file and `"NO_PAYLOAD"` when no payload is found.
+#[wasm_bindgen]
+pub fn extract_payload(jpeg: &[u8]) -> Result<Vec<u8>, JsValue> {
+    let components = decoder::read_levels(jpeg)?;
+    Ok(stego::extract(components.iter().flatten())?)
+}
+
+/// Export the quantized DCT coefficients `encode_jpeg` would write, for
+/// analysis outside the library.
+///
+/// The dump is little-endian binary: the magic `"JDCT"`, a version byte (1)
+/// and the plane count (1 or 3) as bytes, the width and height as `u16`, the
+/// chroma subsampling factors as bytes, the quantization table as 64 `u16`
+/// in zigzag order, then per plane (Y, Cb, Cr) its width and height in blocks
+/// as `u16` followed by 64 `i16` levels per block, in zigzag order with the
+/// blocks in raster order. Every 16-bit value is aligned, so the levels can
+/// be viewed as an `Int16Array` without copying.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to encode.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A `Uint8Array` holding the dump.
+#[wasm_bindgen]
+pub fn export_coefficients(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+) -> Result<Vec<u8>, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let data = image_data.data();
+    Ok(pipeline::export_coefficients(&data, width, height, PixelFormat::Rgba, options)?)
+}
+
+/// Reconstruct an image from a coefficient dump, the inverse of
+/// `export_coefficients`.
+///
+/// The levels may have been edited in between; the header and the plane
+/// sizes must still match. The levels are dequantized with the dump's table,
+/// transformed back and the chroma upsampled, as a JPEG decoder would.
+///
+/// **Parameters:**
+/// - `dump`: The bytes written by `export_coefficients`.
+///
+/// **Returns:**
+/// A new opaque `ImageData`. Throws `"INVALID_COEFFICIENTS"` when the dump is
+/// malformed.
+#[wasm_bindgen]
+pub fn import_coefficients(dump: &[u8]) -> Result<BrowserImageData, JsValue> {
+    let frame = pipeline::import_coefficients(dump)?;
+    let pixels = frame.to_pixels(PixelFormat::Rgba);
+    web::new_image_data(&pixels, frame.width as u32, frame.height as u32, None)
+}
+
+/// Decode a baseline JPEG file, such as one from `encode_jpeg`.
+///
+/// Only sequential 8-bit files are read, in grayscale or YCbCr with 4:4:4,
+/// 4:2:2, 4:4:0 or 4:2:0 chroma; progressive and 12-bit files fail. Browsers decode far more,
+/// so prefer `createImageBitmap` where it is available.
+///
+/// **Parameters:**
+/// - `jpeg`: The bytes of the file.
+///
+/// **Returns:**
+/// A new opaque `ImageData`. Throws `"INVALID_JPEG"` when the file can't be
+/// read.
+#[wasm_bindgen]
+pub fn decode_jpeg(jpeg: &[u8]) -> Result<BrowserImageData, JsValue> {
+    let frame = pipeline::decode_jpeg(jpeg)?;
+    let pixels = frame.to_pixels(PixelFormat::Rgba);
+    web::new_image_data(&pixels, frame.width as u32, frame.height as u32, None)
+}
+
+/// Predict the size of the file `encode_jpeg` would write, without encoding it.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to measure.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// The estimated file size in bytes (typically within 1%, never above).
+#[wasm_bindgen]
+pub fn estimate_jpeg_size(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+) -> Result<u32, JsValue> {
+    Ok(size_estimator(image_data, options)?.estimate(options.compression))
+}
+
+/// Create a `SizeEstimator` that predicts `encode_jpeg` sizes for an image at any `compression`.
+///
+/// The expensive part of the pipeline runs once here, so repeated `estimate`
+/// calls are fast enough to update a size readout while a quality slider moves.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to measure.
+/// - `options`: A `CompressOptions` instance; its `compression` is ignored.
+///
+/// **Returns:**
+/// A `SizeEstimator`; call `estimate(compression)` for a size in bytes.
+#[wasm_bindgen]
+pub fn size_estimator(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+) -> Result<SizeEstimator, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let data = image_data.data();
+    Ok(SizeEstimator::new(&data, width, height, PixelFormat::Rgba, options)?)
+}
+
+/// Encode a baseline JPEG file that fits a byte budget, keeping as much quality as possible.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to encode.
+/// - `max_bytes`: Largest acceptable file size.
+/// - `options`: A `CompressOptions` instance; `compression` is searched.
+/// - `search_subsampling`: Also try 4:4:4, 4:2:2 and 4:2:0 and keep whichever looks best
+///   (by SSIM) within the budget.
+///
+/// **Returns:**
+/// An `EncodedJpeg` with the file `bytes` and the `compression`/`subsampling` used.
+/// If no setting fits, the smallest file found is returned; check `size`.
+#[wasm_bindgen]
+pub fn compress_to_size(
+    image_data: BrowserImageData,
+    max_bytes: u32,
+    options: &CompressOptions,
+    search_subsampling: bool,
+) -> Result<EncodedJpeg, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let data = image_data.data();
+    let max_bytes = max_bytes as usize;
+    Ok(search::search_size(&data, width, height, options, max_bytes, search_subsampling)?)
+}
+
+/// Make a low-quality image placeholder (LQIP) for blur-up loading: a tiny,
+/// heavily compressed JPEG to show, scaled up and blurred, until the real
+/// image arrives.
+///
+/// The image is scaled down until its longer side is at most `max_side`
+/// pixels, then encoded like `compress_to_size` with `max_bytes` as the
+/// budget and every chroma subsampling tried.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to preview.
+/// - `max_side`: Longest side of the placeholder in pixels (e.g. 32).
+/// - `max_bytes`: Largest acceptable file size (e.g. 1024).
+/// - `options`: A `CompressOptions` instance; `compression` and `subsampling`
+///   are searched.
+///
+/// **Returns:**
+/// An `EncodedJpeg`; its `data_url` can go straight into an `<img>` `src`.
+/// If no setting fits, the smallest file found is returned; check `size`.
+#[wasm_bindgen]
+pub fn lqip(
+    image_data: BrowserImageData,
+    max_side: u32,
+    max_bytes: u32,
+    options: &CompressOptions,
+) -> Result<EncodedJpeg, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let data = image_data.data();
+    let (max_side, max_bytes) = (max_side as usize, max_bytes as usize);
+    Ok(placeholder::lqip(&data, width, height, options, max_side, max_bytes)?)
+}
+
+/// Encode an ImageData as a BlurHash string, the compact placeholder format
+/// many image CDNs and front-end libraries decode.
+///
+/// The output matches the reference encoder, so any BlurHash decoder can
+/// render it. Alpha is ignored.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to encode. A small copy (e.g. 32-64
+///   pixels wide) gives the same hash for a fraction of the work.
+/// - `x_components`: Horizontal detail, 1–9 (4 is typical).
+/// - `y_components`: Vertical detail, 1–9 (3 is typical).
+///
+/// **Returns:**
+/// The BlurHash, `4 + 2 * x_components * y_components` characters long.
+#[wasm_bindgen]
+pub fn blurhash(
+    image_data: BrowserImageData,
+    x_components: u32,
+    y_components: u32,
+) -> Result<String, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let data = image_data.data();
+    Ok(placeholder::blurhash(&data, width, height, x_components, y_components)?)
+}
+
+/// Encode an ImageData as a ThumbHash, a placeholder of about 25 bytes that
+/// keeps the aspect ratio and transparency.
+///
+/// The output follows the reference encoder, so any ThumbHash decoder can
+/// render it; `thumbhash_to_image` is one.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to encode. Images larger than 100x100
+///   are scaled down first.
+///
+/// **Returns:**
+/// The ThumbHash bytes.
+#[wasm_bindgen]
+pub fn thumbhash(image_data: BrowserImageData) -> Result<Vec<u8>, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    Ok(placeholder::thumbhash(&image_data.data(), width, height)?)
+}
+
+/// Render a ThumbHash, the inverse of `thumbhash`.
+///
+/// **Parameters:**
+/// - `hash`: The ThumbHash bytes.
+///
+/// **Returns:**
+/// A new `ImageData` at most 32 pixels on its longer side, in the image's
+/// approximate aspect ratio; scale it up with CSS. Throws
+/// `"INVALID_THUMBHASH"` when the hash is truncated.
+#[wasm_bindgen]
+pub fn thumbhash_to_image(hash: &[u8]) -> Result<BrowserImageData, JsValue> {
+    let (width, height, pixels) = placeholder::thumbhash_to_rgba(hash)?;
+    web::new_image_data(&pixels, width as u32, height as u32, None)
+}
+
+/// Find the average and dominant colors of an ImageData, for placeholder
+/// backgrounds and theming.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to analyze.
+///
+/// **Returns:**
+/// An `ImageColors` with both colors as `0xRRGGBB` and the share of the image
+/// near the dominant one.
+#[wasm_bindgen]
+pub fn image_colors(image_data: BrowserImageData) -> Result<ImageColors, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    Ok(placeholder::colors(&image_data.data(), width, height)?)
+}
+
+/// Compress an ImageData and return every intermediate stage alongside the
+/// result, for teaching and for seeing where the artifacts come from.
+///
+/// The stages are the Y, Cb and Cr planes after color conversion, the chroma
+/// after subsampling, the luma DCT coefficients before and after
+/// quantization, the image rebuilt from the quantized coefficients and the
+/// final output. The coefficients always show 8x8 blocks, and
+/// `reuse_prior_grid` is not applied.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to compress.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A `CompressionStages` with one ImageData per stage.
+#[wasm_bindgen]
+pub fn compress_debug(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+) -> Result<CompressionStages, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let frame = pipeline::Frame::from_pixels(&image_data.data(), width, height, PixelFormat::Rgba)?;
+    let stages = pipeline::compress_stages(frame, options);
+
+    let color_space = web::color_space(&image_data);
+    let color_space = color_space.as_deref();
+    let image = |pixels: &[u8], w: usize, h: usize| {
+        web::new_image_data(pixels, w as u32, h as u32, color_space)
+    };
+    let gray = |plane: &[f32], w: usize, h: usize| {
+        let values: Vec<f32> = plane.iter().map(|v| v / 255.0).collect();
+        image(&heatmap::paint_unit(&values, Colormap::Grayscale), w, h)
+    };
+    let coefficients = |blocks: &[[[f32; 8]; 8]]| {
+        let values = heatmap::coefficient_magnitudes(blocks, width, height);
+        image(&heatmap::paint_unit(&values, Colormap::Grayscale), width, height)
+    };
+    let (full, subsampled) = (&stages.full, &stages.subsampled);
+    let (cb, cr) = match &full.chroma {
+        Some(c) => (Some(gray(&c.cb, width, height)?), Some(gray(&c.cr, width, height)?)),
+        None => (None, None),
+    };
+    let (cb_subsampled, cr_subsampled) = match &subsampled.chroma {
+        Some(c) => (
+            Some(gray(&c.cb, c.width, c.height)?),
+            Some(gray(&c.cr, c.width, c.height)?),
+        ),
+        None => (None, None),
+    };
+    Ok(CompressionStages {
+        y: gray(&full.y, width, height)?,
+        cb,
+        cr,
+        cb_subsampled,
+        cr_subsampled,
+        coefficients: coefficients(&stages.coefficients)?,
+        quantized_coefficients: coefficients(&stages.quantized)?,
+        reconstruction: image(&stages.reconstruction.to_pixels(PixelFormat::Rgba), width, height)?,
+        output: image(&stages.output.to_pixels(PixelFormat::Rgba), width, height)?,
+    })
+}
+
+/// Show the subsampled chroma planes before and after the block pass, for
+/// tracking down color bleeding.
+///
+/// The planes stay at their subsampled size, so what subsampling and what
+/// quantization did to the color can be told apart before upsampling blurs
+/// them together. For a grayscale image both planes are a neutral gray.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to analyze.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A `ChromaStages` with the Cb and Cr planes painted in grayscale.
+#[wasm_bindgen]
+pub fn chroma_planes(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+) -> Result<ChromaStages, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let frame = pipeline::Frame::from_pixels(&image_data.data(), width, height, PixelFormat::Rgba)?;
+    let (before, after) = pipeline::chroma_stages(frame, options);
+
+    let color_space = web::color_space(&image_data);
+    let gray = |plane: &[f32]| {
+        let values: Vec<f32> = plane.iter().map(|v| v / 255.0).collect();
+        let pixels = heatmap::paint_unit(&values, Colormap::Grayscale);
+        let (w, h) = (before.width as u32, before.height as u32);
+        web::new_image_data(&pixels, w, h, color_space.as_deref())
+    };
+    Ok(ChromaStages {
+        cb: gray(&before.cb)?,
+        cr: gray(&before.cr)?,
+        cb_compressed: gray(&after.cb)?,
+        cr_compressed: gray(&after.cr)?,
+    })
+}
+
+/// Compress an ImageData with the compression varying by region, as painted
+/// into a mask.
+///
+/// Each 8x8 block is compressed at `options.compression` times the brightness
+/// of the mask under it: black keeps a region as sharp as the compression
+/// scale allows, white compresses it fully, grays fall in between. The mask is
+/// stretched over the image, so a small one is enough. `quality_gradient` is
+/// ignored, and so is the mask under `Degradation.Zigzag`.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to compress.
+/// - `options`: A `CompressOptions` instance.
+/// - `mask`: A grayscale ImageData of any size; only its brightness is used.
+///
+/// **Returns:**
+/// A new `ImageData` with the compressed pixels.
+#[wasm_bindgen]
+pub fn compress_with_mask(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    mask: BrowserImageData,
+) -> Result<BrowserImageData, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let frame = pipeline::Frame::from_pixels(&image_data.data(), width, height, PixelFormat::Rgba)?;
+    let (mask_width, mask_height) = (mask.width() as usize, mask.height() as usize);
+    if mask_width == 0 || mask_height == 0 {
+        return Err(Error::InvalidDimensions.into());
+    }
+    let map = regions::CompressionMap::from_mask(
+        &mask.data(),
+        mask_width,
+        mask_height,
+        width,
+        height,
+        options.compression,
+    );
+    let output = pipeline::compress_frame_with_map(frame, options, map);
+
+    let color_space = web::color_space(&image_data);
+    let pixels = output.to_pixels(PixelFormat::Rgba);
+    web::new_image_data(&pixels, width as u32, height as u32, color_space.as_deref())
+}
+
+/// Compress an ImageData with rectangles of their own compression, a simpler
+/// alternative to `compress_with_mask`.
+///
+/// Every 8x8 block a rectangle touches takes the rectangle's compression, so
+/// its content is covered even off the block grid; where rectangles share a
+/// block, the lowest compression wins. The rest of the image uses
+/// `options.compression`. `quality_gradient` is ignored, and so are the
+/// rectangles under `Degradation.Zigzag`.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to compress.
+/// - `options`: A `CompressOptions` instance.
+/// - `regions`: An array of `QualityRegion`s, in pixels.
+///
+/// **Returns:**
+/// A new `ImageData` with the compressed pixels.
+#[wasm_bindgen]
+pub fn compress_with_regions(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    regions: Vec<QualityRegion>,
+) -> Result<BrowserImageData, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let frame = pipeline::Frame::from_pixels(&image_data.data(), width, height, PixelFormat::Rgba)?;
+    let map = regions::CompressionMap::from_regions(&regions, width, height, options.compression);
+    let output = pipeline::compress_frame_with_map(frame, options, map);
+
+    let color_space = web::color_space(&image_data);
+    let pixels = output.to_pixels(PixelFormat::Rgba);
+    web::new_image_data(&pixels, width as u32, height as u32, color_space.as_deref())
+}
+
+/// Compress each sprite of a sprite sheet on its own and return the sheet.
+///
+/// Every rectangle is cut out and compressed separately, on a block grid
+/// starting at its own corner, so no block straddles two sprites and colors
+/// don't bleed across their edges. Pixels outside every sprite are left
+/// untouched; where sprites overlap, the later one wins.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA sprite sheet.
+/// - `options`: A `CompressOptions` instance; `compression` is ignored.
+/// - `sprites`: An array of `QualityRegion`s, in pixels, each with the
+///   compression of its sprite. They are clipped to the sheet; one entirely
+///   outside it throws `"INVALID_DIMENSIONS"`.
+///
+/// **Returns:**
+/// A new `ImageData` the size of the sheet.
+#[wasm_bindgen]
+pub fn compress_sprites(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    sprites: Vec<QualityRegion>,
+) -> Result<BrowserImageData, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let frame = pipeline::Frame::from_pixels(&image_data.data(), width, height, PixelFormat::Rgba)?;
+    let output = pipeline::compress_sprite_sheet(&frame, &sprites, options)?;
+
+    let color_space = web::color_space(&image_data);
+    let pixels = output.to_pixels(PixelFormat::Rgba);
+    web::new_image_data(&pixels, width as u32, height as u32, color_space.as_deref())
+}
+
+/// Like `compress_sprites`, but return every sprite as its own image.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA sprite sheet.
+/// - `options`: A `CompressOptions` instance; `compression` is ignored.
+/// - `sprites`: An array of `QualityRegion`s, as for `compress_sprites`.
+///
+/// **Returns:**
+/// An array of ImageData objects in the order of `sprites`, each the size of
+/// its (clipped) rectangle.
+#[wasm_bindgen]
+pub fn compress_sprites_separately(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    sprites: Vec<QualityRegion>,
+) -> Result<js_sys::Array, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let frame = pipeline::Frame::from_pixels(&image_data.data(), width, height, PixelFormat::Rgba)?;
+
+    let color_space = web::color_space(&image_data);
+    let images = js_sys::Array::new();
+    for (_, _, sprite) in pipeline::compress_sprites(&frame, &sprites, options)? {
+        let pixels = sprite.to_pixels(PixelFormat::Rgba);
+        let (w, h) = (sprite.width as u32, sprite.height as u32);
+        let image = web::new_image_data(&pixels, w, h, color_space.as_deref())?;
+        images.push(&image);
+    }
+    Ok(images)
+}
+
+/// Split an ImageData into square tiles, e.g. 256x256 for a web map, and
+/// compress each on its own with the same settings.
+///
+/// Tiles run in raster order; those at the right and bottom edges are cut
+/// to the image. Each is compressed separately, as if it were its own file.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to split.
+/// - `tile_size`: Width and height of a tile in pixels, at least 1.
+/// - `options`: A `CompressOptions` instance used for every tile.
+///
+/// **Returns:**
+/// An array of `Tile` objects whose `image_data` holds the compressed pixels.
+#[wasm_bindgen]
+pub fn tile_and_compress(
+    image_data: BrowserImageData,
+    tile_size: u32,
+    options: &CompressOptions,
+) -> Result<js_sys::Array, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let frame = pipeline::Frame::from_pixels(&image_data.data(), width, height, PixelFormat::Rgba)?;
+
+    let color_space = web::color_space(&image_data);
+    let tiles = js_sys::Array::new();
+    for (x, y, tile) in pipeline::compress_tiles(&frame, tile_size as usize, options)? {
+        let pixels = tile.to_pixels(PixelFormat::Rgba);
+        let (w, h) = (tile.width as u32, tile.height as u32);
+        let image = web::new_image_data(&pixels, w, h, color_space.as_deref())?;
+        tiles.push(&JsValue::from(Tile {
+            x: x as u32,
+            y: y as u32,
+            column: x as u32 / tile_size,
+            row: y as u32 / tile_size,
+            width: w,
+            height: h,
+            image_data: Some(image),
+            jpeg: None,
+        }));
+    }
+    Ok(tiles)
+}
+
+/// Like `tile_and_compress`, but encode every tile as a baseline JPEG file,
+/// ready to upload as a map tile.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to split.
+/// - `tile_size`: Width and height of a tile in pixels, from 1 to 65535.
+/// - `options`: A `CompressOptions` instance used for every tile.
+///
+/// **Returns:**
+/// An array of `Tile` objects whose `jpeg` holds the file.
+#[wasm_bindgen]
+pub fn tile_and_encode(
+    image_data: BrowserImageData,
+    tile_size: u32,
+    options: &CompressOptions,
+) -> Result<js_sys::Array, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let frame = pipeline::Frame::from_pixels(&image_data.data(), width, height, PixelFormat::Rgba)?;
+
+    let tiles = js_sys::Array::new();
+    for (x, y, jpeg) in pipeline::encode_tiles(&frame, tile_size as usize, options)? {
+        let (x, y) = (x as u32, y as u32);
+        tiles.push(&JsValue::from(Tile {
+            x,
+            y,
+            column: x / tile_size,
+            row: y / tile_size,
+            width: tile_size.min(width as u32 - x),
+            height: tile_size.min(height as u32 - y),
+            image_data: None,
+            jpeg: Some(jpeg),
+        }));
+    }
+    Ok(tiles)
+}
+
+/// Compress every frame of a live video track, e.g. from `getUserMedia` or
+/// `canvas.captureStream()`, into a new track.
+///
+/// Frames pass through `MediaStreamTrackProcessor` and come out of a
+/// `MediaStreamTrackGenerator`, which only Chromium-based browsers provide.
+/// Each frame is compressed on the main thread before the next one is read,
+/// so slow settings lower the frame rate rather than queueing frames.
+///
+/// **Parameters:**
+/// - `track`: A video `MediaStreamTrack`.
+/// - `options`: A `CompressOptions` instance; change it later with
+///   `set_options`.
+///
+/// **Returns:**
+/// A `CompressedTrack` whose `track` carries the compressed frames.
+#[wasm_bindgen]
+pub fn compress_track(
+    track: JsValue,
+    options: &CompressOptions,
+) -> Result<CompressedTrack, JsValue> {
+    CompressedTrack::new(&track, options)
+}
+
+/// Start the thread pool of a `threads` build, which `tile_and_compress` and
+/// `tile_and_encode` then spread their tiles over.
+///
+/// The workers share the module's memory, so the page must be cross-origin
+/// isolated (served with `Cross-Origin-Opener-Policy: same-origin` and
+/// `Cross-Origin-Embedder-Policy: require-corp`). When it isn't, or the
+/// workers fail to start, nothing is thrown and everything keeps running on
+/// the calling thread.
+///
+/// **Parameters:**
+/// - `threads`: The number of workers, e.g. `navigator.hardwareConcurrency`.
+///
+/// **Returns:**
+/// A `Promise` of the number of threads in use: `threads`, or 1 after a
+/// fallback.
+#[cfg(feature = "threads")]
+#[wasm_bindgen]
+pub fn init_thread_pool(threads: u32) -> Result<js_sys::Promise, JsValue> {
+    threads::start_pool(threads as usize)
+}
+
+/// Compress a tightly packed RGB buffer (3 bytes per pixel, no alpha).
+///
+/// **Parameters:**
+/// - `data`: `width * height * 3` bytes of RGB pixels.
+/// - `width`, `height`: Image dimensions in pixels.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A new `Uint8Array` with the compressed pixels in the same RGB layout.
+#[wasm_bindgen]
+pub fn compress_rgb(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    options: &CompressOptions,
+) -> Result<Vec<u8>, JsValue> {
+    compress_pixels(data, width, height, PixelFormat::Rgb, options)
+}
+
+/// Compress an interleaved 8-bit buffer in any supported channel order.
+///
+/// **Parameters:**
+/// - `data`: `width * height` pixels laid out as described by `format`.
+/// - `width`, `height`: Image dimensions in pixels.
+/// - `format`: Channel order of `data` (`Rgba`, `Bgra`, `Argb`, `Rgbx`, `Rgb`).
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A new `Uint8Array` with the compressed pixels in the same layout as `data`,
+/// holding only the `crop_*` region when one is set, turned by `orientation`.
+#[wasm_bindgen]
+pub fn compress_pixels(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    options: &CompressOptions,
+) -> Result<Vec<u8>, JsValue> {
+    let size = (width as usize, height as usize);
+    let (data, width, height) = pipeline::transform_input(data, size, format.channels(), options)?;
+    Ok(pipeline::compress(&data, width, height, format, options)?)
+}
+
+/// Compress raw pixels read from a `ReadableStream` as they arrive, e.g. rows
+/// from a scanner or a screen recorder.
+///
+/// The rows are compressed a band at a time, as soon as each band and a
+/// margin of rows below it are in, so neither the caller nor the module
+/// holds the whole input. The output is what `compress_pixels` returns, with
+/// three exceptions:
+/// - `orientation` turns the compressed rows at the end, so the block grid
+///   starts at the top-left corner of the stream.
+/// - Settings that depend on the whole image (`error_diffusion`,
+///   `block_shuffle`, `Denoise::Auto`, `MedianCut` or dithered posterizing,
+///   `reuse_prior_grid`) wait for every row.
+/// - `detect_grayscale` is decided as the bands arrive: the first band with
+///   color turns it off for the rest, but the gray bands above it were
+///   already compressed without chroma.
+///
+/// **Parameters:**
+/// - `stream`: A `ReadableStream` of `Uint8Array` chunks holding `width *
+///   height` pixels laid out as described by `format`, in row order and
+///   split anywhere.
+/// - `width`, `height`: Image dimensions in pixels.
+/// - `format`: Channel order of the pixels.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A `Promise` of a `Uint8Array` of the compressed pixels. It rejects
+/// with `"BUFFER_MISMATCH"` when the stream holds more or fewer bytes than
+/// the dimensions need, canceling it in the first case.
+#[wasm_bindgen]
+pub fn compress_pixel_stream(
+    stream: JsValue,
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    options: &CompressOptions,
+) -> Result<js_sys::Promise, JsValue> {
+    let size = (width as usize, height as usize);
+    let output = stream::RowReader::start(&stream, size, format, options.clone())?;
+    Ok(output.unchecked_into())
+}
+
+/// Compress planar 4:2:0 YUV (e.g. from `VideoFrame.copyTo`) without an RGB round trip.
+///
+/// The planes are interpreted with `options.color_matrix` and `options.color_range`;
+/// video sources are usually `Bt709` and `Limited`. Chroma is already subsampled, so
+/// `options.subsampling` and `options.chroma_downsampling` are ignored.
+///
+/// **Parameters:**
+/// - `data`: The Y plane followed by the chroma planes in the given `layout`.
+/// - `width`, `height`: Luma dimensions in pixels.
+/// - `layout`: `I420` or `Nv12`.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A new opaque RGBA `ImageData`.
+#[wasm_bindgen]
+pub fn compress_yuv(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    layout: YuvLayout,
+    options: &CompressOptions,
+) -> Result<BrowserImageData, JsValue> {
+    let frame = pipeline::compress_yuv(data, width as usize, height as usize, layout, options)?;
+    let output = frame.to_pixels(PixelFormat::Rgba);
+
+    BrowserImageData::new_with_u8_clamped_array_and_sh(
+        wasm_bindgen::Clamped(&output),
+        width,
+        height,
+    )
+}
+
+/// Compress an ImageData and return the processed planes as I420 instead of RGBA.
+///
+/// The result can be fed straight into a WebCodecs `VideoFrame` (format `"I420"`)
+/// or a YUV shader. Alpha is dropped; chroma stored at another subsampling is
+/// resampled to 4:2:0 so the layout is always I420.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to compress.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A `Uint8Array` with the Y plane followed by the U and V planes.
+#[wasm_bindgen]
+pub fn compress_to_i420(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+) -> Result<Vec<u8>, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let mut frame =
+        pipeline::Frame::from_pixels(&image_data.data(), width, height, PixelFormat::Rgba)?;
+    Ok(pipeline::compress_to_planes(&mut frame, options).to_i420(options))
+}
+
+/// Compress planar 4:2:0 YUV and return the result as I420, never touching RGB.
+///
+/// **Parameters:**
+/// - `data`: The Y plane followed by the chroma planes in the given `layout`.
+/// - `width`, `height`: Luma dimensions in pixels.
+/// - `layout`: `I420` or `Nv12`.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A `Uint8Array` with the Y plane followed by the U and V planes.
+#[wasm_bindgen]
+pub fn compress_yuv_to_i420(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    layout: YuvLayout,
+    options: &CompressOptions,
+) -> Result<Vec<u8>, JsValue> {
+    let (width, height) = (width as usize, height as usize);
+    let planes = pipeline::compress_yuv_planes(data, width, height, layout, options)?;
+    Ok(planes.to_i420(options))
+}
+
+/// Compress 16-bit-per-channel RGBA (RAW decoders, scientific sensors) without
+/// first squeezing it into 8 bits.
+///
+/// The extra precision is carried through the float pipeline. The integer color
+/// transforms (`YCoCgR`, `Rct`) round to 8-bit steps and should be avoided here.
+///
+/// **Parameters:**
+/// - `data`: `width * height * 4` RGBA samples in the range 0–65535.
+/// - `width`, `height`: Image dimensions in pixels.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A new `Uint16Array` with the compressed RGBA samples.
+#[wasm_bindgen]
+pub fn compress_rgba16(
+    data: &[u16],
+    width: u32,
+    height: u32,
+    options: &CompressOptions,
+) -> Result<Vec<u16>, JsValue> {
+    let mut frame = pipeline::Frame::from_rgba16(data, width as usize, height as usize)?;
+    if options.compression <= 0.0 && !pipeline::tone_maps(options) {
+        return Ok(data.to_vec());
+    }
+    frame.tone_map(options);
+    if options.compression <= 0.0 {
+        return Ok(frame.to_rgba16());
+    }
+    Ok(pipeline::compress_frame(frame, options).to_rgba16())
+}
+
+/// Compress 16-bit RGBA and only quantize to 8 bits for the returned `ImageData`.
+///
+/// **Parameters:**
+/// - `data`: `width * height * 4` RGBA samples in the range 0–65535.
+/// - `width`, `height`: Image dimensions in pixels.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A new 8-bit `ImageData` object containing the compressed pixels.
+#[wasm_bindgen]
+pub fn compress_rgba16_to_image_data(
+    data: &[u16],
+    width: u32,
+    height: u32,
+    options: &CompressOptions,
+) -> Result<BrowserImageData, JsValue> {
+    let mut frame = pipeline::Frame::from_rgba16(data, width as usize, height as usize)?;
+    frame.tone_map(options);
+    let output = pipeline::compress_frame(frame, options).to_pixels(PixelFormat::Rgba);
+
+    BrowserImageData::new_with_u8_clamped_array_and_sh(
+        wasm_bindgen::Clamped(&output),
+        width,
+        height,
+    )
+}
+
+/// Compress linear-light float RGBA (HDR canvases, WebGPU readbacks) without an
+/// intermediate 8-bit quantization.
+///
+/// Color is encoded with `options.transfer_function` before the YCbCr step and
+/// decoded again on output, so the result is linear light as well.
+///
+/// **Parameters:**
+/// - `data`: `width * height * 4` RGBA floats, 1.0 = reference white.
+/// - `width`, `height`: Image dimensions in pixels.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A new `Float32Array` with the compressed linear-light RGBA values.
+#[wasm_bindgen]
+pub fn compress_rgba_f32(
+    data: &[f32],
+    width: u32,
+    height: u32,
+    options: &CompressOptions,
+) -> Result<Vec<f32>, JsValue> {
+    let transfer = options.transfer_function;
+    let mut frame =
+        pipeline::Frame::from_rgba_f32(data, width as usize, height as usize, transfer)?;
+    if options.compression <= 0.0 && !pipeline::tone_maps(options) {
+        return Ok(data.to_vec());
+    }
+    frame.tone_map(options);
+    if options.compression <= 0.0 {
+        return Ok(frame.to_rgba_f32(transfer));
+    }
+    Ok(pipeline::compress_frame(frame, options).to_rgba_f32(transfer))
+}
+
+/// Peak signal-to-noise ratio between two images over the RGB channels.
+///
+/// **Parameters:**
+/// - `original`: The reference ImageData.
+/// - `compressed`: The ImageData to measure; must have the same dimensions.
+///
+/// **Returns:**
+/// The PSNR in decibels (`Infinity` for identical images).
+#[wasm_bindgen]
+pub fn psnr(original: BrowserImageData, compressed: BrowserImageData) -> Result<f64, JsValue> {
+    Ok(psnr_channels(original, compressed)?.combined)
+}
+
+/// Peak signal-to-noise ratio between two images, per channel and combined.
+///
+/// **Parameters:**
+/// - `original`: The reference ImageData.
+/// - `compressed`: The ImageData to measure; must have the same dimensions.
+///
+/// **Returns:**
+/// A `Psnr` with the red, green, blue, alpha and combined values in decibels.
+#[wasm_bindgen]
+pub fn psnr_channels(
+    original: BrowserImageData,
+    compressed: BrowserImageData,
+) -> Result<Psnr, JsValue> {
+    let (original, compressed, _, _) = image_pair(&original, &compressed)?;
+    Ok(metrics::psnr(&original, &compressed))
+}
+
+/// Per-8x8-block map of how the encoder distributes quality across the luma plane.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to analyze.
+/// - `options`: A `CompressOptions` instance.
+/// - `metric`: `Error` (mean squared error), `NonZero` (surviving coefficients) or
+///   `Bits` (estimated coded size).
+///
+/// **Returns:**
+/// A `Float32Array` with one value per block in raster order, `ceil(width / 8)`
+/// blocks per row and `ceil(height / 8)` rows.
+#[wasm_bindgen]
+pub fn block_map(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    metric: BlockMetric,
+) -> Result<Vec<f32>, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    Ok(analysis::block_map(&image_data.data(), width, height, options, metric)?)
+}
+
+/// Per-8x8-block content statistics of the luma of an ImageData, measured
+/// before any quantization.
+///
+/// Useful for picking settings automatically, finding text and line art, or
+/// driving rate control outside the library.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to analyze.
+/// - `options`: A `CompressOptions` instance; color conversion and pre-filters
+///   apply, `compression` is ignored.
+/// - `measure`: `AcEnergy`, `Entropy` or `HighFrequency`.
+///
+/// **Returns:**
+/// A `Float32Array` with one value per block in raster order, `ceil(width / 8)`
+/// blocks per row and `ceil(height / 8)` rows.
+#[wasm_bindgen]
+pub fn block_complexity(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    measure: BlockComplexity,
+) -> Result<Vec<f32>, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    Ok(analysis::block_complexity(&image_data.data(), width, height, options, measure)?)
+}
+
+/// Score an ImageData for the blocking and ringing left by earlier JPEG compression.
+///
+/// Clean images score close to 0 on both; a `blockiness` above about 0.5 or a
+/// `ringing` above about 1 means the image has most likely been compressed
+/// before, and compressing it again mostly adds damage.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to analyze.
+///
+/// **Returns:**
+/// An `ArtifactScore` with the `blockiness` and `ringing` measures.
+#[wasm_bindgen]
+pub fn artifact_score(image_data: BrowserImageData) -> Result<ArtifactScore, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    if width == 0 || height == 0 {
+        return Err(Error::InvalidDimensions.into());
+    }
+    Ok(artifacts::artifact_score(&image_data.data(), width, height))
+}
+
+/// Detect an earlier JPEG encode of an ImageData: where its 8x8 grid lies and
+/// what quality it used.
+///
+/// The grid is found even when the image was cropped after saving, which
+/// shifts it by the cropped amount. The quality is recovered from the
+/// quantization steps left in the luma DCT coefficients; very high qualities
+/// (about 0.95 and up) and images resized since leave too little to go on.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to analyze.
+///
+/// **Returns:**
+/// A `GridDetection` with the `confidence` (0–1; below about 0.3 is
+/// inconclusive), the estimated `canvas.toBlob` `quality` when recovered, and
+/// the (`dx`, `dy`) offset of the grid.
+#[wasm_bindgen]
+pub fn detect_grid(image_data: BrowserImageData) -> Result<GridDetection, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    if width == 0 || height == 0 {
+        return Err(Error::InvalidDimensions.into());
+    }
+    Ok(artifacts::detect_grid(&image_data.data(), width, height))
+}
+
+/// Reduce the blocking and ringing of any decoded JPEG image, such as one the
+/// browser loaded from a user's file.
+///
+/// Jumps at the 8x8 block edges that are small enough to be quantization
+/// error are smoothed out, and ripples next to strong edges flattened, with
+/// strengths that follow the quantization at `estimated_quality`. The block
+/// grid is found as in `detect_grid`, so cropped images work too. The alpha
+/// channel is kept.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData decoded from a JPEG file.
+/// - `estimated_quality`: The `canvas.toBlob` quality (0.01–1.0) the file was
+///   saved at, e.g. the `quality` from `detect_grid`. Overestimating it
+///   filters too little, underestimating it smooths real detail.
+///
+/// **Returns:**
+/// A new `ImageData` with the artifacts reduced.
+#[wasm_bindgen]
+pub fn reduce_artifacts(
+    image_data: BrowserImageData,
+    estimated_quality: f32,
+) -> Result<BrowserImageData, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let frame = pipeline::Frame::from_pixels(&image_data.data(), width, height, PixelFormat::Rgba)?;
+    let output = pipeline::reduce_artifacts(frame, estimated_quality);
+
+    let color_space = web::color_space(&image_data);
+    let pixels = output.to_pixels(PixelFormat::Rgba);
+    web::new_image_data(&pixels, width as u32, height as u32, color_space.as_deref())
+}
+
+/// Renders `block_map` as a tiny ImageData with one pixel per block,
+/// normalized to the largest value.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to analyze.
+/// - `options`: A `CompressOptions` instance.
+/// - `metric`: The per-block quantity to show.
+/// - `colormap`: The color ramp to paint it with.
+///
+/// **Returns:**
+/// An opaque `ImageData` of `ceil(width / 8)` x `ceil(height / 8)` pixels.
+#[wasm_bindgen]
+pub fn block_map_image(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    metric: BlockMetric,
+    colormap: Colormap,
+) -> Result<BrowserImageData, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let values = analysis::block_map(&image_data.data(), width, height, options, metric)?;
+    let output = heatmap::paint(&values, colormap);
+    BrowserImageData::new_with_u8_clamped_array_and_sh(
+        wasm_bindgen::Clamped(&output),
+        width.div_ceil(8) as u32,
+        height.div_ceil(8) as u32,
+    )
+}
+
+/// Draws the block grid over an image, for editors and teaching material.
+///
+/// With a `metric`, every 8x8 block is first tinted by how the compression
+/// treats it, normalized to the largest value in the image, so the grid shows
+/// where the error or the bits go.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to draw on.
+/// - `options`: A `CompressOptions` instance; `subsampling` sets the MCU size
+///   and the rest the compression behind `metric`.
+/// - `cell`: `Block` for the 8x8 grid, `Mcu` for minimum coded units.
+/// - `metric`: The per-block quantity to tint by, as in `block_map`, or unset
+///   for the bare grid.
+/// - `colormap`: The color ramp of the tint.
+///
+/// **Returns:**
+/// A new `ImageData` of the same size.
+#[wasm_bindgen]
+pub fn block_grid_overlay(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    cell: GridCell,
+    metric: Option<BlockMetric>,
+    colormap: Colormap,
+) -> Result<BrowserImageData, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let data = image_data.data();
+    let values = match metric {
+        Some(metric) => Some(analysis::block_map(&data, width, height, options, metric)?),
+        None => None,
+    };
+    let (fx, fy) = match cell {
+        GridCell::Block => (1, 1),
+        GridCell::Mcu => options.subsampling.factors(),
+    };
+    let cell = (8 * fx, 8 * fy);
+    let output = heatmap::grid_overlay(&data, width, cell, values.as_deref(), colormap);
+    let color_space = web::color_space(&image_data);
+    web::new_image_data(&output, width as u32, height as u32, color_space.as_deref())
+}
+
+/// Renders the DCT coefficient magnitudes of one plane, to show the frequency
+/// content of every block and, with `quantized`, what quantization leaves of it.
+///
+/// Each 8x8 block shows as an 8x8 tile of its coefficients, the DC at the top
+/// left and frequencies rising to the right and down. Magnitudes are
+/// log-scaled on a fixed range, so maps before and after quantization
+/// compare directly.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to analyze.
+/// - `options`: A `CompressOptions` instance.
+/// - `plane`: `Y`, `Cb` or `Cr`.
+/// - `quantized`: Show the coefficients after quantization at
+///   `options.compression` instead of before.
+/// - `colormap`: The color ramp to paint it with.
+///
+/// **Returns:**
+/// An opaque `ImageData` the size of the plane: the image size for `Y`, the
+/// subsampled size for `Cb` and `Cr`.
+#[wasm_bindgen]
+pub fn coefficient_map(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    plane: ImagePlane,
+    quantized: bool,
+    colormap: Colormap,
+) -> Result<BrowserImageData, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let frame = pipeline::Frame::from_pixels(&image_data.data(), width, height, PixelFormat::Rgba)?;
+    let (blocks, w, h) = pipeline::plane_coefficients(frame, options, plane, quantized);
+    let values = heatmap::coefficient_magnitudes(&blocks, w, h);
+    let output = heatmap::paint_unit(&values, colormap);
+    BrowserImageData::new_with_u8_clamped_array_and_sh(
+        wasm_bindgen::Clamped(&output),
+        w as u32,
+        h as u32,
+    )
+}
+
+/// Count the DCT coefficient values of one plane per zigzag index, before
+/// and after quantization, to see how much energy each frequency carries and
+/// how much of it quantization removes.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to analyze.
+/// - `options`: A `CompressOptions` instance.
+/// - `plane`: `Y`, `Cb` or `Cr`.
+/// - `bin_width`: Width of a histogram bin in coefficient units, at least 1.
+///   Use a quantization step to get one bin per level.
+///
+/// **Returns:**
+/// A `CoefficientHistogram` with both sets of counts and the mean energy per
+/// index.
+#[wasm_bindgen]
+pub fn coefficient_histogram(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+    plane: ImagePlane,
+    bin_width: f32,
+) -> Result<CoefficientHistogram, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    let data = image_data.data();
+    Ok(analysis::coefficient_histogram(&data, width, height, options, plane, bin_width)?)
+}
+
+/// Break the loss of a compression down by stage, answering whether it comes
+/// from chroma subsampling or from the quality setting.
+///
+/// Runs the pipeline four times: with neither subsampling nor quantization
+/// (color conversion and rounding only), with each of them alone, and with
+/// both, and measures every output against the input.
+///
+/// **Parameters:**
+/// - `image_data`: The RGBA ImageData to analyze.
+/// - `options`: A `CompressOptions` instance.
+///
+/// **Returns:**
+/// A `StageAnalysis` with the PSNR and SSIM of each run.
+#[wasm_bindgen]
+pub fn analyze_stages(
+    image_data: BrowserImageData,
+    options: &CompressOptions,
+) -> Result<StageAnalysis, JsValue> {
+    let width = image_data.width() as usize;
+    let height = image_data.height() as usize;
+    Ok(analysis::analyze_stages(&image_data.data(), width, height, options)?)
+}
+
+/// Structural similarity of the luma of two images.
+///
+/// **Parameters:**
+/// - `original`: The reference ImageData.
+/// - `compressed`: The ImageData to measure; must have the same dimensions.
+/// - `window`: `Block8` (uniform 8x8) or `Gaussian` (11x11, σ = 1.5).
+///
+/// **Returns:**
+/// The mean SSIM, 1.0 for identical images.
+#[wasm_bindgen]
+pub fn ssim(
+    original: BrowserImageData,
+    compressed: BrowserImageData,
+    window: SsimWindow,
+) -> Result<f64, JsValue> {
+    let (original, compressed, width, height) = image_pair(&original, &compressed)?;
+    Ok(metrics::ssim(&original, &compressed, width, height, window))
+}
+
+/// PSNR-HVS-M of the luma of two images: PSNR in the DCT domain with contrast
+/// sensitivity weighting and masking, tracking visible artifacts far better than `psnr`.
+///
+/// **Parameters:**
+/// - `original`: The reference ImageData.
+/// - `compressed`: The ImageData to measure; must have the same dimensions.
+///
+/// **Returns:**
+/// The PSNR-HVS-M in decibels (`Infinity` for identical images).
+#[wasm_bindgen]
+pub fn psnr_hvs_m(
+    original: BrowserImageData,
+    compressed: BrowserImageData,
+) -> Result<f64, JsValue> {
+    let (original, compressed, width, height) = image_pair(&original, &compressed)?;
+    Ok(metrics::psnr_hvs_m(&original, &compressed, width, height))
+}
+
+/// Butteraugli-style perceptual distance between two images.
+///
+/// Differences are measured in an opponent color space, with high-frequency
+/// luma errors masked by the surrounding texture.
+///
+/// **Parameters:**
+/// - `original`: The reference ImageData.
+/// - `compressed`: The ImageData to measure; must have the same dimensions.
+///
+/// **Returns:**
+/// A `PerceptualDistance` with the scalar `score`, its `pnorm` and the per-pixel `map`.
+#[wasm_bindgen]
+pub fn perceptual_distance(
+    original: BrowserImageData,
+    compressed: BrowserImageData,
+) -> Result<PerceptualDistance, JsValue> {
+    let (original, compressed, width, height) = image_pair(&original, &compressed)?;
+    Ok(perceptual::perceptual_distance(&original, &compressed, width, height))
+}
+
+/// Multi-scale SSIM of the luma of two images (five scales, standard weights).
+///
+/// **Parameters:**
+/// - `original`: The reference ImageData.
+/// - `compressed`: The ImageData to measure; must have the same dimensions.
+///
+/// **Returns:**
+/// The MS-SSIM score, 1.0 for identical images.
+#[wasm_bindgen]
+pub fn ms_ssim(original: BrowserImageData, compressed: BrowserImageData) -> Result<f64, JsValue> {
+    let (original, compressed, width, height) = image_pair(&original, &compressed)?;
+    Ok(metrics::ms_ssim(&original, &compressed, width, height))
+}
+
+/// CIEDE2000 color-difference statistics between two images.
+///
+/// **Parameters:**
+/// - `original`: The reference ImageData (sRGB).
+/// - `compressed`: The ImageData to measure; must have the same dimensions.
+///
+/// **Returns:**
+/// A `DeltaE` with the mean, median, 95th/99th percentile and maximum ΔE2000.
+#[wasm_bindgen]
+pub fn delta_e_2000(
+    original: BrowserImageData,
+    compressed: BrowserImageData,
+) -> Result<DeltaE, JsValue> {
+    let (original, compressed, _, _) = image_pair(&original, &compressed)?;
+    Ok(delta_e::delta_e_2000(&original, &compressed))
+}
+
+/// Visualize where two images differ, e.g. to overlay quality loss in an editor.
+///
+/// **Parameters:**
+/// - `original`: The reference ImageData.
+/// - `compressed`: The ImageData to measure; must have the same dimensions.
+/// - `source`: `AbsoluteError` (normalized per image) or `Ssim` (`1 - SSIM`).
+/// - `colormap`: `Grayscale`, `Viridis`, `Inferno` or `Turbo`.
+///
+/// **Returns:**
+/// An opaque `ImageData` of the same size, painted with the colormap.
+#[wasm_bindgen]
+pub fn error_heatmap(
+    original: BrowserImageData,
+    compressed: BrowserImageData,
+    source: HeatmapSource,
+    colormap: Colormap,
+) -> Result<BrowserImageData, JsValue> {
+    let (original, compressed, width, height) = image_pair(&original, &compressed)?;
+    let output = heatmap::error_heatmap(&original, &compressed, width, height, source, colormap);
+    BrowserImageData::new_with_u8_clamped_array_and_sh(
+        wasm_bindgen::Clamped(&output),
+        width as u32,
+        height as u32,
+    )
+}
+
+/// Compare two images pixel by pixel.
+///
+/// **Parameters:**
+/// - `a`: The first ImageData.
+/// - `b`: The second ImageData; must have the same dimensions.
+/// - `amplify`: When set, also render the absolute difference of every
+///   channel multiplied by this factor (e.g. 10), so small differences show.
+///
+/// **Returns:**
+/// An `ImageComparison` with the mean absolute, root mean squared and largest
+/// difference per channel, the percentage of changed pixels and, with
+/// `amplify`, the difference image.
+#[wasm_bindgen]
+pub fn compare(
+    a: BrowserImageData,
+    b: BrowserImageData,
+    amplify: Option<f32>,
+) -> Result<ImageComparison, JsValue> {
+    let (pixels_a, pixels_b, width, height) = image_pair(&a, &b)?;
+    let ([red, green, blue, alpha], changed) = metrics::channel_differences(&pixels_a, &pixels_b);
+    let difference = match amplify {
+        Some(gain) => {
+            let output = metrics::amplified_difference(&pixels_a, &pixels_b, gain.max(0.0));
+            let color_space = web::color_space(&a);
+            let (w, h) = (width as u32, height as u32);
+            Some(web::new_image_data(&output, w, h, color_space.as_deref())?)
+        }
+        None => None,
+    };
+    Ok(ImageComparison {
+        red,
+        green,
+        blue,
+        alpha,
+        changed_pixels: changed * 100.0,
+        difference,
+    })
+}
+
+/// Reads the pixels of two ImageData objects that are compared pixel for pixel.
+///
+/// Returns both buffers together with the shared width and height.
+fn image_pair(
+    original: &BrowserImageData,
+    compressed: &BrowserImageData,
+) -> Result<(Vec<u8>, Vec<u8>, usize, usize), Error> {
+    let (width, height) = (original.width(), original.height());
+    if width == 0 || height == 0 {
+        return Err(Error::InvalidDimensions);
+    }
+    if (width, height) != (compressed.width(), compressed.height()) {
+        return Err(Error::DimensionMismatch);
+    }
+    Ok((
+        original.data().0,
+        compressed.data().0,
+        width as usize,
+        height as usize,
+    ))
+}
@@ -0,0 +1,159 @@
+//! Glue that runs a live video track through the pipeline, with
+//! `MediaStreamTrackProcessor` and `MediaStreamTrackGenerator` (Chromium).
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use js_sys::Reflect;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::ImageData as BrowserImageData;
+
+use crate::options::{CompressOptions, PixelFormat};
+use crate::pipeline;
+use crate::web;
+
+/// The transform run on every `VideoFrame`, given the frame and the stream
+/// controller.
+type FrameTransform = Closure<dyn FnMut(JsValue, JsValue) -> Result<(), JsValue>>;
+
+/// A canvas the frames are drawn on, kept while their size stays the same.
+pub(crate) struct FrameCanvas {
+    width: u32,
+    height: u32,
+    canvas: JsValue,
+    context: JsValue,
+}
+
+impl FrameCanvas {
+    fn new(width: u32, height: u32) -> Result<FrameCanvas, JsValue> {
+        let canvas = web::construct("OffscreenCanvas", &[&width.into(), &height.into()])?;
+        let settings = web::object(&[("willReadFrequently", &true.into())])?;
+        let context = web::call(&canvas, "getContext", &[&"2d".into(), &settings])?;
+        Ok(FrameCanvas {
+            width,
+            height,
+            canvas,
+            context,
+        })
+    }
+
+    /// The pixels on the canvas.
+    fn image_data(&self) -> Result<BrowserImageData, JsValue> {
+        let origin = JsValue::from(0);
+        let area = [&origin, &origin, &self.width.into(), &self.height.into()];
+        web::call(&self.context, "getImageData", &area)?.dyn_into()
+    }
+}
+
+/// A video track whose frames are compressed as they pass, from
+/// `compress_track`.
+///
+/// The processing stops when the source track ends; keep this object (don't
+/// `free` it) until then.
+#[wasm_bindgen]
+pub struct CompressedTrack {
+    track: JsValue,
+    options: Rc<RefCell<CompressOptions>>,
+    _transform: FrameTransform,
+}
+
+impl CompressedTrack {
+    pub(crate) fn new(
+        track: &JsValue,
+        options: &CompressOptions,
+    ) -> Result<CompressedTrack, JsValue> {
+        let source = web::object(&[("track", track)])?;
+        let processor = web::construct("MediaStreamTrackProcessor", &[&source])?;
+        let kind = web::object(&[("kind", &"video".into())])?;
+        let generator = web::construct("MediaStreamTrackGenerator", &[&kind])?;
+
+        let options = Rc::new(RefCell::new(options.clone()));
+        let shared = Rc::clone(&options);
+        let mut canvas = None;
+        let transform: FrameTransform = Closure::new(move |frame: JsValue, controller: JsValue| {
+            let output = compress_video_frame(&frame, &mut canvas, &shared.borrow());
+            // Frames hold decoder memory until closed, so close even on error.
+            web::call(&frame, "close", &[])?;
+            web::call(&controller, "enqueue", &[&output?])?;
+            Ok(())
+        });
+        let transformer = web::object(&[("transform", transform.as_ref())])?;
+        let stream = web::construct("TransformStream", &[&transformer])?;
+        let readable = Reflect::get(&processor, &"readable".into())?;
+        let piped = web::call(&readable, "pipeThrough", &[&stream])?;
+        let writable = Reflect::get(&generator, &"writable".into())?;
+        web::call(&piped, "pipeTo", &[&writable])?;
+
+        Ok(CompressedTrack {
+            track: generator,
+            options,
+            _transform: transform,
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl CompressedTrack {
+    /// The processed video track, for a `MediaStream`, a `<video>` element
+    /// or an `RTCPeerConnection`.
+    #[wasm_bindgen(getter)]
+    pub fn track(&self) -> JsValue {
+        self.track.clone()
+    }
+
+    /// Replaces the options for every frame from now on.
+    pub fn set_options(&self, options: &CompressOptions) {
+        *self.options.borrow_mut() = options.clone();
+    }
+}
+
+/// Draws a `VideoFrame` on `canvas` (replaced when the frame size changes),
+/// compresses its pixels and returns them as a new `VideoFrame` with the
+/// same timestamp.
+fn compress_video_frame(
+    frame: &JsValue,
+    canvas: &mut Option<FrameCanvas>,
+    options: &CompressOptions,
+) -> Result<JsValue, JsValue> {
+    let target = draw_video_frame(frame, canvas)?;
+    let (width, height) = (target.width, target.height);
+    let origin = JsValue::from(0);
+    if options.compression > 0.0 {
+        let image = target.image_data()?;
+        let (w, h) = (width as usize, height as usize);
+        let pixels = pipeline::compress(&image.data(), w, h, PixelFormat::Rgba, options)?;
+        let output = web::new_image_data(&pixels, width, height, None)?;
+        web::call(&target.context, "putImageData", &[&output, &origin, &origin])?;
+    }
+    let timestamp = Reflect::get(frame, &"timestamp".into())?;
+    let init = web::object(&[("timestamp", &timestamp)])?;
+    web::construct("VideoFrame", &[&target.canvas, &init])
+}
+
+/// The pixels of a `VideoFrame`, drawn on `canvas` (replaced when the frame
+/// size changes).
+pub(crate) fn video_frame_pixels(
+    frame: &JsValue,
+    canvas: &mut Option<FrameCanvas>,
+) -> Result<BrowserImageData, JsValue> {
+    draw_video_frame(frame, canvas)?.image_data()
+}
+
+/// Draws a `VideoFrame` on `canvas`, replaced by one of the frame's size
+/// when it has another, and returns the canvas.
+fn draw_video_frame<'a>(
+    frame: &JsValue,
+    canvas: &'a mut Option<FrameCanvas>,
+) -> Result<&'a FrameCanvas, JsValue> {
+    let size = |name: &str| Reflect::get(frame, &name.into()).map(|v| v.as_f64().unwrap_or(0.0));
+    let (width, height) = (size("displayWidth")? as u32, size("displayHeight")? as u32);
+    let target = match canvas.take() {
+        Some(target) if (target.width, target.height) == (width, height) => target,
+        _ => FrameCanvas::new(width, height)?,
+    };
+    let target = canvas.insert(target);
+    let origin = JsValue::from(0);
+    web::call(&target.context, "drawImage", &[frame, &origin, &origin])?;
+    Ok(target)
+}
@@ -0,0 +1,449 @@
+use wasm_bindgen::prelude::*;
+
+/// Peak signal-to-noise ratio between two images, in decibels.
+///
+/// Identical channels report `Infinity`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Psnr {
+    /// Red channel.
+    pub red: f64,
+    /// Green channel.
+    pub green: f64,
+    /// Blue channel.
+    pub blue: f64,
+    /// Alpha channel.
+    pub alpha: f64,
+    /// Red, green and blue pooled into a single mean squared error.
+    pub combined: f64,
+}
+
+/// Computes per-channel and combined PSNR for two RGBA buffers of equal length.
+pub(crate) fn psnr(original: &[u8], compressed: &[u8]) -> Psnr {
+    let mut sums = [0u64; 4];
+    for (a, b) in original.chunks_exact(4).zip(compressed.chunks_exact(4)) {
+        for c in 0..4 {
+            let d = a[c] as i64 - b[c] as i64;
+            sums[c] += (d * d) as u64;
+        }
+    }
+
+    let pixels = (original.len() / 4).max(1) as f64;
+    Psnr {
+        red: psnr_from_mse(sums[0] as f64 / pixels),
+        green: psnr_from_mse(sums[1] as f64 / pixels),
+        blue: psnr_from_mse(sums[2] as f64 / pixels),
+        alpha: psnr_from_mse(sums[3] as f64 / pixels),
+        combined: psnr_from_mse((sums[0] + sums[1] + sums[2]) as f64 / (3.0 * pixels)),
+    }
+}
+
+/// PSNR for 8-bit samples with the given mean squared error.
+pub(crate) fn psnr_from_mse(mse: f64) -> f64 {
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * libm::log10(255.0 * 255.0 / mse)
+    }
+}
+
+/// How one channel of two images differs, in 8-bit levels.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelDifference {
+    /// Mean absolute difference.
+    pub mae: f64,
+    /// Root mean squared difference.
+    pub rmse: f64,
+    /// Largest absolute difference.
+    pub max: u8,
+}
+
+/// Per-channel differences (red, green, blue, alpha) of two RGBA buffers of
+/// equal length, and the share (0–1) of pixels that differ in any channel.
+pub(crate) fn channel_differences(a: &[u8], b: &[u8]) -> ([ChannelDifference; 4], f64) {
+    let mut abs_sums = [0u64; 4];
+    let mut squared_sums = [0u64; 4];
+    let mut max = [0u8; 4];
+    let mut changed = 0usize;
+    for (pa, pb) in a.chunks_exact(4).zip(b.chunks_exact(4)) {
+        for c in 0..4 {
+            let d = pa[c].abs_diff(pb[c]);
+            abs_sums[c] += d as u64;
+            squared_sums[c] += (d as u64).pow(2);
+            max[c] = max[c].max(d);
+        }
+        changed += (pa != pb) as usize;
+    }
+    let pixels = (a.len() / 4).max(1) as f64;
+    let channels = std::array::from_fn(|c| ChannelDifference {
+        mae: abs_sums[c] as f64 / pixels,
+        rmse: (squared_sums[c] as f64 / pixels).sqrt(),
+        max: max[c],
+    });
+    (channels, changed as f64 / pixels)
+}
+
+/// Opaque RGBA image of the absolute per-channel difference of two RGBA
+/// buffers, multiplied by `gain` so small differences become visible.
+pub(crate) fn amplified_difference(a: &[u8], b: &[u8], gain: f32) -> Vec<u8> {
+    a.chunks_exact(4)
+        .zip(b.chunks_exact(4))
+        .flat_map(|(pa, pb)| {
+            let channel = |c: usize| (pa[c].abs_diff(pb[c]) as f32 * gain).min(255.0) as u8;
+            [channel(0), channel(1), channel(2), 255]
+        })
+        .collect()
+}
+
+/// Window used to gather the local statistics of SSIM.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SsimWindow {
+    /// Uniform 8x8 window (fast, matches the codec's block grid).
+    Block8,
+    /// 11x11 gaussian window with σ = 1.5, as in the original SSIM paper.
+    Gaussian,
+}
+
+/// SSIM stabilizing constant for the means, `(0.01 * 255)²`.
+const SSIM_C1: f64 = 6.5025;
+/// SSIM stabilizing constant for the variances, `(0.03 * 255)²`.
+const SSIM_C2: f64 = 58.5225;
+
+/// Mean luma SSIM of two RGBA buffers of `width`x`height` pixels.
+pub(crate) fn ssim(
+    original: &[u8],
+    compressed: &[u8],
+    width: usize,
+    height: usize,
+    window: SsimWindow,
+) -> f64 {
+    mean(&ssim_map(
+        &luma(original),
+        &luma(compressed),
+        width,
+        height,
+        window,
+    ))
+}
+
+/// BT.601 luma of an RGBA buffer.
+pub(crate) fn luma(rgba: &[u8]) -> Vec<f64> {
+    rgba.chunks_exact(4)
+        .map(|px| 0.299 * px[0] as f64 + 0.587 * px[1] as f64 + 0.114 * px[2] as f64)
+        .collect()
+}
+
+/// Computes the local SSIM of two `w`x`h` planes at every window position.
+///
+/// Windows never hang over the edge; on planes smaller than the window the
+/// window is cropped to fit.
+pub(crate) fn ssim_map(x: &[f64], y: &[f64], w: usize, h: usize, window: SsimWindow) -> Vec<f64> {
+    ssim_terms(x, y, w, h, window).0
+}
+
+/// Local luma SSIM of two RGBA buffers, with one value per input pixel.
+///
+/// Each pixel takes the SSIM of the window centered on it; pixels closer to the
+/// edge than half a window reuse the nearest fully covered window.
+pub(crate) fn ssim_pixel_map(
+    original: &[u8],
+    compressed: &[u8],
+    width: usize,
+    height: usize,
+) -> Vec<f64> {
+    let window = SsimWindow::Gaussian;
+    let map = ssim_map(&luma(original), &luma(compressed), width, height, window);
+    let (kx, ky) = (
+        window_taps(window, width).len(),
+        window_taps(window, height).len(),
+    );
+    let (map_w, map_h) = (width + 1 - kx, height + 1 - ky);
+
+    let mut out = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let my = y.saturating_sub(ky / 2).min(map_h - 1);
+        for x in 0..width {
+            let mx = x.saturating_sub(kx / 2).min(map_w - 1);
+            out.push(map[my * map_w + mx]);
+        }
+    }
+    out
+}
+
+/// Local SSIM and contrast-structure (SSIM without the luminance term) maps.
+fn ssim_terms(
+    x: &[f64],
+    y: &[f64],
+    w: usize,
+    h: usize,
+    window: SsimWindow,
+) -> (Vec<f64>, Vec<f64>) {
+    let x_taps = window_taps(window, w);
+    let y_taps = window_taps(window, h);
+    let filter = |plane: &[f64]| filter_valid(plane, w, h, &x_taps, &y_taps);
+
+    let xx: Vec<f64> = x.iter().map(|v| v * v).collect();
+    let yy: Vec<f64> = y.iter().map(|v| v * v).collect();
+    let xy: Vec<f64> = x.iter().zip(y).map(|(a, b)| a * b).collect();
+    let (mu_x, mu_y) = (filter(x), filter(y));
+    let (e_xx, e_yy, e_xy) = (filter(&xx), filter(&yy), filter(&xy));
+
+    let n = mu_x.len();
+    let mut ssim = Vec::with_capacity(n);
+    let mut cs = Vec::with_capacity(n);
+    for i in 0..n {
+        let (mx, my) = (mu_x[i], mu_y[i]);
+        let var_x = (e_xx[i] - mx * mx).max(0.0);
+        let var_y = (e_yy[i] - my * my).max(0.0);
+        let cov = e_xy[i] - mx * my;
+        let contrast = (2.0 * cov + SSIM_C2) / (var_x + var_y + SSIM_C2);
+        let luminance = (2.0 * mx * my + SSIM_C1) / (mx * mx + my * my + SSIM_C1);
+        ssim.push(luminance * contrast);
+        cs.push(contrast);
+    }
+
+    (ssim, cs)
+}
+
+/// Per-scale weights of MS-SSIM (Wang, Simoncelli & Bovik, 2003), finest first.
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+/// Multi-scale luma SSIM of two RGBA buffers of `width`x`height` pixels.
+///
+/// Images too small for all five scales use the scales that fit, with the
+/// weights renormalized.
+pub(crate) fn ms_ssim(original: &[u8], compressed: &[u8], width: usize, height: usize) -> f64 {
+    let (mut x, mut y) = (luma(original), luma(compressed));
+    let mut scales = 1;
+    while scales < MS_SSIM_WEIGHTS.len() && (width >> scales).min(height >> scales) >= 1 {
+        scales += 1;
+    }
+    let weights = &MS_SSIM_WEIGHTS[..scales];
+    let total: f64 = weights.iter().sum();
+    let (mut w, mut h) = (width, height);
+
+    let mut score = 1.0;
+    for (scale, &weight) in weights.iter().enumerate() {
+        let (ssim, cs) = ssim_terms(&x, &y, w, h, SsimWindow::Gaussian);
+        let term = if scale + 1 == scales {
+            mean(&ssim)
+        } else {
+            mean(&cs)
+        };
+        // Negative values (anti-correlated structure) would make the power undefined.
+        score *= libm::pow(term.max(0.0), weight / total);
+
+        if scale + 1 < scales {
+            x = halve(&x, w, h);
+            y = halve(&y, w, h);
+            w /= 2;
+            h /= 2;
+        }
+    }
+    score
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Halves a plane by averaging 2x2 groups, dropping an odd last row or column.
+fn halve(plane: &[f64], w: usize, h: usize) -> Vec<f64> {
+    let (out_w, out_h) = (w / 2, h / 2);
+    let mut out = Vec::with_capacity(out_w * out_h);
+    for y in 0..out_h {
+        for x in 0..out_w {
+            let at = |dx: usize, dy: usize| plane[(2 * y + dy) * w + 2 * x + dx];
+            out.push((at(0, 0) + at(1, 0) + at(0, 1) + at(1, 1)) / 4.0);
+        }
+    }
+    out
+}
+
+/// Normalized window weights along an axis of `len` samples.
+fn window_taps(window: SsimWindow, len: usize) -> Vec<f64> {
+    let full: Vec<f64> = match window {
+        SsimWindow::Block8 => vec![1.0; 8],
+        SsimWindow::Gaussian => (-5i32..=5)
+            .map(|d| libm::exp(-(d * d) as f64 / (2.0 * 1.5 * 1.5)))
+            .collect(),
+    };
+    let keep = full.len().min(len);
+    let start = (full.len() - keep) / 2;
+    let taps = &full[start..start + keep];
+    let total: f64 = taps.iter().sum();
+    taps.iter().map(|t| t / total).collect()
+}
+
+/// Separable convolution keeping only positions where the window fits entirely.
+fn filter_valid(plane: &[f64], w: usize, h: usize, x_taps: &[f64], y_taps: &[f64]) -> Vec<f64> {
+    let out_w = w + 1 - x_taps.len();
+    let out_h = h + 1 - y_taps.len();
+
+    let mut rows = vec![0.0; out_w * h];
+    for y in 0..h {
+        for x in 0..out_w {
+            rows[y * out_w + x] = x_taps
+                .iter()
+                .enumerate()
+                .map(|(k, t)| plane[y * w + x + k] * t)
+                .sum();
+        }
+    }
+
+    let mut out = vec![0.0; out_w * out_h];
+    for y in 0..out_h {
+        for x in 0..out_w {
+            out[y * out_w + x] = y_taps
+                .iter()
+                .enumerate()
+                .map(|(k, t)| rows[(y + k) * out_w + x] * t)
+                .sum();
+        }
+    }
+    out
+}
+
+/// Contrast sensitivity weights of PSNR-HVS-M per DCT coefficient.
+const HVS_CSF: [[f64; 8]; 8] = [
+    [
+        1.608443, 2.339554, 2.573509, 1.608443, 1.072295, 0.643377, 0.504610, 0.421887,
+    ],
+    [
+        2.144591, 2.144591, 1.838221, 1.354478, 0.989811, 0.443708, 0.428918, 0.467911,
+    ],
+    [
+        1.838221, 1.979622, 1.608443, 1.072295, 0.643377, 0.451493, 0.372972, 0.459555,
+    ],
+    [
+        1.838221, 1.513829, 1.169777, 0.887417, 0.504610, 0.295806, 0.321689, 0.415082,
+    ],
+    [
+        1.429727, 1.169777, 0.695543, 0.459555, 0.378457, 0.236102, 0.249855, 0.334222,
+    ],
+    [
+        1.072295, 0.735288, 0.467911, 0.402111, 0.317717, 0.247453, 0.227744, 0.279729,
+    ],
+    [
+        0.525206, 0.402111, 0.329937, 0.295806, 0.249855, 0.212687, 0.214459, 0.254803,
+    ],
+    [
+        0.357432, 0.279729, 0.270896, 0.262603, 0.229778, 0.257351, 0.249855, 0.259950,
+    ],
+];
+
+/// Masking weights of PSNR-HVS-M per DCT coefficient (`HVS_CSF` squared, normalized).
+const HVS_MASK: [[f64; 8]; 8] = [
+    [
+        0.390625, 0.826446, 1.000000, 0.390625, 0.173611, 0.062500, 0.038447, 0.026874,
+    ],
+    [
+        0.694444, 0.694444, 0.510204, 0.277008, 0.147929, 0.029727, 0.027778, 0.033058,
+    ],
+    [
+        0.510204, 0.591716, 0.390625, 0.173611, 0.062500, 0.030779, 0.021004, 0.031888,
+    ],
+    [
+        0.510204, 0.346021, 0.206612, 0.118906, 0.038447, 0.013212, 0.015625, 0.026015,
+    ],
+    [
+        0.308642, 0.206612, 0.073046, 0.031888, 0.021626, 0.008417, 0.009426, 0.016866,
+    ],
+    [
+        0.173611, 0.081633, 0.033058, 0.024414, 0.015242, 0.009246, 0.007831, 0.011891,
+    ],
+    [
+        0.041649, 0.024414, 0.016437, 0.013212, 0.009426, 0.006830, 0.006944, 0.009803,
+    ],
+    [
+        0.019290, 0.011891, 0.011025, 0.010412, 0.008101, 0.009803, 0.009426, 0.010203,
+    ],
+];
+
+/// PSNR-HVS-M (Ponomarenko et al., 2007) of the luma of two RGBA buffers.
+///
+/// Errors are measured per 8x8 DCT coefficient, weighted by contrast
+/// sensitivity and reduced by the masking ability of each block. Partial blocks
+/// at the right/bottom edge are padded by edge replication, as in the codec.
+pub(crate) fn psnr_hvs_m(original: &[u8], compressed: &[u8], width: usize, height: usize) -> f64 {
+    let (x, y) = (luma(original), luma(compressed));
+    let mut sum = 0.0;
+    let mut count = 0usize;
+    for by in (0..height).step_by(8) {
+        for bx in (0..width).step_by(8) {
+            let a = read_block(&x, width, height, bx, by);
+            let b = read_block(&y, width, height, bx, by);
+            let (a_dct, b_dct) = (dct_block(&a), dct_block(&b));
+            let mask = masking(&a, &a_dct).max(masking(&b, &b_dct));
+
+            for k in 0..8 {
+                for l in 0..8 {
+                    let mut u = (a_dct[k][l] - b_dct[k][l]).abs();
+                    if k != 0 || l != 0 {
+                        let threshold = mask / HVS_MASK[k][l];
+                        u = if u < threshold { 0.0 } else { u - threshold };
+                    }
+                    sum += (u * HVS_CSF[k][l]).powi(2);
+                    count += 1;
+                }
+            }
+        }
+    }
+    psnr_from_mse(sum / count as f64)
+}
+
+/// Reads the 8x8 block at (`bx`, `by`), replicating the edge beyond the plane.
+fn read_block(plane: &[f64], w: usize, h: usize, bx: usize, by: usize) -> [[f64; 8]; 8] {
+    let mut block = [[0.0; 8]; 8];
+    for u in 0..8 {
+        for v in 0..8 {
+            block[u][v] = plane[(by + u).min(h - 1) * w + (bx + v).min(w - 1)];
+        }
+    }
+    block
+}
+
+fn dct_block(block: &[[f64; 8]; 8]) -> [[f64; 8]; 8] {
+    crate::dct::dct2d(block.map(|row| row.map(|v| v as f32))).map(|row| row.map(|v| v as f64))
+}
+
+/// Masking strength of a block: its weighted AC energy, scaled down for
+/// blocks whose activity is concentrated in only part of the block.
+fn masking(block: &[[f64; 8]; 8], dct: &[[f64; 8]; 8]) -> f64 {
+    let mut energy = 0.0;
+    for k in 0..8 {
+        for l in 0..8 {
+            if k != 0 || l != 0 {
+                energy += dct[k][l] * dct[k][l] * HVS_MASK[k][l];
+            }
+        }
+    }
+
+    let whole = variance(block, 0..8, 0..8);
+    let ratio = if whole != 0.0 {
+        (variance(block, 0..4, 0..4)
+            + variance(block, 0..4, 4..8)
+            + variance(block, 4..8, 4..8)
+            + variance(block, 4..8, 0..4))
+            / whole
+    } else {
+        0.0
+    };
+    (energy * ratio).sqrt() / 32.0
+}
+
+/// Unbiased variance times sample count over a sub-rectangle of a block.
+fn variance(
+    block: &[[f64; 8]; 8],
+    rows: std::ops::Range<usize>,
+    cols: std::ops::Range<usize>,
+) -> f64 {
+    let values: Vec<f64> = rows
+        .flat_map(|u| cols.clone().map(move |v| block[u][v]))
+        .collect();
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() * n / (n - 1.0)
+}
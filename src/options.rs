@@ -0,0 +1,775 @@
+use wasm_bindgen::prelude::*;
+
+/// How the `compression` value maps to the quantization table scale.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionScale {
+    /// Scale `1 + 20 * compression`, the original mapping; most of the visible
+    /// change happens in the first fifth of the range.
+    Linear,
+    /// Scale `21 ^ compression`: every step multiplies the quantizer step size by
+    /// the same ratio, so equal increments lose roughly equal quality (PSNR
+    /// falls about linearly). Same end points as `Linear`.
+    Perceptual,
+    /// `compression = 1 - quality` of `canvas.toBlob("image/jpeg", quality)`,
+    /// with the libjpeg quality scaling the Chrome and Firefox encoders use.
+    /// Reaches both finer (quality above 0.5) and coarser tables than the
+    /// other scales.
+    Browser,
+}
+
+/// Largest quantization table scale of `Linear` and `Perceptual`, reached at `compression` 1.0.
+const MAX_TABLE_SCALE: f32 = 21.0;
+
+impl CompressionScale {
+    /// Factor applied to the standard quantization table at `compression` (0.0–1.0).
+    pub(crate) fn table_scale(self, compression: f32) -> f32 {
+        match self {
+            CompressionScale::Linear => 1.0 + compression * (MAX_TABLE_SCALE - 1.0),
+            CompressionScale::Perceptual => libm::powf(MAX_TABLE_SCALE, compression),
+            CompressionScale::Browser => libjpeg_scale(1.0 - compression) as f32 / 100.0,
+        }
+    }
+
+    /// The `compression` value that produces table scale `scale`, clamped to
+    /// the range the scale covers.
+    pub(crate) fn compression(self, scale: f32) -> f32 {
+        match self {
+            CompressionScale::Linear => {
+                (scale.clamp(1.0, MAX_TABLE_SCALE) - 1.0) / (MAX_TABLE_SCALE - 1.0)
+            }
+            CompressionScale::Perceptual => {
+                libm::logf(scale.clamp(1.0, MAX_TABLE_SCALE)) / libm::logf(MAX_TABLE_SCALE)
+            }
+            CompressionScale::Browser => {
+                let quality = if scale >= 1.0 {
+                    0.5 / scale
+                } else {
+                    1.0 - scale / 2.0
+                };
+                1.0 - quality.clamp(0.01, 1.0)
+            }
+        }
+    }
+
+    /// The `canvas.toBlob` quality whose tables match `compression` on this scale.
+    ///
+    /// No compression at all corresponds to quality 1.0.
+    pub(crate) fn to_blob_quality(self, compression: f32) -> f32 {
+        if compression <= 0.0 {
+            return 1.0;
+        }
+        1.0 - CompressionScale::Browser.compression(self.table_scale(compression.min(1.0)))
+    }
+
+    /// The `compression` on this scale whose tables match `canvas.toBlob` `quality`.
+    ///
+    /// `Linear` and `Perceptual` cannot go finer than quality 0.5; that and
+    /// higher qualities map to 0.0 (no compression).
+    pub(crate) fn compression_for_to_blob_quality(self, quality: f32) -> f32 {
+        let scale = CompressionScale::Browser.table_scale(1.0 - quality.clamp(0.0, 1.0));
+        self.compression(scale)
+    }
+}
+
+/// libjpeg's `jpeg_quality_scaling`: the percentage the standard tables are
+/// scaled by for a 0.0–1.0 quality, after rounding it to an integer 1–100.
+pub(crate) fn libjpeg_scale(quality: f32) -> u32 {
+    let quality = (quality * 100.0).round().clamp(1.0, 100.0) as u32;
+    if quality < 50 {
+        5000 / quality
+    } else {
+        200 - quality * 2
+    }
+}
+
+/// Shape of the compression ramp across the image.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QualityGradient {
+    /// The same compression everywhere.
+    None,
+    /// From `compression` at the top to `gradient_compression` at the bottom.
+    Vertical,
+    /// From `compression` at the left to `gradient_compression` at the right.
+    Horizontal,
+    /// From `compression` at the center to `gradient_compression` at the corners.
+    Radial,
+}
+
+/// Size of the square transform blocks.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockSize {
+    /// 4x4: finer grain, smaller artifacts.
+    Size4,
+    /// 8x8, as in JPEG.
+    Size8,
+    /// 16x16: smoother, larger blocks and ringing.
+    Size16,
+    /// 32x32: very coarse blocks.
+    Size32,
+}
+
+impl BlockSize {
+    /// Width and height of a block in samples.
+    pub(crate) fn samples(self) -> usize {
+        match self {
+            BlockSize::Size4 => 4,
+            BlockSize::Size8 => 8,
+            BlockSize::Size16 => 16,
+            BlockSize::Size32 => 32,
+        }
+    }
+}
+
+/// Chroma subsampling layout applied to the Cb/Cr planes.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChromaSubsampling {
+    /// 4:4:4 — chroma kept at full resolution.
+    Yuv444,
+    /// 4:2:2 — chroma halved horizontally (2x1 groups).
+    Yuv422,
+    /// 4:2:0 — chroma halved in both directions (2x2 groups).
+    Yuv420,
+}
+
+impl ChromaSubsampling {
+    /// Horizontal and vertical subsampling factors.
+    pub(crate) fn factors(self) -> (usize, usize) {
+        match self {
+            ChromaSubsampling::Yuv444 => (1, 1),
+            ChromaSubsampling::Yuv422 => (2, 1),
+            ChromaSubsampling::Yuv420 => (2, 2),
+        }
+    }
+}
+
+/// Filter used when reducing the chroma planes to their subsampled size.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChromaDownsampling {
+    /// Picks the top-left pixel of each group (legacy behavior, aliases on fine color detail).
+    Nearest,
+    /// Averages every pixel of the group.
+    Box,
+    /// Tent filter reaching into neighboring groups; smoother than `Box`.
+    Triangle,
+}
+
+/// Position of each subsampled chroma sample relative to the luma grid.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChromaSiting {
+    /// Chroma sits at the center of its group (JPEG/JFIF).
+    Centered,
+    /// Chroma sits on the top-left luma sample of its group (common in video).
+    CoSited,
+}
+
+impl ChromaSiting {
+    /// Offset of the chroma sample from the start of a group of `factor` pixels.
+    pub(crate) fn offset(self, factor: usize) -> f32 {
+        match self {
+            ChromaSiting::Centered => (factor as f32 - 1.0) / 2.0,
+            ChromaSiting::CoSited => 0.0,
+        }
+    }
+}
+
+/// Filter `resize_and_compress` scales the image with.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResizeFilter {
+    /// Averages the pixels each output pixel covers; blocky when enlarging.
+    Box,
+    /// Tent filter (bilinear); soft.
+    Triangle,
+    /// Mitchell–Netravali cubic (B = C = 1/3); sharp with little ringing.
+    Mitchell,
+    /// Three-lobed Lanczos window; the sharpest, with slight halos at hard edges.
+    Lanczos3,
+}
+
+/// Filter used when bringing the chroma planes back to full resolution.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChromaUpsampling {
+    /// Replicates each chroma sample over its group (blocky color edges).
+    Nearest,
+    /// Linear interpolation between neighboring samples, like libjpeg-turbo's "fancy upsampling".
+    Bilinear,
+    /// Catmull-Rom cubic interpolation; sharper than `Bilinear` with slight overshoot.
+    CatmullRom,
+}
+
+/// Color transform separating luma from chroma before compression.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorTransform {
+    /// Classic JPEG YCbCr, configured by `color_matrix` and `color_range`.
+    YCbCr,
+    /// YCoCg: shift/add only, often decorrelates better than YCbCr.
+    YCoCg,
+    /// YCoCg-R: integer lifting form of YCoCg, exactly reversible.
+    YCoCgR,
+    /// JPEG 2000 reversible color transform; exact round trips for low-loss work.
+    Rct,
+}
+
+/// YCbCr matrix used for the color conversion and its inverse.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMatrix {
+    /// ITU-R BT.601 (standard JPEG / SD video).
+    Bt601,
+    /// ITU-R BT.709 (HD video).
+    Bt709,
+    /// ITU-R BT.2020 (UHD / wide-gamut video).
+    Bt2020,
+}
+
+/// Numeric range of the YCbCr planes.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorRange {
+    /// Full swing: every plane spans 0–255 (JPEG/JFIF).
+    Full,
+    /// Studio swing: luma 16–235, chroma 16–240 (broadcast/video).
+    Limited,
+}
+
+/// What happens to the alpha channel of the input.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Copy the original alpha to the output untouched.
+    Preserve,
+    /// Discard alpha and return a fully opaque image.
+    Opaque,
+    /// Run alpha through the DCT/quantization pipeline as a fourth plane,
+    /// at the strength given by `alpha_compression`.
+    Compress,
+    /// Composite over `background` and return an opaque image, like saving to a real JPEG.
+    Flatten,
+}
+
+/// Byte layout of interleaved 8-bit pixel buffers.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// R, G, B, A (canvas `ImageData`).
+    Rgba,
+    /// B, G, R, A (Windows and many native capture APIs).
+    Bgra,
+    /// A, R, G, B.
+    Argb,
+    /// R, G, B and an unused padding byte (written back as 255).
+    Rgbx,
+    /// Tightly packed R, G, B.
+    Rgb,
+}
+
+impl PixelFormat {
+    /// Bytes per pixel.
+    pub(crate) fn channels(self) -> usize {
+        match self {
+            PixelFormat::Rgb => 3,
+            _ => 4,
+        }
+    }
+
+    /// Byte offsets of the red, green and blue channels within a pixel.
+    pub(crate) fn rgb_offsets(self) -> [usize; 3] {
+        match self {
+            PixelFormat::Rgba | PixelFormat::Rgbx | PixelFormat::Rgb => [0, 1, 2],
+            PixelFormat::Bgra => [2, 1, 0],
+            PixelFormat::Argb => [1, 2, 3],
+        }
+    }
+
+    /// Byte offset of the alpha channel, if the format carries one.
+    pub(crate) fn alpha_offset(self) -> Option<usize> {
+        match self {
+            PixelFormat::Rgba | PixelFormat::Bgra => Some(3),
+            PixelFormat::Argb => Some(0),
+            PixelFormat::Rgbx | PixelFormat::Rgb => None,
+        }
+    }
+
+    /// Byte offset of the padding channel, if any.
+    pub(crate) fn padding_offset(self) -> Option<usize> {
+        match self {
+            PixelFormat::Rgbx => Some(3),
+            _ => None,
+        }
+    }
+}
+
+/// Memory layout of planar 4:2:0 YUV buffers (as produced by `VideoFrame.copyTo`).
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum YuvLayout {
+    /// Y plane, then the U plane, then the V plane.
+    I420,
+    /// Y plane, then a single plane of interleaved U/V pairs.
+    Nv12,
+}
+
+/// Transfer function (gamma curve) relating linear light to encoded values.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferFunction {
+    /// No curve: encoded values equal linear light.
+    Linear,
+    /// IEC 61966-2-1 sRGB curve.
+    Srgb,
+    /// Pure 2.2 power law.
+    Gamma22,
+    /// ITU-R BT.709 camera curve.
+    Bt709,
+}
+
+/// Tone-mapping curve applied to HDR (float/16-bit) input before compression.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMapping {
+    /// No curve; only `exposure` is applied, clipping at reference white.
+    None,
+    /// Reinhard `x / (1 + x)`; gentle highlight roll-off.
+    Reinhard,
+    /// ACES-style filmic curve with more contrast.
+    Aces,
+}
+
+/// Dither pattern applied to the quantizer's rounding thresholds.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dither {
+    /// Plain rounding.
+    None,
+    /// 8x8 Bayer matrix; regular, visible cross-hatch texture.
+    Ordered,
+    /// Blue-noise-like pattern; finer grain without a visible grid.
+    BlueNoise,
+}
+
+/// Noise-reduction pre-filter applied before the DCT.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Denoise {
+    /// No denoising.
+    None,
+    /// Bilateral filter tuned to the noise level estimated from the luma plane.
+    Auto,
+    /// Bilateral filter tuned to the noise level given in `denoise_strength`.
+    Manual,
+}
+
+/// Color-reduction stage applied before the DCT pipeline.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Posterize {
+    /// Keep every color.
+    None,
+    /// Round each channel to `posterize_levels` evenly spaced steps.
+    Levels,
+    /// Map to an adaptive median-cut palette of `posterize_levels` colors.
+    MedianCut,
+}
+
+/// Coefficient rearrangement applied to the quantized DCT blocks.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoefficientEffect {
+    /// Leave the coefficients alone.
+    None,
+    /// Negate the picked AC coefficients, reversing the phase of their ringing.
+    FlipSigns,
+    /// Swap each picked AC coefficient with its mirror across the block
+    /// diagonal, turning horizontal detail vertical and vice versa.
+    Swap,
+}
+
+/// How each block loses detail.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Degradation {
+    /// Scale the quantization table with `compression` (regular JPEG).
+    Quantize,
+    /// Keep only the first `keep_coefficients` coefficients of each block in
+    /// zigzag order, otherwise nearly lossless.
+    Zigzag,
+    /// Keep only the DC coefficient of each block, quantized at `compression`:
+    /// a mosaic of flat 8x8 blocks (larger in subsampled chroma) on the JPEG
+    /// grid. Much faster than the full transform.
+    DcOnly,
+}
+
+/// Frequency-domain filter applied to each block's DCT coefficients.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrequencyFilter {
+    /// Pass every frequency.
+    None,
+    /// Pass frequencies up to `frequency_max`.
+    LowPass,
+    /// Pass frequencies from `frequency_min` up.
+    HighPass,
+    /// Pass frequencies from `frequency_min` to `frequency_max`.
+    BandPass,
+    /// Pass everything except `frequency_min` to `frequency_max`.
+    BandStop,
+}
+
+/// How the frequency of a DCT coefficient is measured for `FrequencyFilter`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrequencyMeasure {
+    /// Zigzag index, 0 (DC) to 63.
+    Zigzag,
+    /// Radial distance `sqrt(u² + v²)` from DC, 0 to about 9.9; treats
+    /// horizontal and vertical detail of the same fineness alike.
+    Radial,
+}
+
+/// Plane that feeds a Y, Cb or Cr slot of the reconstruction.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaneSource {
+    /// The compressed luma plane.
+    Y,
+    /// The compressed Cb (or Co) plane.
+    Cb,
+    /// The compressed Cr (or Cg) plane.
+    Cr,
+    /// No signal: black in the Y slot, no color in a chroma slot.
+    Zero,
+}
+
+/// Rotation or mirroring applied to the input before compression; the eight
+/// cases the EXIF orientation tag describes.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    /// Leave the image as it is.
+    None,
+    /// Mirror left to right.
+    FlipHorizontal,
+    /// Mirror top to bottom.
+    FlipVertical,
+    /// Turn a quarter clockwise.
+    Rotate90,
+    /// Turn upside down.
+    Rotate180,
+    /// Turn a quarter counter-clockwise.
+    Rotate270,
+    /// Mirror across the main diagonal, swapping rows and columns.
+    Transpose,
+    /// Mirror across the other diagonal.
+    Transverse,
+}
+
+/// Displacement of whole blocks applied before reconstruction.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockShuffle {
+    /// Keep every block in place.
+    None,
+    /// Exchange each picked block with one nearby.
+    Swap,
+    /// Replace each picked block with a copy of one nearby, leaving the
+    /// original in place too (smeared, repeated blocks).
+    Displace,
+}
+
+/// Options controlling the compression pipeline.
+///
+/// Construct with `new CompressOptions()` and adjust the fields you need;
+/// every field starts at its default.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct CompressOptions {
+    /// Compression strength from 0.0 (none) to 1.0 (strongest).
+    pub compression: f32,
+    /// How `compression` maps to the quantizer strength. Defaults to `Linear`.
+    pub compression_scale: CompressionScale,
+    /// Varies the compression across the image, block by block, from
+    /// `compression` to `gradient_compression`. Defaults to `None`.
+    pub quality_gradient: QualityGradient,
+    /// Compression (0.0–1.0) at the far end of `quality_gradient`. Defaults to `1.0`.
+    pub gradient_compression: f32,
+    /// Transform block size. Other sizes than 8x8 quantize with the table
+    /// resampled to the block size; the settings that work on 8x8 coefficients
+    /// (`degradation`, `frequency_filter`, `error_diffusion`, the glitch,
+    /// coefficient and block shuffle effects) and the block statistics are then
+    /// skipped. JPEG files always use 8x8. Defaults to `Size8`.
+    pub block_size: BlockSize,
+    /// Blend the block pass over four grids offset by half a block, so no
+    /// block edge stays visible. Four times slower. Defaults to `false`.
+    pub block_overlap: bool,
+    /// How blocks lose detail. `Zigzag` replaces the quantization table scaling
+    /// with a cut-off after `keep_coefficients`; `compression` must still be
+    /// above 0. `DcOnly` reduces every block to its average. Defaults to `Quantize`.
+    pub degradation: Degradation,
+    /// Coefficients (1–64, in zigzag order) each block keeps under
+    /// `Degradation::Zigzag`. Defaults to `64`.
+    pub keep_coefficients: u32,
+    /// DCT-domain filter applied before quantization. The DC coefficient always
+    /// passes, so block averages are kept. Defaults to `None`.
+    pub frequency_filter: FrequencyFilter,
+    /// Scale `frequency_min` and `frequency_max` are given on. Defaults to `Zigzag`.
+    pub frequency_measure: FrequencyMeasure,
+    /// Lower edge of the high-pass, band-pass and band-stop filters. Defaults to `0.0`.
+    pub frequency_min: f32,
+    /// Upper edge of the low-pass, band-pass and band-stop filters. Defaults to `63.0`.
+    pub frequency_max: f32,
+    /// Gain (0.0–1.0) of the frequencies the filter rejects: `0.0` zeroes
+    /// them, higher values only attenuate. Defaults to `0.0`.
+    pub frequency_attenuation: f32,
+    /// Chroma subsampling layout. Defaults to `Yuv420`.
+    pub subsampling: ChromaSubsampling,
+    /// Chroma downsampling filter. Defaults to `Box`.
+    pub chroma_downsampling: ChromaDownsampling,
+    /// Chroma upsampling filter. Defaults to `Bilinear`.
+    pub chroma_upsampling: ChromaUpsampling,
+    /// Chroma sample siting used by both resampling filters. Defaults to `Centered`.
+    pub chroma_siting: ChromaSiting,
+    /// Color transform. Defaults to `YCbCr`.
+    pub color_transform: ColorTransform,
+    /// YCbCr conversion matrix. Defaults to `Bt601`.
+    pub color_matrix: ColorMatrix,
+    /// YCbCr range. Defaults to `Full`.
+    pub color_range: ColorRange,
+    /// Switch to a luma-only pipeline when the input is grayscale. Defaults to `true`.
+    pub detect_grayscale: bool,
+    /// Alpha channel policy. Defaults to `Preserve`.
+    pub alpha_mode: AlphaMode,
+    /// Compression strength (0.0–1.0) for the alpha plane in `AlphaMode::Compress`.
+    /// Defaults to `0.5`.
+    pub alpha_compression: f32,
+    /// Input color is premultiplied by alpha: it is un-premultiplied before the
+    /// color conversion and re-premultiplied on output. Defaults to `false`.
+    pub premultiplied_alpha: bool,
+    /// Background color (`0xRRGGBB`) used by `AlphaMode::Flatten`. Defaults to white.
+    pub background: u32,
+    /// Curve applied to linear-light float input before the color conversion
+    /// (and inverted on output). Defaults to `Srgb`.
+    pub transfer_function: TransferFunction,
+    /// Decode to linear light (via `transfer_function`) before the transform and
+    /// quantization, and re-encode afterwards. Shifts quantization error away from
+    /// the shadows. Defaults to `false`.
+    pub linear_light: bool,
+    /// Tone-mapping curve for float and 16-bit input. Defaults to `None`
+    /// (input passes through untouched).
+    pub tone_mapping: ToneMapping,
+    /// Exposure adjustment in stops, applied before `tone_mapping`. Defaults to `0.0`.
+    pub exposure: f32,
+    /// Filter `resize_and_compress` scales the image with. Defaults to `Lanczos3`.
+    pub resize_filter: ResizeFilter,
+    /// Dither pattern used while quantizing, to break up banding in smooth
+    /// gradients. Defaults to `None`.
+    pub dither: Dither,
+    /// Dither amplitude from 0.0 to 1.0 (a full quantization step). Defaults to `0.5`.
+    pub dither_strength: f32,
+    /// Diffuse each block's DC rounding error into its neighbors, preserving
+    /// average intensity in gradients. Defaults to `false`.
+    pub error_diffusion: bool,
+    /// Standard deviation (in pixels) of a gaussian blur applied to the chroma
+    /// planes before subsampling; reduces color bleeding around saturated edges.
+    /// Defaults to `0.0` (off).
+    pub chroma_blur: f32,
+    /// Luma pre-filter strength from 0 (off) to 100, like cjpeg's `-smooth`.
+    /// Tames dithered or noisy sources before the DCT. Defaults to `0`.
+    pub smoothing: u32,
+    /// Strength of the unsharp mask applied to the reconstructed luma
+    /// (`1.0` doubles edge contrast). Defaults to `0.0` (off).
+    pub sharpen_amount: f32,
+    /// Gaussian radius (standard deviation in pixels) of the unsharp mask.
+    /// Defaults to `1.0`.
+    pub sharpen_radius: f32,
+    /// Minimum luma difference (0–255) the unsharp mask acts on. Defaults to `0.0`.
+    pub sharpen_threshold: f32,
+    /// Smooth the block edges of the reconstruction, with a strength that
+    /// follows the quantization steps. Skipped with `block_overlap`, which
+    /// leaves no edges. Defaults to `false`.
+    pub deblock: bool,
+    /// Flatten the ringing next to strong edges of the reconstruction, as
+    /// around text on a flat background. Defaults to `false`.
+    pub dering: bool,
+    /// Noise-reduction pre-filter. Chroma is filtered more strongly than luma.
+    /// Defaults to `None`.
+    pub denoise: Denoise,
+    /// Noise standard deviation (in 0–255 levels) assumed by `Denoise::Manual`.
+    /// Defaults to `5.0`.
+    pub denoise_strength: f32,
+    /// Color-reduction pre-stage. Defaults to `None`.
+    pub posterize: Posterize,
+    /// Steps per channel (`Levels`) or palette size (`MedianCut`, up to 256).
+    /// Defaults to `8`.
+    pub posterize_levels: u32,
+    /// Floyd–Steinberg dithering while posterizing. Defaults to `false`.
+    pub posterize_dither: bool,
+    /// Compute the SSIM of the output in `compress_jpeg_with_result`.
+    /// Defaults to `false`.
+    pub measure_ssim: bool,
+    /// Compute a 4x3 BlurHash of the input in `compress_jpeg_with_result`.
+    /// Defaults to `false`.
+    pub measure_blurhash: bool,
+    /// Compute the average and dominant colors of the input in
+    /// `compress_jpeg_with_result`. Defaults to `false`.
+    pub measure_colors: bool,
+    /// Detect an earlier JPEG encode of the input and recompress on its 8x8 grid
+    /// with compatible quantization steps, so re-saving adds little new damage.
+    /// Defaults to `false`.
+    pub reuse_prior_grid: bool,
+    /// Fraction (0.0–1.0) of quantized DCT coefficients in the glitch band that
+    /// are moved by a random number of steps, for JPEG glitch art. Only applies
+    /// when `compression` is above 0. Defaults to `0.0` (off).
+    pub glitch_amount: f32,
+    /// First zigzag index (0 is DC, 63 the highest frequency) of the band
+    /// `glitch_amount` acts on. Defaults to `0`.
+    pub glitch_band_start: u32,
+    /// Last zigzag index (inclusive) of the glitch band. Defaults to `63`.
+    pub glitch_band_end: u32,
+    /// Seed picking which coefficients glitch and how; the same seed always
+    /// gives the same output. Defaults to `0`.
+    pub glitch_seed: u32,
+    /// Sign flips or swaps of quantized AC coefficients, after any glitch.
+    /// Only applies when `compression` is above 0. Defaults to `None`.
+    pub coefficient_effect: CoefficientEffect,
+    /// Fraction (0.0–1.0) of the AC coefficients `coefficient_effect` acts on.
+    /// Defaults to `0.25`.
+    pub coefficient_effect_amount: f32,
+    /// Seed picking the coefficients `coefficient_effect` acts on. Defaults to `0`.
+    pub coefficient_effect_seed: u32,
+    /// Datamosh-style displacement of quantized 8x8 blocks, per plane. Only
+    /// applies when `compression` is above 0. Defaults to `None`.
+    pub block_shuffle: BlockShuffle,
+    /// Fraction (0.0–1.0) of the blocks `block_shuffle` moves. Defaults to `0.1`.
+    pub block_shuffle_amount: f32,
+    /// Farthest a block moves horizontally and vertically, in blocks.
+    /// Defaults to `4`.
+    pub block_shuffle_radius: u32,
+    /// Seed picking which blocks move and where. Defaults to `0`.
+    pub block_shuffle_seed: u32,
+    /// Horizontal shift of the Cb plane, in subsampled chroma samples, applied
+    /// before upsampling (positive moves right). Defaults to `0`.
+    pub cb_shift_x: i32,
+    /// Vertical shift of the Cb plane (positive moves down). Defaults to `0`.
+    pub cb_shift_y: i32,
+    /// Horizontal shift of the Cr plane. Defaults to `0`.
+    pub cr_shift_x: i32,
+    /// Vertical shift of the Cr plane. Defaults to `0`.
+    pub cr_shift_y: i32,
+    /// Plane reconstructed as luma. Defaults to `Y`.
+    pub y_source: PlaneSource,
+    /// Plane reconstructed as Cb. Defaults to `Cb`.
+    pub cb_source: PlaneSource,
+    /// Plane reconstructed as Cr. Defaults to `Cr`.
+    pub cr_source: PlaneSource,
+    /// Left edge of the region of the input to process, in pixels. Defaults to `0`.
+    pub crop_x: u32,
+    /// Top edge of the region of the input to process. Defaults to `0`.
+    pub crop_y: u32,
+    /// Width of the region of the input to process, clipped to the image; `0`
+    /// processes the whole image. Only the region is returned. Applies to
+    /// `compress_jpeg_with_options`, `compress_jpeg_with_result` and
+    /// `compress_pixels`. Defaults to `0`.
+    pub crop_width: u32,
+    /// Height of the region of the input to process, clipped to the image;
+    /// `0` processes the whole image. Defaults to `0`.
+    pub crop_height: u32,
+    /// Rotation or mirroring applied after the crop, in the same functions.
+    /// Quarter turns and diagonal mirrors swap the output's width and height.
+    /// Defaults to `None`.
+    pub orientation: Orientation,
+}
+
+impl Default for CompressOptions {
+    fn default() -> Self {
+        CompressOptions {
+            compression: 0.5,
+            compression_scale: CompressionScale::Linear,
+            quality_gradient: QualityGradient::None,
+            gradient_compression: 1.0,
+            block_size: BlockSize::Size8,
+            block_overlap: false,
+            degradation: Degradation::Quantize,
+            keep_coefficients: 64,
+            frequency_filter: FrequencyFilter::None,
+            frequency_measure: FrequencyMeasure::Zigzag,
+            frequency_min: 0.0,
+            frequency_max: 63.0,
+            frequency_attenuation: 0.0,
+            subsampling: ChromaSubsampling::Yuv420,
+            chroma_downsampling: ChromaDownsampling::Box,
+            chroma_upsampling: ChromaUpsampling::Bilinear,
+            chroma_siting: ChromaSiting::Centered,
+            color_transform: ColorTransform::YCbCr,
+            color_matrix: ColorMatrix::Bt601,
+            color_range: ColorRange::Full,
+            detect_grayscale: true,
+            alpha_mode: AlphaMode::Preserve,
+            alpha_compression: 0.5,
+            premultiplied_alpha: false,
+            background: 0xFFFFFF,
+            transfer_function: TransferFunction::Srgb,
+            linear_light: false,
+            tone_mapping: ToneMapping::None,
+            exposure: 0.0,
+            resize_filter: ResizeFilter::Lanczos3,
+            dither: Dither::None,
+            dither_strength: 0.5,
+            error_diffusion: false,
+            chroma_blur: 0.0,
+            smoothing: 0,
+            sharpen_amount: 0.0,
+            sharpen_radius: 1.0,
+            sharpen_threshold: 0.0,
+            deblock: false,
+            dering: false,
+            denoise: Denoise::None,
+            denoise_strength: 5.0,
+            posterize: Posterize::None,
+            posterize_levels: 8,
+            posterize_dither: false,
+            measure_ssim: false,
+            measure_blurhash: false,
+            measure_colors: false,
+            reuse_prior_grid: false,
+            glitch_amount: 0.0,
+            glitch_band_start: 0,
+            glitch_band_end: 63,
+            glitch_seed: 0,
+            coefficient_effect: CoefficientEffect::None,
+            coefficient_effect_amount: 0.25,
+            coefficient_effect_seed: 0,
+            block_shuffle: BlockShuffle::None,
+            block_shuffle_amount: 0.1,
+            block_shuffle_radius: 4,
+            block_shuffle_seed: 0,
+            cb_shift_x: 0,
+            cb_shift_y: 0,
+            cr_shift_x: 0,
+            cr_shift_y: 0,
+            y_source: PlaneSource::Y,
+            cb_source: PlaneSource::Cb,
+            cr_source: PlaneSource::Cr,
+            crop_x: 0,
+            crop_y: 0,
+            crop_width: 0,
+            crop_height: 0,
+            orientation: Orientation::None,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl CompressOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> CompressOptions {
+        CompressOptions::default()
+    }
+}
@@ -0,0 +1,108 @@
+use wasm_bindgen::prelude::*;
+
+use crate::filters;
+use crate::options::TransferFunction;
+
+/// Result of `perceptual_distance`: a butteraugli-style score and the
+/// per-pixel distortion map it was pooled from.
+///
+/// Distances are calibrated so that around 1.0 differences start to become
+/// noticeable side by side; above about 2.0 they are plainly visible.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct PerceptualDistance {
+    /// Largest distance anywhere in the image (butteraugli's headline number).
+    pub score: f64,
+    /// 3-norm of the distance map; less sensitive to a single bad spot than `score`.
+    pub pnorm: f64,
+    /// Width of `map` in pixels.
+    pub width: u32,
+    /// Height of `map` in pixels.
+    pub height: u32,
+    map: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl PerceptualDistance {
+    /// Per-pixel distances, row-major, `width * height` values.
+    #[wasm_bindgen(getter)]
+    pub fn map(&self) -> Vec<f32> {
+        self.map.clone()
+    }
+}
+
+/// Standard deviation (in pixels) separating low from high frequencies.
+const BAND_SIGMA: f32 = 1.5;
+/// Standard deviation (in pixels) of the neighborhood that masks high-frequency errors.
+const MASK_SIGMA: f32 = 3.0;
+/// Local high-frequency activity at which an error's visibility is halved.
+const MASK_HALF: f32 = 0.015;
+/// Weights of the high-frequency Y, low-frequency Y, X and B differences.
+const WEIGHTS: [f32; 4] = [1.0, 0.6, 3.0, 0.3];
+/// Scale bringing the distance to butteraugli-like units.
+const SCALE: f32 = 60.0;
+
+/// Computes the perceptual distance between two sRGB RGBA buffers.
+pub(crate) fn perceptual_distance(
+    original: &[u8],
+    compressed: &[u8],
+    width: usize,
+    height: usize,
+) -> PerceptualDistance {
+    let a = opponent_planes(original);
+    let b = opponent_planes(compressed);
+    let blur = |plane: &[f32], sigma| filters::gaussian_blur(plane, width, height, sigma);
+
+    // Split luma into bands; chroma only matters at low frequencies.
+    let (a_low, b_low) = (blur(&a[1], BAND_SIGMA), blur(&b[1], BAND_SIGMA));
+    let a_high: Vec<f32> = a[1].iter().zip(&a_low).map(|(v, l)| v - l).collect();
+    let b_high: Vec<f32> = b[1].iter().zip(&b_low).map(|(v, l)| v - l).collect();
+    let activity: Vec<f32> = a_high.iter().map(|v| v.abs()).collect();
+    let mask = blur(&activity, MASK_SIGMA);
+    let (a_x, b_x) = (blur(&a[0], BAND_SIGMA), blur(&b[0], BAND_SIGMA));
+    let (a_b, b_b) = (blur(&a[2], BAND_SIGMA), blur(&b[2], BAND_SIGMA));
+
+    let map: Vec<f32> = (0..width * height)
+        .map(|i| {
+            let masking = 1.0 / (1.0 + mask[i] / MASK_HALF);
+            let high = (a_high[i] - b_high[i]) * masking;
+            let low = a_low[i] - b_low[i];
+            let x = a_x[i] - b_x[i];
+            let blue = a_b[i] - b_b[i];
+            let sum = WEIGHTS[0] * high * high
+                + WEIGHTS[1] * low * low
+                + WEIGHTS[2] * x * x
+                + WEIGHTS[3] * blue * blue;
+            SCALE * sum.sqrt()
+        })
+        .collect();
+
+    let score = map.iter().fold(0.0f32, |m, &d| m.max(d)) as f64;
+    let cubes: f64 = map.iter().map(|&d| (d as f64).powi(3)).sum();
+    PerceptualDistance {
+        score,
+        pnorm: libm::cbrt(cubes / map.len() as f64),
+        width: width as u32,
+        height: height as u32,
+        map,
+    }
+}
+
+/// Converts sRGB RGBA to X, Y and B planes of an XYB-like opponent space:
+/// linear light, cone responses with cube-root compression, then red-green
+/// opponency (X), luminance (Y) and blue (B).
+fn opponent_planes(rgba: &[u8]) -> [Vec<f32>; 3] {
+    let n = rgba.len() / 4;
+    let mut planes = [vec![0.0; n], vec![0.0; n], vec![0.0; n]];
+    for (m_i, px) in rgba.chunks_exact(4).enumerate() {
+        let [r, g, b] =
+            [px[0], px[1], px[2]].map(|v| TransferFunction::Srgb.decode(v as f32 / 255.0));
+        let l = libm::cbrtf(0.30 * r + 0.622 * g + 0.078 * b + 0.0038);
+        let m = libm::cbrtf(0.23 * r + 0.692 * g + 0.078 * b + 0.0038);
+        let s = libm::cbrtf(0.2434 * r + 0.2048 * g + 0.5518 * b + 0.0038);
+        planes[0][m_i] = (l - m) / 2.0;
+        planes[1][m_i] = (l + m) / 2.0;
+        planes[2][m_i] = s;
+    }
+    planes
+}
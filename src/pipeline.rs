@@ -0,0 +1,1774 @@
+use std::borrow::Cow;
+
+use crate::artifacts;
+use crate::coefficients::{self, CoefficientDump};
+use crate::color::ColorConverter;
+use crate::dct::{self, process_blocks, BlockStats};
+use crate::decoder;
+use crate::effects;
+use crate::encoder::{self, ChunkedEncoder};
+use crate::error::Error;
+use crate::filters;
+use crate::heatmap::ImagePlane;
+use crate::options::{
+    AlphaMode, ChromaSubsampling, ColorMatrix, ColorRange, ColorTransform, CompressOptions,
+    CompressionScale, Denoise, Orientation, PixelFormat, Posterize, ResizeFilter, ToneMapping,
+    TransferFunction, YuvLayout,
+};
+use crate::postfilter;
+use crate::quant::{self, Band, Quantizer};
+use crate::regions::{CompressionMap, QualityRegion};
+use crate::sampling;
+
+/// Decoded pixels on a 0–255 float scale, independent of the input format.
+#[derive(Clone)]
+pub(crate) struct Frame {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) rgb: Vec<[f32; 3]>,
+    /// Straight or premultiplied alpha, `None` for opaque formats.
+    pub(crate) alpha: Option<Vec<f32>>,
+}
+
+impl Frame {
+    /// Decodes an interleaved 8-bit buffer.
+    pub(crate) fn from_pixels(
+        data: &[u8],
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+    ) -> Result<Frame, Error> {
+        check_dimensions(width, height, data.len(), format.channels())?;
+
+        let [r, g, b] = format.rgb_offsets();
+        let pixels = data.chunks_exact(format.channels());
+        Ok(Frame {
+            width,
+            height,
+            rgb: pixels.clone().map(|px| [px[r] as f32, px[g] as f32, px[b] as f32]).collect(),
+            alpha: format.alpha_offset().map(|a| pixels.map(|px| px[a] as f32).collect()),
+        })
+    }
+
+    /// Decodes 16-bit RGBA, keeping the extra precision as fractional 0–255 values.
+    pub(crate) fn from_rgba16(data: &[u16], width: usize, height: usize) -> Result<Frame, Error> {
+        check_dimensions(width, height, data.len(), 4)?;
+
+        let pixels = data.chunks_exact(4);
+        let scale = |v: u16| v as f32 / 257.0;
+        Ok(Frame {
+            width,
+            height,
+            rgb: pixels.clone().map(|px| [scale(px[0]), scale(px[1]), scale(px[2])]).collect(),
+            alpha: Some(pixels.map(|px| scale(px[3])).collect()),
+        })
+    }
+
+    /// Encodes to 16-bit RGBA, rounding and clamping every channel.
+    pub(crate) fn to_rgba16(&self) -> Vec<u16> {
+        let scale = |v: f32| (v * 257.0).round().clamp(0.0, 65535.0) as u16;
+        self.rgb
+            .iter()
+            .enumerate()
+            .flat_map(|(m_i, &[r, g, b])| {
+                let a = self.alpha.as_ref().map_or(255.0, |a| a[m_i]);
+                [scale(r), scale(g), scale(b), scale(a)]
+            })
+            .collect()
+    }
+
+    /// Decodes linear-light float RGBA (1.0 = white), encoding color with `transfer`.
+    pub(crate) fn from_rgba_f32(
+        data: &[f32],
+        width: usize,
+        height: usize,
+        transfer: TransferFunction,
+    ) -> Result<Frame, Error> {
+        check_dimensions(width, height, data.len(), 4)?;
+
+        let pixels = data.chunks_exact(4);
+        let encode = |v: f32| transfer.encode(v) * 255.0;
+        Ok(Frame {
+            width,
+            height,
+            rgb: pixels.clone().map(|px| [encode(px[0]), encode(px[1]), encode(px[2])]).collect(),
+            alpha: Some(pixels.map(|px| px[3] * 255.0).collect()),
+        })
+    }
+
+    /// Encodes to linear-light float RGBA, undoing `transfer`. Values are not clamped.
+    pub(crate) fn to_rgba_f32(&self, transfer: TransferFunction) -> Vec<f32> {
+        let decode = |v: f32| transfer.decode(v / 255.0);
+        self.rgb
+            .iter()
+            .enumerate()
+            .flat_map(|(m_i, &[r, g, b])| {
+                let a = self.alpha.as_ref().map_or(1.0, |a| a[m_i] / 255.0);
+                [decode(r), decode(g), decode(b), a]
+            })
+            .collect()
+    }
+
+    /// Applies `options.exposure` and `options.tone_mapping` in linear light.
+    ///
+    /// A no-op when neither is set, so in-range input passes through untouched.
+    pub(crate) fn tone_map(&mut self, options: &CompressOptions) {
+        if !tone_maps(options) {
+            return;
+        }
+        let transfer = options.transfer_function;
+        let gain = libm::exp2f(options.exposure);
+        let mapping = options.tone_mapping;
+        for px in self.rgb.iter_mut() {
+            *px = px.map(|c| {
+                let linear = transfer.decode(c / 255.0) * gain;
+                transfer.encode(mapping.apply(linear)) * 255.0
+            });
+        }
+    }
+
+    /// Grows the frame by `left` columns and `top` rows that repeat its edge.
+    fn pad(&self, left: usize, top: usize) -> Frame {
+        let (width, height) = (self.width + left, self.height + top);
+        let source = |i: usize| {
+            let (x, y) = (
+                (i % width).saturating_sub(left),
+                (i / width).saturating_sub(top),
+            );
+            y * self.width + x
+        };
+        Frame {
+            width,
+            height,
+            rgb: (0..width * height).map(|i| self.rgb[source(i)]).collect(),
+            alpha: self
+                .alpha
+                .as_ref()
+                .map(|a| (0..width * height).map(|i| a[source(i)]).collect()),
+        }
+    }
+
+    /// Scales the frame down to `width`x`height` (no larger than it is),
+    /// averaging the pixels each output pixel covers.
+    pub(crate) fn shrink(&self, width: usize, height: usize) -> Frame {
+        // Source pixels under the output pixels `i..i + 1` of an axis.
+        let span = |i: usize, len: usize, source_len: usize| {
+            let from = i * source_len / len;
+            from..((i + 1) * source_len).div_ceil(len).max(from + 1)
+        };
+        let covered = |i: usize| {
+            let xs = span(i % width, width, self.width);
+            let ys = span(i / width, height, self.height);
+            ys.flat_map(move |y| xs.clone().map(move |x| y * self.width + x))
+        };
+        let rgb = (0..width * height)
+            .map(|i| {
+                let (sum, count) = covered(i).fold(([0.0; 3], 0), |(sum, count), j| {
+                    let px = self.rgb[j];
+                    ([sum[0] + px[0], sum[1] + px[1], sum[2] + px[2]], count + 1)
+                });
+                sum.map(|c| c / count as f32)
+            })
+            .collect();
+        let alpha = self.alpha.as_ref().map(|a| {
+            (0..width * height)
+                .map(|i| {
+                    let (sum, count) = covered(i).fold((0.0, 0), |(s, n), j| (s + a[j], n + 1));
+                    sum / count as f32
+                })
+                .collect()
+        });
+        Frame {
+            width,
+            height,
+            rgb,
+            alpha,
+        }
+    }
+
+    /// Resamples the frame to `width`x`height` with `filter`.
+    ///
+    /// Unless the color is `premultiplied` already, it is weighted by alpha
+    /// while filtering, so fully transparent pixels don't bleed their hidden
+    /// color into the edges. Overshoot below zero, and alpha beyond 0–255, is
+    /// clipped.
+    pub(crate) fn resize(
+        &self,
+        width: usize,
+        height: usize,
+        filter: ResizeFilter,
+        premultiplied: bool,
+    ) -> Frame {
+        let resize = |plane: &[f32]| {
+            sampling::resize(plane, self.width, self.height, width, height, filter)
+        };
+        let alpha = self.alpha.as_ref().map(|a| {
+            resize(a).into_iter().map(|v| v.clamp(0.0, 255.0)).collect::<Vec<_>>()
+        });
+        let weighted = !premultiplied && alpha.is_some();
+        let weight = |a: Option<&Vec<f32>>, i: usize| match a {
+            Some(a) if weighted => a[i] / 255.0,
+            _ => 1.0,
+        };
+        let channels: Vec<Vec<f32>> = (0..3)
+            .map(|c| {
+                let plane: Vec<f32> = self
+                    .rgb
+                    .iter()
+                    .enumerate()
+                    .map(|(i, px)| px[c] * weight(self.alpha.as_ref(), i))
+                    .collect();
+                resize(&plane)
+            })
+            .collect();
+        let rgb = (0..width * height)
+            .map(|i| {
+                let a = weight(alpha.as_ref(), i);
+                [0, 1, 2].map(|c| if a > 0.0 { (channels[c][i] / a).max(0.0) } else { 0.0 })
+            })
+            .collect();
+        Frame {
+            width,
+            height,
+            rgb,
+            alpha,
+        }
+    }
+
+    /// Drops the first `left` columns and `top` rows, undoing [`Frame::pad`].
+    fn crop(&self, left: usize, top: usize) -> Frame {
+        self.region(left, top, self.width - left, self.height - top)
+    }
+
+    /// The `width`x`height` rectangle at (`left`, `top`), which must lie
+    /// inside the frame.
+    fn region(&self, left: usize, top: usize, width: usize, height: usize) -> Frame {
+        let source = |i: usize| (i / width + top) * self.width + i % width + left;
+        Frame {
+            width,
+            height,
+            rgb: (0..width * height).map(|i| self.rgb[source(i)]).collect(),
+            alpha: self
+                .alpha
+                .as_ref()
+                .map(|a| (0..width * height).map(|i| a[source(i)]).collect()),
+        }
+    }
+
+    /// Copies `other` over the frame with its top-left corner at (`left`,
+    /// `top`), where it must fit.
+    fn paste(&mut self, other: &Frame, left: usize, top: usize) {
+        for y in 0..other.height {
+            let (from, to) = (y * other.width, (y + top) * self.width + left);
+            let len = other.width;
+            self.rgb[to..to + len].copy_from_slice(&other.rgb[from..from + len]);
+            // Sprites made opaque by the alpha policy stay opaque on the sheet.
+            match (self.alpha.as_mut(), other.alpha.as_ref()) {
+                (Some(alpha), Some(source)) => {
+                    alpha[to..to + len].copy_from_slice(&source[from..from + len])
+                }
+                (Some(alpha), None) => alpha[to..to + len].fill(255.0),
+                (None, _) => {}
+            }
+        }
+    }
+
+    /// Clamps and truncates every channel to the 8-bit value
+    /// [`Frame::to_pixels`] would store.
+    fn stored(mut self) -> Frame {
+        let store = |c: f32| c.clamp(0.0, 255.0).trunc();
+        for px in self.rgb.iter_mut() {
+            *px = px.map(store);
+        }
+        for a in self.alpha.iter_mut().flatten() {
+            *a = store(*a);
+        }
+        self
+    }
+
+    /// Encodes back to an interleaved 8-bit buffer, clamping every channel.
+    pub(crate) fn to_pixels(&self, format: PixelFormat) -> Vec<u8> {
+        let channels = format.channels();
+        let [r_at, g_at, b_at] = format.rgb_offsets();
+        let mut output = vec![0u8; self.rgb.len() * channels];
+
+        for (m_i, px) in output.chunks_exact_mut(channels).enumerate() {
+            let [r, g, b] = self.rgb[m_i];
+            px[r_at] = r.clamp(0.0, 255.0) as u8;
+            px[g_at] = g.clamp(0.0, 255.0) as u8;
+            px[b_at] = b.clamp(0.0, 255.0) as u8;
+            if let Some(offset) = format.alpha_offset() {
+                px[offset] = self.alpha.as_ref().map_or(255.0, |a| a[m_i]).clamp(0.0, 255.0) as u8;
+            }
+            if let Some(offset) = format.padding_offset() {
+                px[offset] = 255;
+            }
+        }
+        output
+    }
+}
+
+/// Luma and (subsampled) chroma planes flowing through the block stage.
+#[derive(Clone)]
+pub(crate) struct Planes {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) y: Vec<f32>,
+    /// `None` when the input was detected as grayscale.
+    pub(crate) chroma: Option<ChromaPlanes>,
+    /// Per-block results of the last block pass for Y, Cb and Cr (empty until quantized).
+    pub(crate) block_stats: [Vec<BlockStats>; 3],
+}
+
+#[derive(Clone)]
+pub(crate) struct ChromaPlanes {
+    pub(crate) cb: Vec<f32>,
+    pub(crate) cr: Vec<f32>,
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) fx: usize,
+    pub(crate) fy: usize,
+}
+
+impl ChromaPlanes {
+    /// The Cb and Cr planes moved by the configured chroma shifts.
+    fn shifted(&self, options: &CompressOptions) -> (Vec<f32>, Vec<f32>) {
+        let (w, h) = (self.width, self.height);
+        (
+            effects::shift_plane(&self.cb, w, h, options.cb_shift_x, options.cb_shift_y),
+            effects::shift_plane(&self.cr, w, h, options.cr_shift_x, options.cr_shift_y),
+        )
+    }
+}
+
+impl Planes {
+    /// Converts RGB to luma/chroma and subsamples the chroma planes.
+    ///
+    /// Grayscale input carries no chroma, so only the luma plane is kept.
+    fn from_rgb(rgb: &[[f32; 3]], width: usize, height: usize, options: &CompressOptions) -> Planes {
+        let converter = ColorConverter::new(options);
+        let mut y = vec![0.0; width * height];
+        let mut cb = vec![0.0; width * height];
+        let mut cr = vec![0.0; width * height];
+        for (m_i, &[r, g, b]) in rgb.iter().enumerate() {
+            (y[m_i], cb[m_i], cr[m_i]) = converter.forward(r, g, b);
+        }
+
+        let noise = match options.denoise {
+            Denoise::None => 0.0,
+            Denoise::Auto => filters::estimate_noise(&y, width, height),
+            Denoise::Manual => options.denoise_strength,
+        };
+        // Chroma noise is far less visible than the color detail it hides, so
+        // the chroma planes tolerate a much wider range kernel.
+        let y = filters::bilateral(&y, width, height, LUMA_DENOISE_RANGE * noise);
+        let cb = filters::bilateral(&cb, width, height, CHROMA_DENOISE_RANGE * noise);
+        let cr = filters::bilateral(&cr, width, height, CHROMA_DENOISE_RANGE * noise);
+
+        let y = filters::smooth(&y, width, height, options.smoothing);
+
+        if options.detect_grayscale && is_grayscale(rgb) {
+            return Planes {
+                width,
+                height,
+                y,
+                chroma: None,
+                block_stats: Default::default(),
+            };
+        }
+
+        // Softening chroma before subsampling keeps saturated edges from ringing
+        // and bleeding into their neighbors once the planes are quantized.
+        let cb = filters::gaussian_blur(&cb, width, height, options.chroma_blur);
+        let cr = filters::gaussian_blur(&cr, width, height, options.chroma_blur);
+
+        let (fx, fy) = options.subsampling.factors();
+        let siting = options.chroma_siting;
+        let method = options.chroma_downsampling;
+        let (cb, sub_w, sub_h) = sampling::downsample(&cb, width, height, fx, fy, method, siting);
+        let (cr, _, _) = sampling::downsample(&cr, width, height, fx, fy, method, siting);
+
+        Planes {
+            width,
+            height,
+            y,
+            chroma: Some(ChromaPlanes { cb, cr, width: sub_w, height: sub_h, fx, fy }),
+            block_stats: Default::default(),
+        }
+    }
+
+    /// Wraps an already-planar 4:2:0 buffer, skipping color conversion and subsampling.
+    ///
+    /// Sample values are interpreted with the configured `color_matrix` and `color_range`.
+    fn from_yuv(data: &[u8], width: usize, height: usize, layout: YuvLayout) -> Result<Planes, Error> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidDimensions);
+        }
+        let (sub_w, sub_h) = sampling::subsampled_size(width, height, 2, 2);
+        let luma_len = width * height;
+        let chroma_len = sub_w * sub_h;
+        if data.len() != luma_len + 2 * chroma_len {
+            return Err(Error::BufferMismatch);
+        }
+
+        let to_f32 = |s: &[u8]| s.iter().map(|&v| v as f32).collect::<Vec<f32>>();
+        let (luma, chroma) = data.split_at(luma_len);
+        let (cb, cr) = match layout {
+            YuvLayout::I420 => {
+                let (u, v) = chroma.split_at(chroma_len);
+                (to_f32(u), to_f32(v))
+            }
+            YuvLayout::Nv12 => (
+                chroma.iter().step_by(2).map(|&v| v as f32).collect(),
+                chroma.iter().skip(1).step_by(2).map(|&v| v as f32).collect(),
+            ),
+        };
+
+        Ok(Planes {
+            width,
+            height,
+            y: to_f32(luma),
+            chroma: Some(ChromaPlanes { cb, cr, width: sub_w, height: sub_h, fx: 2, fy: 2 }),
+            block_stats: Default::default(),
+        })
+    }
+
+    /// Runs the DCT/quantization block pass over every plane.
+    fn quantize(&mut self, q: &Quantizer) {
+        let coefficients = if q.block_size != 8 {
+            // Other block sizes transform the planes themselves.
+            self.forward_with(|_, _, _| Vec::new())
+        } else if q.dc_only() {
+            self.forward_with(dct::forward_dc_blocks)
+        } else {
+            self.forward()
+        };
+        self.reconstruct(&coefficients, q);
+    }
+
+    /// Forward DCT of the Y, Cb and Cr planes (chroma empty for grayscale).
+    pub(crate) fn forward(&self) -> [Vec<[[f32; 8]; 8]>; 3] {
+        self.forward_with(dct::forward_blocks)
+    }
+
+    /// [`Planes::forward`] with another block transform.
+    fn forward_with<F>(&self, transform: F) -> [Vec<[[f32; 8]; 8]>; 3]
+    where
+        F: Fn(&[f32], usize, usize) -> Vec<[[f32; 8]; 8]>,
+    {
+        let y = transform(&self.y, self.width, self.height);
+        match &self.chroma {
+            None => [y, Vec::new(), Vec::new()],
+            Some(c) => [
+                y,
+                transform(&c.cb, c.width, c.height),
+                transform(&c.cr, c.width, c.height),
+            ],
+        }
+    }
+
+    /// Replaces the planes with the quantized reconstruction of `coefficients`,
+    /// which must come from [`Planes::forward`] on these same planes.
+    fn reconstruct(&mut self, coefficients: &[Vec<[[f32; 8]; 8]>; 3], q: &Quantizer) {
+        let (w, h) = (self.width, self.height);
+        let (y, y_stats) = block_pass(&coefficients[0], &self.y, w, h, q);
+        self.y = y;
+        self.block_stats[0] = y_stats;
+        if let Some(c) = self.chroma.as_mut() {
+            let (w, h) = (c.width, c.height);
+            let (cb, cb_stats) = block_pass(&coefficients[1], &c.cb, w, h, &q.plane(1));
+            let (cr, cr_stats) = block_pass(&coefficients[2], &c.cr, w, h, &q.plane(2));
+            (c.cb, c.cr) = (cb, cr);
+            self.block_stats[1] = cb_stats;
+            self.block_stats[2] = cr_stats;
+        }
+    }
+
+    /// Sharpens the reconstructed luma to counteract the softening from
+    /// quantization and chroma subsampling.
+    fn sharpen(&mut self, options: &CompressOptions) {
+        self.y = filters::unsharp_mask(
+            &self.y,
+            self.width,
+            self.height,
+            options.sharpen_amount,
+            options.sharpen_radius,
+            options.sharpen_threshold,
+        );
+    }
+
+    /// Serializes the planes as I420 (Y, then U, then V at 4:2:0 resolution).
+    ///
+    /// Chroma stored at another subsampling is resampled to 4:2:0 first.
+    pub(crate) fn to_i420(&self, options: &CompressOptions) -> Vec<u8> {
+        let (w, h) = (self.width, self.height);
+        let (sub_w, sub_h) = sampling::subsampled_size(w, h, 2, 2);
+        let (cb, cr) = match &self.chroma {
+            None => (vec![128.0; sub_w * sub_h], vec![128.0; sub_w * sub_h]),
+            Some(c) if (c.fx, c.fy) == (2, 2) => c.shifted(options),
+            Some(c) => {
+                let (up, down) = (options.chroma_upsampling, options.chroma_downsampling);
+                let siting = options.chroma_siting;
+                let resample = |plane: &[f32]| {
+                    let full = sampling::upsample(plane, w, h, c.fx, c.fy, up, siting);
+                    sampling::downsample(&full, w, h, 2, 2, down, siting).0
+                };
+                let (cb, cr) = c.shifted(options);
+                (resample(&cb), resample(&cr))
+            }
+        };
+
+        let (lo, y_hi, c_hi) = match options.color_range {
+            ColorRange::Full => (0.0, 255.0, 255.0),
+            ColorRange::Limited => (16.0, 235.0, 240.0),
+        };
+        let luma = self.y.iter().map(|&v| v.round().clamp(lo, y_hi) as u8);
+        let chroma = cb.into_iter().chain(cr).map(|v| v.round().clamp(lo, c_hi) as u8);
+        luma.chain(chroma).collect()
+    }
+
+    /// Upsamples the chroma planes, applies the plane remapping and converts
+    /// back to (unclamped) RGB.
+    fn to_rgb(&self, options: &CompressOptions) -> Vec<[f32; 3]> {
+        let converter = ColorConverter::new(options);
+        let n = self.width * self.height;
+        let (cb, cr) = match &self.chroma {
+            None => (vec![128.0; n], vec![128.0; n]),
+            Some(c) => {
+                let method = options.chroma_upsampling;
+                let siting = options.chroma_siting;
+                let (w, h) = (self.width, self.height);
+                let (cb, cr) = c.shifted(options);
+                (
+                    sampling::upsample(&cb, w, h, c.fx, c.fy, method, siting),
+                    sampling::upsample(&cr, w, h, c.fx, c.fy, method, siting),
+                )
+            }
+        };
+        let [y, cb, cr] = effects::remap_planes([self.y.clone(), cb, cr], options);
+
+        (0..n)
+            .map(|m_i| {
+                let (r, g, b) = converter.inverse(y[m_i], cb[m_i], cr[m_i]);
+                [r, g, b]
+            })
+            .collect()
+    }
+}
+
+/// Runs the block pass over one plane. 8x8 blocks start from its cached
+/// `coefficients`; other block sizes transform `input` themselves and report
+/// no statistics. Lapped passes report the statistics of the aligned grid;
+/// deblocking and deringing come after the statistics are taken.
+fn block_pass(
+    coefficients: &[[[f32; 8]; 8]],
+    input: &[f32],
+    w: usize,
+    h: usize,
+    q: &Quantizer,
+) -> (Vec<f32>, Vec<BlockStats>) {
+    let (output, stats) = match q.block_size {
+        8 => dct::reconstruct_blocks(coefficients, input, w, h, q),
+        n => (dct::process_sized_blocks(input, w, h, n, q), Vec::new()),
+    };
+    let mut output = if q.overlap {
+        dct::lapped_blocks(output, input, w, h, q)
+    } else {
+        output
+    };
+    let step = q.table[0][0] as f32 / 8.0;
+    if q.deblock && !q.overlap {
+        postfilter::deblock(&mut output, w, h, q.block_size, step);
+    }
+    if q.dering {
+        postfilter::dering(&mut output, w, h, q.block_size, step);
+    }
+    (output, stats)
+}
+
+/// Whether `options` set an exposure or tone mapping for [`Frame::tone_map`].
+pub(crate) fn tone_maps(options: &CompressOptions) -> bool {
+    options.tone_mapping != ToneMapping::None || options.exposure != 0.0
+}
+
+/// Runs the compression pipeline over an interleaved 8-bit buffer.
+///
+/// Formats without alpha are treated as fully opaque. The output uses the
+/// same layout as the input.
+pub(crate) fn compress(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    options: &CompressOptions,
+) -> Result<Vec<u8>, Error> {
+    let frame = Frame::from_pixels(data, width, height, format)?;
+    if options.compression <= 0.0 {
+        return Ok(data.to_vec());
+    }
+    Ok(compress_frame(frame, options).to_pixels(format))
+}
+
+/// Cuts the region `options.crop_*` selects out of an interleaved buffer of
+/// `channels` per pixel, returning it with its size. Without a crop the
+/// buffer is passed through as is.
+pub(crate) fn crop<'a>(
+    data: &'a [u8],
+    (width, height): (usize, usize),
+    channels: usize,
+    options: &CompressOptions,
+) -> Result<(Cow<'a, [u8]>, usize, usize), Error> {
+    check_dimensions(width, height, data.len(), channels)?;
+    if options.crop_width == 0 || options.crop_height == 0 {
+        return Ok((Cow::Borrowed(data), width, height));
+    }
+    let (x, y, w, h) = crop_rect((width, height), options)?;
+    let region = data
+        .chunks_exact(width * channels)
+        .skip(y)
+        .take(h)
+        .flat_map(|row| &row[x * channels..(x + w) * channels])
+        .copied()
+        .collect();
+    Ok((Cow::Owned(region), w, h))
+}
+
+/// The left and top edges, width and height of the region `options.crop_*`
+/// selects in an image of `width` by `height`, or of the whole image without
+/// a crop.
+pub(crate) fn crop_rect(
+    (width, height): (usize, usize),
+    options: &CompressOptions,
+) -> Result<(usize, usize, usize, usize), Error> {
+    if options.crop_width == 0 || options.crop_height == 0 {
+        return Ok((0, 0, width, height));
+    }
+    let (x, y) = (options.crop_x as usize, options.crop_y as usize);
+    if x >= width || y >= height {
+        return Err(Error::InvalidDimensions);
+    }
+    let w = (options.crop_width as usize).min(width - x);
+    let h = (options.crop_height as usize).min(height - y);
+    Ok((x, y, w, h))
+}
+
+/// Rotates or mirrors an interleaved buffer of `channels` per pixel as
+/// `orientation` says, returning it with its new size.
+pub(crate) fn orient(
+    data: Cow<'_, [u8]>,
+    (width, height): (usize, usize),
+    channels: usize,
+    orientation: Orientation,
+) -> (Cow<'_, [u8]>, usize, usize) {
+    if orientation == Orientation::None {
+        return (data, width, height);
+    }
+    let swapped = matches!(
+        orientation,
+        Orientation::Rotate90
+            | Orientation::Rotate270
+            | Orientation::Transpose
+            | Orientation::Transverse
+    );
+    let (w, h) = if swapped { (height, width) } else { (width, height) };
+    // Source pixel of output pixel (x, y).
+    let source = |x: usize, y: usize| match orientation {
+        Orientation::None => (x, y),
+        Orientation::FlipHorizontal => (width - 1 - x, y),
+        Orientation::FlipVertical => (x, height - 1 - y),
+        Orientation::Rotate90 => (y, height - 1 - x),
+        Orientation::Rotate180 => (width - 1 - x, height - 1 - y),
+        Orientation::Rotate270 => (width - 1 - y, x),
+        Orientation::Transpose => (y, x),
+        Orientation::Transverse => (width - 1 - y, height - 1 - x),
+    };
+    let mut out = vec![0; data.len()];
+    for (i, px) in out.chunks_exact_mut(channels).enumerate() {
+        let (sx, sy) = source(i % w, i / w);
+        let at = (sy * width + sx) * channels;
+        px.copy_from_slice(&data[at..at + channels]);
+    }
+    (Cow::Owned(out), w, h)
+}
+
+/// Applies the crop and then the orientation of `options` to an input buffer.
+pub(crate) fn transform_input<'a>(
+    data: &'a [u8],
+    size: (usize, usize),
+    channels: usize,
+    options: &CompressOptions,
+) -> Result<(Cow<'a, [u8]>, usize, usize), Error> {
+    let (data, width, height) = crop(data, size, channels, options)?;
+    Ok(orient(data, (width, height), channels, options.orientation))
+}
+
+/// Runs [`compress`] over an image whose rows arrive a few at a time, holding
+/// the rows of one band and its margins instead of the whole input.
+///
+/// Each band is compressed once the rows below it are in, with `margin` rows
+/// of context on both sides: enough for the filters before and after the
+/// block pass and for the blocks next to the band, so the result is the one
+/// the whole image would give. Settings that look at the whole image (see
+/// [`needs_whole_image`]) make the whole image one band. Grayscale detection
+/// is turned off for the rest of the image by the first band with color, so
+/// the gray bands above it keep no chroma.
+pub(crate) struct BandCompressor {
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    options: CompressOptions,
+    band_rows: usize,
+    margin: usize,
+    /// Input rows from image row `top` on.
+    rows: Vec<u8>,
+    top: usize,
+    /// First row not compressed yet.
+    next: usize,
+    output: Vec<u8>,
+}
+
+impl BandCompressor {
+    pub(crate) fn new(
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+        options: CompressOptions,
+    ) -> Result<BandCompressor, Error> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidDimensions);
+        }
+        let (band_rows, margin) = if needs_whole_image(&options) {
+            (height, 0)
+        } else {
+            // Bands start on a row of chroma blocks, so the grid is the image's.
+            let align = options.block_size.samples().max(8) * options.subsampling.factors().1;
+            let margin = band_margin(&options, align);
+            ((2 * margin).max(4 * align), margin)
+        };
+        Ok(BandCompressor {
+            width,
+            height,
+            format,
+            options,
+            band_rows,
+            margin,
+            rows: Vec::new(),
+            top: 0,
+            next: 0,
+            output: Vec::with_capacity(width * height * format.channels()),
+        })
+    }
+
+    /// Adds the next whole rows of the image, compressing every band they
+    /// complete. More rows than the image has are [`Error::BufferMismatch`].
+    pub(crate) fn push_rows(&mut self, data: &[u8]) -> Result<(), Error> {
+        let stride = self.width * self.format.channels();
+        let received = self.top + (self.rows.len() + data.len()) / stride;
+        if !data.len().is_multiple_of(stride) || received > self.height {
+            return Err(Error::BufferMismatch);
+        }
+        self.rows.extend_from_slice(data);
+        while self.next < self.height
+            && received >= (self.next + self.band_rows + self.margin).min(self.height)
+        {
+            self.compress_band()?;
+        }
+        Ok(())
+    }
+
+    /// The compressed image, as [`compress`] returns it. Missing rows are
+    /// [`Error::BufferMismatch`].
+    pub(crate) fn finish(self) -> Result<Vec<u8>, Error> {
+        if self.next < self.height {
+            return Err(Error::BufferMismatch);
+        }
+        Ok(self.output)
+    }
+
+    /// Compresses the band from row `next` and drops the rows no later band
+    /// needs.
+    fn compress_band(&mut self) -> Result<(), Error> {
+        let stride = self.width * self.format.channels();
+        let end = (self.next + self.band_rows).min(self.height);
+        let bottom = (end + self.margin).min(self.height);
+        let window = &self.rows[..(bottom - self.top) * stride];
+        let band = (self.next - self.top) * stride..(end - self.top) * stride;
+        if self.options.compression <= 0.0 {
+            self.output.extend_from_slice(&window[band]);
+        } else {
+            let frame = Frame::from_pixels(window, self.width, bottom - self.top, self.format)?;
+            if self.options.detect_grayscale && !is_grayscale(&frame.rgb) {
+                self.options.detect_grayscale = false;
+            }
+            let placement = Band {
+                top: self.top,
+                rows: bottom - self.top,
+                height: self.height,
+                fy: self.options.subsampling.factors().1,
+            };
+            let output = compress_band(frame, &self.options, placement).to_pixels(self.format);
+            self.output.extend_from_slice(&output[band]);
+        }
+        self.next = end;
+        let top = end.saturating_sub(self.margin).max(self.top);
+        self.rows.drain(..(top - self.top) * stride);
+        self.top = top;
+        Ok(())
+    }
+}
+
+/// Whether a setting of `options` makes every block depend on the whole
+/// image, which [`BandCompressor`] then takes as one band: error diffusion,
+/// the block shuffle, automatic denoising, palette or dithered posterizing
+/// and reuse of a prior grid.
+fn needs_whole_image(options: &CompressOptions) -> bool {
+    let posterize_dither = options.posterize != Posterize::None && options.posterize_dither;
+    options.error_diffusion
+        || effects::Displacement::new(options).moves_blocks()
+        || options.denoise == Denoise::Auto
+        || options.posterize == Posterize::MedianCut
+        || posterize_dither
+        || options.reuse_prior_grid
+}
+
+/// Rows of context above and below a band of [`BandCompressor`], a multiple
+/// of `align`.
+fn band_margin(options: &CompressOptions, align: usize) -> usize {
+    let fy = options.subsampling.factors().1;
+    let blur = |sigma: f32| if sigma > 0.0 { (3.0 * sigma).ceil() as usize } else { 0 };
+    let shift = options.cb_shift_y.unsigned_abs().max(options.cr_shift_y.unsigned_abs());
+    // Denoising, smoothing, chroma blur and downsampling before the block pass;
+    // sharpening, chroma shifts and upsampling after it.
+    let reach = 2 + 1 + blur(options.chroma_blur) + 3 * fy
+        + blur(options.sharpen_radius)
+        + (shift as usize + 3) * fy;
+    // Plus the block of every sample and the blocks deblocked, deringed and
+    // lapped with it.
+    reach.next_multiple_of(align) + 2 * align
+}
+
+/// Cuts every sprite rectangle out of a sheet and compresses it on its own,
+/// with the rectangle's compression, so no block mixes pixels of two
+/// sprites. Rectangles are clipped to the frame; one left empty is
+/// [`Error::InvalidDimensions`].
+///
+/// Returns the compressed sprites with their positions, in the order given.
+pub(crate) fn compress_sprites(
+    frame: &Frame,
+    sprites: &[QualityRegion],
+    options: &CompressOptions,
+) -> Result<Vec<(usize, usize, Frame)>, Error> {
+    sprites
+        .iter()
+        .map(|sprite| {
+            let (x, y) = (sprite.x as usize, sprite.y as usize);
+            let width = (sprite.width as usize).min(frame.width.saturating_sub(x));
+            let height = (sprite.height as usize).min(frame.height.saturating_sub(y));
+            if width == 0 || height == 0 {
+                return Err(Error::InvalidDimensions);
+            }
+            let region = frame.region(x, y, width, height);
+            if sprite.compression <= 0.0 {
+                return Ok((x, y, region));
+            }
+            let options = CompressOptions {
+                compression: sprite.compression.min(1.0),
+                ..options.clone()
+            };
+            Ok((x, y, compress_frame(region, &options)))
+        })
+        .collect()
+}
+
+/// Compresses every sprite of a sheet on its own with [`compress_sprites`]
+/// and puts them back in place. Pixels outside every sprite are left as they
+/// are; where sprites overlap, the later one wins.
+pub(crate) fn compress_sprite_sheet(
+    frame: &Frame,
+    sprites: &[QualityRegion],
+    options: &CompressOptions,
+) -> Result<Frame, Error> {
+    let mut sheet = frame.clone();
+    for (x, y, sprite) in compress_sprites(frame, sprites, options)? {
+        sheet.paste(&sprite, x, y);
+    }
+    Ok(sheet)
+}
+
+/// Splits a frame into `tile_size`-pixel squares, narrower or shorter at the
+/// right and bottom edges, and runs `work` on each. The tiles are spread over
+/// the rayon pool of the `threads` feature once it runs, else over threads
+/// where the platform has them (not in plain WebAssembly), and come back in
+/// raster order with their top-left corners.
+pub(crate) fn map_tiles<T: Send>(
+    frame: &Frame,
+    tile_size: usize,
+    work: impl Fn(Frame) -> T + Sync,
+) -> Vec<(usize, usize, T)> {
+    let rects: Vec<(usize, usize)> = (0..frame.height)
+        .step_by(tile_size)
+        .flat_map(|y| (0..frame.width).step_by(tile_size).map(move |x| (x, y)))
+        .collect();
+    let tile = |&(x, y): &(usize, usize)| {
+        let (w, h) = (tile_size.min(frame.width - x), tile_size.min(frame.height - y));
+        (x, y, work(frame.region(x, y, w, h)))
+    };
+    #[cfg(feature = "threads")]
+    if crate::threads::pool_ready() {
+        use rayon::prelude::*;
+        return rects.par_iter().map(tile).collect();
+    }
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    if threads <= 1 || rects.len() <= 1 {
+        return rects.iter().map(tile).collect();
+    }
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = rects
+            .chunks(rects.len().div_ceil(threads))
+            .map(|chunk| scope.spawn(|| chunk.iter().map(tile).collect::<Vec<_>>()))
+            .collect();
+        workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect()
+    })
+}
+
+/// Compresses every `tile_size`-pixel tile of a frame on its own, see
+/// [`map_tiles`].
+pub(crate) fn compress_tiles(
+    frame: &Frame,
+    tile_size: usize,
+    options: &CompressOptions,
+) -> Result<Vec<(usize, usize, Frame)>, Error> {
+    if tile_size == 0 {
+        return Err(Error::InvalidDimensions);
+    }
+    Ok(map_tiles(frame, tile_size, |tile| match options.compression <= 0.0 {
+        true => tile,
+        false => compress_frame(tile, options),
+    }))
+}
+
+/// Encodes every `tile_size`-pixel tile of a frame as its own baseline JPEG
+/// file, see [`map_tiles`].
+pub(crate) fn encode_tiles(
+    frame: &Frame,
+    tile_size: usize,
+    options: &CompressOptions,
+) -> Result<Vec<(usize, usize, Vec<u8>)>, Error> {
+    if tile_size == 0 {
+        return Err(Error::InvalidDimensions);
+    }
+    check_jpeg_dimensions(tile_size.min(frame.width), tile_size.min(frame.height))?;
+    let options = jfif_options(options);
+    Ok(map_tiles(frame, tile_size, |tile| CachedPlanes::new(tile, &options).encode_jpeg(&options)))
+}
+
+/// Scales an image to `target_width`x`target_height` with
+/// `options.resize_filter` and compresses the result.
+pub(crate) fn resize_and_compress(
+    data: &[u8],
+    (width, height): (usize, usize),
+    (target_width, target_height): (usize, usize),
+    format: PixelFormat,
+    options: &CompressOptions,
+) -> Result<Vec<u8>, Error> {
+    let frame = Frame::from_pixels(data, width, height, format)?;
+    if target_width == 0 || target_height == 0 {
+        return Err(Error::InvalidDimensions);
+    }
+    let filter = options.resize_filter;
+    let frame = frame.resize(target_width, target_height, filter, options.premultiplied_alpha);
+    if options.compression <= 0.0 {
+        return Ok(frame.to_pixels(format));
+    }
+    Ok(compress_frame(frame, options).to_pixels(format))
+}
+
+/// Applies the alpha policy, the color conversion and the block pass to a frame.
+pub(crate) fn compress_frame(frame: Frame, options: &CompressOptions) -> Frame {
+    compress_frame_with_stats(frame, options).0
+}
+
+/// Re-saves a frame `generations` times, passing every generation to
+/// `on_generation` and returning the last.
+///
+/// Each generation is stored as 8 bits before the next one is compressed, as
+/// a real decode would. With `jitter` above 0, every generation picks its
+/// compression up to `jitter` away from `options.compression` and moves the
+/// 8x8 grid by a few pixels, like an image edited or cropped between saves.
+pub(crate) fn simulate_generations(
+    mut frame: Frame,
+    options: &CompressOptions,
+    generations: u32,
+    jitter: f32,
+    mut on_generation: impl FnMut(&Frame),
+) -> Frame {
+    let jitter = jitter.clamp(0.0, 1.0);
+    for generation in 0..generations {
+        let mut options = options.clone();
+        let (mut left, mut top) = (0, 0);
+        if jitter > 0.0 {
+            let h = effects::hash(generation, &[options.compression.to_bits()]);
+            let offset = (h >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0;
+            options.compression = (options.compression + offset * jitter).clamp(0.01, 1.0);
+            (left, top) = ((h & 7) as usize, (h >> 3 & 7) as usize);
+        }
+        frame = compress_frame(frame.pad(left, top), &options)
+            .crop(left, top)
+            .stored();
+        on_generation(&frame);
+    }
+    frame
+}
+
+/// Deblocks and derings a frame decoded from a JPEG file saved at `quality`
+/// (a `canvas.toBlob` quality), with filter strengths from the luma table of
+/// that quality.
+///
+/// The filters run on the 8x8 grid [`artifacts::detect_grid`] finds, or on
+/// the grid from the top-left corner when it finds none. Chroma, which
+/// browsers code in 16x16 blocks, is filtered at full resolution on the same
+/// grid, which takes in every 16x16 one. Only the change the filters make,
+/// rounded to whole levels, is added to the frame, so untouched pixels keep
+/// their exact values.
+pub(crate) fn reduce_artifacts(frame: Frame, quality: f32) -> Frame {
+    let (width, height) = (frame.width, frame.height);
+    let detection = artifacts::detect_grid(&frame.to_pixels(PixelFormat::Rgba), width, height);
+    let (left, top) = if detection.confidence < PRIOR_GRID_CONFIDENCE {
+        (0, 0)
+    } else {
+        ((8 - detection.dx as usize) % 8, (8 - detection.dy as usize) % 8)
+    };
+    let options = CompressOptions {
+        subsampling: ChromaSubsampling::Yuv444,
+        ..CompressOptions::new()
+    };
+    let mut padded = frame.pad(left, top);
+    let mut planes = prepare_planes(&mut padded, &options);
+    let unfiltered = planes.to_rgb(&options);
+
+    let table = quant::quant_table(1.0 - quality.clamp(0.01, 1.0), CompressionScale::Browser);
+    let step = table[0][0] as f32 / 8.0;
+    let (w, h) = (planes.width, planes.height);
+    let chroma = planes.chroma.as_mut().map(|c| [&mut c.cb, &mut c.cr]);
+    for plane in std::iter::once(&mut planes.y).chain(chroma.into_iter().flatten()) {
+        postfilter::deblock(plane, w, h, 8, step);
+        postfilter::dering(plane, w, h, 8, step);
+    }
+    let filtered = planes.to_rgb(&options);
+    for ((px, after), before) in padded.rgb.iter_mut().zip(filtered).zip(unfiltered) {
+        *px = std::array::from_fn(|c| px[c] + (after[c] - before[c]).round());
+    }
+    padded.crop(left, top)
+}
+
+/// Compresses a frame at each of `compressions`, sharing one color conversion
+/// and forward DCT. Settings of 0 or below return the frame untouched.
+pub(crate) fn compress_sweep(
+    frame: Frame,
+    options: &CompressOptions,
+    compressions: &[f32],
+) -> Vec<Frame> {
+    let cache = CachedPlanes::new(frame.clone(), options);
+    compressions
+        .iter()
+        .map(|&compression| {
+            if compression <= 0.0 {
+                return frame.clone();
+            }
+            let options = CompressOptions {
+                compression,
+                ..options.clone()
+            };
+            cache.compress(&options).0
+        })
+        .collect()
+}
+
+/// Like [`compress_frame`], also returning the per-block statistics of the
+/// Y, Cb and Cr planes.
+pub(crate) fn compress_frame_with_stats(
+    mut frame: Frame,
+    options: &CompressOptions,
+) -> (Frame, [Vec<BlockStats>; 3]) {
+    if options.reuse_prior_grid {
+        return compress_on_prior_grid(frame, options);
+    }
+    let planes = compress_to_planes(&mut frame, options);
+    let output = finish_frame(&planes, &frame, options);
+    (output, planes.block_stats)
+}
+
+/// Like [`compress_frame`] for the frame of a band of a taller image, placed
+/// in it as `band` says. A prior grid isn't looked for.
+pub(crate) fn compress_band(mut frame: Frame, options: &CompressOptions, band: Band) -> Frame {
+    let c_factor = options.compression.clamp(0.0, 1.0);
+    let mut planes = prepare_planes(&mut frame, options);
+    if c_factor > 0.0 {
+        planes.quantize(&Quantizer::new(c_factor, options).in_band(band));
+    }
+    planes.sharpen(options);
+    finish_band(&planes, &frame, options, Some(band))
+}
+
+/// The intermediate results of compressing a frame, for `compress_debug`.
+pub(crate) struct Stages {
+    /// The planes after color conversion, chroma at full resolution.
+    pub(crate) full: Planes,
+    /// The planes the block pass starts from, chroma subsampled.
+    pub(crate) subsampled: Planes,
+    /// DCT coefficients of the luma blocks.
+    pub(crate) coefficients: Vec<[[f32; 8]; 8]>,
+    /// The same coefficients after quantization, as the inverse DCT sees them.
+    pub(crate) quantized: Vec<[[f32; 8]; 8]>,
+    /// The frame rebuilt from the quantized planes, before sharpening.
+    pub(crate) reconstruction: Frame,
+    pub(crate) output: Frame,
+}
+
+/// Compresses a frame like [`compress_frame`], keeping every intermediate
+/// stage. The luma coefficients are always those of 8x8 blocks.
+pub(crate) fn compress_stages(mut frame: Frame, options: &CompressOptions) -> Stages {
+    let full_options = CompressOptions {
+        subsampling: ChromaSubsampling::Yuv444,
+        ..options.clone()
+    };
+    let full = prepare_planes(&mut frame.clone(), &full_options);
+    let subsampled = prepare_planes(&mut frame, options);
+
+    let (w, h) = (subsampled.width, subsampled.height);
+    let coefficients = dct::forward_blocks(&subsampled.y, w, h);
+    let c_factor = options.compression.clamp(0.0, 1.0);
+    let mut planes = subsampled.clone();
+    let quantized = if c_factor > 0.0 {
+        let quantizer = Quantizer::new(c_factor, options);
+        planes.quantize(&quantizer);
+        dct::quantize_blocks(&coefficients, w, h, &quantizer)
+    } else {
+        coefficients.clone()
+    };
+    let reconstruction = finish_frame(&planes, &frame, options);
+    planes.sharpen(options);
+    let output = finish_frame(&planes, &frame, options);
+    Stages {
+        full,
+        subsampled,
+        coefficients,
+        quantized,
+        reconstruction,
+        output,
+    }
+}
+
+/// The 8x8 DCT coefficients of one plane of a frame, dequantized after
+/// quantization when `quantized` is set, with the plane's width and height.
+///
+/// The chroma of a grayscale frame is a neutral plane at the subsampled size.
+pub(crate) fn plane_coefficients(
+    mut frame: Frame,
+    options: &CompressOptions,
+    plane: ImagePlane,
+    quantized: bool,
+) -> (Vec<[[f32; 8]; 8]>, usize, usize) {
+    let planes = prepare_planes(&mut frame, options);
+    let (samples, w, h) = match (plane, planes.chroma) {
+        (ImagePlane::Y, _) => (planes.y, planes.width, planes.height),
+        (ImagePlane::Cb, Some(c)) => (c.cb, c.width, c.height),
+        (ImagePlane::Cr, Some(c)) => (c.cr, c.width, c.height),
+        (_, None) => {
+            let (fx, fy) = options.subsampling.factors();
+            let (w, h) = sampling::subsampled_size(planes.width, planes.height, fx, fy);
+            (vec![128.0; w * h], w, h)
+        }
+    };
+    let coefficients = dct::forward_blocks(&samples, w, h);
+    let c_factor = options.compression.clamp(0.0, 1.0);
+    if !quantized || c_factor <= 0.0 {
+        return (coefficients, w, h);
+    }
+    let quantizer = Quantizer::new(c_factor, options).plane(plane as u32);
+    (dct::quantize_blocks(&coefficients, w, h, &quantizer), w, h)
+}
+
+/// The subsampled chroma planes of a frame as the block pass receives them
+/// and as it leaves them, before upsampling.
+///
+/// The chroma of a grayscale frame is a neutral plane at the subsampled size.
+pub(crate) fn chroma_stages(
+    mut frame: Frame,
+    options: &CompressOptions,
+) -> (ChromaPlanes, ChromaPlanes) {
+    let mut planes = prepare_planes(&mut frame, options);
+    let before = planes.chroma.clone();
+    let c_factor = options.compression.clamp(0.0, 1.0);
+    if c_factor > 0.0 && before.is_some() {
+        planes.quantize(&Quantizer::new(c_factor, options));
+    }
+    match (before, planes.chroma) {
+        (Some(before), Some(after)) => (before, after),
+        _ => {
+            let (fx, fy) = options.subsampling.factors();
+            let (w, h) = sampling::subsampled_size(planes.width, planes.height, fx, fy);
+            let neutral = ChromaPlanes {
+                cb: vec![128.0; w * h],
+                cr: vec![128.0; w * h],
+                width: w,
+                height: h,
+                fx,
+                fy,
+            };
+            (neutral.clone(), neutral)
+        }
+    }
+}
+
+/// Like [`compress_frame`], with the compression of every block taken from
+/// `map` instead of `options.compression`.
+pub(crate) fn compress_frame_with_map(
+    mut frame: Frame,
+    options: &CompressOptions,
+    map: CompressionMap,
+) -> Frame {
+    let mut planes = prepare_planes(&mut frame, options);
+    planes.quantize(&Quantizer::mapped(map, options));
+    planes.sharpen(options);
+    finish_frame(&planes, &frame, options)
+}
+
+/// [`artifacts::detect_grid`] confidence from which `reuse_prior_grid` trusts
+/// the detected grid.
+const PRIOR_GRID_CONFIDENCE: f64 = 0.3;
+
+/// Recompresses a frame on the 8x8 grid of its earlier JPEG encode: the frame
+/// is padded so the old block boundaries fall on the new ones, and the
+/// quantization steps are adapted to the old table when its quality is known.
+fn compress_on_prior_grid(
+    frame: Frame,
+    options: &CompressOptions,
+) -> (Frame, [Vec<BlockStats>; 3]) {
+    let (width, height) = (frame.width, frame.height);
+    let detection = artifacts::detect_grid(&frame.to_pixels(PixelFormat::Rgba), width, height);
+    let options = CompressOptions {
+        reuse_prior_grid: false,
+        ..options.clone()
+    };
+    if detection.confidence < PRIOR_GRID_CONFIDENCE {
+        return compress_frame_with_stats(frame, &options);
+    }
+
+    let left = (8 - detection.dx as usize) % 8;
+    let top = (8 - detection.dy as usize) % 8;
+    let mut padded = frame.pad(left, top);
+    let mut planes = prepare_planes(&mut padded, &options);
+    let c_factor = options.compression.clamp(0.0, 1.0);
+    if c_factor > 0.0 {
+        let mut quantizer = Quantizer::new(c_factor, &options);
+        if let Some(quality) = detection.quality {
+            let prior = quant::quant_table(1.0 - quality as f32, CompressionScale::Browser);
+            quantizer = quantizer.reusing(&prior);
+        }
+        planes.quantize(&quantizer);
+    }
+    planes.sharpen(&options);
+    let output = finish_frame(&planes, &padded, &options);
+    (output.crop(left, top), planes.block_stats)
+}
+
+/// Reconstructs the output frame from compressed planes, applying the alpha
+/// policy to the alpha of the prepared input `frame`.
+fn finish_frame(planes: &Planes, frame: &Frame, options: &CompressOptions) -> Frame {
+    finish_band(planes, frame, options, None)
+}
+
+/// [`finish_frame`] for the planes of a band of a taller image, if `band`
+/// says so.
+fn finish_band(
+    planes: &Planes,
+    frame: &Frame,
+    options: &CompressOptions,
+    band: Option<Band>,
+) -> Frame {
+    let mut rgb = planes.to_rgb(options);
+    if options.linear_light {
+        let transfer = options.transfer_function;
+        for px in rgb.iter_mut() {
+            *px = px.map(|c| transfer.encode(c.max(0.0) / 255.0) * 255.0);
+        }
+    }
+
+    let alpha = match (options.alpha_mode, &frame.alpha) {
+        (AlphaMode::Opaque | AlphaMode::Flatten, _) | (_, None) => None,
+        (AlphaMode::Compress, Some(alpha)) if options.alpha_compression > 0.0 => {
+            let alpha_q =
+                Quantizer::new(options.alpha_compression.clamp(0.0, 1.0), options).plane(3);
+            let alpha_q = match band {
+                Some(band) => alpha_q.in_band(band),
+                None => alpha_q,
+            };
+            let alpha = process_blocks(alpha, frame.width, frame.height, &alpha_q);
+            Some(alpha.into_iter().map(|a| a.clamp(0.0, 255.0)).collect())
+        }
+        (_, Some(alpha)) => Some(alpha.clone()),
+    };
+
+    if let (true, Some(alpha)) = (options.premultiplied_alpha, &alpha) {
+        for (px, &a) in rgb.iter_mut().zip(alpha) {
+            *px = px.map(|c| c.clamp(0.0, 255.0) * a / 255.0);
+        }
+    }
+
+    Frame { width: frame.width, height: frame.height, rgb, alpha }
+}
+
+/// Prepared planes together with their forward DCT, so the block pass can be
+/// repeated at different compression levels without redoing the color
+/// conversion, filtering and transform (e.g. while searching for a quality target).
+pub(crate) struct CachedPlanes {
+    frame: Frame,
+    planes: Planes,
+    coefficients: [Vec<[[f32; 8]; 8]>; 3],
+}
+
+impl CachedPlanes {
+    pub(crate) fn new(mut frame: Frame, options: &CompressOptions) -> CachedPlanes {
+        let planes = prepare_planes(&mut frame, options);
+        let coefficients = planes.forward();
+        CachedPlanes {
+            frame,
+            planes,
+            coefficients,
+        }
+    }
+
+    /// Encodes the cached planes as a baseline JPEG file at `options.compression`.
+    ///
+    /// The cache must have been built with [`jfif_options`].
+    pub(crate) fn encode_jpeg(&self, options: &CompressOptions) -> Vec<u8> {
+        let c_factor = options.compression.clamp(0.0, 1.0);
+        let quantizer = Quantizer::new(c_factor, options).baseline();
+        encoder::encode(&self.planes, &self.coefficients, &quantizer)
+    }
+
+    /// Like [`CachedPlanes::encode_jpeg`], hiding `payload` in the coefficients.
+    pub(crate) fn encode_jpeg_with_payload(
+        &self,
+        options: &CompressOptions,
+        payload: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let c_factor = options.compression.clamp(0.0, 1.0);
+        let quantizer = Quantizer::new(c_factor, options).baseline();
+        encoder::encode_with_payload(&self.planes, &self.coefficients, &quantizer, payload)
+    }
+
+    /// The quantized coefficients [`CachedPlanes::encode_jpeg`] would write,
+    /// as a [`CoefficientDump`].
+    pub(crate) fn coefficient_dump(&self, options: &CompressOptions) -> CoefficientDump {
+        let c_factor = options.compression.clamp(0.0, 1.0);
+        let quantizer = Quantizer::new(c_factor, options).baseline();
+        let levels = encoder::levels(&self.planes, &self.coefficients, &quantizer);
+        CoefficientDump::new(&self.planes, levels, quantizer.table)
+    }
+
+    /// Bytes [`CachedPlanes::encode_jpeg_with_payload`] can hide at
+    /// `options.compression`.
+    pub(crate) fn payload_capacity(&self, options: &CompressOptions) -> usize {
+        let c_factor = options.compression.clamp(0.0, 1.0);
+        let quantizer = Quantizer::new(c_factor, options).baseline();
+        encoder::payload_capacity(&self.planes, &self.coefficients, &quantizer)
+    }
+
+    /// Size in bytes [`CachedPlanes::encode_jpeg`] would produce, without
+    /// running the entropy coder.
+    pub(crate) fn estimate_jpeg_size(&self, options: &CompressOptions) -> usize {
+        let c_factor = options.compression.clamp(0.0, 1.0);
+        let quantizer = Quantizer::new(c_factor, options).baseline();
+        encoder::encoded_size(&self.planes, &self.coefficients, &quantizer)
+    }
+
+    /// Runs the rest of the pipeline at `options.compression`.
+    ///
+    /// `options` must match the ones the cache was built with apart from
+    /// `compression` and the quantizer settings.
+    pub(crate) fn compress(&self, options: &CompressOptions) -> (Frame, [Vec<BlockStats>; 3]) {
+        let mut planes = self.planes.clone();
+        let c_factor = options.compression.clamp(0.0, 1.0);
+        if c_factor > 0.0 {
+            planes.reconstruct(&self.coefficients, &Quantizer::new(c_factor, options));
+        }
+        planes.sharpen(options);
+        let output = finish_frame(&planes, &self.frame, options);
+        (output, planes.block_stats)
+    }
+}
+
+/// Prepares the frame color (un-premultiply, flatten), converts it to planes
+/// and runs the block pass (plus optional sharpening) over them.
+pub(crate) fn compress_to_planes(frame: &mut Frame, options: &CompressOptions) -> Planes {
+    let c_factor = options.compression.clamp(0.0, 1.0);
+    let mut planes = prepare_planes(frame, options);
+    if c_factor > 0.0 {
+        planes.quantize(&Quantizer::new(c_factor, options));
+    }
+    planes.sharpen(options);
+    planes
+}
+
+/// Prepares the frame color (un-premultiply, flatten, posterize, linearize)
+/// and converts it to the planes the block pass runs on.
+pub(crate) fn prepare_planes(frame: &mut Frame, options: &CompressOptions) -> Planes {
+    if let (true, Some(alpha)) = (options.premultiplied_alpha, &frame.alpha) {
+        for (px, &a) in frame.rgb.iter_mut().zip(alpha) {
+            let k = if a > 0.0 { 255.0 / a } else { 0.0 };
+            *px = px.map(|c| (c * k).min(255.0));
+        }
+    }
+
+    if let (AlphaMode::Flatten, Some(alpha)) = (options.alpha_mode, &frame.alpha) {
+        let background = [
+            (options.background >> 16 & 0xFF) as f32,
+            (options.background >> 8 & 0xFF) as f32,
+            (options.background & 0xFF) as f32,
+        ];
+        for (px, &a) in frame.rgb.iter_mut().zip(alpha) {
+            let a = a / 255.0;
+            for c in 0..3 {
+                px[c] = px[c] * a + background[c] * (1.0 - a);
+            }
+        }
+    }
+
+    let (levels, dither) = (options.posterize_levels, options.posterize_dither);
+    options.posterize.apply(&mut frame.rgb, frame.width, levels, dither);
+
+    if options.linear_light {
+        let transfer = options.transfer_function;
+        for px in frame.rgb.iter_mut() {
+            *px = px.map(|c| transfer.decode(c / 255.0) * 255.0);
+        }
+    }
+
+    Planes::from_rgb(&frame.rgb, frame.width, frame.height, options)
+}
+
+/// Encodes an interleaved 8-bit buffer as a baseline JPEG file.
+///
+/// The pre-filters (denoise, smoothing, chroma blur, posterize) and the
+/// quantizer settings apply as usual; sharpening and the alpha channel cannot
+/// be represented in the file and are skipped.
+pub(crate) fn encode_jpeg(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    options: &CompressOptions,
+) -> Result<Vec<u8>, Error> {
+    let frame = Frame::from_pixels(data, width, height, format)?;
+    check_jpeg_dimensions(width, height)?;
+    let options = jfif_options(options);
+    Ok(CachedPlanes::new(frame, &options).encode_jpeg(&options))
+}
+
+/// Like [`encode_jpeg`], returning an encoder that produces the file in
+/// pieces. Only the YCbCr planes are kept from the input; each MCU row is
+/// transformed and quantized as it is written.
+pub(crate) fn encode_jpeg_chunked(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    options: &CompressOptions,
+) -> Result<ChunkedEncoder, Error> {
+    let mut frame = Frame::from_pixels(data, width, height, format)?;
+    check_jpeg_dimensions(width, height)?;
+    let options = jfif_options(options);
+    let planes = prepare_planes(&mut frame, &options);
+    let c_factor = options.compression.clamp(0.0, 1.0);
+    let quantizer = Quantizer::new(c_factor, &options).baseline();
+    Ok(ChunkedEncoder::new(planes, &quantizer))
+}
+
+/// The quantized coefficients [`encode_jpeg`] would write, as a binary dump.
+pub(crate) fn export_coefficients(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    options: &CompressOptions,
+) -> Result<Vec<u8>, Error> {
+    let frame = Frame::from_pixels(data, width, height, format)?;
+    check_jpeg_dimensions(width, height)?;
+    let options = jfif_options(options);
+    Ok(CachedPlanes::new(frame, &options).coefficient_dump(&options).to_bytes())
+}
+
+/// Decodes a dump from [`export_coefficients`] back into an opaque frame.
+pub(crate) fn import_coefficients(dump: &[u8]) -> Result<Frame, Error> {
+    let planes = CoefficientDump::from_bytes(dump)?.to_planes();
+    let options = jfif_options(&CompressOptions::default());
+    Ok(Frame {
+        width: planes.width,
+        height: planes.height,
+        rgb: planes.to_rgb(&options),
+        alpha: None,
+    })
+}
+
+/// Decodes a baseline JPEG file into an opaque frame.
+///
+/// Grayscale files and YCbCr ones whose chroma is at full resolution or
+/// halved horizontally, vertically or both are understood; other sampling
+/// layouts and 12-bit samples fail with [`Error::InvalidJpeg`].
+pub(crate) fn decode_jpeg(jpeg: &[u8]) -> Result<Frame, Error> {
+    let file = decoder::read_jpeg(jpeg)?;
+    if file.precision != 8 {
+        return Err(Error::InvalidJpeg);
+    }
+    let (w, h) = (file.width, file.height);
+    let chroma = match &file.components[..] {
+        [_] => None,
+        [y, cb, cr]
+            if (1..=2).contains(&y.h)
+                && (1..=2).contains(&y.v)
+                && (cb.h, cb.v, cr.h, cr.v) == (1, 1, 1, 1) =>
+        {
+            let (cw, ch) = sampling::subsampled_size(w, h, y.h, y.v);
+            Some(ChromaPlanes {
+                cb: coefficients::dequantize(&cb.levels, &cb.table, cw, ch),
+                cr: coefficients::dequantize(&cr.levels, &cr.table, cw, ch),
+                width: cw,
+                height: ch,
+                fx: y.h,
+                fy: y.v,
+            })
+        }
+        _ => return Err(Error::InvalidJpeg),
+    };
+    let luma = &file.components[0];
+    let planes = Planes {
+        width: w,
+        height: h,
+        y: coefficients::dequantize(&luma.levels, &luma.table, w, h),
+        chroma,
+        block_stats: Default::default(),
+    };
+    let options = jfif_options(&CompressOptions::default());
+    Ok(Frame {
+        width: w,
+        height: h,
+        rgb: planes.to_rgb(&options),
+        alpha: None,
+    })
+}
+
+/// Like [`encode_jpeg`], hiding `payload` in the quantized coefficients.
+pub(crate) fn encode_jpeg_with_payload(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    options: &CompressOptions,
+    payload: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let frame = Frame::from_pixels(data, width, height, format)?;
+    check_jpeg_dimensions(width, height)?;
+    let options = jfif_options(options);
+    CachedPlanes::new(frame, &options).encode_jpeg_with_payload(&options, payload)
+}
+
+/// Bytes [`encode_jpeg_with_payload`] can hide in an interleaved 8-bit buffer.
+pub(crate) fn payload_capacity(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    options: &CompressOptions,
+) -> Result<usize, Error> {
+    let frame = Frame::from_pixels(data, width, height, format)?;
+    check_jpeg_dimensions(width, height)?;
+    let options = jfif_options(options);
+    Ok(CachedPlanes::new(frame, &options).payload_capacity(&options))
+}
+
+/// `options` with the color settings JFIF decoders assume: full-range BT.601 YCbCr.
+pub(crate) fn jfif_options(options: &CompressOptions) -> CompressOptions {
+    CompressOptions {
+        color_transform: ColorTransform::YCbCr,
+        color_matrix: ColorMatrix::Bt601,
+        color_range: ColorRange::Full,
+        ..options.clone()
+    }
+}
+
+/// JPEG stores dimensions as 16-bit values.
+pub(crate) fn check_jpeg_dimensions(width: usize, height: usize) -> Result<(), Error> {
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(Error::InvalidDimensions);
+    }
+    Ok(())
+}
+
+/// Runs the block pass over planar 4:2:0 YUV, leaving the result in planar form.
+pub(crate) fn compress_yuv_planes(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    layout: YuvLayout,
+    options: &CompressOptions,
+) -> Result<Planes, Error> {
+    let mut planes = Planes::from_yuv(data, width, height, layout)?;
+    let c_factor = options.compression.clamp(0.0, 1.0);
+    if c_factor > 0.0 {
+        planes.quantize(&Quantizer::new(c_factor, options));
+    }
+    planes.sharpen(options);
+    Ok(planes)
+}
+
+/// Runs the block pass over planar 4:2:0 YUV and reconstructs an opaque frame.
+pub(crate) fn compress_yuv(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    layout: YuvLayout,
+    options: &CompressOptions,
+) -> Result<Frame, Error> {
+    let planes = compress_yuv_planes(data, width, height, layout, options)?;
+    Ok(Frame { width, height, rgb: planes.to_rgb(options), alpha: None })
+}
+
+/// Validates dimensions against a buffer of `len` elements with `channels` per pixel.
+pub(crate) fn check_dimensions(
+    width: usize,
+    height: usize,
+    len: usize,
+    channels: usize,
+) -> Result<(), Error> {
+    if width == 0 || height == 0 {
+        return Err(Error::InvalidDimensions);
+    }
+    if len != width * height * channels {
+        return Err(Error::BufferMismatch);
+    }
+    Ok(())
+}
+
+/// Bilateral range sigma for luma, as a multiple of the noise estimate.
+const LUMA_DENOISE_RANGE: f32 = 2.0;
+/// Bilateral range sigma for chroma, as a multiple of the noise estimate.
+const CHROMA_DENOISE_RANGE: f32 = 4.0;
+
+/// Largest per-pixel channel spread still treated as gray.
+const GRAYSCALE_TOLERANCE: f32 = 2.0;
+
+/// Returns `true` when R, G and B agree (within tolerance) for every pixel.
+fn is_grayscale(rgb: &[[f32; 3]]) -> bool {
+    rgb.iter().all(|&[r, g, b]| r.max(g).max(b) - r.min(g).min(b) <= GRAYSCALE_TOLERANCE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::{BlockSize, QualityGradient};
+
+    const SUBSAMPLINGS: [ChromaSubsampling; 3] =
+        [ChromaSubsampling::Yuv444, ChromaSubsampling::Yuv422, ChromaSubsampling::Yuv420];
+
+    /// Smooth RGBA gradients, so decoders that round differently stay close.
+    fn gradient(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                let diagonal = (x + y) * 255 / (width + height);
+                [(x * 255 / width) as u8, (y * 255 / height) as u8, diagonal as u8, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decode_jpeg_matches_image_crate() {
+        let (w, h) = (203, 131);
+        for subsampling in SUBSAMPLINGS {
+            let options = CompressOptions {
+                compression: 0.5,
+                subsampling,
+                ..Default::default()
+            };
+            let jpeg = encode_jpeg(&gradient(w, h), w, h, PixelFormat::Rgba, &options).unwrap();
+            let ours = decode_jpeg(&jpeg).unwrap();
+            assert_eq!((ours.width, ours.height), (w, h));
+            let theirs = image::load_from_memory(&jpeg).unwrap().to_rgb8().into_raw();
+            let ours = ours.to_pixels(PixelFormat::Rgb);
+            let diff = ours.iter().zip(&theirs).map(|(&a, &b)| a.abs_diff(b)).max();
+            assert!(diff <= Some(3), "{subsampling:?} differs by {diff:?}");
+        }
+    }
+
+    #[test]
+    fn band_compressor_matches_compress() {
+        let cases = [
+            CompressOptions::default(),
+            CompressOptions {
+                subsampling: ChromaSubsampling::Yuv420,
+                chroma_blur: 1.5,
+                sharpen_amount: 0.5,
+                sharpen_radius: 1.0,
+                cb_shift_y: 3,
+                deblock: true,
+                dering: true,
+                ..Default::default()
+            },
+            CompressOptions {
+                block_size: BlockSize::Size16,
+                block_overlap: true,
+                quality_gradient: QualityGradient::Vertical,
+                gradient_compression: 0.9,
+                ..Default::default()
+            },
+            CompressOptions {
+                error_diffusion: true,
+                ..Default::default()
+            },
+        ];
+        let (w, h) = (37, 301);
+        let data = gradient(w, h);
+        for options in cases {
+            let whole = compress(&data, w, h, PixelFormat::Rgba, &options).unwrap();
+            let mut bands = BandCompressor::new(w, h, PixelFormat::Rgba, options.clone()).unwrap();
+            for rows in data.chunks(7 * w * 4) {
+                bands.push_rows(rows).unwrap();
+            }
+            assert!(bands.finish().unwrap() == whole, "{options:?}");
+        }
+    }
+
+    #[test]
+    fn band_compressor_rejects_wrong_row_counts() {
+        let (w, h) = (5, 3);
+        let data = gradient(w, h);
+        let mut short = BandCompressor::new(w, h, PixelFormat::Rgba, Default::default()).unwrap();
+        short.push_rows(&data[..2 * w * 4]).unwrap();
+        assert_eq!(short.finish().err(), Some(Error::BufferMismatch));
+        let mut long = BandCompressor::new(w, h, PixelFormat::Rgba, Default::default()).unwrap();
+        long.push_rows(&data).unwrap();
+        assert_eq!(long.push_rows(&data[..w * 4]), Err(Error::BufferMismatch));
+    }
+
+    #[test]
+    fn chunked_encoder_matches_encode_jpeg() {
+        let (w, h) = (77, 45);
+        let data = gradient(w, h);
+        for subsampling in SUBSAMPLINGS {
+            let options = CompressOptions {
+                subsampling,
+                ..Default::default()
+            };
+            let whole = encode_jpeg(&data, w, h, PixelFormat::Rgba, &options).unwrap();
+            let mut encoder =
+                encode_jpeg_chunked(&data, w, h, PixelFormat::Rgba, &options).unwrap();
+            let mut chunked = Vec::new();
+            while let Some(chunk) = encoder.next_chunk(100) {
+                chunked.extend(chunk);
+            }
+            assert!(chunked == whole, "{subsampling:?}");
+        }
+    }
+}
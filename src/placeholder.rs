@@ -0,0 +1,433 @@
+//! Tiny previews shown while the full image loads.
+
+use std::f32::consts::PI;
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::Error;
+use crate::options::{CompressOptions, PixelFormat, TransferFunction};
+use crate::pipeline::{self, Frame};
+use crate::result::EncodedJpeg;
+use crate::search;
+
+/// Scales an RGBA buffer down until its longer side is at most `max_side`
+/// pixels and encodes the best-looking baseline JPEG of it that fits
+/// `max_bytes`, searching the compression and the chroma subsampling.
+///
+/// Images already within `max_side` keep their size. When nothing fits, the
+/// smallest file found is returned.
+pub(crate) fn lqip(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    options: &CompressOptions,
+    max_side: usize,
+    max_bytes: usize,
+) -> Result<EncodedJpeg, Error> {
+    let frame = Frame::from_pixels(data, width, height, PixelFormat::Rgba)?;
+    let max_side = max_side.max(1);
+    let (w, h) = if width.max(height) <= max_side {
+        (width, height)
+    } else if width >= height {
+        (max_side, (height * max_side).div_ceil(width))
+    } else {
+        ((width * max_side).div_ceil(height), max_side)
+    };
+    let small = frame.shrink(w, h).to_pixels(PixelFormat::Rgba);
+    search::search_size(&small, w, h, options, max_bytes, true)
+}
+
+/// Digits of the base 83 BlurHash encodes its numbers in.
+const BASE83: &[u8; 83] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// Appends `value` as `digits` base 83 digits, most significant first.
+fn push_base83(hash: &mut String, value: u32, digits: u32) {
+    for i in (0..digits).rev() {
+        hash.push(BASE83[(value / 83u32.pow(i) % 83) as usize] as char);
+    }
+}
+
+/// Encodes a `width`x`height` RGBA buffer as a BlurHash string with
+/// `x_components` by `y_components` (each clamped to 1–9) cosine components,
+/// following the reference implementation so any BlurHash decoder reads it.
+///
+/// The components are the image's DCT over its whole area in linear light,
+/// the same transform the block pass uses on 8x8 blocks; the first one is
+/// the average color. Alpha is ignored.
+pub(crate) fn blurhash(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    x_components: u32,
+    y_components: u32,
+) -> Result<String, Error> {
+    pipeline::check_dimensions(width, height, data.len(), 4)?;
+    let (cx, cy) = (x_components.clamp(1, 9) as usize, y_components.clamp(1, 9) as usize);
+    let srgb = TransferFunction::Srgb;
+    let linear: Vec<[f32; 3]> = data
+        .chunks_exact(4)
+        .map(|px| [0, 1, 2].map(|c| srgb.decode(px[c] as f32 / 255.0)))
+        .collect();
+    let basis = |k: usize, len: usize| -> Vec<f32> {
+        (0..len).map(|x| libm::cosf(PI * k as f32 * x as f32 / len as f32)).collect()
+    };
+
+    // Separable transform: every row against the horizontal basis, then the
+    // row sums against the vertical one.
+    let mut factors = vec![[0.0f32; 3]; cx * cy];
+    for i in 0..cx {
+        let bx = basis(i, width);
+        let rows: Vec<[f32; 3]> = linear
+            .chunks_exact(width)
+            .map(|row| {
+                row.iter().zip(&bx).fold([0.0; 3], |sum, (px, &b)| {
+                    [sum[0] + px[0] * b, sum[1] + px[1] * b, sum[2] + px[2] * b]
+                })
+            })
+            .collect();
+        for j in 0..cy {
+            let by = basis(j, height);
+            let norm = if i == 0 && j == 0 { 1.0 } else { 2.0 } / (width * height) as f32;
+            let sum = rows.iter().zip(&by).fold([0.0; 3], |sum, (row, &b)| {
+                [sum[0] + row[0] * b, sum[1] + row[1] * b, sum[2] + row[2] * b]
+            });
+            factors[j * cx + i] = sum.map(|c| c * norm);
+        }
+    }
+
+    let mut hash = String::with_capacity(4 + 2 * cx * cy);
+    push_base83(&mut hash, ((cx - 1) + (cy - 1) * 9) as u32, 1);
+    let ac = &factors[1..];
+    let max_value = if ac.is_empty() {
+        push_base83(&mut hash, 0, 1);
+        1.0
+    } else {
+        let actual = ac.iter().flatten().fold(0.0f32, |m, &v| m.max(v.abs()));
+        let quantized = (actual * 166.0 - 0.5).floor().clamp(0.0, 82.0);
+        push_base83(&mut hash, quantized as u32, 1);
+        (quantized + 1.0) / 166.0
+    };
+    let to_srgb = |v: f32| (srgb.encode(v.clamp(0.0, 1.0)) * 255.0 + 0.5) as u32;
+    let [r, g, b] = factors[0].map(to_srgb);
+    push_base83(&mut hash, r << 16 | g << 8 | b, 4);
+    for factor in ac {
+        let level = factor.map(|v| {
+            let scaled = (v / max_value).abs().sqrt().copysign(v);
+            (scaled * 9.0 + 9.5).floor().clamp(0.0, 18.0) as u32
+        });
+        push_base83(&mut hash, level[0] * 19 * 19 + level[1] * 19 + level[2], 2);
+    }
+    Ok(hash)
+}
+
+/// Longest side ThumbHash encodes; larger images are scaled down first.
+const THUMBHASH_MAX_SIDE: usize = 100;
+/// Longest side of the preview [`thumbhash_to_rgba`] renders.
+const THUMBHASH_PREVIEW_SIDE: f64 = 32.0;
+
+/// The `(cx, cy)` frequencies ThumbHash keeps of an `nx`x`ny` channel, in
+/// the order it stores them: a triangle, so the diagonals that matter least
+/// take no space.
+fn thumbhash_frequencies(nx: usize, ny: usize) -> impl Iterator<Item = (usize, usize)> {
+    (0..ny).flat_map(move |cy| {
+        (0..)
+            .take_while(move |cx| cx * ny < nx * (ny - cy))
+            .map(move |cx| (cx, cy))
+    })
+}
+
+/// Cosines of frequency `k` at the pixel centers of an axis of `len`.
+fn thumbhash_basis(k: usize, len: usize) -> Vec<f64> {
+    let step = std::f64::consts::PI / len as f64;
+    (0..len).map(|x| libm::cos(step * k as f64 * (x as f64 + 0.5))).collect()
+}
+
+/// Encodes a `width`x`height` RGBA buffer as a ThumbHash, following the
+/// reference implementation so any ThumbHash decoder reads it.
+///
+/// Images are scaled down to fit 100x100 first. The pixels are composited
+/// over their average color and split into luminance, yellow–blue, red–green
+/// and alpha; each channel keeps the lowest frequencies of its DCT over the
+/// whole image, luminance more of them along the longer side. Alpha is only
+/// stored when some pixel is translucent.
+pub(crate) fn thumbhash(data: &[u8], width: usize, height: usize) -> Result<Vec<u8>, Error> {
+    let frame = Frame::from_pixels(data, width, height, PixelFormat::Rgba)?;
+    let longest = width.max(height);
+    let (w, h) = if longest <= THUMBHASH_MAX_SIDE {
+        (width, height)
+    } else {
+        let fit = |len: usize| (len * THUMBHASH_MAX_SIDE).div_ceil(longest);
+        (fit(width), fit(height))
+    };
+    let frame = frame.shrink(w, h);
+    let alpha: Vec<f64> = match &frame.alpha {
+        Some(alpha) => alpha.iter().map(|&a| a as f64 / 255.0).collect(),
+        None => vec![1.0; w * h],
+    };
+
+    let (mut average, mut coverage) = ([0.0f64; 3], 0.0f64);
+    for (px, &a) in frame.rgb.iter().zip(&alpha) {
+        for c in 0..3 {
+            average[c] += a / 255.0 * px[c] as f64;
+        }
+        coverage += a;
+    }
+    if coverage > 0.0 {
+        average = average.map(|c| c / coverage);
+    }
+    let has_alpha = coverage < (w * h) as f64;
+    let lpqa: Vec<[f64; 4]> = frame
+        .rgb
+        .iter()
+        .zip(&alpha)
+        .map(|(px, &a)| {
+            let [r, g, b] = [0, 1, 2].map(|c| average[c] * (1.0 - a) + a / 255.0 * px[c] as f64);
+            [(r + g + b) / 3.0, (r + g) / 2.0 - b, r - g, a]
+        })
+        .collect();
+
+    // DC, AC terms normalized to 0–1 and their scale, per channel.
+    let encode = |channel: usize, nx: usize, ny: usize| {
+        let (mut dc, mut ac, mut scale) = (0.0, Vec::new(), 0.0f64);
+        for (cx, cy) in thumbhash_frequencies(nx, ny) {
+            let (fx, fy) = (thumbhash_basis(cx, w), thumbhash_basis(cy, h));
+            let sum: f64 = lpqa
+                .iter()
+                .enumerate()
+                .map(|(i, px)| px[channel] * fx[i % w] * fy[i / w])
+                .sum();
+            let f = sum / (w * h) as f64;
+            if (cx, cy) == (0, 0) {
+                dc = f;
+            } else {
+                ac.push(f);
+                scale = scale.max(f.abs());
+            }
+        }
+        if scale > 0.0 {
+            ac.iter_mut().for_each(|f| *f = 0.5 + 0.5 / scale * *f);
+        }
+        (dc, ac, scale)
+    };
+    let l_limit = if has_alpha { 5.0 } else { 7.0 };
+    let side = |len: usize| ((l_limit * len as f64 / w.max(h) as f64).round() as usize).max(1);
+    let (lx, ly) = (side(w), side(h));
+    let (l_dc, l_ac, l_scale) = encode(0, lx.max(3), ly.max(3));
+    let (p_dc, p_ac, p_scale) = encode(1, 3, 3);
+    let (q_dc, q_ac, q_scale) = encode(2, 3, 3);
+
+    let quantize = |value: f64, max: f64| (value * max).round() as u32;
+    let landscape = w > h;
+    let header24 = quantize(l_dc, 63.0)
+        | ((31.5 + 31.5 * p_dc).round() as u32) << 6
+        | ((31.5 + 31.5 * q_dc).round() as u32) << 12
+        | quantize(l_scale, 31.0) << 18
+        | (has_alpha as u32) << 23;
+    let header16 = (if landscape { ly } else { lx }) as u32
+        | quantize(p_scale, 63.0) << 3
+        | quantize(q_scale, 63.0) << 9
+        | (landscape as u32) << 15;
+    let mut hash = header24.to_le_bytes()[..3].to_vec();
+    hash.extend_from_slice(&(header16 as u16).to_le_bytes());
+    let mut terms = vec![l_ac, p_ac, q_ac];
+    if has_alpha {
+        let (a_dc, a_ac, a_scale) = encode(3, 5, 5);
+        hash.push((quantize(a_dc, 15.0) | quantize(a_scale, 15.0) << 4) as u8);
+        terms.push(a_ac);
+    }
+    let nibbles: Vec<u8> = terms.iter().flatten().map(|&f| quantize(f, 15.0) as u8).collect();
+    hash.extend(nibbles.chunks(2).map(|pair| pair[0] | pair.get(1).map_or(0, |n| n << 4)));
+    Ok(hash)
+}
+
+/// Renders a ThumbHash as an RGBA preview of its approximate aspect ratio,
+/// at most 32 pixels on its longer side.
+pub(crate) fn thumbhash_to_rgba(hash: &[u8]) -> Result<(usize, usize, Vec<u8>), Error> {
+    let header = hash.get(..5).ok_or(Error::InvalidThumbHash)?;
+    let header24 = u32::from_le_bytes([header[0], header[1], header[2], 0]);
+    let header16 = u16::from_le_bytes([header[3], header[4]]) as u32;
+    let l_dc = (header24 & 63) as f64 / 63.0;
+    let p_dc = (header24 >> 6 & 63) as f64 / 31.5 - 1.0;
+    let q_dc = (header24 >> 12 & 63) as f64 / 31.5 - 1.0;
+    let l_scale = (header24 >> 18 & 31) as f64 / 31.0;
+    let has_alpha = header24 >> 23 & 1 == 1;
+    let p_scale = (header16 >> 3 & 63) as f64 / 63.0;
+    let q_scale = (header16 >> 9 & 63) as f64 / 63.0;
+    let landscape = header16 >> 15 == 1;
+    let (l_limit, short) = (if has_alpha { 5 } else { 7 }, (header16 & 7) as usize);
+    if short == 0 {
+        return Err(Error::InvalidThumbHash);
+    }
+    let (lx, ly) = if landscape { (l_limit, short) } else { (short, l_limit) };
+    let (a_dc, a_scale) = match has_alpha {
+        true => {
+            let byte = *hash.get(5).ok_or(Error::InvalidThumbHash)?;
+            ((byte & 15) as f64 / 15.0, (byte >> 4) as f64 / 15.0)
+        }
+        false => (1.0, 0.0),
+    };
+
+    let start = if has_alpha { 6 } else { 5 };
+    let mut index = 0;
+    let mut decode = |nx: usize, ny: usize, scale: f64| -> Result<Vec<f64>, Error> {
+        thumbhash_frequencies(nx, ny)
+            .skip(1)
+            .map(|_| {
+                let byte = *hash.get(start + index / 2).ok_or(Error::InvalidThumbHash)?;
+                let nibble = byte >> (index % 2 * 4) & 15;
+                index += 1;
+                Ok((nibble as f64 / 7.5 - 1.0) * scale)
+            })
+            .collect()
+    };
+    let (nx, ny) = (lx.max(3), ly.max(3));
+    let l_ac = decode(nx, ny, l_scale)?;
+    // Saturation is boosted to make up for what quantization took.
+    let p_ac = decode(3, 3, p_scale * 1.25)?;
+    let q_ac = decode(3, 3, q_scale * 1.25)?;
+    let a_ac = if has_alpha { decode(5, 5, a_scale)? } else { Vec::new() };
+
+    let ratio = lx as f64 / ly as f64;
+    let (w, h) = if ratio > 1.0 {
+        (THUMBHASH_PREVIEW_SIDE, (THUMBHASH_PREVIEW_SIDE / ratio).round())
+    } else {
+        ((THUMBHASH_PREVIEW_SIDE * ratio).round(), THUMBHASH_PREVIEW_SIDE)
+    };
+    let (w, h) = (w as usize, h as usize);
+    let n = nx.max(if has_alpha { 5 } else { 3 });
+    let columns: Vec<Vec<f64>> = (0..n).map(|k| thumbhash_basis(k, w)).collect();
+    let rows: Vec<Vec<f64>> = (0..n.max(ny)).map(|k| thumbhash_basis(k, h)).collect();
+    let mut rgba = vec![0u8; w * h * 4];
+    for (i, px) in rgba.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i % w, i / w);
+        let apply = |dc: f64, ac: &[f64], nx: usize, ny: usize| {
+            let terms = thumbhash_frequencies(nx, ny).skip(1).zip(ac);
+            dc + terms.map(|((cx, cy), f)| f * columns[cx][x] * rows[cy][y] * 2.0).sum::<f64>()
+        };
+        let l = apply(l_dc, &l_ac, nx, ny);
+        let p = apply(p_dc, &p_ac, 3, 3);
+        let q = apply(q_dc, &q_ac, 3, 3);
+        let a = if has_alpha { apply(a_dc, &a_ac, 5, 5) } else { a_dc };
+        let b = l - 2.0 / 3.0 * p;
+        let r = (3.0 * l - b + q) / 2.0;
+        let g = r - q;
+        for (out, value) in px.iter_mut().zip([r, g, b, a]) {
+            *out = (255.0 * value.clamp(0.0, 1.0)) as u8;
+        }
+    }
+    Ok((w, h, rgba))
+}
+
+/// Levels per channel of the color histogram the dominant color is picked
+/// from; each bin spans 32 values.
+const DOMINANT_LEVELS: usize = 8;
+
+/// Average and dominant color of an image, as `0xRRGGBB` like
+/// `CompressOptions.background`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageColors {
+    /// Mean of all pixels, weighted by alpha.
+    pub average: u32,
+    /// Mean of the 8x8 blocks whose colors fall in the most common histogram
+    /// bin.
+    pub dominant: u32,
+    /// Share (0–1) of the image's opaque area that is close to `dominant`.
+    pub dominant_share: f32,
+}
+
+/// Average and dominant color of a `width`x`height` RGBA buffer.
+///
+/// Works on the mean color of every 8x8 block (the DC terms of the block
+/// pass), premultiplied by alpha so transparent pixels don't count. The
+/// dominant color is the mean of the blocks in the fullest bin of a coarse
+/// RGB histogram, so it is a color of the image rather than a bin center.
+/// Fully transparent images give black with a share of 0.
+pub(crate) fn colors(data: &[u8], width: usize, height: usize) -> Result<ImageColors, Error> {
+    pipeline::check_dimensions(width, height, data.len(), 4)?;
+    let blocks_x = width.div_ceil(8);
+    // Alpha-weighted color sums and alpha of every block.
+    let mut blocks = vec![([0.0f32; 3], 0.0f32); blocks_x * height.div_ceil(8)];
+    for (i, px) in data.chunks_exact(4).enumerate() {
+        let (sum, weight) = &mut blocks[i / width / 8 * blocks_x + i % width / 8];
+        let a = px[3] as f32 / 255.0;
+        for c in 0..3 {
+            sum[c] += a * px[c] as f32;
+        }
+        *weight += a;
+    }
+
+    let mut bins = vec![([0.0f32; 3], 0.0f32); DOMINANT_LEVELS.pow(3)];
+    let (mut total, mut coverage) = ([0.0f32; 3], 0.0f32);
+    for &(sum, weight) in blocks.iter().filter(|(_, weight)| *weight > 0.0) {
+        let bin = sum
+            .map(|c| (c / weight) as usize * DOMINANT_LEVELS / 256)
+            .iter()
+            .fold(0, |bin, &level| bin * DOMINANT_LEVELS + level.min(DOMINANT_LEVELS - 1));
+        for c in 0..3 {
+            bins[bin].0[c] += sum[c];
+            total[c] += sum[c];
+        }
+        bins[bin].1 += weight;
+        coverage += weight;
+    }
+    let pack = |sum: [f32; 3], weight: f32| {
+        let [r, g, b] = sum.map(|c| (c / weight.max(f32::MIN_POSITIVE)).round() as u32);
+        r.min(255) << 16 | g.min(255) << 8 | b.min(255)
+    };
+    let (dominant, share) = bins
+        .iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or((0, 0.0), |&(sum, weight)| (pack(sum, weight), weight));
+    Ok(ImageColors {
+        average: pack(total, coverage),
+        dominant,
+        dominant_share: if coverage > 0.0 { share / coverage } else { 0.0 },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 32x24 RGBA test image: red across, green down, a product pattern in
+    /// blue and, when `translucent`, alpha fading from left to right.
+    fn image(translucent: bool) -> Vec<u8> {
+        let (w, h) = (32, 24);
+        (0..w * h)
+            .flat_map(|i| {
+                let (x, y) = (i % w, i / w);
+                let alpha = if translucent { 255 - x * 200 / (w - 1) } else { 255 };
+                [x * 255 / (w - 1), y * 255 / (h - 1), x * y % 256, alpha].map(|v| v as u8)
+            })
+            .collect()
+    }
+
+    // Expected values are from the reference encoders (the C BlurHash and
+    // the JavaScript ThumbHash) run on the same pixels.
+
+    #[test]
+    fn blurhash_matches_reference() {
+        let hash = blurhash(&image(false), 32, 24, 4, 3).unwrap();
+        assert_eq!(hash, "L$HewC2lwtX4l@WBjwe@gFfmfTfi");
+    }
+
+    #[test]
+    fn thumbhash_matches_reference() {
+        assert_eq!(
+            thumbhash(&image(false), 32, 24).unwrap(),
+            [
+                221, 248, 13, 29, 154, 96, 118, 135, 112, 136, 136, 134, 136, 103, 136, 135, 243,
+                163, 15, 248, 119
+            ]
+        );
+        assert_eq!(
+            thumbhash(&image(true), 32, 24).unwrap(),
+            [
+                217, 200, 137, 20, 144, 41, 65, 102, 80, 136, 134, 104, 88, 79, 232, 81, 175, 247,
+                135, 135, 136, 120, 135, 136, 7
+            ]
+        );
+    }
+}
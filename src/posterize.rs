@@ -0,0 +1,127 @@
+use crate::options::Posterize;
+
+/// Largest palette `Posterize::MedianCut` builds.
+const MAX_PALETTE: usize = 256;
+
+impl Posterize {
+    /// Reduces the colors of a `width`-pixel-wide RGB image in place.
+    ///
+    /// `levels` is the number of steps per channel for `Levels` and the palette
+    /// size for `MedianCut`. With `dither`, each pixel's mapping error is spread
+    /// to its unprocessed neighbors (Floyd–Steinberg).
+    pub(crate) fn apply(self, rgb: &mut [[f32; 3]], width: usize, levels: u32, dither: bool) {
+        match self {
+            Posterize::None => {}
+            Posterize::Levels => {
+                let step = 255.0 / (levels.max(2) - 1) as f32;
+                let nearest =
+                    |c: [f32; 3]| c.map(|v| ((v / step).round() * step).clamp(0.0, 255.0));
+                map_colors(rgb, width, dither, nearest);
+            }
+            Posterize::MedianCut => {
+                let palette = median_cut(rgb, (levels as usize).clamp(2, MAX_PALETTE));
+                map_colors(rgb, width, dither, |c| nearest_color(&palette, c));
+            }
+        }
+    }
+}
+
+/// Replaces every pixel with `nearest(pixel)`, optionally diffusing the error.
+fn map_colors(
+    rgb: &mut [[f32; 3]],
+    width: usize,
+    dither: bool,
+    nearest: impl Fn([f32; 3]) -> [f32; 3],
+) {
+    if !dither {
+        for px in rgb.iter_mut() {
+            *px = nearest(*px);
+        }
+        return;
+    }
+
+    let height = rgb.len() / width;
+    for y in 0..height {
+        for x in 0..width {
+            let m_i = y * width + x;
+            let old = rgb[m_i].map(|v| v.clamp(0.0, 255.0));
+            let new = nearest(old);
+            rgb[m_i] = new;
+
+            let error = [old[0] - new[0], old[1] - new[1], old[2] - new[2]];
+            let mut spread = |dx: isize, dy: usize, weight: f32| {
+                let nx = x as isize + dx;
+                if nx >= 0 && (nx as usize) < width && y + dy < height {
+                    let px = &mut rgb[(y + dy) * width + nx as usize];
+                    for c in 0..3 {
+                        px[c] += error[c] * weight;
+                    }
+                }
+            };
+            spread(1, 0, 7.0 / 16.0);
+            spread(-1, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(1, 1, 1.0 / 16.0);
+        }
+    }
+}
+
+/// Builds a palette of at most `size` colors by repeatedly splitting the box
+/// with the widest channel range at its median.
+fn median_cut(rgb: &[[f32; 3]], size: usize) -> Vec<[f32; 3]> {
+    let mut boxes = vec![rgb.to_vec()];
+    while boxes.len() < size {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| (i, widest_channel(b)))
+            .max_by(|a, b| a.1 .1.total_cmp(&b.1 .1));
+        let Some((i, (channel, range))) = widest else {
+            break;
+        };
+        if range <= 0.0 {
+            break;
+        }
+
+        let mut colors = boxes.swap_remove(i);
+        colors.sort_unstable_by(|a, b| a[channel].total_cmp(&b[channel]));
+        let upper = colors.split_off(colors.len() / 2);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    boxes
+        .iter()
+        .map(|b| {
+            let mut sum = [0.0f64; 3];
+            for px in b {
+                for c in 0..3 {
+                    sum[c] += px[c] as f64;
+                }
+            }
+            sum.map(|s| (s / b.len() as f64) as f32)
+        })
+        .collect()
+}
+
+/// Channel with the largest spread in `colors`, and that spread.
+fn widest_channel(colors: &[[f32; 3]]) -> (usize, f32) {
+    (0..3)
+        .map(|c| {
+            let (lo, hi) = colors.iter().fold((f32::MAX, f32::MIN), |(lo, hi), px| {
+                (lo.min(px[c]), hi.max(px[c]))
+            });
+            (c, hi - lo)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap()
+}
+
+fn nearest_color(palette: &[[f32; 3]], c: [f32; 3]) -> [f32; 3] {
+    let distance = |p: &[f32; 3]| (0..3).map(|i| (p[i] - c[i]).powi(2)).sum::<f32>();
+    *palette
+        .iter()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .unwrap()
+}
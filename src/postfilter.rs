@@ -0,0 +1,135 @@
+//! Post-filters that hide coding artifacts in reconstructed planes.
+
+use crate::sampling::clamp_index;
+
+/// Largest step across a block edge, in quantization steps of the block
+/// mean, that is still taken for blocking rather than a real edge.
+const DEBLOCK_EDGE: f32 = 2.5;
+/// Largest difference between neighboring samples on either side of a block
+/// edge, in steps of the block mean, for the sides to count as smooth.
+const DEBLOCK_SMOOTH: f32 = 0.5;
+
+/// Smooths the block edges of a `w`x`h` plane coded in `block`x`block`
+/// blocks, in the manner of the H.264 loop filter.
+///
+/// `step` is the quantization step of a block's mean (the DC step over 8). A
+/// line across an edge is only filtered when the jump at the edge stays
+/// within a few steps and both sides are smooth, so real edges and texture
+/// survive. Lines whose sides are flat get the strong filter, which ramps the
+/// jump over three samples each side; others only have the two edge samples
+/// moved towards each other by at most `step`.
+pub(crate) fn deblock(plane: &mut [f32], w: usize, h: usize, block: usize, step: f32) {
+    if step <= 0.0 {
+        return;
+    }
+    let edge = DEBLOCK_EDGE * step;
+    let smooth = DEBLOCK_SMOOTH * step + 1.0;
+    // Vertical edges, then horizontal ones, each line of eight samples
+    // straddling an edge at its middle.
+    for x in (block..w).step_by(block).filter(|&x| x >= 4 && x + 4 <= w) {
+        for y in 0..h {
+            let index = |i: usize| y * w + x + i - 4;
+            filter_line(plane, index, edge, smooth, step);
+        }
+    }
+    for y in (block..h).step_by(block).filter(|&y| y >= 4 && y + 4 <= h) {
+        for x in 0..w {
+            let index = |i: usize| (y + i - 4) * w + x;
+            filter_line(plane, index, edge, smooth, step);
+        }
+    }
+}
+
+/// Filters the eight samples `p3 p2 p1 p0 | q0 q1 q2 q3` of `plane` at
+/// `index(0..8)`, if the jump at the edge looks like blocking.
+fn filter_line(
+    plane: &mut [f32],
+    index: impl Fn(usize) -> usize,
+    edge: f32,
+    smooth: f32,
+    tc: f32,
+) {
+    let s: [f32; 8] = std::array::from_fn(|i| plane[index(i)]);
+    let [p3, p2, p1, p0, q0, q1, q2, q3] = s;
+    if (p0 - q0).abs() >= edge || (p1 - p0).abs() >= smooth || (q1 - q0).abs() >= smooth {
+        return;
+    }
+    let filtered = if (p2 - p0).abs() < smooth && (q2 - q0).abs() < smooth {
+        [
+            p3,
+            (2.0 * p3 + 3.0 * p2 + p1 + p0 + q0) / 8.0,
+            (p2 + p1 + p0 + q0) / 4.0,
+            (p2 + 2.0 * p1 + 2.0 * p0 + 2.0 * q0 + q1) / 8.0,
+            (p1 + 2.0 * p0 + 2.0 * q0 + 2.0 * q1 + q2) / 8.0,
+            (p0 + q0 + q1 + q2) / 4.0,
+            (p0 + q0 + q1 + 3.0 * q2 + 2.0 * q3) / 8.0,
+            q3,
+        ]
+    } else {
+        let delta = ((4.0 * (q0 - p0) + (p1 - q1)) / 8.0).clamp(-tc, tc);
+        [p3, p2, p1, p0 + delta, q0 - delta, q1, q2, q3]
+    };
+    for (i, value) in filtered.into_iter().enumerate() {
+        plane[index(i)] = value;
+    }
+}
+
+/// Smallest range of sample values within a block for it to count as holding
+/// a strong edge, the source of ringing.
+const DERING_RANGE: f32 = 48.0;
+/// Largest change deringing makes to a sample, in steps of the block mean.
+const DERING_CLIP: f32 = 2.0;
+
+/// Suppresses the ringing (mosquito noise) next to strong edges of a `w`x`h`
+/// plane coded in `block`x`block` blocks, in the manner of the MPEG-4
+/// deringing filter.
+///
+/// Blocks whose values span less than [`DERING_RANGE`] are left alone. In the
+/// others, every sample is split off as above or below the block's mid-range;
+/// samples whose 3x3 neighborhood lies entirely on their own side are
+/// smoothed, which flattens the ripples on both sides of an edge without
+/// blurring across it. Changes are limited by `step`, the quantization step
+/// of a block's mean, so detail coarser than the quantization survives.
+pub(crate) fn dering(plane: &mut [f32], w: usize, h: usize, block: usize, step: f32) {
+    if step <= 0.0 {
+        return;
+    }
+    let input = plane.to_vec();
+    let at = |x: usize, y: usize, dx: isize, dy: isize| {
+        let x = clamp_index(x as isize + dx, w);
+        let y = clamp_index(y as isize + dy, h);
+        input[y * w + x]
+    };
+    let clip = DERING_CLIP * step;
+    for by in (0..h).step_by(block) {
+        for bx in (0..w).step_by(block) {
+            let (x_end, y_end) = ((bx + block).min(w), (by + block).min(h));
+            let samples = (by..y_end).flat_map(|y| (bx..x_end).map(move |x| (x, y)));
+            let (min, max) = samples.clone().fold((f32::MAX, f32::MIN), |(lo, hi), (x, y)| {
+                let value = input[y * w + x];
+                (lo.min(value), hi.max(value))
+            });
+            if max - min < DERING_RANGE {
+                continue;
+            }
+            let threshold = (min + max) / 2.0;
+            for (x, y) in samples {
+                let side = input[y * w + x] >= threshold;
+                let mut sum = 0.0;
+                let mut uniform = true;
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        let value = at(x, y, dx, dy);
+                        uniform &= (value >= threshold) == side;
+                        // 1-2-1 weights in both directions.
+                        sum += value * ((2 - dx.abs()) * (2 - dy.abs())) as f32;
+                    }
+                }
+                if uniform {
+                    let value = input[y * w + x];
+                    plane[y * w + x] = value + (sum / 16.0 - value).clamp(-clip, clip);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Python bindings of the `python` feature, over numpy arrays of shape
+//! (height, width, 3 or 4) in `uint8`. They run the same pipeline as the
+//! WebAssembly build, with `cos`, `exp` and the like from `libm` on both.
+
+use numpy::ndarray::Array3;
+use numpy::{IntoPyArray, PyArray3, PyReadonlyArray3, PyUntypedArrayMethods};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::error::Error;
+use crate::metrics::{self, SsimWindow};
+use crate::options::{ChromaDownsampling, ChromaSubsampling, CompressOptions, PixelFormat};
+use crate::pipeline::{self, Frame};
+
+impl From<Error> for PyErr {
+    fn from(err: Error) -> PyErr {
+        PyValueError::new_err(err.code())
+    }
+}
+
+/// The pixels of an RGB or RGBA array.
+struct Pixels<'a> {
+    data: &'a [u8],
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+}
+
+impl<'a> Pixels<'a> {
+    fn new(image: &'a PyReadonlyArray3<'_, u8>) -> PyResult<Pixels<'a>> {
+        let [height, width, channels] = image.shape() else {
+            unreachable!("the array has three dimensions");
+        };
+        let format = match channels {
+            3 => PixelFormat::Rgb,
+            4 => PixelFormat::Rgba,
+            _ => return Err(PyValueError::new_err("expected 3 (RGB) or 4 (RGBA) channels")),
+        };
+        let data = image
+            .as_slice()
+            .map_err(|_| PyValueError::new_err("expected a C-contiguous array"))?;
+        Ok(Pixels {
+            data,
+            width: *width,
+            height: *height,
+            format,
+        })
+    }
+
+    /// The pixels as RGBA, as the metrics take them.
+    fn to_rgba(&self) -> Result<Vec<u8>, Error> {
+        let frame = Frame::from_pixels(self.data, self.width, self.height, self.format)?;
+        Ok(frame.to_pixels(PixelFormat::Rgba))
+    }
+}
+
+/// Options from the keyword arguments shared by `compress` and
+/// `encode_jpeg`; `None` keeps the default.
+fn options(
+    compression: f32,
+    subsampling: Option<&str>,
+    chroma_downsampling: Option<&str>,
+    deblock: bool,
+    dering: bool,
+) -> PyResult<CompressOptions> {
+    let mut options = CompressOptions {
+        compression,
+        deblock,
+        dering,
+        ..CompressOptions::default()
+    };
+    if let Some(subsampling) = subsampling {
+        options.subsampling = match subsampling {
+            "444" => ChromaSubsampling::Yuv444,
+            "422" => ChromaSubsampling::Yuv422,
+            "420" => ChromaSubsampling::Yuv420,
+            _ => return Err(PyValueError::new_err("subsampling must be '444', '422' or '420'")),
+        };
+    }
+    if let Some(downsampling) = chroma_downsampling {
+        options.chroma_downsampling = match downsampling {
+            "nearest" => ChromaDownsampling::Nearest,
+            "box" => ChromaDownsampling::Box,
+            "triangle" => ChromaDownsampling::Triangle,
+            _ => {
+                let message = "chroma_downsampling must be 'nearest', 'box' or 'triangle'";
+                return Err(PyValueError::new_err(message));
+            }
+        };
+    }
+    Ok(options)
+}
+
+/// Wraps `pixels` in an array of shape (`height`, `width`, `channels`).
+fn to_array(
+    py: Python<'_>,
+    pixels: Vec<u8>,
+    width: usize,
+    height: usize,
+    channels: usize,
+) -> PyResult<Bound<'_, PyArray3<u8>>> {
+    let array = Array3::from_shape_vec((height, width, channels), pixels)
+        .map_err(|err| PyValueError::new_err(err.to_string()))?;
+    Ok(array.into_pyarray(py))
+}
+
+/// The pixels of two arrays of the same size as RGBA, with that size.
+fn rgba_pair(
+    original: &PyReadonlyArray3<'_, u8>,
+    compressed: &PyReadonlyArray3<'_, u8>,
+) -> PyResult<(Vec<u8>, Vec<u8>, usize, usize)> {
+    let (original, compressed) = (Pixels::new(original)?, Pixels::new(compressed)?);
+    if (original.width, original.height) != (compressed.width, compressed.height) {
+        return Err(Error::DimensionMismatch.into());
+    }
+    let (width, height) = (original.width, original.height);
+    Ok((original.to_rgba()?, compressed.to_rgba()?, width, height))
+}
+
+/// Simulates JPEG compression, returning an array of the input's shape.
+///
+/// **Parameters:**
+/// - `image`: RGB or RGBA array.
+/// - `compression`: 0.0 (none) to 1.0 (heaviest).
+/// - `subsampling`: `"444"`, `"422"` or `"420"`.
+/// - `chroma_downsampling`: `"nearest"`, `"box"` or `"triangle"`.
+/// - `deblock`, `dering`: post-filters applied after decoding.
+#[pyfunction]
+#[pyo3(signature = (
+    image,
+    compression = 0.5,
+    subsampling = None,
+    chroma_downsampling = None,
+    deblock = false,
+    dering = false,
+))]
+fn compress<'py>(
+    py: Python<'py>,
+    image: PyReadonlyArray3<'py, u8>,
+    compression: f32,
+    subsampling: Option<&str>,
+    chroma_downsampling: Option<&str>,
+    deblock: bool,
+    dering: bool,
+) -> PyResult<Bound<'py, PyArray3<u8>>> {
+    let pixels = Pixels::new(&image)?;
+    let options = options(compression, subsampling, chroma_downsampling, deblock, dering)?;
+    let (data, width, height, format) = (pixels.data, pixels.width, pixels.height, pixels.format);
+    let output = py.detach(|| pipeline::compress(data, width, height, format, &options))?;
+    let channels = image.shape()[2];
+    to_array(py, output, width, height, channels)
+}
+
+/// Encodes `image` as a baseline JPEG file, taking the same options as
+/// `compress`.
+///
+/// **Returns:** the file as `bytes`.
+#[pyfunction]
+#[pyo3(signature = (
+    image,
+    compression = 0.5,
+    subsampling = None,
+    chroma_downsampling = None,
+    deblock = false,
+    dering = false,
+))]
+fn encode_jpeg<'py>(
+    py: Python<'py>,
+    image: PyReadonlyArray3<'py, u8>,
+    compression: f32,
+    subsampling: Option<&str>,
+    chroma_downsampling: Option<&str>,
+    deblock: bool,
+    dering: bool,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let pixels = Pixels::new(&image)?;
+    let options = options(compression, subsampling, chroma_downsampling, deblock, dering)?;
+    let (data, width, height, format) = (pixels.data, pixels.width, pixels.height, pixels.format);
+    let jpeg = py.detach(|| pipeline::encode_jpeg(data, width, height, format, &options))?;
+    Ok(PyBytes::new(py, &jpeg))
+}
+
+/// Decodes a baseline JPEG file written by `encode_jpeg`.
+///
+/// **Returns:** an RGB array.
+#[pyfunction]
+fn decode_jpeg<'py>(py: Python<'py>, jpeg: &[u8]) -> PyResult<Bound<'py, PyArray3<u8>>> {
+    let frame = pipeline::decode_jpeg(jpeg)?;
+    let pixels = frame.to_pixels(PixelFormat::Rgb);
+    to_array(py, pixels, frame.width, frame.height, 3)
+}
+
+/// Combined PSNR over R, G and B, in dB.
+#[pyfunction]
+fn psnr(original: PyReadonlyArray3<'_, u8>, compressed: PyReadonlyArray3<'_, u8>) -> PyResult<f64> {
+    let (original, compressed, _, _) = rgba_pair(&original, &compressed)?;
+    Ok(metrics::psnr(&original, &compressed).combined)
+}
+
+/// SSIM of luma with a Gaussian window.
+#[pyfunction]
+fn ssim(original: PyReadonlyArray3<'_, u8>, compressed: PyReadonlyArray3<'_, u8>) -> PyResult<f64> {
+    let (original, compressed, width, height) = rgba_pair(&original, &compressed)?;
+    Ok(metrics::ssim(&original, &compressed, width, height, SsimWindow::Gaussian))
+}
+
+/// Multi-scale SSIM of luma.
+#[pyfunction]
+fn ms_ssim(
+    original: PyReadonlyArray3<'_, u8>,
+    compressed: PyReadonlyArray3<'_, u8>,
+) -> PyResult<f64> {
+    let (original, compressed, width, height) = rgba_pair(&original, &compressed)?;
+    Ok(metrics::ms_ssim(&original, &compressed, width, height))
+}
+
+#[pymodule]
+fn compress_jpeg(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(compress, module)?)?;
+    module.add_function(wrap_pyfunction!(encode_jpeg, module)?)?;
+    module.add_function(wrap_pyfunction!(decode_jpeg, module)?)?;
+    module.add_function(wrap_pyfunction!(psnr, module)?)?;
+    module.add_function(wrap_pyfunction!(ssim, module)?)?;
+    module.add_function(wrap_pyfunction!(ms_ssim, module)?)?;
+    Ok(())
+}
@@ -0,0 +1,343 @@
+use std::rc::Rc;
+
+use crate::dct::ZIGZAG;
+use crate::effects::{self, Displacement, Glitch, Scramble};
+use crate::options::{
+    self, CompressOptions, CompressionScale, Degradation, Dither, QualityGradient,
+};
+use crate::regions::CompressionMap;
+
+/// Standard JPEG luminance quantization table (ITU-T T.81, Annex K).
+pub(crate) const STD_QUANT: [[u32; 8]; 8] = [
+    [16, 11, 10, 16, 24, 40, 51, 61], [12, 12, 14, 19, 26, 58, 60, 55],
+    [14, 13, 16, 24, 40, 57, 69, 56], [14, 17, 22, 29, 51, 87, 80, 62],
+    [18, 22, 37, 56, 68, 109, 103, 77], [24, 35, 55, 64, 81, 104, 113, 92],
+    [49, 64, 78, 87, 103, 121, 120, 101], [72, 92, 95, 98, 112, 100, 103, 99],
+];
+
+/// The standard table multiplied by `scale`.
+pub(crate) fn scaled_table(scale: f32) -> [[u32; 8]; 8] {
+    STD_QUANT.map(|r| r.map(|v| (v as f32 * scale).floor().max(1.0) as u32))
+}
+
+/// Quantization table for `compression` (0.0–1.0) on the given scale.
+///
+/// The `Browser` scale reproduces libjpeg's tables exactly: entries are
+/// rounded rather than truncated and limited to 8 bits.
+pub(crate) fn quant_table(compression: f32, scale: CompressionScale) -> [[u32; 8]; 8] {
+    match scale {
+        CompressionScale::Browser => {
+            let percent = options::libjpeg_scale(1.0 - compression);
+            STD_QUANT.map(|r| r.map(|v| ((v * percent + 50) / 100).clamp(1, 255)))
+        }
+        _ => scaled_table(scale.table_scale(compression)),
+    }
+}
+
+/// The steps the block pass quantizes every plane with at
+/// `options.compression`, limited to 255 as in a JPEG file when `baseline` is
+/// set. With a `quality_gradient` these are the finest steps of the ramp.
+pub(crate) fn effective_table(options: &CompressOptions, baseline: bool) -> [[u32; 8]; 8] {
+    let quantizer = Quantizer::new(options.compression.clamp(0.0, 1.0), options);
+    if baseline {
+        quantizer.baseline().table
+    } else {
+        quantizer.table
+    }
+}
+
+/// The compression ramp of `quality_gradient` over a plane.
+#[derive(Clone, Copy, Debug)]
+struct Ramp {
+    shape: QualityGradient,
+    /// Compression at the start and at the end of the ramp.
+    from: f32,
+    to: f32,
+}
+
+impl Ramp {
+    fn new(compression: f32, options: &CompressOptions) -> Option<Self> {
+        let shaded = options.degradation != Degradation::Zigzag;
+        (shaded && options.quality_gradient != QualityGradient::None).then_some(Ramp {
+            shape: options.quality_gradient,
+            from: compression,
+            to: options.gradient_compression.clamp(0.0, 1.0),
+        })
+    }
+
+    /// Compression at (`x`, `y`), given as fractions (0–1) of the image size.
+    fn compression(&self, x: f32, y: f32) -> f32 {
+        let t = match self.shape {
+            QualityGradient::None => 0.0,
+            QualityGradient::Vertical => y,
+            QualityGradient::Horizontal => x,
+            // Half the diagonal reaches the corners.
+            QualityGradient::Radial => ((x - 0.5).powi(2) + (y - 0.5).powi(2)).sqrt() * 2f32.sqrt(),
+        };
+        self.from + (self.to - self.from) * t.min(1.0)
+    }
+}
+
+/// Where planes being quantized sit in a taller image they are a band of: the
+/// image row the band starts at, its rows and the image height, in luma rows,
+/// with the vertical chroma subsampling.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Band {
+    pub(crate) top: usize,
+    pub(crate) rows: usize,
+    pub(crate) height: usize,
+    pub(crate) fy: usize,
+}
+
+impl Band {
+    /// The first row and the rows of the band, and the rows of the image, in
+    /// plane `plane`.
+    fn plane_rows(self, plane: u32) -> (usize, usize, usize) {
+        let fy = if matches!(plane, 1 | 2) { self.fy } else { 1 };
+        (self.top / fy, self.rows.div_ceil(fy), self.height.div_ceil(fy))
+    }
+}
+
+/// Everything the block pass needs to quantize one plane.
+#[derive(Clone)]
+pub(crate) struct Quantizer {
+    pub(crate) table: [[u32; 8]; 8],
+    /// Feed DC rounding error forward into neighboring blocks.
+    pub(crate) error_diffusion: bool,
+    dither: Dither,
+    dither_strength: f32,
+    /// Frequency filter gain of each coefficient, applied before rounding.
+    gains: [[f32; 8]; 8],
+    /// Number of leading zigzag coefficients kept in each block (1–64).
+    keep: usize,
+    glitch: Glitch,
+    scramble: Scramble,
+    displacement: Displacement,
+    /// Width and height of the transform blocks.
+    pub(crate) block_size: usize,
+    /// Whether the block pass is lapped over half-block offset grids.
+    pub(crate) overlap: bool,
+    /// Whether block edges are smoothed after the block pass.
+    pub(crate) deblock: bool,
+    /// Whether ringing next to strong edges is flattened after the block pass.
+    pub(crate) dering: bool,
+    /// Index of the plane being quantized, so each plane glitches differently.
+    plane: u32,
+    /// Compression ramp across the plane; `table` then holds the finest steps
+    /// of the ramp.
+    ramp: Option<Ramp>,
+    /// Compression of every region, overriding the ramp; `table` then holds
+    /// the finest steps of the map.
+    map: Option<Rc<CompressionMap>>,
+    compression_scale: CompressionScale,
+    /// Blocks per row and per column of the plane being quantized.
+    grid: (usize, usize),
+    /// The image the planes are a band of, if they are one.
+    band: Option<Band>,
+    /// Block row of the image the plane being quantized starts at.
+    first_row: usize,
+}
+
+impl Quantizer {
+    /// Quantizer for a compression factor in 0.0–1.0, mapped through
+    /// `options.compression_scale`, with the remaining settings from `options`.
+    ///
+    /// `Degradation::Zigzag` ignores the compression factor: coefficients are
+    /// rounded to whole steps and only the first `keep_coefficients` survive.
+    ///
+    /// With a `quality_gradient`, the table is the one for the lower of
+    /// `compression` and `gradient_compression`; blocks further along the
+    /// ramp quantize with coarser steps, so their levels in this table's steps
+    /// stay whole numbers, as a JPEG file stores them.
+    pub(crate) fn new(compression: f32, options: &CompressOptions) -> Self {
+        let ramp = Ramp::new(compression, options);
+        let compression = ramp.map_or(compression, |r| r.from.min(r.to));
+        let (table, keep) = match options.degradation {
+            Degradation::Quantize => (quant_table(compression, options.compression_scale), 64),
+            Degradation::Zigzag => ([[1; 8]; 8], options.keep_coefficients.clamp(1, 64) as usize),
+            Degradation::DcOnly => (quant_table(compression, options.compression_scale), 1),
+        };
+        Quantizer {
+            table,
+            error_diffusion: options.error_diffusion,
+            dither: options.dither,
+            dither_strength: options.dither_strength.clamp(0.0, 1.0),
+            gains: effects::frequency_gains(options),
+            keep,
+            glitch: Glitch::new(options),
+            scramble: Scramble::new(options),
+            displacement: Displacement::new(options),
+            block_size: options.block_size.samples(),
+            overlap: options.block_overlap,
+            deblock: options.deblock,
+            dering: options.dering,
+            plane: 0,
+            ramp,
+            map: None,
+            compression_scale: options.compression_scale,
+            grid: (1, 1),
+            band: None,
+            first_row: 0,
+        }
+    }
+
+    /// Quantizer whose compression varies by region as `map` says, with the
+    /// remaining settings from `options`. Like a `quality_gradient`, which the
+    /// map replaces, it is ignored under `Degradation::Zigzag`.
+    pub(crate) fn mapped(map: CompressionMap, options: &CompressOptions) -> Self {
+        let mut quantizer = Quantizer::new(map.finest(), options);
+        if options.degradation != Degradation::Zigzag {
+            quantizer.map = Some(Rc::new(map));
+        }
+        quantizer
+    }
+
+    /// Whether only the DC coefficient of each block survives, so the forward
+    /// transform can skip the AC coefficients.
+    pub(crate) fn dc_only(&self) -> bool {
+        self.keep == 1
+    }
+
+    /// Limits every table entry to 255, the largest step an 8-bit baseline JPEG
+    /// quantization table can store.
+    pub(crate) fn baseline(mut self) -> Self {
+        for q in self.table.iter_mut().flatten() {
+            *q = (*q).min(255);
+        }
+        self
+    }
+
+    /// Adapts the table to an earlier encode that used `prior`: every step is
+    /// rounded to a whole multiple of the prior one: down to the multiple
+    /// below, or up to the prior step itself when it is finer. Coefficients
+    /// already on the old grid stay on the new one, and steps finer than the
+    /// prior ones would keep detail that is already gone.
+    pub(crate) fn reusing(mut self, prior: &[[u32; 8]; 8]) -> Self {
+        for (q, &p) in self.table.iter_mut().flatten().zip(prior.iter().flatten()) {
+            *q = (*q / p).max(1) * p;
+        }
+        self
+    }
+
+    /// The same quantizer for plane `plane` (0 for luma, 1 and 2 for chroma,
+    /// 3 for alpha).
+    pub(crate) fn plane(&self, plane: u32) -> Self {
+        Quantizer {
+            plane,
+            ..self.clone()
+        }
+    }
+
+    /// The same quantizer for the planes of `band`, which places their blocks
+    /// in the whole image: the dither pattern, the glitch and coefficient
+    /// effects and the `quality_gradient` ramp then line up across bands.
+    /// The band must start on a row of `block_size`-sample chroma blocks.
+    pub(crate) fn in_band(&self, band: Band) -> Self {
+        Quantizer {
+            band: Some(band),
+            ..self.clone()
+        }
+    }
+
+    /// The same quantizer for a plane of `blocks_x` by `blocks_y` blocks, which
+    /// places the blocks on the region map or `quality_gradient` ramp.
+    pub(crate) fn on_grid(&self, blocks_x: usize, blocks_y: usize) -> Self {
+        let (first_row, blocks_y) = match self.band {
+            None => (0, blocks_y),
+            Some(band) => {
+                let (top, rows, height) = band.plane_rows(self.plane);
+                let n = self.block_size;
+                // Lapped passes pad the plane above by half a block, and so
+                // the image too.
+                let padding = if blocks_y > rows.div_ceil(n) { n / 2 } else { 0 };
+                (top / n, (height + padding).div_ceil(n))
+            }
+        };
+        Quantizer {
+            grid: (blocks_x.max(1), blocks_y.max(1)),
+            first_row,
+            ..self.clone()
+        }
+    }
+
+    /// Quantization steps of the block at (`bx`, `by`): the table, coarsened
+    /// to the compression of the region map or `quality_gradient` ramp at the
+    /// block center.
+    fn block_table(&self, bx: usize, by: usize) -> [[u32; 8]; 8] {
+        let x = (bx as f32 + 0.5) / self.grid.0 as f32;
+        let y = (by as f32 + 0.5) / self.grid.1 as f32;
+        let compression = match (&self.map, self.ramp) {
+            (Some(map), _) => map.at(x, y),
+            (None, Some(ramp)) => ramp.compression(x, y),
+            (None, None) => return self.table,
+        };
+        let steps = quant_table(compression, self.compression_scale);
+        let mut table = self.table;
+        for (q, &step) in table.iter_mut().flatten().zip(steps.iter().flatten()) {
+            // Whole multiples of the table steps, so levels stay whole numbers.
+            *q *= (step as f32 / *q as f32).round().max(1.0) as u32;
+        }
+        table
+    }
+
+    /// Quantizes and dequantizes the coefficients of the block at (`bx`, `by`).
+    ///
+    /// The frequency filter scales the coefficients first. Dithering shifts
+    /// each rounding threshold by a pattern offset indexed by block position,
+    /// so neighboring blocks in a smooth gradient round to different levels
+    /// instead of banding together. Levels past the kept zigzag prefix are
+    /// then dropped, and the glitch and coefficient effects act on what remains.
+    pub(crate) fn quantize(&self, mut dct: [[f32; 8]; 8], bx: usize, by: usize) -> [[f32; 8]; 8] {
+        let by = by + self.first_row;
+        let table = self.block_table(bx, by);
+        for u in 0..8 {
+            for v in 0..8 {
+                let q = table[u][v] as f32;
+                // Offset the pattern per coefficient so frequencies don't dither in lockstep.
+                let d = self.dither.offset(bx + 3 * v + 5 * u, by + 5 * v + 3 * u);
+                dct[u][v] = (dct[u][v] * self.gains[u][v] / q + d * self.dither_strength).round();
+            }
+        }
+        for &(u, v) in &ZIGZAG[self.keep..] {
+            dct[u][v] = 0.0;
+        }
+        self.glitch.corrupt(&mut dct, self.plane, bx, by);
+        self.scramble.apply(&mut dct, self.plane, bx, by);
+        for u in 0..8 {
+            for v in 0..8 {
+                dct[u][v] *= table[u][v] as f32;
+            }
+        }
+        dct
+    }
+
+    /// Quantizes and dequantizes an `n`x`n` block of coefficients in place.
+    ///
+    /// Steps come from the 8x8 table at the nearest frequency, scaled by
+    /// `n / 8` so the error per sample matches 8x8 blocks. Dithering works as
+    /// in [`Quantizer::quantize`]; the 8x8-only settings are skipped.
+    pub(crate) fn quantize_sized(&self, coeffs: &mut [f32], n: usize, bx: usize, by: usize) {
+        let by = by + self.first_row;
+        let (table, scale) = (self.block_table(bx, by), n as f32 / 8.0);
+        for u in 0..n {
+            for v in 0..n {
+                let q = table[u * 8 / n][v * 8 / n] as f32 * scale;
+                let d = self.dither.offset(bx + 3 * v + 5 * u, by + 5 * v + 3 * u);
+                let c = &mut coeffs[u * n + v];
+                *c = (*c / q + d * self.dither_strength).round() * q;
+            }
+        }
+    }
+
+    /// Whether [`Quantizer::displace`] moves any block, which makes the
+    /// levels of a block depend on others anywhere in the plane.
+    pub(crate) fn displaces(&self) -> bool {
+        self.displacement.moves_blocks()
+    }
+
+    /// Applies the block shuffle to the quantized blocks of a plane, stored in
+    /// raster order `blocks_x` to a row.
+    pub(crate) fn displace<T: Copy>(&self, blocks: &mut [T], blocks_x: usize) {
+        self.displacement.apply(blocks, blocks_x, self.plane);
+    }
+}
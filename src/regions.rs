@@ -0,0 +1,130 @@
+//! Compression that varies by region, as a value per 8x8 luma block.
+
+use wasm_bindgen::prelude::*;
+
+/// A rectangle of the image with its own compression, for
+/// `compress_with_regions`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QualityRegion {
+    /// Left edge in pixels.
+    pub x: u32,
+    /// Top edge in pixels.
+    pub y: u32,
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// Compression (0.0–1.0) inside the rectangle.
+    pub compression: f32,
+}
+
+#[wasm_bindgen]
+impl QualityRegion {
+    #[wasm_bindgen(constructor)]
+    pub fn new(x: u32, y: u32, width: u32, height: u32, compression: f32) -> QualityRegion {
+        QualityRegion {
+            x,
+            y,
+            width,
+            height,
+            compression,
+        }
+    }
+}
+
+/// Compression (0.0–1.0) of every 8x8 block of the luma plane, in raster
+/// order. Planes on other grids look up the block under their own block's
+/// center.
+#[derive(Clone, Debug)]
+pub(crate) struct CompressionMap {
+    blocks_x: usize,
+    blocks_y: usize,
+    values: Vec<f32>,
+}
+
+impl CompressionMap {
+    /// Map for a `width`x`height` image from an RGBA `mask` of any size,
+    /// stretched over the image: each block gets `compression` times the mean
+    /// brightness (0–1) of the mask under it, so black keeps a block at the
+    /// finest quantization and white compresses it fully.
+    pub(crate) fn from_mask(
+        mask: &[u8],
+        mask_width: usize,
+        mask_height: usize,
+        width: usize,
+        height: usize,
+        compression: f32,
+    ) -> Self {
+        let (blocks_x, blocks_y) = (width.div_ceil(8), height.div_ceil(8));
+        // Mask samples under the pixels `start..end` of an image axis of `len`.
+        let span = |start: usize, end: usize, len: usize, mask_len: usize| {
+            let from = start * mask_len / len;
+            (from, (end * mask_len).div_ceil(len).max(from + 1))
+        };
+        let compression = compression.clamp(0.0, 1.0);
+        let values = (0..blocks_x * blocks_y)
+            .map(|i| {
+                let (bx, by) = (i % blocks_x * 8, i / blocks_x * 8);
+                let (x0, x1) = span(bx, (bx + 8).min(width), width, mask_width);
+                let (y0, y1) = span(by, (by + 8).min(height), height, mask_height);
+                let sum: u32 = (y0..y1)
+                    .flat_map(|y| (x0..x1).map(move |x| (y * mask_width + x) * 4))
+                    .map(|p| mask[p] as u32 + mask[p + 1] as u32 + mask[p + 2] as u32)
+                    .sum();
+                let brightness = sum as f32 / (3.0 * 255.0 * ((x1 - x0) * (y1 - y0)) as f32);
+                compression * brightness
+            })
+            .collect();
+        CompressionMap {
+            blocks_x,
+            blocks_y,
+            values,
+        }
+    }
+
+    /// Map for a `width`x`height` image with `compression` everywhere but in
+    /// the blocks `regions` touch, which take the compression of their region.
+    /// Where regions share a block, the lowest compression wins, so whatever
+    /// a region holds stays at least as sharp as asked.
+    pub(crate) fn from_regions(
+        regions: &[QualityRegion],
+        width: usize,
+        height: usize,
+        compression: f32,
+    ) -> Self {
+        let (blocks_x, blocks_y) = (width.div_ceil(8), height.div_ceil(8));
+        let mut values = vec![compression.clamp(0.0, 1.0); blocks_x * blocks_y];
+        let mut covered = vec![false; values.len()];
+        for region in regions.iter().filter(|r| r.width > 0 && r.height > 0) {
+            let (x, y) = (region.x as usize, region.y as usize);
+            let x_end = x.saturating_add(region.width as usize).min(width).div_ceil(8);
+            let y_end = y.saturating_add(region.height as usize).min(height).div_ceil(8);
+            for by in y / 8..y_end {
+                for bx in x / 8..x_end {
+                    let i = by * blocks_x + bx;
+                    let inside = region.compression.clamp(0.0, 1.0);
+                    values[i] = if covered[i] { values[i].min(inside) } else { inside };
+                    covered[i] = true;
+                }
+            }
+        }
+        CompressionMap {
+            blocks_x,
+            blocks_y,
+            values,
+        }
+    }
+
+    /// Compression at (`x`, `y`), given as fractions (0–1) of the image size.
+    pub(crate) fn at(&self, x: f32, y: f32) -> f32 {
+        let bx = ((x * self.blocks_x as f32) as usize).min(self.blocks_x - 1);
+        let by = ((y * self.blocks_y as f32) as usize).min(self.blocks_y - 1);
+        self.values[by * self.blocks_x + bx]
+    }
+
+    /// The lowest compression in the map.
+    pub(crate) fn finest(&self) -> f32 {
+        self.values.iter().copied().fold(1.0, f32::min)
+    }
+}
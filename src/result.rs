@@ -0,0 +1,329 @@
+use wasm_bindgen::prelude::*;
+use web_sys::ImageData as BrowserImageData;
+
+use crate::dct::{self, BlockStats};
+use crate::metrics::ChannelDifference;
+use crate::options::ChromaSubsampling;
+use crate::placeholder::ImageColors;
+use crate::web;
+
+/// Output of `compress_jpeg_with_result`: the compressed image plus the
+/// measurements requested in the options.
+#[wasm_bindgen]
+pub struct CompressionResult {
+    image_data: BrowserImageData,
+    /// Compression strength the output was produced with.
+    pub compression: f32,
+    /// Luma SSIM against the input (gaussian window), when `measure_ssim` is set.
+    pub ssim: Option<f64>,
+    /// Combined RGB PSNR against the input in decibels, when measured.
+    pub psnr: Option<f64>,
+    /// Estimated size of the output as a baseline JPEG file, when measured.
+    pub estimated_bytes: Option<u32>,
+    /// Butteraugli-style `perceptual_distance` score against the input, when measured.
+    pub butteraugli: Option<f64>,
+    /// Statistics of the block pass, when measured.
+    pub stats: Option<CompressionStats>,
+    /// Average and dominant colors of the input, when `measure_colors` is set.
+    pub colors: Option<ImageColors>,
+    pub(crate) blurhash: Option<String>,
+}
+
+impl CompressionResult {
+    pub(crate) fn new(image_data: BrowserImageData, compression: f32) -> CompressionResult {
+        CompressionResult {
+            image_data,
+            compression,
+            ssim: None,
+            psnr: None,
+            estimated_bytes: None,
+            butteraugli: None,
+            stats: None,
+            colors: None,
+            blurhash: None,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl CompressionResult {
+    /// The compressed pixels.
+    #[wasm_bindgen(getter)]
+    pub fn image_data(&self) -> BrowserImageData {
+        self.image_data.clone()
+    }
+
+    /// A 4x3 BlurHash of the input, when `measure_blurhash` is set.
+    #[wasm_bindgen(getter)]
+    pub fn blurhash(&self) -> Option<String> {
+        self.blurhash.clone()
+    }
+}
+
+/// What the block pass did to an image, for logging and tuning defaults.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct CompressionStats {
+    /// Uncompressed 24-bit RGB size divided by `estimated_bytes`.
+    pub compression_ratio: f64,
+    /// Estimated size of the output as a baseline JPEG file.
+    pub estimated_bytes: u32,
+    /// Percentage of luma DCT coefficients quantized to zero.
+    pub zeroed_luma: f64,
+    /// Percentage of Cb coefficients quantized to zero; unset for grayscale output.
+    pub zeroed_cb: Option<f64>,
+    /// Percentage of Cr coefficients quantized to zero; unset for grayscale output.
+    pub zeroed_cr: Option<f64>,
+    /// Mean squared quantization error per sample over all blocks of all planes.
+    pub mean_error: f64,
+    /// Number of 8x8 blocks processed across all planes.
+    pub blocks: u32,
+    /// Chroma subsampling of the planes; unset for grayscale output.
+    pub subsampling: Option<ChromaSubsampling>,
+}
+
+impl CompressionStats {
+    pub(crate) fn new(
+        stats: &[Vec<BlockStats>; 3],
+        width: usize,
+        height: usize,
+        subsampling: ChromaSubsampling,
+    ) -> CompressionStats {
+        let zeroed = |blocks: &[BlockStats]| {
+            let nonzero: usize = blocks.iter().map(|s| s.nonzero as usize).sum();
+            (!blocks.is_empty())
+                .then(|| 100.0 * (1.0 - nonzero as f64 / (blocks.len() * 64) as f64))
+        };
+        let blocks: usize = stats.iter().map(Vec::len).sum();
+        let error: f64 = stats.iter().flatten().map(|s| s.error as f64).sum();
+        let estimated_bytes = dct::estimated_bytes(stats.iter().flatten());
+
+        CompressionStats {
+            compression_ratio: (width * height * 3) as f64 / estimated_bytes as f64,
+            estimated_bytes: estimated_bytes as u32,
+            zeroed_luma: zeroed(&stats[0]).unwrap_or(0.0),
+            zeroed_cb: zeroed(&stats[1]),
+            zeroed_cr: zeroed(&stats[2]),
+            mean_error: if blocks > 0 {
+                error / blocks as f64
+            } else {
+                0.0
+            },
+            blocks: blocks as u32,
+            subsampling: (!stats[1].is_empty()).then_some(subsampling),
+        }
+    }
+}
+
+/// A baseline JPEG file produced by `compress_to_size`, with the settings that produced it.
+#[wasm_bindgen]
+pub struct EncodedJpeg {
+    bytes: Vec<u8>,
+    /// Compression strength used for the file.
+    pub compression: f32,
+    /// Chroma subsampling used for the file.
+    pub subsampling: ChromaSubsampling,
+}
+
+impl EncodedJpeg {
+    pub(crate) fn new(bytes: Vec<u8>, compression: f32, subsampling: ChromaSubsampling) -> Self {
+        EncodedJpeg {
+            bytes,
+            compression,
+            subsampling,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl EncodedJpeg {
+    /// The encoded file.
+    #[wasm_bindgen(getter)]
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+
+    /// Size of the file in bytes.
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> u32 {
+        self.bytes.len() as u32
+    }
+
+    /// The file as a `data:image/jpeg;base64,…` URL, ready for an `<img>`
+    /// `src` or a CSS `background-image`.
+    #[wasm_bindgen(getter)]
+    pub fn data_url(&self) -> String {
+        web::data_url("image/jpeg", &self.bytes)
+    }
+}
+
+/// Pixel-by-pixel comparison of two images, from `compare`.
+#[wasm_bindgen]
+pub struct ImageComparison {
+    /// Differences of the red channel.
+    pub red: ChannelDifference,
+    /// Differences of the green channel.
+    pub green: ChannelDifference,
+    /// Differences of the blue channel.
+    pub blue: ChannelDifference,
+    /// Differences of the alpha channel.
+    pub alpha: ChannelDifference,
+    /// Percentage (0–100) of pixels that differ in any channel.
+    pub changed_pixels: f64,
+    pub(crate) difference: Option<BrowserImageData>,
+}
+
+#[wasm_bindgen]
+impl ImageComparison {
+    /// The amplified absolute difference, when requested.
+    #[wasm_bindgen(getter)]
+    pub fn difference(&self) -> Option<BrowserImageData> {
+        self.difference.clone()
+    }
+}
+
+/// Every intermediate stage of one compression, from `compress_debug`. Planes
+/// and coefficients are painted in grayscale.
+#[wasm_bindgen]
+pub struct CompressionStages {
+    pub(crate) y: BrowserImageData,
+    pub(crate) cb: Option<BrowserImageData>,
+    pub(crate) cr: Option<BrowserImageData>,
+    pub(crate) cb_subsampled: Option<BrowserImageData>,
+    pub(crate) cr_subsampled: Option<BrowserImageData>,
+    pub(crate) coefficients: BrowserImageData,
+    pub(crate) quantized_coefficients: BrowserImageData,
+    pub(crate) reconstruction: BrowserImageData,
+    pub(crate) output: BrowserImageData,
+}
+
+#[wasm_bindgen]
+impl CompressionStages {
+    /// The luma plane after color conversion.
+    #[wasm_bindgen(getter)]
+    pub fn y(&self) -> BrowserImageData {
+        self.y.clone()
+    }
+
+    /// The Cb plane at full resolution; unset for grayscale output.
+    #[wasm_bindgen(getter)]
+    pub fn cb(&self) -> Option<BrowserImageData> {
+        self.cb.clone()
+    }
+
+    /// The Cr plane at full resolution; unset for grayscale output.
+    #[wasm_bindgen(getter)]
+    pub fn cr(&self) -> Option<BrowserImageData> {
+        self.cr.clone()
+    }
+
+    /// The Cb plane after subsampling, at its own size; unset for grayscale output.
+    #[wasm_bindgen(getter)]
+    pub fn cb_subsampled(&self) -> Option<BrowserImageData> {
+        self.cb_subsampled.clone()
+    }
+
+    /// The Cr plane after subsampling, at its own size; unset for grayscale output.
+    #[wasm_bindgen(getter)]
+    pub fn cr_subsampled(&self) -> Option<BrowserImageData> {
+        self.cr_subsampled.clone()
+    }
+
+    /// Log-scaled magnitudes of the luma DCT coefficients, one 8x8 tile per
+    /// block with the DC at its top left.
+    #[wasm_bindgen(getter)]
+    pub fn coefficients(&self) -> BrowserImageData {
+        self.coefficients.clone()
+    }
+
+    /// The same coefficients after quantization; what is dark here and bright
+    /// in `coefficients` is what the compression removed.
+    #[wasm_bindgen(getter)]
+    pub fn quantized_coefficients(&self) -> BrowserImageData {
+        self.quantized_coefficients.clone()
+    }
+
+    /// The image rebuilt from the quantized coefficients, before sharpening.
+    #[wasm_bindgen(getter)]
+    pub fn reconstruction(&self) -> BrowserImageData {
+        self.reconstruction.clone()
+    }
+
+    /// The final image.
+    #[wasm_bindgen(getter)]
+    pub fn output(&self) -> BrowserImageData {
+        self.output.clone()
+    }
+}
+
+/// One tile from `tile_and_compress` or `tile_and_encode`, with its place
+/// in the image.
+#[wasm_bindgen]
+pub struct Tile {
+    /// Left edge in the image, in pixels.
+    pub x: u32,
+    /// Top edge in the image, in pixels.
+    pub y: u32,
+    /// Column of the tile in the grid, from 0 at the left.
+    pub column: u32,
+    /// Row of the tile in the grid, from 0 at the top.
+    pub row: u32,
+    /// Width in pixels; less than the tile size at the right edge.
+    pub width: u32,
+    /// Height in pixels; less than the tile size at the bottom edge.
+    pub height: u32,
+    pub(crate) image_data: Option<BrowserImageData>,
+    pub(crate) jpeg: Option<Vec<u8>>,
+}
+
+#[wasm_bindgen]
+impl Tile {
+    /// The compressed pixels, from `tile_and_compress`.
+    #[wasm_bindgen(getter)]
+    pub fn image_data(&self) -> Option<BrowserImageData> {
+        self.image_data.clone()
+    }
+
+    /// The baseline JPEG file, from `tile_and_encode`.
+    #[wasm_bindgen(getter)]
+    pub fn jpeg(&self) -> Option<Vec<u8>> {
+        self.jpeg.clone()
+    }
+}
+
+/// The subsampled chroma planes around the block pass, from `chroma_planes`,
+/// painted in grayscale at their own size.
+#[wasm_bindgen]
+pub struct ChromaStages {
+    pub(crate) cb: BrowserImageData,
+    pub(crate) cr: BrowserImageData,
+    pub(crate) cb_compressed: BrowserImageData,
+    pub(crate) cr_compressed: BrowserImageData,
+}
+
+#[wasm_bindgen]
+impl ChromaStages {
+    /// The Cb plane after subsampling, as the block pass receives it.
+    #[wasm_bindgen(getter)]
+    pub fn cb(&self) -> BrowserImageData {
+        self.cb.clone()
+    }
+
+    /// The Cr plane after subsampling, as the block pass receives it.
+    #[wasm_bindgen(getter)]
+    pub fn cr(&self) -> BrowserImageData {
+        self.cr.clone()
+    }
+
+    /// The Cb plane after the block pass, before upsampling.
+    #[wasm_bindgen(getter)]
+    pub fn cb_compressed(&self) -> BrowserImageData {
+        self.cb_compressed.clone()
+    }
+
+    /// The Cr plane after the block pass, before upsampling.
+    #[wasm_bindgen(getter)]
+    pub fn cr_compressed(&self) -> BrowserImageData {
+        self.cr_compressed.clone()
+    }
+}
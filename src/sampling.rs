@@ -0,0 +1,231 @@
+use std::f32::consts::PI;
+
+use crate::options::{ChromaDownsampling, ChromaSiting, ChromaUpsampling, ResizeFilter};
+
+/// Size of a `w`x`h` plane after subsampling by `fx`/`fy`.
+///
+/// Partial groups at the right/bottom edge still get their own sample, so odd
+/// dimensions never lose the last column or row.
+pub(crate) fn subsampled_size(w: usize, h: usize, fx: usize, fy: usize) -> (usize, usize) {
+    (w.div_ceil(fx), h.div_ceil(fy))
+}
+
+/// Reduces a `w`x`h` plane by `fx`/`fy` using the given filter.
+///
+/// Returns the subsampled plane together with its width and height.
+pub(crate) fn downsample(
+    input: &[f32],
+    w: usize,
+    h: usize,
+    fx: usize,
+    fy: usize,
+    method: ChromaDownsampling,
+    siting: ChromaSiting,
+) -> (Vec<f32>, usize, usize) {
+    let (sub_w, sub_h) = subsampled_size(w, h, fx, fy);
+
+    if method == ChromaDownsampling::Nearest {
+        let mut out = vec![0.0; sub_w * sub_h];
+        for y in 0..sub_h {
+            for x in 0..sub_w {
+                let src_y = (y * fy).min(h - 1);
+                let src_x = (x * fx).min(w - 1);
+                out[y * sub_w + x] = input[src_y * w + src_x];
+            }
+        }
+        return (out, sub_w, sub_h);
+    }
+
+    let horizontal = apply_taps(input, w, h, &downsample_taps(w, fx, method, siting), true);
+    let out = apply_taps(&horizontal, sub_w, h, &downsample_taps(h, fy, method, siting), false);
+    (out, sub_w, sub_h)
+}
+
+/// Expands a plane subsampled by `fx`/`fy` back to `w`x`h` using the given filter.
+pub(crate) fn upsample(
+    input: &[f32],
+    w: usize,
+    h: usize,
+    fx: usize,
+    fy: usize,
+    method: ChromaUpsampling,
+    siting: ChromaSiting,
+) -> Vec<f32> {
+    let (sub_w, sub_h) = subsampled_size(w, h, fx, fy);
+    let x_taps = upsample_taps(w, sub_w, fx, method, siting);
+    let y_taps = upsample_taps(h, sub_h, fy, method, siting);
+    let horizontal = apply_taps(input, sub_w, sub_h, &x_taps, true);
+    apply_taps(&horizontal, w, sub_h, &y_taps, false)
+}
+
+/// Scales a `w`x`h` plane to `out_w`x`out_h` with the given filter.
+///
+/// When shrinking, the kernel is stretched to the output pixel spacing, so
+/// every source pixel contributes and fine detail is filtered out rather
+/// than aliased.
+pub(crate) fn resize(
+    input: &[f32],
+    w: usize,
+    h: usize,
+    out_w: usize,
+    out_h: usize,
+    filter: ResizeFilter,
+) -> Vec<f32> {
+    let horizontal = apply_taps(input, w, h, &resize_taps(w, out_w, filter), true);
+    apply_taps(&horizontal, out_w, h, &resize_taps(h, out_h, filter), false)
+}
+
+/// Normalized source taps for every output sample along one axis.
+pub(crate) type Taps = Vec<Vec<(usize, f32)>>;
+
+fn downsample_taps(
+    len: usize,
+    factor: usize,
+    method: ChromaDownsampling,
+    siting: ChromaSiting,
+) -> Taps {
+    let count = len.div_ceil(factor);
+    let f = factor as f32;
+    let radius = match method {
+        ChromaDownsampling::Triangle => f,
+        _ => f / 2.0,
+    };
+
+    (0..count)
+        .map(|i| {
+            let center = i as f32 * f + siting.offset(factor);
+            let first = (center - radius).floor() as isize;
+            let last = (center + radius).ceil() as isize;
+            // Taps falling outside the plane replicate the edge pixel.
+            let mut taps: Vec<(usize, f32)> = (first..=last)
+                .map(|s| (clamp_index(s, len), kernel(method, (s as f32 - center) / f)))
+                .filter(|&(_, w)| w > 0.0)
+                .collect();
+            let total: f32 = taps.iter().map(|&(_, w)| w).sum();
+            for tap in taps.iter_mut() {
+                tap.1 /= total;
+            }
+            taps
+        })
+        .collect()
+}
+
+fn upsample_taps(
+    len: usize,
+    count: usize,
+    factor: usize,
+    method: ChromaUpsampling,
+    siting: ChromaSiting,
+) -> Taps {
+    let f = factor as f32;
+    let clamp = |i: isize| clamp_index(i, count);
+
+    (0..len)
+        .map(|x| {
+            if method == ChromaUpsampling::Nearest {
+                return vec![((x / factor).min(count - 1), 1.0)];
+            }
+
+            let pos = (x as f32 - siting.offset(factor)) / f;
+            let base = pos.floor();
+            let t = pos - base;
+            let base = base as isize;
+            match method {
+                ChromaUpsampling::CatmullRom => {
+                    let t2 = t * t;
+                    let t3 = t2 * t;
+                    vec![
+                        (clamp(base - 1), 0.5 * (-t3 + 2.0 * t2 - t)),
+                        (clamp(base), 0.5 * (3.0 * t3 - 5.0 * t2 + 2.0)),
+                        (clamp(base + 1), 0.5 * (-3.0 * t3 + 4.0 * t2 + t)),
+                        (clamp(base + 2), 0.5 * (t3 - t2)),
+                    ]
+                }
+                _ => vec![(clamp(base), 1.0 - t), (clamp(base + 1), t)],
+            }
+        })
+        .collect()
+}
+
+fn resize_taps(len: usize, count: usize, filter: ResizeFilter) -> Taps {
+    let scale = len as f32 / count as f32;
+    let stretch = scale.max(1.0);
+    let radius = resize_radius(filter) * stretch;
+
+    (0..count)
+        .map(|i| {
+            // Pixel centers of the output, mapped onto the source axis.
+            let center = (i as f32 + 0.5) * scale - 0.5;
+            let first = (center - radius).floor() as isize;
+            let last = (center + radius).ceil() as isize;
+            let mut taps: Vec<(usize, f32)> = (first..=last)
+                .map(|s| {
+                    let t = (s as f32 - center) / stretch;
+                    (clamp_index(s, len), resize_kernel(filter, t))
+                })
+                .filter(|&(_, w)| w != 0.0)
+                .collect();
+            let total: f32 = taps.iter().map(|&(_, w)| w).sum();
+            for tap in taps.iter_mut() {
+                tap.1 /= total;
+            }
+            taps
+        })
+        .collect()
+}
+
+pub(crate) fn clamp_index(i: isize, len: usize) -> usize {
+    i.clamp(0, len as isize - 1) as usize
+}
+
+fn kernel(method: ChromaDownsampling, t: f32) -> f32 {
+    let t = t.abs();
+    match method {
+        ChromaDownsampling::Triangle => (1.0 - t).max(0.0),
+        _ if t < 0.5 => 1.0,
+        _ if t == 0.5 => 0.5,
+        _ => 0.0,
+    }
+}
+
+/// Half-width of a resize kernel at unit scale.
+fn resize_radius(filter: ResizeFilter) -> f32 {
+    match filter {
+        ResizeFilter::Box => 0.5,
+        ResizeFilter::Triangle => 1.0,
+        ResizeFilter::Mitchell => 2.0,
+        ResizeFilter::Lanczos3 => 3.0,
+    }
+}
+
+fn resize_kernel(filter: ResizeFilter, t: f32) -> f32 {
+    let t = t.abs();
+    let sinc = |x: f32| if x == 0.0 { 1.0 } else { libm::sinf(PI * x) / (PI * x) };
+    match filter {
+        ResizeFilter::Box => kernel(ChromaDownsampling::Box, t),
+        ResizeFilter::Triangle => kernel(ChromaDownsampling::Triangle, t),
+        // Mitchell–Netravali with B = C = 1/3.
+        ResizeFilter::Mitchell if t < 1.0 => (7.0 * t * t * t - 12.0 * t * t + 16.0 / 3.0) / 6.0,
+        ResizeFilter::Mitchell if t < 2.0 => {
+            (-7.0 / 3.0 * t * t * t + 12.0 * t * t - 20.0 * t + 32.0 / 3.0) / 6.0
+        }
+        ResizeFilter::Lanczos3 if t < 3.0 => sinc(t) * sinc(t / 3.0),
+        _ => 0.0,
+    }
+}
+
+/// Runs `taps` along the rows (`horizontal`) or columns of a `w`x`h` plane.
+pub(crate) fn apply_taps(input: &[f32], w: usize, h: usize, taps: &Taps, horizontal: bool) -> Vec<f32> {
+    let (out_w, out_h) = if horizontal { (taps.len(), h) } else { (w, taps.len()) };
+    let mut out = vec![0.0; out_w * out_h];
+    for y in 0..out_h {
+        for x in 0..out_w {
+            out[y * out_w + x] = if horizontal {
+                taps[x].iter().map(|&(s, wt)| input[y * w + s] * wt).sum()
+            } else {
+                taps[y].iter().map(|&(s, wt)| input[s * w + x] * wt).sum()
+            };
+        }
+    }
+    out
+}
@@ -0,0 +1,172 @@
+use wasm_bindgen::prelude::*;
+
+use crate::error::Error;
+use crate::metrics::{self, SsimWindow};
+use crate::options::{ChromaSubsampling, CompressOptions, PixelFormat};
+use crate::perceptual;
+use crate::pipeline::{self, CachedPlanes, Frame};
+use crate::result::EncodedJpeg;
+
+/// Quality measure a compression search aims for.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QualityMetric {
+    /// Luma SSIM (gaussian window); the result must stay at or above the target.
+    Ssim,
+    /// Butteraugli-style `perceptual_distance` score; the result must stay at or below the target.
+    Butteraugli,
+}
+
+impl QualityMetric {
+    fn measure(self, original: &[u8], output: &[u8], width: usize, height: usize) -> f64 {
+        match self {
+            QualityMetric::Ssim => {
+                metrics::ssim(original, output, width, height, SsimWindow::Gaussian)
+            }
+            QualityMetric::Butteraugli => {
+                perceptual::perceptual_distance(original, output, width, height).score
+            }
+        }
+    }
+
+    fn meets(self, value: f64, target: f64) -> bool {
+        match self {
+            QualityMetric::Ssim => value >= target,
+            QualityMetric::Butteraugli => value <= target,
+        }
+    }
+}
+
+/// Bisection steps of the search; 8 steps resolve `compression` to 1/256.
+const SEARCH_STEPS: usize = 8;
+
+/// Outcome of [`search_quality`].
+pub(crate) struct SearchOutcome {
+    /// Strongest compression found that meets the target (0.0 if none does).
+    pub(crate) compression: f32,
+    /// Metric value of `pixels`.
+    pub(crate) score: f64,
+    /// Compressed RGBA pixels.
+    pub(crate) pixels: Vec<u8>,
+}
+
+/// Bisects `compression` for the strongest setting whose output still meets
+/// `target`, reusing one cached forward DCT for every attempt.
+pub(crate) fn search_quality(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    options: &CompressOptions,
+    metric: QualityMetric,
+    target: f64,
+) -> Result<SearchOutcome, Error> {
+    let frame = Frame::from_pixels(data, width, height, PixelFormat::Rgba)?;
+    let cache = CachedPlanes::new(frame, options);
+
+    let mut best = SearchOutcome {
+        compression: 0.0,
+        score: metric.measure(data, data, width, height),
+        pixels: data.to_vec(),
+    };
+    let (mut lo, mut hi) = (0.0f32, 1.0f32);
+    for _ in 0..SEARCH_STEPS {
+        let compression = (lo + hi) / 2.0;
+        let trial = CompressOptions {
+            compression,
+            ..options.clone()
+        };
+        let pixels = cache.compress(&trial).0.to_pixels(PixelFormat::Rgba);
+        let score = metric.measure(data, &pixels, width, height);
+        if metric.meets(score, target) {
+            best = SearchOutcome {
+                compression,
+                score,
+                pixels,
+            };
+            lo = compression;
+        } else {
+            hi = compression;
+        }
+    }
+    Ok(best)
+}
+
+/// Finds the best-looking baseline JPEG no larger than `max_bytes`.
+///
+/// For each candidate subsampling (just `options.subsampling` unless
+/// `search_subsampling` is set) the weakest compression that fits is bisected;
+/// the candidate with the highest SSIM wins. When nothing fits, the smallest
+/// file found is returned.
+pub(crate) fn search_size(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    options: &CompressOptions,
+    max_bytes: usize,
+    search_subsampling: bool,
+) -> Result<EncodedJpeg, Error> {
+    let frame = Frame::from_pixels(data, width, height, PixelFormat::Rgba)?;
+    pipeline::check_jpeg_dimensions(width, height)?;
+    let candidates = if search_subsampling {
+        vec![
+            ChromaSubsampling::Yuv444,
+            ChromaSubsampling::Yuv422,
+            ChromaSubsampling::Yuv420,
+        ]
+    } else {
+        vec![options.subsampling]
+    };
+
+    let mut best: Option<(f64, EncodedJpeg)> = None;
+    let mut smallest: Option<EncodedJpeg> = None;
+    for subsampling in candidates {
+        let base = pipeline::jfif_options(&CompressOptions {
+            subsampling,
+            ..options.clone()
+        });
+        let cache = CachedPlanes::new(frame.clone(), &base);
+        let encode = |compression: f32| {
+            let trial = CompressOptions {
+                compression,
+                ..base.clone()
+            };
+            cache.encode_jpeg(&trial)
+        };
+
+        let strongest = encode(1.0);
+        if strongest.len() > max_bytes {
+            if smallest
+                .as_ref()
+                .is_none_or(|s| strongest.len() < s.size() as usize)
+            {
+                smallest = Some(EncodedJpeg::new(strongest, 1.0, subsampling));
+            }
+            continue;
+        }
+
+        let (mut lo, mut hi) = (0.0f32, 1.0f32);
+        let mut fit = strongest;
+        for _ in 0..SEARCH_STEPS {
+            let compression = (lo + hi) / 2.0;
+            let bytes = encode(compression);
+            if bytes.len() <= max_bytes {
+                fit = bytes;
+                hi = compression;
+            } else {
+                lo = compression;
+            }
+        }
+
+        let trial = CompressOptions {
+            compression: hi,
+            ..base.clone()
+        };
+        let pixels = cache.compress(&trial).0.to_pixels(PixelFormat::Rgba);
+        let score = metrics::ssim(data, &pixels, width, height, SsimWindow::Gaussian);
+        if best.as_ref().is_none_or(|(s, _)| score > *s) {
+            best = Some((score, EncodedJpeg::new(fit, hi, subsampling)));
+        }
+    }
+
+    Ok(best.map(|(_, jpeg)| jpeg).or(smallest).unwrap())
+}
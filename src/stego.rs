@@ -0,0 +1,80 @@
+//! JSteg-style steganography in quantized DCT coefficients.
+//!
+//! Each bit goes into the least significant bit of the magnitude of an AC
+//! level of 2 or more, so no level changes between zero and nonzero and the
+//! reader finds exactly the coefficients the writer used. Levels are visited
+//! component by component, block by block in raster order, in zigzag order
+//! within a block. A 32-bit big-endian length precedes the payload.
+
+use crate::dct::ZIGZAG;
+use crate::error::Error;
+
+/// Bytes of the length prefix.
+const LENGTH_BYTES: usize = 4;
+
+/// Whether a level can carry a bit without changing which levels are zero.
+fn usable(level: i32) -> bool {
+    level.abs() >= 2
+}
+
+/// Largest payload in bytes that [`embed`] fits into `blocks`.
+pub(crate) fn capacity<'a>(blocks: impl IntoIterator<Item = &'a [[i32; 8]; 8]>) -> usize {
+    let bits: usize = blocks
+        .into_iter()
+        .map(|block| ZIGZAG[1..].iter().filter(|&&(u, v)| usable(block[u][v])).count())
+        .sum();
+    (bits / 8).saturating_sub(LENGTH_BYTES)
+}
+
+/// Hides `payload` in the levels of `blocks`.
+pub(crate) fn embed<'a>(
+    blocks: impl IntoIterator<Item = &'a mut [[i32; 8]; 8]>,
+    payload: &[u8],
+) -> Result<(), Error> {
+    let length = u32::try_from(payload.len()).map_err(|_| Error::PayloadTooLarge)?;
+    let length = length.to_be_bytes();
+    let mut bits = length
+        .iter()
+        .chain(payload)
+        .flat_map(|&byte| (0..8).rev().map(move |i| (byte >> i & 1) as i32))
+        .peekable();
+    for block in blocks {
+        for &(u, v) in &ZIGZAG[1..] {
+            let level = &mut block[u][v];
+            if !usable(*level) {
+                continue;
+            }
+            let Some(bit) = bits.next() else {
+                return Ok(());
+            };
+            let magnitude = level.abs() & !1 | bit;
+            *level = magnitude * level.signum();
+        }
+    }
+    match bits.peek() {
+        None => Ok(()),
+        Some(_) => Err(Error::PayloadTooLarge),
+    }
+}
+
+/// Recovers the payload [`embed`] hid in `blocks`.
+pub(crate) fn extract<'a>(
+    blocks: impl IntoIterator<Item = &'a [[i32; 8]; 8]>,
+) -> Result<Vec<u8>, Error> {
+    let bits: Vec<u8> = blocks
+        .into_iter()
+        .flat_map(|block| ZIGZAG[1..].iter().map(move |&(u, v)| block[u][v]))
+        .filter(|&level| usable(level))
+        .map(|level| (level.abs() & 1) as u8)
+        .collect();
+    let mut bytes = bits
+        .chunks_exact(8)
+        .map(|byte| byte.iter().fold(0, |value, &bit| value << 1 | bit));
+    let length: Vec<u8> = bytes.by_ref().take(LENGTH_BYTES).collect();
+    let length = <[u8; LENGTH_BYTES]>::try_from(length).map_err(|_| Error::NoPayload)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > bits.len() / 8 - LENGTH_BYTES {
+        return Err(Error::NoPayload);
+    }
+    Ok(bytes.take(length).collect())
+}
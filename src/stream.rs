@@ -0,0 +1,396 @@
+//! Continuous compression of a stream of frames, such as a live preview,
+//! pixel rows read from a `ReadableStream`, and JPEG files written out in
+//! pieces.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use js_sys::{Function, Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::ImageData as BrowserImageData;
+
+use crate::encoder::ChunkedEncoder;
+use crate::error::Error;
+use crate::media::{self, FrameCanvas};
+use crate::options::{CompressOptions, Orientation, PixelFormat};
+use crate::pipeline::{self, BandCompressor, Frame};
+use crate::web;
+
+/// A frame waiting in a `FrameCompressor`, as it was pushed.
+struct PendingFrame {
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    color_space: Option<String>,
+    timestamp: f64,
+}
+
+/// A frame compressed by a `FrameCompressor`.
+#[wasm_bindgen]
+pub struct CompressedFrame {
+    image_data: BrowserImageData,
+    /// The timestamp the frame was pushed with, e.g. `VideoFrame.timestamp`.
+    pub timestamp: f64,
+}
+
+#[wasm_bindgen]
+impl CompressedFrame {
+    /// The compressed pixels.
+    #[wasm_bindgen(getter)]
+    pub fn image_data(&self) -> BrowserImageData {
+        self.image_data.clone()
+    }
+
+    /// The compressed pixels as a new `VideoFrame` with the frame's
+    /// `timestamp`, e.g. for a `VideoEncoder` or a `MediaStreamTrackGenerator`.
+    /// Close it once it is used.
+    pub fn to_video_frame(&self) -> Result<JsValue, JsValue> {
+        let pixels = Uint8Array::from(&self.image_data.data()[..]);
+        let init = web::object(&[
+            ("format", &"RGBA".into()),
+            ("codedWidth", &self.image_data.width().into()),
+            ("codedHeight", &self.image_data.height().into()),
+            ("timestamp", &self.timestamp.into()),
+        ])?;
+        web::construct("VideoFrame", &[&pixels, &init])
+    }
+}
+
+/// Compresses a stream of frames with settings configured once, for live
+/// previews and streaming.
+///
+/// `push` takes each `VideoFrame` as it arrives and closes it; `pull` returns
+/// a promise of the next compressed frame, which waits for a push when none
+/// is queued. The queue holds at most `capacity` frames. Pushing onto a full
+/// queue drops the oldest frame, so the output never falls further behind
+/// the input; `ready` turns false first, as the signal to skip frames at the
+/// source instead.
+#[wasm_bindgen]
+pub struct FrameCompressor {
+    options: CompressOptions,
+    capacity: usize,
+    queue: VecDeque<PendingFrame>,
+    dropped: u32,
+    /// The resolve and reject functions of the `pull` promises waiting for a
+    /// frame, oldest first.
+    waiting: VecDeque<(Function, Function)>,
+    /// The canvas `VideoFrame`s are drawn on to read their pixels.
+    canvas: Option<FrameCanvas>,
+}
+
+impl FrameCompressor {
+    /// Queues a frame, dropping the oldest one if the queue is full, and
+    /// hands frames to waiting pulls. Returns whether nothing was dropped.
+    fn enqueue(&mut self, frame: PendingFrame) -> Result<bool, Error> {
+        let channels = frame.format.channels();
+        pipeline::check_dimensions(frame.width, frame.height, frame.data.len(), channels)?;
+        let room = self.queue.len() < self.capacity;
+        if !room {
+            self.queue.pop_front();
+            self.dropped += 1;
+        }
+        self.queue.push_back(frame);
+        self.serve();
+        Ok(room)
+    }
+
+    /// Compresses queued frames for the pulls waiting for them.
+    fn serve(&mut self) {
+        while let Some((resolve, reject)) = self.waiting.pop_front() {
+            let Some(pending) = self.queue.pop_front() else {
+                self.waiting.push_front((resolve, reject));
+                break;
+            };
+            // A settled promise has no one left to report a failed call to.
+            let _ = match self.compress(pending) {
+                Ok(frame) => resolve.call1(&JsValue::UNDEFINED, &frame.into()),
+                Err(err) => reject.call1(&JsValue::UNDEFINED, &err),
+            };
+        }
+    }
+
+    fn compress(&self, pending: PendingFrame) -> Result<CompressedFrame, JsValue> {
+        let (width, height) = (pending.width, pending.height);
+        let frame = Frame::from_pixels(&pending.data, width, height, pending.format)?;
+        let output = if self.options.compression <= 0.0 {
+            frame
+        } else {
+            pipeline::compress_frame(frame, &self.options)
+        };
+        let pixels = output.to_pixels(PixelFormat::Rgba);
+        let color_space = pending.color_space.as_deref();
+        Ok(CompressedFrame {
+            image_data: web::new_image_data(&pixels, width as u32, height as u32, color_space)?,
+            timestamp: pending.timestamp,
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl FrameCompressor {
+    /// A compressor with `options` and room for `capacity` (at least 1)
+    /// waiting frames. Use 1 for the lowest latency.
+    #[wasm_bindgen(constructor)]
+    pub fn new(options: &CompressOptions, capacity: u32) -> FrameCompressor {
+        FrameCompressor {
+            options: options.clone(),
+            capacity: capacity.max(1) as usize,
+            queue: VecDeque::new(),
+            dropped: 0,
+            waiting: VecDeque::new(),
+            canvas: None,
+        }
+    }
+
+    /// Replaces the options for every frame compressed from now on, including
+    /// those already queued.
+    pub fn set_options(&mut self, options: &CompressOptions) {
+        self.options = options.clone();
+    }
+
+    /// Queues a `VideoFrame`, e.g. from a `MediaStreamTrackProcessor` or a
+    /// `VideoDecoder`, with its timestamp. Its pixels are read by drawing it
+    /// on a canvas, and it is closed, even when that fails.
+    ///
+    /// **Returns:**
+    /// `false` when the queue was full and its oldest frame was dropped to
+    /// make room.
+    pub fn push(&mut self, frame: &JsValue) -> Result<bool, JsValue> {
+        let timestamp = Reflect::get(frame, &"timestamp".into()).map(|t| t.as_f64());
+        let image_data = media::video_frame_pixels(frame, &mut self.canvas);
+        // Frames hold decoder memory until closed, so close even on error.
+        web::call(frame, "close", &[])?;
+        self.push_image_data(image_data?, timestamp?.unwrap_or(0.0))
+    }
+
+    /// Queues an RGBA frame. Returns `false` when the queue was full and its
+    /// oldest frame was dropped to make room.
+    pub fn push_image_data(
+        &mut self,
+        image_data: BrowserImageData,
+        timestamp: f64,
+    ) -> Result<bool, JsValue> {
+        Ok(self.enqueue(PendingFrame {
+            data: image_data.data().to_vec(),
+            width: image_data.width() as usize,
+            height: image_data.height() as usize,
+            format: PixelFormat::Rgba,
+            color_space: web::color_space(&image_data),
+            timestamp,
+        })?)
+    }
+
+    /// Queues an interleaved 8-bit frame in any supported channel order, such
+    /// as the `RGBA` or `BGRA` buffer `VideoFrame.copyTo` fills. Returns
+    /// `false` when the oldest queued frame was dropped to make room.
+    pub fn push_pixels(
+        &mut self,
+        data: Vec<u8>,
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        timestamp: f64,
+    ) -> Result<bool, JsValue> {
+        Ok(self.enqueue(PendingFrame {
+            data,
+            width: width as usize,
+            height: height as usize,
+            format,
+            color_space: None,
+            timestamp,
+        })?)
+    }
+
+    /// The next compressed frame: the oldest queued one, or else the next
+    /// one pushed.
+    ///
+    /// **Returns:**
+    /// A `Promise` of a `CompressedFrame`. Pulls waiting together get frames
+    /// in the order they were made.
+    pub fn pull(&mut self) -> Promise {
+        let mut settle = None;
+        let promise = Promise::new(&mut |resolve, reject| settle = Some((resolve, reject)));
+        self.waiting.extend(settle);
+        self.serve();
+        promise
+    }
+
+    /// Drops every queued frame. Waiting pulls keep waiting for new ones.
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+    /// Whether another frame fits without dropping one.
+    #[wasm_bindgen(getter)]
+    pub fn ready(&self) -> bool {
+        self.queue.len() < self.capacity
+    }
+
+    /// Number of frames waiting for `pull`.
+    #[wasm_bindgen(getter)]
+    pub fn queued(&self) -> u32 {
+        self.queue.len() as u32
+    }
+
+    /// Number of frames dropped so far because the queue was full.
+    #[wasm_bindgen(getter)]
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+}
+
+/// Pixel rows being read from a `ReadableStream` and compressed a band at a
+/// time, as soon as the rows of each band are in.
+pub(crate) struct RowReader {
+    reader: JsValue,
+    bands: BandCompressor,
+    /// The input row being read, and how much of it has arrived.
+    row: Vec<u8>,
+    filled: usize,
+    /// Index of the input row being read.
+    y: usize,
+    height: usize,
+    /// The `crop_*` region, as left and top edges, width and height.
+    crop: (usize, usize, usize, usize),
+    channels: usize,
+    orientation: Orientation,
+}
+
+impl RowReader {
+    /// Starts reading `stream`, which must yield `Uint8Array` chunks holding
+    /// `width * height` pixels of `format` in total, split anywhere.
+    pub(crate) fn start(
+        stream: &JsValue,
+        (width, height): (usize, usize),
+        format: PixelFormat,
+        options: CompressOptions,
+    ) -> Result<JsValue, JsValue> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidDimensions.into());
+        }
+        let crop = pipeline::crop_rect((width, height), &options)?;
+        let orientation = options.orientation;
+        RowReader {
+            reader: web::call(stream, "getReader", &[])?,
+            bands: BandCompressor::new(crop.2, crop.3, format, options)?,
+            row: vec![0; width * format.channels()],
+            filled: 0,
+            y: 0,
+            height,
+            crop,
+            channels: format.channels(),
+            orientation,
+        }
+        .read()
+    }
+
+    /// A promise of the compressed pixels, taking in chunks until the stream
+    /// is done.
+    fn read(mut self) -> Result<JsValue, JsValue> {
+        let chunk = web::call(&self.reader, "read", &[])?;
+        web::then(&chunk, move |chunk| {
+            if Reflect::get(&chunk, &"done".into())?.is_truthy() {
+                return self.finish();
+            }
+            let value: Uint8Array = Reflect::get(&chunk, &"value".into())?.dyn_into()?;
+            if let Err(err) = self.take(&value.to_vec()) {
+                web::call(&self.reader, "cancel", &[])?;
+                return Err(err.into());
+            }
+            self.read()
+        })
+    }
+
+    /// Copies a chunk into the rows, handing every completed row of the crop
+    /// region on to the bands.
+    fn take(&mut self, mut chunk: &[u8]) -> Result<(), Error> {
+        let (x, top, w, h) = self.crop;
+        while !chunk.is_empty() {
+            if self.y == self.height {
+                return Err(Error::BufferMismatch);
+            }
+            let n = chunk.len().min(self.row.len() - self.filled);
+            self.row[self.filled..self.filled + n].copy_from_slice(&chunk[..n]);
+            (self.filled, chunk) = (self.filled + n, &chunk[n..]);
+            if self.filled < self.row.len() {
+                break;
+            }
+            if (top..top + h).contains(&self.y) {
+                self.bands.push_rows(&self.row[x * self.channels..(x + w) * self.channels])?;
+            }
+            (self.filled, self.y) = (0, self.y + 1);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<JsValue, JsValue> {
+        if self.y != self.height {
+            return Err(Error::BufferMismatch.into());
+        }
+        let size = (self.crop.2, self.crop.3);
+        let output = Cow::Owned(self.bands.finish()?);
+        let (output, _, _) = pipeline::orient(output, size, self.channels, self.orientation);
+        Ok(Uint8Array::from(&output[..]).into())
+    }
+}
+
+/// Where `ChunkWriter` hands the pieces of a file.
+enum ChunkSink {
+    /// Called with every piece; a returned promise is waited for.
+    Callback(Function),
+    /// The writer of a `WritableStream`, closed after the last piece.
+    Stream(JsValue),
+}
+
+/// A JPEG file handed to a sink in pieces, each one encoded only once the
+/// sink has taken the previous one.
+pub(crate) struct ChunkWriter {
+    encoder: ChunkedEncoder,
+    sink: ChunkSink,
+    chunk_size: usize,
+    written: usize,
+}
+
+impl ChunkWriter {
+    /// Starts writing the file of `encoder` to `sink`, a function or a
+    /// `WritableStream`, in pieces of about `chunk_size` bytes. The returned
+    /// promise resolves to the file size once the last piece is taken.
+    pub(crate) fn start(
+        encoder: ChunkedEncoder,
+        sink: &JsValue,
+        chunk_size: usize,
+    ) -> Result<JsValue, JsValue> {
+        let sink = match sink.dyn_ref::<Function>() {
+            Some(callback) => ChunkSink::Callback(callback.clone()),
+            None => ChunkSink::Stream(web::call(sink, "getWriter", &[])?),
+        };
+        ChunkWriter {
+            encoder,
+            sink,
+            chunk_size,
+            written: 0,
+        }
+        .write()
+    }
+
+    fn write(mut self) -> Result<JsValue, JsValue> {
+        let Some(chunk) = self.encoder.next_chunk(self.chunk_size) else {
+            let written = JsValue::from(self.written as f64);
+            return match &self.sink {
+                ChunkSink::Callback(_) => Ok(written),
+                ChunkSink::Stream(writer) => {
+                    web::then(&web::call(writer, "close", &[])?, move |_| Ok(written))
+                }
+            };
+        };
+        self.written += chunk.len();
+        let chunk = Uint8Array::from(&chunk[..]);
+        let taken = match &self.sink {
+            ChunkSink::Callback(callback) => callback.call1(&JsValue::NULL, &chunk)?,
+            ChunkSink::Stream(writer) => web::call(writer, "write", &[&chunk])?,
+        };
+        web::then(&Promise::resolve(&taken), move |_| self.write())
+    }
+}
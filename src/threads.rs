@@ -0,0 +1,49 @@
+//! The rayon thread pool of the `threads` feature.
+//!
+//! In the browser the pool runs on Web Workers started by wasm-bindgen-rayon,
+//! which share the module's memory and so need a cross-origin isolated page
+//! (COOP and COEP headers). Until `init_thread_pool` has started them, and
+//! for good when it can't, all work stays on the calling thread.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use js_sys::{Promise, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::web;
+
+/// Whether work may go to the pool. Native builds have rayon's own pool from
+/// the start.
+static POOL_READY: AtomicBool = AtomicBool::new(!cfg!(target_arch = "wasm32"));
+
+pub(crate) fn pool_ready() -> bool {
+    POOL_READY.load(Ordering::Acquire)
+}
+
+/// Starts a pool of `threads` workers, resolving to the number of threads
+/// the crate will use: `threads`, or 1 when the page isn't cross-origin
+/// isolated or the workers fail to start.
+pub(crate) fn start_pool(threads: usize) -> Result<Promise, JsValue> {
+    let isolated = Reflect::get(&js_sys::global(), &"crossOriginIsolated".into())?;
+    if !isolated.is_truthy() || threads <= 1 || pool_ready() {
+        let threads = if pool_ready() { rayon::current_num_threads() } else { 1 };
+        return Ok(Promise::resolve(&JsValue::from(threads as u32)));
+    }
+    let started = web::then(&spawn_workers(threads), move |_| {
+        POOL_READY.store(true, Ordering::Release);
+        Ok(JsValue::from(threads as u32))
+    })?;
+    let fallback = Closure::once_into_js(|_: JsValue| JsValue::from(1));
+    Ok(web::call(&started, "catch", &[&fallback])?.unchecked_into())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn spawn_workers(threads: usize) -> JsValue {
+    wasm_bindgen_rayon::init_thread_pool(threads).into()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn spawn_workers(_threads: usize) -> JsValue {
+    Promise::resolve(&JsValue::UNDEFINED).into()
+}
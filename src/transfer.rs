@@ -0,0 +1,69 @@
+use crate::options::{ToneMapping, TransferFunction};
+
+impl TransferFunction {
+    /// Encodes a linear-light value (1.0 = reference white) to the curve's signal value.
+    ///
+    /// Values outside 0–1 are extended symmetrically so HDR highlights survive
+    /// until they are clamped (or tone mapped) later.
+    pub(crate) fn encode(self, linear: f32) -> f32 {
+        let v = linear.abs();
+        let encoded = match self {
+            TransferFunction::Linear => v,
+            TransferFunction::Srgb => {
+                if v <= 0.0031308 {
+                    12.92 * v
+                } else {
+                    1.055 * libm::powf(v, 1.0 / 2.4) - 0.055
+                }
+            }
+            TransferFunction::Gamma22 => libm::powf(v, 1.0 / 2.2),
+            TransferFunction::Bt709 => {
+                if v < 0.018 {
+                    4.5 * v
+                } else {
+                    1.099 * libm::powf(v, 0.45) - 0.099
+                }
+            }
+        };
+        encoded.copysign(linear)
+    }
+
+    /// Inverse of [`TransferFunction::encode`].
+    pub(crate) fn decode(self, encoded: f32) -> f32 {
+        let v = encoded.abs();
+        let linear = match self {
+            TransferFunction::Linear => v,
+            TransferFunction::Srgb => {
+                if v <= 0.04045 {
+                    v / 12.92
+                } else {
+                    libm::powf((v + 0.055) / 1.055, 2.4)
+                }
+            }
+            TransferFunction::Gamma22 => libm::powf(v, 2.2),
+            TransferFunction::Bt709 => {
+                if v < 0.081 {
+                    v / 4.5
+                } else {
+                    libm::powf((v + 0.099) / 1.099, 1.0 / 0.45)
+                }
+            }
+        };
+        linear.copysign(encoded)
+    }
+}
+
+impl ToneMapping {
+    /// Compresses a linear-light value of any magnitude into 0–1.
+    pub(crate) fn apply(self, linear: f32) -> f32 {
+        let x = linear.max(0.0);
+        match self {
+            ToneMapping::None => x.min(1.0),
+            ToneMapping::Reinhard => x / (1.0 + x),
+            // Narkowicz's fit of the ACES filmic curve.
+            ToneMapping::Aces => {
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
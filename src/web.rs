@@ -0,0 +1,113 @@
+use js_sys::{Array, Function, Object, Reflect, Uint8ClampedArray};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::ImageData as BrowserImageData;
+
+/// Reads the `colorSpace` attribute of an ImageData (`"srgb"`, `"display-p3"`),
+/// or `None` on browsers that predate it.
+pub(crate) fn color_space(image_data: &BrowserImageData) -> Option<String> {
+    Reflect::get(image_data, &JsValue::from_str("colorSpace"))
+        .ok()
+        .and_then(|v| v.as_string())
+}
+
+/// Creates an RGBA ImageData, tagged with `color_space` when it isn't plain sRGB.
+pub(crate) fn new_image_data(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    color_space: Option<&str>,
+) -> Result<BrowserImageData, JsValue> {
+    let color_space = match color_space {
+        None | Some("srgb") => {
+            return BrowserImageData::new_with_u8_clamped_array_and_sh(
+                wasm_bindgen::Clamped(pixels),
+                width,
+                height,
+            );
+        }
+        Some(color_space) => color_space,
+    };
+
+    let settings = Object::new();
+    Reflect::set(&settings, &"colorSpace".into(), &color_space.into())?;
+    let constructor: Function = Reflect::get(&js_sys::global(), &"ImageData".into())?.dyn_into()?;
+    let args = Array::of4(
+        &Uint8ClampedArray::from(pixels),
+        &width.into(),
+        &height.into(),
+        &settings,
+    );
+    Reflect::construct(&constructor, &args)?.dyn_into()
+}
+
+/// Alphabet of standard base64 (RFC 4648).
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A `data:` URL holding `bytes` of type `mime`, base64-encoded.
+pub(crate) fn data_url(mime: &str, bytes: &[u8]) -> String {
+    let mut url = format!("data:{mime};base64,");
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().fold(0u32, |g, &b| g << 8 | b as u32) << (8 * (3 - chunk.len()));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                url.push(BASE64[(group >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                url.push('=');
+            }
+        }
+    }
+    url
+}
+
+/// Creates an instance of the global class `name`, for browser APIs web-sys
+/// isn't built with.
+pub(crate) fn construct(name: &str, args: &[&JsValue]) -> Result<JsValue, JsValue> {
+    let constructor: Function = Reflect::get(&js_sys::global(), &name.into())?.dyn_into()?;
+    Reflect::construct(&constructor, &args.iter().collect::<Array>())
+}
+
+/// Calls the method `name` of `target`.
+pub(crate) fn call(target: &JsValue, name: &str, args: &[&JsValue]) -> Result<JsValue, JsValue> {
+    let method: Function = Reflect::get(target, &name.into())?.dyn_into()?;
+    Reflect::apply(&method, target, &args.iter().collect::<Array>())
+}
+
+/// A plain object with the given properties.
+pub(crate) fn object(properties: &[(&str, &JsValue)]) -> Result<Object, JsValue> {
+    let object = Object::new();
+    for &(key, value) in properties {
+        Reflect::set(&object, &key.into(), value)?;
+    }
+    Ok(object)
+}
+
+/// Runs `f` on the value `promise` resolves to, returning the chained
+/// promise. An `Err` from `f` rejects it; a promise returned by `f` is
+/// waited for.
+pub(crate) fn then(
+    promise: &JsValue,
+    f: impl FnOnce(JsValue) -> Result<JsValue, JsValue> + 'static,
+) -> Result<JsValue, JsValue> {
+    call(promise, "then", &[&Closure::once_into_js(f)])
+}
+
+/// A promise of the `result` of an IndexedDB request, rejected with its
+/// `error`.
+pub(crate) fn request(request: &JsValue) -> js_sys::Promise {
+    js_sys::Promise::new(&mut |resolve, reject| {
+        let target = request.clone();
+        let success = Closure::once_into_js(move || {
+            let result = Reflect::get(&target, &"result".into())?;
+            resolve.call1(&JsValue::UNDEFINED, &result)
+        });
+        let target = request.clone();
+        let failure = Closure::once_into_js(move || {
+            let error = Reflect::get(&target, &"error".into())?;
+            reject.call1(&JsValue::UNDEFINED, &error)
+        });
+        // Setting properties of a request object can't fail.
+        let _ = Reflect::set(request, &"onsuccess".into(), &success);
+        let _ = Reflect::set(request, &"onerror".into(), &failure);
+    })
+}
@@ -0,0 +1,323 @@
+//! `CompressorWorkerApi`, the message handling of a Web Worker that
+//! compresses for a page.
+//!
+//! Messages are plain objects, as `postMessage` can't clone exported
+//! classes: pixels and files travel as `ArrayBuffer`s, listed for transfer
+//! in both directions, and options as a plain object of `CompressOptions`
+//! fields. The schema is in the TypeScript section below.
+
+use js_sys::{Array, Object, Promise, Reflect, Uint8Array};
+use wasm_bindgen::convert::TryFromJsValue;
+use wasm_bindgen::prelude::*;
+
+use crate::error::Error;
+use crate::options::{CompressOptions, PixelFormat};
+use crate::{pipeline, web};
+
+#[wasm_bindgen(typescript_custom_section)]
+const MESSAGE_SCHEMA: &str = r#"
+/** The fields of `CompressOptions` as a plain object, for messages. */
+export type CompressOptionsInit = {
+    [K in keyof CompressOptions as CompressOptions[K] extends Function ? never : K]?:
+        CompressOptions[K];
+};
+
+/** RGBA pixels, `width * height * 4` bytes. */
+export interface PixelMessage {
+    id: number;
+    pixels: ArrayBuffer;
+    width: number;
+    height: number;
+}
+
+export type CompressorRequest =
+    | ({ type: "compress"; options?: CompressOptionsInit } & PixelMessage)
+    | ({ type: "encode_jpeg"; options?: CompressOptionsInit } & PixelMessage)
+    | { type: "decode_jpeg"; id: number; jpeg: ArrayBuffer };
+
+export type CompressorResponse =
+    | ({ type: "compress" } & PixelMessage)
+    | { type: "encode_jpeg"; id: number; jpeg: ArrayBuffer }
+    | ({ type: "decode_jpeg" } & PixelMessage)
+    | { type: "error"; id: number; code: string };
+
+/** The handshake of `CompressorWorkerApi.init`. */
+export type CompressorControlMessage =
+    | { type: "init"; module_or_path: WebAssembly.Module | string | URL }
+    | { type: "ready" }
+    | { type: "error"; message: string };
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "CompressorRequest")]
+    pub type CompressorRequest;
+
+    #[wasm_bindgen(typescript_type = "CompressorResponse")]
+    pub type CompressorResponse;
+}
+
+/// Code of the error response to a message that isn't a `CompressorRequest`.
+const INVALID_REQUEST: &str = "INVALID_REQUEST";
+
+/// A request read from a message.
+enum Request {
+    Compress(Pixels, CompressOptions),
+    EncodeJpeg(Pixels, CompressOptions),
+    DecodeJpeg(Vec<u8>),
+}
+
+/// RGBA pixels of a message.
+struct Pixels {
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+}
+
+/// The body of a response, before it becomes a message.
+enum Response {
+    Compress(Pixels),
+    EncodeJpeg(Vec<u8>),
+    DecodeJpeg(Pixels),
+}
+
+impl Pixels {
+    fn read(message: &JsValue) -> Result<Pixels, JsValue> {
+        let size = |name: &str| Reflect::get(message, &name.into()).map(|v| v.as_f64());
+        let (Some(width), Some(height)) = (size("width")?, size("height")?) else {
+            return Err(INVALID_REQUEST.into());
+        };
+        Ok(Pixels {
+            data: bytes(message, "pixels")?,
+            width: width as usize,
+            height: height as usize,
+        })
+    }
+
+    /// The fields of a `PixelMessage`, with the buffer holding `data`.
+    fn fields(&self) -> [(&'static str, JsValue); 3] {
+        [
+            ("pixels", Uint8Array::from(&self.data[..]).buffer().into()),
+            ("width", (self.width as u32).into()),
+            ("height", (self.height as u32).into()),
+        ]
+    }
+}
+
+/// The bytes of the `ArrayBuffer` or typed array under `name`.
+fn bytes(message: &JsValue, name: &str) -> Result<Vec<u8>, JsValue> {
+    let value = Reflect::get(message, &name.into())?;
+    if !value.is_object() {
+        return Err(INVALID_REQUEST.into());
+    }
+    Ok(Uint8Array::new(&value).to_vec())
+}
+
+impl Request {
+    fn read(message: &JsValue, defaults: &CompressOptions) -> Result<Request, JsValue> {
+        let kind = Reflect::get(message, &"type".into())?.as_string();
+        let options = || read_options(message, defaults);
+        match kind.as_deref() {
+            Some("compress") => Ok(Request::Compress(Pixels::read(message)?, options()?)),
+            Some("encode_jpeg") => Ok(Request::EncodeJpeg(Pixels::read(message)?, options()?)),
+            Some("decode_jpeg") => Ok(Request::DecodeJpeg(bytes(message, "jpeg")?)),
+            _ => Err(INVALID_REQUEST.into()),
+        }
+    }
+
+    fn run(self) -> Result<Response, Error> {
+        match self {
+            Request::Compress(pixels, options) => {
+                let size = (pixels.width, pixels.height);
+                let (data, width, height) =
+                    pipeline::transform_input(&pixels.data, size, 4, &options)?;
+                let rgba = PixelFormat::Rgba;
+                let data = pipeline::compress(&data, width, height, rgba, &options)?;
+                Ok(Response::Compress(Pixels {
+                    data,
+                    width,
+                    height,
+                }))
+            }
+            Request::EncodeJpeg(pixels, options) => {
+                let Pixels {
+                    data,
+                    width,
+                    height,
+                } = pixels;
+                let rgba = PixelFormat::Rgba;
+                let jpeg = pipeline::encode_jpeg(&data, width, height, rgba, &options)?;
+                Ok(Response::EncodeJpeg(jpeg))
+            }
+            Request::DecodeJpeg(jpeg) => {
+                let frame = pipeline::decode_jpeg(&jpeg)?;
+                Ok(Response::DecodeJpeg(Pixels {
+                    data: frame.to_pixels(PixelFormat::Rgba),
+                    width: frame.width,
+                    height: frame.height,
+                }))
+            }
+        }
+    }
+}
+
+impl Response {
+    fn to_message(&self, id: &JsValue) -> Result<Object, JsValue> {
+        let (kind, fields) = match self {
+            Response::Compress(pixels) => ("compress", pixels.fields().to_vec()),
+            Response::DecodeJpeg(pixels) => ("decode_jpeg", pixels.fields().to_vec()),
+            Response::EncodeJpeg(jpeg) => {
+                let buffer = Uint8Array::from(&jpeg[..]).buffer().into();
+                ("encode_jpeg", vec![("jpeg", buffer)])
+            }
+        };
+        let message = web::object(&[("type", &kind.into()), ("id", id)])?;
+        for (key, value) in fields {
+            Reflect::set(&message, &key.into(), &value)?;
+        }
+        Ok(message)
+    }
+}
+
+/// `defaults` with the fields of the request's `options` object set on top,
+/// through the setters of a `CompressOptions` instance so they are checked
+/// the same way.
+fn read_options(message: &JsValue, defaults: &CompressOptions) -> Result<CompressOptions, JsValue> {
+    let fields = Reflect::get(message, &"options".into())?;
+    if fields.is_undefined() || fields.is_null() {
+        return Ok(defaults.clone());
+    }
+    let options = JsValue::from(defaults.clone());
+    for entry in Object::entries(&fields.dyn_into()?) {
+        let entry: Array = entry.unchecked_into();
+        Reflect::set(&options, &entry.get(0), &entry.get(1))?;
+    }
+    CompressOptions::try_from_js_value(options)
+}
+
+/// Handles the requests a page posts to a Web Worker.
+///
+/// In the worker, initialize the module with the first message, which
+/// `CompressorWorkerApi.init` sends, then hand the worker over. A failed
+/// `init` is posted back as `{ type: "error" }` so the page's wait ends:
+///
+/// ```js
+/// self.onmessage = async ({ data }) => {
+///     try {
+///         await init(data.module_or_path);
+///     } catch (err) {
+///         self.postMessage({ type: "error", message: String(err) });
+///         return;
+///     }
+///     new CompressorWorkerApi(new CompressOptions()).listen();
+/// };
+/// ```
+#[wasm_bindgen]
+pub struct CompressorWorkerApi {
+    defaults: CompressOptions,
+}
+
+#[wasm_bindgen]
+impl CompressorWorkerApi {
+    /// An API applying each request's `options` on top of `defaults`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(defaults: &CompressOptions) -> CompressorWorkerApi {
+        CompressorWorkerApi {
+            defaults: defaults.clone(),
+        }
+    }
+
+    /// Answers a request. Failures become `"error"` responses carrying the
+    /// error code, or `"INVALID_REQUEST"` for a malformed message, so this
+    /// never throws.
+    ///
+    /// **Parameters:**
+    /// - `request`: The `data` of a `MessageEvent`.
+    ///
+    /// **Returns:**
+    /// The `CompressorResponse` to post back, with the same `id`.
+    pub fn handle(&self, request: &CompressorRequest) -> CompressorResponse {
+        let id = Reflect::get(request, &"id".into()).unwrap_or(JsValue::UNDEFINED);
+        let response = Request::read(request, &self.defaults)
+            .and_then(|request| Ok(request.run()?))
+            .and_then(|response| response.to_message(&id));
+        let message = response.unwrap_or_else(|err| {
+            let code = err.as_string().unwrap_or_else(|| INVALID_REQUEST.into());
+            let fields = [("type", &"error".into()), ("id", &id), ("code", &code.into())];
+            web::object(&fields).unwrap_or_default()
+        });
+        message.unchecked_into()
+    }
+
+    /// The buffers of a response, to pass as the transfer list of
+    /// `postMessage` so they move to the page instead of being copied.
+    pub fn transfer_list(response: &CompressorResponse) -> Array {
+        ["pixels", "jpeg"]
+            .into_iter()
+            .filter_map(|key| Reflect::get(response, &key.into()).ok())
+            .filter(JsValue::is_object)
+            .collect()
+    }
+
+    /// Makes this API answer every message the worker receives from now on,
+    /// then posts `{ type: "ready" }` to the page.
+    pub fn listen(self) -> Result<(), JsValue> {
+        let scope = js_sys::global();
+        let target = scope.clone();
+        let on_message = Closure::<dyn FnMut(JsValue)>::new(move |event: JsValue| {
+            let request = Reflect::get(&event, &"data".into()).unwrap_or(JsValue::UNDEFINED);
+            let response = self.handle(request.unchecked_ref());
+            let transfer = CompressorWorkerApi::transfer_list(&response);
+            // A failed post has no one left to be reported to.
+            let _ = web::call(&target, "postMessage", &[&response, &transfer]);
+        });
+        Reflect::set(&scope, &"onmessage".into(), &on_message.into_js_value())?;
+        let ready = web::object(&[("type", &"ready".into())])?;
+        web::call(&scope, "postMessage", &[&ready])?;
+        Ok(())
+    }
+
+    /// Starts the module in `worker`, on the page: posts the `"init"` message
+    /// the worker script waits for and waits for its `"ready"` answer, or
+    /// for the `error` or `messageerror` event of a worker that fails first.
+    ///
+    /// **Parameters:**
+    /// - `worker`: A `Worker` running a script as shown above.
+    /// - `module_or_path`: What the worker passes to `init`: the URL of the
+    ///   `.wasm` file, or a compiled `WebAssembly.Module` (which structured
+    ///   cloning shares, so the worker doesn't fetch and compile it again).
+    ///
+    /// **Returns:**
+    /// A `Promise` that resolves once the worker listens. It rejects with
+    /// the worker's first message when that isn't `{ type: "ready" }`, or
+    /// with the event when the worker fails to load or to read the message.
+    pub fn init(worker: &JsValue, module_or_path: &JsValue) -> Result<Promise, JsValue> {
+        let ready = Promise::new(&mut |resolve, reject| {
+            let failed = reject.clone();
+            // Whichever event comes first settles the promise; the others
+            // then find it settled.
+            let on_event = Closure::<dyn FnMut(JsValue)>::new(move |event: JsValue| {
+                let data = Reflect::get(&event, &"data".into()).unwrap_or_default();
+                let kind = Reflect::get(&event, &"type".into()).unwrap_or_default();
+                let answer = Reflect::get(&data, &"type".into()).unwrap_or_default();
+                let _ = match (kind.as_string().as_deref(), answer.as_string().as_deref()) {
+                    (Some("message"), Some("ready")) => resolve.call0(&JsValue::UNDEFINED),
+                    (Some("message"), _) => reject.call1(&JsValue::UNDEFINED, &data),
+                    _ => reject.call1(&JsValue::UNDEFINED, &event),
+                };
+            })
+            .into_js_value();
+            let once = web::object(&[("once", &true.into())]).unwrap_or_default();
+            for kind in ["message", "error", "messageerror"] {
+                let args = [&kind.into(), &on_event, once.as_ref()];
+                if let Err(err) = web::call(worker, "addEventListener", &args) {
+                    let _ = failed.call1(&JsValue::UNDEFINED, &err);
+                }
+            }
+        });
+        let fields = [("type", &"init".into()), ("module_or_path", module_or_path)];
+        let message = web::object(&fields)?;
+        web::call(worker, "postMessage", &[message.as_ref()])?;
+        Ok(ready)
+    }
+}